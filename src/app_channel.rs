@@ -0,0 +1,172 @@
+use crate::CastData;
+use crate::CastID;
+use crate::CastMessage;
+use crate::CastType;
+use crate::WrappedMessage;
+use std::collections::VecDeque;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TrySendError;
+
+// How much cast data can wait for an app to read it,
+// and how much an origin app can put in before its sender is full
+pub const APP_CHANNEL_CAPACITY: usize = 256;
+
+// What to do with cast data when an app does not keep up reading it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    #[default]
+    DropOldest,
+    DropNewest,
+    // Stop reading from source until app makes room, subscribers wait too
+    Block,
+    // Stop delivering to app, and leave the cast when nobody needs it
+    Unsubscribe,
+}
+
+pub fn app_channel() -> (SyncSender<CastData>, Receiver<CastData>) {
+    sync_channel(APP_CHANNEL_CAPACITY)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Delivery {
+    Sent,
+    // Waits for app to make room
+    Held,
+    Dropped,
+    Unsubscribe,
+    // App has dropped its receiver
+    Gone,
+}
+
+// Sending side of a bounded channel to app.
+// What does not fit into the channel waits here, std channels can not
+// drop what is already in them, so DropOldest drops oldest of waiting data.
+#[derive(Debug)]
+pub struct ToApp {
+    sender: SyncSender<CastData>,
+    policy: OverflowPolicy,
+    waiting: VecDeque<CastData>,
+}
+
+impl ToApp {
+    pub fn new(sender: SyncSender<CastData>, policy: OverflowPolicy) -> Self {
+        ToApp {
+            sender,
+            policy,
+            waiting: VecDeque::new(),
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: OverflowPolicy) {
+        self.policy = policy;
+    }
+
+    // Move waiting data into channel, false when app is gone
+    pub fn flush(&mut self) -> bool {
+        while let Some(data) = self.waiting.pop_front() {
+            match self.sender.try_send(data) {
+                Ok(()) => {}
+                Err(TrySendError::Full(data)) => {
+                    self.waiting.push_front(data);
+                    break;
+                }
+                Err(TrySendError::Disconnected(_data)) => return false,
+            }
+        }
+        true
+    }
+
+    // With Block policy no more data should be taken from source
+    pub fn is_blocked(&self) -> bool {
+        self.policy == OverflowPolicy::Block && !self.waiting.is_empty()
+    }
+
+    pub fn send(&mut self, data: CastData) -> Delivery {
+        if !self.flush() {
+            return Delivery::Gone;
+        }
+        let data = if self.waiting.is_empty() {
+            match self.sender.try_send(data) {
+                Ok(()) => return Delivery::Sent,
+                Err(TrySendError::Full(data)) => data,
+                Err(TrySendError::Disconnected(_data)) => return Delivery::Gone,
+            }
+        } else {
+            data
+        };
+        match self.policy {
+            OverflowPolicy::DropNewest => Delivery::Dropped,
+            OverflowPolicy::Unsubscribe => Delivery::Unsubscribe,
+            OverflowPolicy::Block => {
+                self.waiting.push_back(data);
+                Delivery::Held
+            }
+            OverflowPolicy::DropOldest => {
+                self.waiting.push_back(data);
+                if self.waiting.len() > APP_CHANNEL_CAPACITY {
+                    self.waiting.pop_front();
+                    Delivery::Dropped
+                } else {
+                    Delivery::Held
+                }
+            }
+        }
+    }
+}
+
+// Origin side, data from app is wrapped into cast messages.
+// These are moved to cast's source channel only when there is room,
+// so when a cast can not keep up, app's SyncSender gets full.
+pub struct FromApp {
+    c_type: CastType,
+    id: CastID,
+    receiver: Receiver<CastData>,
+    sender: SyncSender<WrappedMessage>,
+    pending: Option<WrappedMessage>,
+}
+
+impl FromApp {
+    pub fn new(
+        c_type: CastType,
+        id: CastID,
+        receiver: Receiver<CastData>,
+        sender: SyncSender<WrappedMessage>,
+    ) -> Self {
+        FromApp {
+            c_type,
+            id,
+            receiver,
+            sender,
+            pending: None,
+        }
+    }
+
+    // Returns whether any data was moved
+    pub fn serve(&mut self) -> bool {
+        let mut any_data_processed = false;
+        loop {
+            let message = if let Some(message) = self.pending.take() {
+                message
+            } else if let Ok(data) = self.receiver.try_recv() {
+                WrappedMessage::Cast(match self.c_type {
+                    CastType::Broadcast => CastMessage::new_broadcast(self.id, data),
+                    CastType::Multicast => CastMessage::new_multicast(self.id, data),
+                    CastType::Unicast => CastMessage::new_unicast(self.id, data),
+                })
+            } else {
+                break;
+            };
+            match self.sender.try_send(message) {
+                Ok(()) => any_data_processed = true,
+                Err(TrySendError::Full(message)) => {
+                    self.pending = Some(message);
+                    break;
+                }
+                Err(TrySendError::Disconnected(_message)) => break,
+            }
+        }
+        any_data_processed
+    }
+}
@@ -1,37 +1,48 @@
-use std::time::{Duration, SystemTime};
+use crate::Clock;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub struct BandwidthMonitor {
     used_tokens_history: [u64; 16],
     used_tokens_index: usize,
     current_usage: u64,
-    current_start: SystemTime,
+    current_start: Duration,
     period_time: Duration,
+    clock: Arc<dyn Clock>,
 }
 impl BandwidthMonitor {
-    pub fn new(period_time: Duration) -> Self {
+    pub fn new(period_time: Duration, clock: Arc<dyn Clock>) -> Self {
         Self {
             used_tokens_history: [0; 16],
             used_tokens_index: 0,
             current_usage: 0,
-            current_start: SystemTime::now(),
+            current_start: clock.now(),
             period_time,
+            clock,
         }
     }
     pub fn average(&self) -> u64 {
         let sum: u64 = self.used_tokens_history.iter().sum();
         sum >> 4
     }
-    pub fn update(&mut self, used_tokens: u64) {
-        let now = SystemTime::now();
+    pub fn period_time(&self) -> Duration {
+        self.period_time
+    }
+    // Returns true when history got filled up again,
+    // that is every 16 periods
+    pub fn update(&mut self, used_tokens: u64) -> bool {
+        let now = self.clock.now();
         self.current_usage += used_tokens;
-        if now.duration_since(self.current_start).unwrap() >= self.period_time {
+        if now.saturating_sub(self.current_start) >= self.period_time {
             self.used_tokens_history[self.used_tokens_index] = self.current_usage;
             self.current_usage = 0;
             self.current_start = now;
             self.used_tokens_index += 1;
             if self.used_tokens_index >= 16 {
                 self.used_tokens_index = 0;
+                return true;
             }
         }
+        false
     }
 }
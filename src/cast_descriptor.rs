@@ -0,0 +1,75 @@
+use crate::Requirement;
+
+// Names and content types longer than this are cut
+pub const MAX_DESCRIPTOR_TEXT_LEN: usize = 255;
+
+// What a broadcast or multicast is about.
+// It is a part of StartBroadcast/StartMulticast configuration,
+// so it is signed by origin along with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CastDescriptor {
+    pub name: String,
+    pub content_type: String,
+    // Expected bytes per second
+    pub rate: u32,
+    // Subscribers have to fulfill it, e.g. Requirement::Has(Capabilities::Admin)
+    pub requirement: Requirement,
+}
+
+impl Default for CastDescriptor {
+    fn default() -> Self {
+        CastDescriptor::new("", "", 0, Requirement::None)
+    }
+}
+
+impl CastDescriptor {
+    pub fn new(name: &str, content_type: &str, rate: u32, requirement: Requirement) -> Self {
+        CastDescriptor {
+            name: cut(name),
+            content_type: cut(content_type),
+            rate,
+            requirement,
+        }
+    }
+
+    pub fn from(bytes: &mut Vec<u8>) -> Self {
+        let name = take_text(bytes);
+        let content_type = take_text(bytes);
+        let rate = u32::from_be_bytes(bytes.drain(0..4).as_slice().try_into().unwrap());
+        let requirement = Requirement::from(bytes);
+        CastDescriptor {
+            name,
+            content_type,
+            rate,
+            requirement,
+        }
+    }
+
+    pub fn append_bytes_to(&self, bytes: &mut Vec<u8>) {
+        for text in [&self.name, &self.content_type] {
+            let text = cut(text);
+            bytes.push(text.len() as u8);
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        bytes.extend_from_slice(&self.rate.to_be_bytes());
+        self.requirement.append_bytes_to(bytes);
+    }
+
+    pub fn len_in_bytes(&self) -> usize {
+        6 + cut(&self.name).len() + cut(&self.content_type).len() + self.requirement.len() as usize
+    }
+}
+
+fn take_text(bytes: &mut Vec<u8>) -> String {
+    let len = bytes.remove(0) as usize;
+    String::from_utf8_lossy(&bytes.drain(0..len).collect::<Vec<u8>>()).to_string()
+}
+
+// At most MAX_DESCRIPTOR_TEXT_LEN bytes, without splitting a character
+fn cut(text: &str) -> String {
+    let mut end = text.len().min(MAX_DESCRIPTOR_TEXT_LEN);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    text[..end].to_string()
+}
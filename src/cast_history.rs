@@ -0,0 +1,57 @@
+use crate::CastData;
+use std::collections::VecDeque;
+
+// How much of recent cast data to keep for late joiners
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HistoryLimit {
+    Count(usize),
+    Bytes(usize),
+}
+
+// Ring of most recent CastData of a broadcast or multicast.
+// A subscriber can ask for it when subscribing,
+// it is then sent before any live data.
+pub struct CastHistory {
+    limit: HistoryLimit,
+    ring: VecDeque<CastData>,
+    bytes: usize,
+}
+
+impl CastHistory {
+    pub fn new(limit: HistoryLimit) -> Self {
+        CastHistory {
+            limit,
+            ring: VecDeque::new(),
+            bytes: 0,
+        }
+    }
+
+    pub fn set_limit(&mut self, limit: HistoryLimit) {
+        self.limit = limit;
+        self.trim();
+    }
+
+    pub fn record(&mut self, data: CastData) {
+        self.bytes += data.len();
+        self.ring.push_back(data);
+        self.trim();
+    }
+
+    // Oldest first
+    pub fn entries(&self) -> Vec<CastData> {
+        self.ring.iter().cloned().collect()
+    }
+
+    fn trim(&mut self) {
+        while match self.limit {
+            HistoryLimit::Count(count) => self.ring.len() > count,
+            HistoryLimit::Bytes(bytes) => self.bytes > bytes,
+        } {
+            if let Some(data) = self.ring.pop_front() {
+                self.bytes -= data.len();
+            } else {
+                break;
+            }
+        }
+    }
+}
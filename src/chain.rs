@@ -0,0 +1,725 @@
+use crate::equivocation::append_with_len;
+use crate::equivocation::take_with_len;
+use crate::succession::has_quorum;
+use crate::BlockID;
+use crate::Capabilities;
+use crate::Configuration;
+use crate::GnomeId;
+use crate::Policy;
+use crate::Requirement;
+use crate::Swarm;
+use crate::SwarmTime;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+
+// How many accepted blocks we remember and how many we share with neighbors
+pub const CHAIN_HISTORY_LEN: usize = 16;
+pub const CHAIN_INFO_LEN: usize = 8;
+// Every that many accepted blocks we send our ChainInfo to neighbors
+pub const CHAIN_INFO_INTERVAL: u64 = 4;
+// How long we wait for neighbor's proofs and for winner's registries
+pub const MERGE_TIMEOUT: SwarmTime = SwarmTime(60);
+
+// A single accepted block as seen by a gnome. Hash is chained, so it
+// covers every block accepted before it, and two gnomes with the same
+// hash for given round_start have the same view of the swarm's history.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChainLink {
+    pub round_start: SwarmTime,
+    pub hash: u64,
+    pub signer: GnomeId,
+}
+
+impl ChainLink {
+    pub fn append_bytes_to(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.round_start.0.to_be_bytes());
+        bytes.extend(self.hash.to_be_bytes());
+        bytes.extend(self.signer.0.to_be_bytes());
+    }
+
+    pub fn from(bytes: &mut Vec<u8>) -> Option<Self> {
+        if bytes.len() < 20 {
+            return None;
+        }
+        let round_start = SwarmTime(u32::from_be_bytes(
+            bytes.drain(0..4).collect::<Vec<u8>>().try_into().unwrap(),
+        ));
+        let hash = u64::from_be_bytes(bytes.drain(0..8).collect::<Vec<u8>>().try_into().unwrap());
+        let signer = GnomeId(u64::from_be_bytes(
+            bytes.drain(0..8).collect::<Vec<u8>>().try_into().unwrap(),
+        ));
+        Some(ChainLink {
+            round_start,
+            hash,
+            signer,
+        })
+    }
+
+    pub fn len_in_bytes(&self) -> usize {
+        20
+    }
+}
+
+// What signer has signed for a link. With it anyone can check that
+// given gnome has really signed a block or config at given round,
+// and rebuild link's chained hash from hash of previous link.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainProof {
+    pub round_start: SwarmTime,
+    pub signer: GnomeId,
+    pub digest: u64,
+    pub is_block: bool,
+    // (signed bytes, signature)
+    pub signed: (Vec<u8>, Vec<u8>),
+}
+
+impl ChainProof {
+    // Pubkey is always taken from key registry
+    pub fn verify(&self, swarm: &Swarm, sha_hash: fn(&[u8]) -> u64) -> bool {
+        if !self.is_block && self.digest != sha_hash(&self.signed.0) {
+            eprintln!("Config digest does not match @{}", self.round_start);
+            return false;
+        }
+        let pub_key = if let Some(key) = swarm.key_reg.get(self.signer) {
+            key
+        } else {
+            eprintln!("No pubkey to verify ChainProof of {}", self.signer);
+            return false;
+        };
+        let mut bytes = self.signed.0.clone();
+        (swarm.verify)(
+            self.signer,
+            &pub_key,
+            self.round_start,
+            &mut bytes,
+            &self.signed.1,
+        )
+    }
+
+    pub fn append_bytes_to(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.round_start.0.to_be_bytes());
+        bytes.extend(self.signer.0.to_be_bytes());
+        bytes.extend(self.digest.to_be_bytes());
+        bytes.push(self.is_block as u8);
+        append_with_len(bytes, &self.signed.0);
+        append_with_len(bytes, &self.signed.1);
+    }
+
+    pub fn from(bytes: &mut Vec<u8>) -> Option<Self> {
+        if bytes.len() < 21 {
+            return None;
+        }
+        let round_start = SwarmTime(u32::from_be_bytes(
+            bytes.drain(0..4).collect::<Vec<u8>>().try_into().unwrap(),
+        ));
+        let signer = GnomeId(u64::from_be_bytes(
+            bytes.drain(0..8).collect::<Vec<u8>>().try_into().unwrap(),
+        ));
+        let digest = u64::from_be_bytes(bytes.drain(0..8).collect::<Vec<u8>>().try_into().unwrap());
+        let is_block = bytes.remove(0) > 0;
+        let signed = (take_with_len(bytes)?, take_with_len(bytes)?);
+        Some(ChainProof {
+            round_start,
+            signer,
+            digest,
+            is_block,
+            signed,
+        })
+    }
+
+    pub fn len_in_bytes(&self) -> usize {
+        25 + self.signed.0.len() + self.signed.1.len()
+    }
+}
+
+struct Accepted {
+    link: ChainLink,
+    // Digest is needed to rebuild our chain on top of a neighbor's when
+    // we have just joined, and to tell the app which blocks were rolled back
+    digest: u64,
+    is_block: bool,
+    // Adopted links come without signed bytes, so we can not prove them
+    signed: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+impl Accepted {
+    fn proof(&self) -> Option<ChainProof> {
+        self.signed.as_ref().map(|signed| ChainProof {
+            round_start: self.link.round_start,
+            signer: self.link.signer,
+            digest: self.digest,
+            is_block: self.is_block,
+            signed: signed.clone(),
+        })
+    }
+}
+
+// Fork we have asked a neighbor to prove his branch of
+struct Dispute {
+    fork_point: SwarmTime,
+    started: SwarmTime,
+    proofs: Vec<ChainProof>,
+}
+
+// How a fork with a neighbor was resolved
+#[derive(Debug, PartialEq, Eq)]
+pub enum Resolution {
+    // Our branch stays, also when neighbor's proofs are not all here or valid
+    Kept,
+    // Our branch lost, ids of rolled back blocks oldest first
+    RolledBack(Vec<BlockID>),
+    // Neighbor has signed proofs of configs we can not parse
+    Misbehaved,
+}
+
+// After we lose a fork we sync registries with the winner. We only take
+// capabilities and policies that were changed since the fork point,
+// either on our rolled back branch or on winner's verified one,
+// every other entry stays as we have it.
+// Founder is never taken from sync, it is restored to the one from
+// before the fork and winner's signed transfers are applied on top of it.
+pub struct Merge {
+    pub winner: GnomeId,
+    started: SwarmTime,
+    capabilities: HashSet<Capabilities>,
+    policies: HashSet<Policy>,
+    // Winner's entries, applied to swarm only once merge finishes,
+    // so an expired or aborted merge leaves our registries as they were
+    synced_capabilities: HashMap<Capabilities, Vec<GnomeId>>,
+    synced_policies: HashMap<Policy, Requirement>,
+    founder_before_fork: Option<GnomeId>,
+    founder_changes: Vec<Configuration>,
+}
+
+impl Merge {
+    // Both lists of configs are oldest first
+    pub fn new(
+        winner: GnomeId,
+        started: SwarmTime,
+        ours: &[Configuration],
+        theirs: &[Configuration],
+    ) -> Self {
+        let mut capabilities = HashSet::new();
+        let mut policies = HashSet::new();
+        let mut founder_before_fork = None;
+        let mut founder_changes = vec![];
+        for (is_ours, config) in ours
+            .iter()
+            .map(|c| (true, c))
+            .chain(theirs.iter().map(|c| (false, c)))
+        {
+            match config {
+                Configuration::SetRunningCapability(_g, cap, _ids) => {
+                    capabilities.insert(*cap);
+                }
+                Configuration::SetRunningPolicy(_g, policy, _req) => {
+                    policies.insert(*policy);
+                }
+                Configuration::AddOwner(_g, _n) => {
+                    capabilities.insert(Capabilities::Owner);
+                }
+                Configuration::TransferFounder(founder, _n)
+                    if is_ours && founder_before_fork.is_none() =>
+                {
+                    founder_before_fork = Some(*founder);
+                }
+                Configuration::TransferFounder(_f, _n) if !is_ours => {
+                    founder_changes.push(config.clone());
+                }
+                Configuration::FounderSuccession(_c, _e) if !is_ours => {
+                    founder_changes.push(config.clone());
+                }
+                _ => {}
+            }
+        }
+        Merge {
+            winner,
+            started,
+            capabilities,
+            policies,
+            synced_capabilities: HashMap::new(),
+            synced_policies: HashMap::new(),
+            founder_before_fork,
+            founder_changes,
+        }
+    }
+
+    pub fn sync_capabilities(&mut self, pairs: Vec<(Capabilities, Vec<GnomeId>)>) {
+        for (cap, ids) in pairs {
+            if !self.capabilities.contains(&cap) {
+                eprintln!("Keeping our {:?}, not changed since fork", cap);
+                continue;
+            }
+            self.synced_capabilities.entry(cap).or_default().extend(ids);
+        }
+    }
+
+    pub fn sync_policies(&mut self, pairs: Vec<(Policy, Requirement)>) {
+        for (policy, req) in pairs {
+            if !self.policies.contains(&policy) {
+                eprintln!("Keeping our {:?}, not changed since fork", policy);
+                continue;
+            }
+            self.synced_policies.insert(policy, req);
+        }
+    }
+
+    // Entries changed since fork are replaced with winner's,
+    // those winner does not have are removed.
+    // Returns (old, new) founder when it has changed.
+    pub fn finish(mut self, swarm: &mut Swarm) -> Option<(GnomeId, GnomeId)> {
+        for cap in &self.capabilities {
+            swarm.capability_reg.remove(cap);
+            if let Some(ids) = self.synced_capabilities.remove(cap) {
+                swarm.insert_capability(*cap, ids);
+            }
+        }
+        for policy in &self.policies {
+            if let Some(req) = self.synced_policies.remove(policy) {
+                swarm.policy_reg.insert(*policy, req);
+            } else {
+                swarm.policy_reg.remove(policy);
+            }
+        }
+        let old_founder = swarm.founder();
+        if let Some(founder) = self.founder_before_fork {
+            swarm.transfer_founder(founder);
+        }
+        for config in self.founder_changes {
+            match config {
+                Configuration::TransferFounder(founder, new_founder)
+                    if swarm.founder() == founder =>
+                {
+                    swarm.transfer_founder(new_founder);
+                }
+                Configuration::FounderSuccession(candidate, endorsements)
+                    if has_quorum(swarm, candidate, &endorsements) =>
+                {
+                    swarm.transfer_founder(candidate);
+                }
+                _ => {}
+            }
+        }
+        let new_founder = swarm.founder();
+        if new_founder != old_founder {
+            Some((old_founder, new_founder))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct ChainHistory {
+    links: VecDeque<Accepted>,
+    accepted_count: u64,
+    // A gnome that has joined an existing swarm does not know what came
+    // before him, so he adopts first chain he receives from a neighbor
+    anchored: bool,
+    forks: HashMap<SwarmTime, HashSet<GnomeId>>,
+    disputes: HashMap<GnomeId, Dispute>,
+    merge: Option<Merge>,
+}
+
+impl ChainHistory {
+    pub fn new(anchored: bool) -> Self {
+        ChainHistory {
+            links: VecDeque::with_capacity(CHAIN_HISTORY_LEN),
+            accepted_count: 0,
+            anchored,
+            forks: HashMap::new(),
+            disputes: HashMap::new(),
+            merge: None,
+        }
+    }
+
+    pub fn head(&self) -> u64 {
+        if let Some(accepted) = self.links.back() {
+            accepted.link.hash
+        } else {
+            0
+        }
+    }
+
+    // Returns true when it is time to share our chain with neighbors
+    pub fn append(&mut self, proof: ChainProof, sha_hash: fn(&[u8]) -> u64) -> bool {
+        let hash = chain_hash(self.head(), proof.round_start, proof.digest, sha_hash);
+        self.push(Accepted {
+            link: ChainLink {
+                round_start: proof.round_start,
+                hash,
+                signer: proof.signer,
+            },
+            digest: proof.digest,
+            is_block: proof.is_block,
+            signed: Some(proof.signed),
+        });
+        self.accepted_count += 1;
+        self.accepted_count.is_multiple_of(CHAIN_INFO_INTERVAL)
+    }
+
+    fn push(&mut self, accepted: Accepted) {
+        if self.links.len() >= CHAIN_HISTORY_LEN {
+            self.links.pop_front();
+        }
+        self.links.push_back(accepted);
+    }
+
+    pub fn summary(&self) -> Vec<ChainLink> {
+        let skip = self.links.len().saturating_sub(CHAIN_INFO_LEN);
+        self.links
+            .iter()
+            .skip(skip)
+            .map(|accepted| accepted.link)
+            .collect()
+    }
+
+    // Proofs of all our links since given round, oldest first
+    pub fn proofs_since(&self, fork_point: SwarmTime) -> Vec<ChainProof> {
+        self.links
+            .iter()
+            .filter(|a| a.link.round_start >= fork_point)
+            .filter_map(|a| a.proof())
+            .collect()
+    }
+
+    // Compare neighbor's summary with our history.
+    // If there is a divergence we return the fork point together with
+    // all neighbors that disagree with us on it, but only when given
+    // neighbor was not reported before.
+    pub fn compare(
+        &mut self,
+        neighbor: GnomeId,
+        theirs: &[ChainLink],
+        sha_hash: fn(&[u8]) -> u64,
+    ) -> Option<(SwarmTime, Vec<GnomeId>)> {
+        if theirs.is_empty() {
+            return None;
+        }
+        if !self.anchored {
+            self.adopt(theirs, sha_hash);
+            return None;
+        }
+        let fork_point = self.fork_point(theirs)?;
+        let oldest = self.links.front().unwrap().link.round_start;
+        self.forks.retain(|r_st, _n| *r_st >= oldest);
+        let disagreeing = self.forks.entry(fork_point).or_default();
+        if disagreeing.insert(neighbor) {
+            let mut gnomes: Vec<GnomeId> = disagreeing.iter().copied().collect();
+            gnomes.sort();
+            Some((fork_point, gnomes))
+        } else {
+            None
+        }
+    }
+
+    // We only look at the time range both histories cover.
+    // First round_start inside that range where one of us has no block,
+    // or has a different chained hash is where we have split.
+    fn fork_point(&self, theirs: &[ChainLink]) -> Option<SwarmTime> {
+        let (ours_first, ours_last) = match (self.links.front(), self.links.back()) {
+            (Some(first), Some(last)) => (first.link.round_start, last.link.round_start),
+            _ => return None,
+        };
+        let their_first = theirs.first().unwrap().round_start;
+        let their_last = theirs.last().unwrap().round_start;
+        let from = ours_first.max(their_first);
+        let to = ours_last.min(their_last);
+        if from > to {
+            return None;
+        }
+        let ours: Vec<&ChainLink> = self
+            .links
+            .iter()
+            .map(|accepted| &accepted.link)
+            .filter(|l| l.round_start >= from && l.round_start <= to)
+            .collect();
+        let theirs: Vec<&ChainLink> = theirs
+            .iter()
+            .filter(|l| l.round_start >= from && l.round_start <= to)
+            .collect();
+        let mut our_iter = ours.into_iter();
+        let mut their_iter = theirs.into_iter();
+        loop {
+            match (our_iter.next(), their_iter.next()) {
+                (None, None) => return None,
+                (Some(our), None) => return Some(our.round_start),
+                (None, Some(their)) => return Some(their.round_start),
+                (Some(our), Some(their)) => {
+                    if our.round_start != their.round_start {
+                        return Some(our.round_start.min(their.round_start));
+                    }
+                    if our.hash != their.hash {
+                        return Some(our.round_start);
+                    }
+                }
+            }
+        }
+    }
+
+    // Neighbor's summary is not signed, so before we rank his branch
+    // we ask him for proofs of every link since fork point.
+    // Returns false when we are already waiting for his proofs.
+    pub fn dispute(&mut self, neighbor: GnomeId, fork_point: SwarmTime, now: SwarmTime) -> bool {
+        if self.disputes.contains_key(&neighbor) {
+            return false;
+        }
+        self.disputes.insert(
+            neighbor,
+            Dispute {
+                fork_point,
+                started: now,
+                proofs: vec![],
+            },
+        );
+        true
+    }
+
+    // Returns true once all proofs from neighbor are here
+    pub fn add_proof(
+        &mut self,
+        neighbor: GnomeId,
+        chunk_no: u8,
+        total_chunks: u8,
+        proof: ChainProof,
+    ) -> bool {
+        if let Some(dispute) = self.disputes.get_mut(&neighbor) {
+            dispute.proofs.push(proof);
+            chunk_no >= total_chunks
+        } else {
+            eprintln!("Unexpected ChainProof from {}", neighbor);
+            false
+        }
+    }
+
+    // Both sides of a fork run this with proofs of each other's branch and
+    // come to the same conclusion about which branch is canonical:
+    // 1. branch with a block signed by swarm's founder,
+    // 2. branch signed by more distinct gnomes,
+    // 3. longer branch,
+    // 4. branch with higher chained hash.
+    // Only links with valid signatures are ranked, if any of neighbor's
+    // proofs is invalid we keep our branch.
+    // When our branch loses we replace it with winner's verified one,
+    // return ids of rolled back blocks and start merging with winner.
+    pub fn resolve(
+        &mut self,
+        neighbor: GnomeId,
+        swarm: &Swarm,
+        sha_hash: fn(&[u8]) -> u64,
+        now: SwarmTime,
+    ) -> Resolution {
+        let dispute = if let Some(dispute) = self.disputes.remove(&neighbor) {
+            dispute
+        } else {
+            return Resolution::Kept;
+        };
+        let fork_point = dispute.fork_point;
+        let theirs = if let Some(theirs) =
+            self.verify_branch(&dispute.proofs, fork_point, swarm, sha_hash)
+        {
+            theirs
+        } else {
+            return Resolution::Kept;
+        };
+        // Parsed before we touch our links, so malformed proofs change nothing
+        let mut their_configs = vec![];
+        for proof in dispute.proofs.iter().filter(|proof| !proof.is_block) {
+            if let Some(config) = Configuration::try_from_bytes(proof.signed.0.clone()) {
+                their_configs.push(config);
+            } else {
+                eprintln!("Malformed config proof from {}", neighbor);
+                return Resolution::Misbehaved;
+            }
+        }
+        let ours: Vec<ChainLink> = self
+            .links
+            .iter()
+            .filter(|a| a.link.round_start >= fork_point && a.signed.is_some())
+            .map(|a| a.link)
+            .collect();
+        let founder = swarm.name.founder;
+        let our_branch = branch_rank(&ours, fork_point, founder);
+        let their_branch = branch_rank(&theirs, fork_point, founder);
+        if our_branch >= their_branch {
+            eprintln!("Our branch since {} is canonical", fork_point);
+            return Resolution::Kept;
+        }
+        eprintln!("Rolling back our branch since {}", fork_point);
+        let mut rolled_back = vec![];
+        let mut our_configs = vec![];
+        while let Some(accepted) = self.links.pop_back() {
+            if accepted.link.round_start < fork_point {
+                self.links.push_back(accepted);
+                break;
+            }
+            if accepted.is_block {
+                rolled_back.push(BlockID(accepted.digest));
+            } else if let Some((bytes, _s)) = accepted.signed {
+                our_configs.push(Configuration::from_bytes(bytes));
+            }
+        }
+        rolled_back.reverse();
+        our_configs.reverse();
+        for (link, proof) in theirs.into_iter().zip(dispute.proofs) {
+            self.push(Accepted {
+                link,
+                digest: proof.digest,
+                is_block: proof.is_block,
+                signed: Some(proof.signed),
+            });
+        }
+        self.forks.remove(&fork_point);
+        self.merge = Some(Merge::new(neighbor, now, &our_configs, &their_configs));
+        Resolution::RolledBack(rolled_back)
+    }
+
+    // Rebuild neighbor's branch from his proofs on top of the last link
+    // before fork point, that link we both share
+    fn verify_branch(
+        &self,
+        proofs: &[ChainProof],
+        fork_point: SwarmTime,
+        swarm: &Swarm,
+        sha_hash: fn(&[u8]) -> u64,
+    ) -> Option<Vec<ChainLink>> {
+        let mut prev = if let Some(accepted) = self
+            .links
+            .iter()
+            .rev()
+            .find(|a| a.link.round_start < fork_point)
+        {
+            accepted.link
+        } else {
+            eprintln!("No common link before {}", fork_point);
+            return None;
+        };
+        let mut links = Vec::with_capacity(proofs.len());
+        for proof in proofs {
+            if proof.round_start < fork_point || proof.round_start <= prev.round_start {
+                eprintln!("ChainProof out of order @{}", proof.round_start);
+                return None;
+            }
+            if !proof.verify(swarm, sha_hash) {
+                eprintln!("Invalid ChainProof from {}", proof.signer);
+                return None;
+            }
+            prev = ChainLink {
+                round_start: proof.round_start,
+                hash: chain_hash(prev.hash, proof.round_start, proof.digest, sha_hash),
+                signer: proof.signer,
+            };
+            links.push(prev);
+        }
+        Some(links)
+    }
+
+    pub fn merging_with(&self) -> Option<GnomeId> {
+        self.merge.as_ref().map(|merge| merge.winner)
+    }
+
+    pub fn merge_mut(&mut self, neighbor: GnomeId) -> Option<&mut Merge> {
+        self.merge.as_mut().filter(|merge| merge.winner == neighbor)
+    }
+
+    pub fn end_merge(&mut self) -> Option<Merge> {
+        self.merge.take()
+    }
+
+    // Neighbor that does not deliver in time is no longer waited for
+    pub fn expire(&mut self, now: SwarmTime) {
+        self.disputes.retain(|n_id, dispute| {
+            let keep = now - dispute.started <= MERGE_TIMEOUT;
+            if !keep {
+                eprintln!("No ChainProof from {} in time", n_id);
+            }
+            keep
+        });
+        if self
+            .merge
+            .as_ref()
+            .is_some_and(|merge| now - merge.started > MERGE_TIMEOUT)
+        {
+            eprintln!("Merge timed out");
+            self.merge = None;
+        }
+    }
+
+    pub fn neighbor_dropped(&mut self, neighbor: GnomeId) {
+        self.disputes.remove(&neighbor);
+        if self.merging_with() == Some(neighbor) {
+            eprintln!("Merge with {} aborted", neighbor);
+            self.merge = None;
+        }
+    }
+
+    // Take neighbor's links as our own and rebuild all our later links
+    // on top of them
+    fn adopt(&mut self, theirs: &[ChainLink], sha_hash: fn(&[u8]) -> u64) {
+        let their_last = theirs.last().unwrap().round_start;
+        let ours = std::mem::take(&mut self.links);
+        for link in theirs {
+            self.links.push_back(Accepted {
+                link: *link,
+                digest: 0,
+                is_block: false,
+                signed: None,
+            });
+        }
+        for accepted in ours {
+            if accepted.link.round_start > their_last {
+                let hash = chain_hash(
+                    self.head(),
+                    accepted.link.round_start,
+                    accepted.digest,
+                    sha_hash,
+                );
+                self.push(Accepted {
+                    link: ChainLink {
+                        round_start: accepted.link.round_start,
+                        hash,
+                        signer: accepted.link.signer,
+                    },
+                    ..accepted
+                });
+            }
+        }
+        eprintln!("Adopted chain with head: {}", self.head());
+        self.anchored = true;
+    }
+}
+
+fn chain_hash(prev: u64, round_start: SwarmTime, digest: u64, sha_hash: fn(&[u8]) -> u64) -> u64 {
+    let mut bytes = Vec::with_capacity(20);
+    bytes.extend(prev.to_be_bytes());
+    bytes.extend(round_start.0.to_be_bytes());
+    bytes.extend(digest.to_be_bytes());
+    sha_hash(&bytes)
+}
+
+// (founder signed, distinct signers, length, head hash)
+fn branch_rank(
+    links: &[ChainLink],
+    fork_point: SwarmTime,
+    founder: GnomeId,
+) -> (bool, usize, usize, u64) {
+    let branch: Vec<&ChainLink> = links
+        .iter()
+        .filter(|l| l.round_start >= fork_point)
+        .collect();
+    let mut signers = HashSet::new();
+    for link in &branch {
+        signers.insert(link.signer);
+    }
+    let head = if let Some(link) = branch.last() {
+        link.hash
+    } else {
+        0
+    };
+    (
+        signers.contains(&founder),
+        signers.len(),
+        branch.len(),
+        head,
+    )
+}
@@ -0,0 +1,43 @@
+use std::sync::mpsc::Receiver;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+// Source of time for a Gnome.
+// By default gnomes use SystemClock, but tests can provide
+// a clock that moves forward only when told to.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Duration;
+    fn sleep(&self, duration: Duration);
+    // Whether time passes on its own, so that we can block waiting for it
+    fn is_real(&self) -> bool {
+        false
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::ZERO)
+    }
+    fn sleep(&self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+    fn is_real(&self) -> bool {
+        true
+    }
+}
+
+// Same as Receiver::recv_timeout, but with a clock that is not real
+// nobody is going to move time forward while we wait,
+// so we only take what has already arrived
+pub fn recv_timeout<T>(receiver: &Receiver<T>, timeout: Duration, clock: &dyn Clock) -> Option<T> {
+    if clock.is_real() {
+        receiver.recv_timeout(timeout).ok()
+    } else {
+        receiver.try_recv().ok()
+    }
+}
@@ -28,6 +28,9 @@ impl CastData {
     pub fn bytes(self) -> Vec<u8> {
         self.0
     }
+    pub fn ref_bytes(&self) -> &Vec<u8> {
+        &self.0
+    }
     pub fn len(&self) -> usize {
         self.0.len()
     }
@@ -0,0 +1,158 @@
+use crate::GnomeId;
+use crate::Signature;
+use crate::Swarm;
+use crate::SwarmTime;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// Evidence is sent as a Configuration, so it has to fit in a single
+// Reconfigure payload together with reporter's signature.
+pub const MAX_EVIDENCE_LEN: usize = 1100;
+
+// Two different payloads signed by the same gnome for the same round.
+// Anyone can verify it with offender's pubkey, so it can be proposed
+// to the swarm and every gnome will come to the same conclusion.
+// Pubkey is always taken from key registry, never from the reporter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Equivocation {
+    pub offender: GnomeId,
+    pub round_start: SwarmTime,
+    // (signed bytes, signature)
+    pub first: (Vec<u8>, Vec<u8>),
+    pub second: (Vec<u8>, Vec<u8>),
+}
+
+impl Equivocation {
+    pub fn verify(&self, swarm: &Swarm) -> bool {
+        if self.first.0 == self.second.0 {
+            eprintln!("Equivocation with identical payloads");
+            return false;
+        }
+        let pub_key = if let Some(key) = swarm.key_reg.get(self.offender) {
+            key
+        } else {
+            eprintln!("No pubkey to verify Equivocation of {}", self.offender);
+            return false;
+        };
+        let mut first_bytes = self.first.0.clone();
+        let mut second_bytes = self.second.0.clone();
+        (swarm.verify)(
+            self.offender,
+            &pub_key,
+            self.round_start,
+            &mut first_bytes,
+            &self.first.1,
+        ) && (swarm.verify)(
+            self.offender,
+            &pub_key,
+            self.round_start,
+            &mut second_bytes,
+            &self.second.1,
+        )
+    }
+
+    pub fn len_in_bytes(&self) -> usize {
+        20 + self.first.0.len() + self.first.1.len() + self.second.0.len() + self.second.1.len()
+    }
+
+    pub fn append_bytes_to(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.offender.0.to_be_bytes());
+        bytes.extend(self.round_start.0.to_be_bytes());
+        append_with_len(bytes, &self.first.0);
+        append_with_len(bytes, &self.first.1);
+        append_with_len(bytes, &self.second.0);
+        append_with_len(bytes, &self.second.1);
+    }
+
+    // None when bytes are too short, evidence comes from peers
+    pub fn from(bytes: &mut Vec<u8>) -> Option<Self> {
+        if bytes.len() < 12 {
+            return None;
+        }
+        let offender = GnomeId(u64::from_be_bytes(
+            bytes.drain(0..8).collect::<Vec<u8>>().try_into().unwrap(),
+        ));
+        let round_start = SwarmTime(u32::from_be_bytes(
+            bytes.drain(0..4).collect::<Vec<u8>>().try_into().unwrap(),
+        ));
+        let first = (take_with_len(bytes)?, take_with_len(bytes)?);
+        let second = (take_with_len(bytes)?, take_with_len(bytes)?);
+        Some(Equivocation {
+            offender,
+            round_start,
+            first,
+            second,
+        })
+    }
+
+    // Stands in for malformed evidence, it never verifies
+    pub fn malformed(offender: GnomeId) -> Self {
+        Equivocation {
+            offender,
+            round_start: SwarmTime(0),
+            first: (vec![], vec![]),
+            second: (vec![], vec![]),
+        }
+    }
+}
+
+pub fn append_with_len(bytes: &mut Vec<u8>, value: &[u8]) {
+    bytes.extend((value.len() as u16).to_be_bytes());
+    bytes.extend(value);
+}
+
+pub fn take_with_len(bytes: &mut Vec<u8>) -> Option<Vec<u8>> {
+    if bytes.len() < 2 {
+        return None;
+    }
+    let len = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+    if bytes.len() < 2 + len {
+        return None;
+    }
+    bytes.drain(0..2);
+    Some(bytes.drain(0..len).collect())
+}
+
+// Remembers signed payloads received from neighbors in recent rounds.
+// Once the same signer is seen with a different payload for the same
+// round_start we have a proof that he is trying to split the swarm.
+#[derive(Default)]
+pub struct EquivocationDetector {
+    seen: HashMap<(GnomeId, u32), (Vec<u8>, Vec<u8>)>,
+    reported: HashSet<(GnomeId, u32)>,
+}
+
+impl EquivocationDetector {
+    pub fn observe(
+        &mut self,
+        round_start: SwarmTime,
+        signature: Signature,
+        bytes: Vec<u8>,
+    ) -> Option<Equivocation> {
+        let (offender, sign) = match signature {
+            Signature::Regular(gid, sign) => (gid, sign),
+            Signature::Extended(gid, _pub_key, sign) => (gid, sign),
+        };
+        let key = (offender, round_start.0);
+        if let Some((prev_bytes, prev_sign)) = self.seen.get(&key) {
+            if *prev_bytes == bytes || self.reported.contains(&key) {
+                return None;
+            }
+            self.reported.insert(key);
+            Some(Equivocation {
+                offender,
+                round_start,
+                first: (prev_bytes.clone(), prev_sign.clone()),
+                second: (bytes, sign),
+            })
+        } else {
+            self.seen.insert(key, (bytes, sign));
+            None
+        }
+    }
+
+    pub fn prune(&mut self, older_than: SwarmTime) {
+        self.seen.retain(|(_g, r_st), _v| *r_st >= older_than.0);
+        self.reported.retain(|(_g, r_st)| *r_st >= older_than.0);
+    }
+}
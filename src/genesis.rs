@@ -0,0 +1,145 @@
+use crate::equivocation::take_with_len;
+use crate::GnomeId;
+use crate::Swarm;
+use crate::SwarmName;
+use crate::SwarmSyncResponse;
+use crate::SwarmTime;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+// Founder's signature over SwarmName (with founder's id inside).
+// It is created once by the founder and passed to every newcomer
+// with SwarmSyncResponse, so that no neighbor can claim to be a founder
+// without having founder's private key.
+// Binding of pub_key to founder's GnomeId is checked by swarm's verify fn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Genesis {
+    pub founder: GnomeId,
+    pub pub_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl Genesis {
+    pub fn verify(&self, swarm: &Swarm) -> bool {
+        if !swarm.name.founder.is_any() && swarm.name.founder != self.founder {
+            eprintln!(
+                "Genesis founder {} does not match {}",
+                self.founder, swarm.name
+            );
+            return false;
+        }
+        let mut bytes = SwarmName {
+            founder: self.founder,
+            name: swarm.name.name.clone(),
+        }
+        .as_bytes();
+        (swarm.verify)(
+            self.founder,
+            &self.pub_key,
+            SwarmTime(0),
+            &mut bytes,
+            &self.signature,
+        )
+    }
+
+    pub fn len_in_bytes(&self) -> usize {
+        12 + self.pub_key.len() + self.signature.len()
+    }
+
+    pub fn append_bytes_to(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.founder.0.to_be_bytes());
+        bytes.extend((self.pub_key.len() as u16).to_be_bytes());
+        bytes.extend(&self.pub_key);
+        bytes.extend((self.signature.len() as u16).to_be_bytes());
+        bytes.extend(&self.signature);
+    }
+
+    // None when bytes are too short, Genesis comes from peers
+    pub fn from(bytes: &mut Vec<u8>) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None;
+        }
+        let founder = GnomeId(u64::from_be_bytes(
+            bytes.drain(0..8).collect::<Vec<u8>>().try_into().unwrap(),
+        ));
+        let pub_key = take_with_len(bytes)?;
+        let signature = take_with_len(bytes)?;
+        Some(Genesis {
+            founder,
+            pub_key,
+            signature,
+        })
+    }
+}
+
+// Founder is the one from SwarmName we are joining, or when we do not
+// know it, the one majority of distinct neighbors agree on.
+// Genesis is pinned only for such founder, and in the latter case only
+// when at least two neighbors agree, since anyone can sign a Genesis
+// of his own. Otherwise founder stays unpinned.
+// Neighbors that claim otherwise are marked as disputed.
+pub fn choose_sync_response(
+    swarm: &mut Swarm,
+    disputed: &mut HashSet<GnomeId>,
+    mut responses: Vec<(GnomeId, bool, SwarmSyncResponse)>,
+) -> Option<(GnomeId, bool, SwarmSyncResponse)> {
+    if responses.is_empty() {
+        return None;
+    }
+    // Every neighbor has a single vote
+    let mut responders = HashSet::new();
+    responses.retain(|(n_id, _full, _r)| responders.insert(*n_id));
+    let (founder, pinnable) = if !swarm.name.founder.is_any() {
+        (swarm.name.founder, true)
+    } else {
+        let mut votes: HashMap<GnomeId, usize> = HashMap::new();
+        for (_n_id, _full, response) in &responses {
+            *votes.entry(response.founder).or_default() += 1;
+        }
+        let (top, count) = votes
+            .into_iter()
+            .max_by_key(|(founder, count)| (*count, *founder))
+            .unwrap();
+        if count * 2 > responses.len() {
+            if count < 2 {
+                eprintln!("Unable to cross-check founder {}", top);
+            }
+            (top, count >= 2)
+        } else {
+            eprintln!("No majority for founder, using first response");
+            (responses[0].2.founder, false)
+        }
+    };
+    for (n_id, _full, response) in &responses {
+        if response.founder != founder {
+            eprintln!(
+                "Neighbor {} claims {} is founder, not {}",
+                n_id, response.founder, founder
+            );
+            disputed.insert(*n_id);
+        }
+    }
+    responses.retain(|(_n_id, _full, response)| response.founder == founder);
+    if responses.is_empty() {
+        eprintln!("No neighbor agrees that {} is founder", founder);
+        return None;
+    }
+    if pinnable {
+        for (n_id, _full, response) in &responses {
+            if let Some(genesis) = &response.genesis {
+                if genesis.founder == founder && swarm.set_genesis(genesis.clone()) {
+                    eprintln!("Founder {} verified with Genesis", founder);
+                    break;
+                }
+                eprintln!("Neighbor {} sent invalid Genesis", n_id);
+            }
+        }
+    } else {
+        eprintln!("Founder {} stays unpinned", founder);
+    }
+    let idx = responses
+        .iter()
+        .position(|(_n_id, full, _r)| *full)
+        .unwrap_or(0);
+    Some(responses.swap_remove(idx))
+}
@@ -1,4 +1,18 @@
+use crate::app_channel::app_channel;
+use crate::app_channel::FromApp;
 use crate::band_mon::BandwidthMonitor;
+use crate::chain::ChainHistory;
+use crate::chain::ChainProof;
+use crate::chain::Resolution;
+use crate::clock::Clock;
+use crate::clock::SystemClock;
+use crate::equivocation::Equivocation;
+use crate::equivocation::EquivocationDetector;
+use crate::equivocation::MAX_EVIDENCE_LEN;
+use crate::genesis::choose_sync_response;
+use crate::genesis::Genesis;
+use crate::gnome_to_manager::BandwidthUsage;
+use crate::gnome_to_manager::DiscoveryStatus;
 use crate::gnome_to_manager::GnomeToManager;
 use crate::internal::InternalMsg;
 use crate::manager_to_gnome::ManagerToGnome;
@@ -7,29 +21,50 @@ use crate::message::Configuration;
 use crate::message::Header;
 use crate::message::Payload;
 use crate::message::Signature;
+use crate::message::MAX_USER_CONFIG_ID;
 use crate::multicast::CastType;
 use crate::multicast::{CastMessage, Multicast};
 use crate::neighbor::NeighborResponse;
 use crate::neighbor::Neighborhood;
 use crate::neighbor::SwarmSyncRequestParams;
+use crate::neighbor_table::NeighborStatus;
+use crate::neighbor_table::NeighborTable;
 use crate::next_state::ChangeConfig;
+use crate::next_state::RoundPosition;
+use crate::next_state::TurnEnd;
+use crate::next_state::TurnOutcome;
+use crate::outbox::TrafficShares;
+use crate::outbox::MAX_CAST_BACKLOG;
+use crate::succession::has_quorum;
 use crate::swarm::Swarm;
+use crate::swarm_discovery::swarm_pages;
+use crate::swarm_discovery::SwarmQueries;
+use crate::token_bucket::TokenBuckets;
+use crate::unicast::UnicastIDs;
+use crate::unicast::Unicasts;
 use crate::ByteSet;
+use crate::Capabilities;
 use crate::CastData;
 use crate::CastID;
+use crate::DropReason;
+use crate::Endorsement;
 use crate::GnomeToApp;
 use crate::KeyRegistry;
 use crate::Message;
 use crate::Neighbor;
 use crate::NeighborRequest;
+use crate::NeighborRetention;
 use crate::NextState;
 use crate::SwarmName;
 use crate::SwarmSyncResponse;
 use crate::SwarmTime;
 use crate::SyncData;
 use crate::ToGnome;
+use crate::TraceEvent;
+use crate::Tracer;
 use crate::WrappedMessage;
 use crate::DEFAULT_NEIGHBORS_PER_GNOME;
+use crate::PRESYNC_NEIGHBORS;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -38,11 +73,9 @@ use std::fmt;
 // use std::net::IpAddr;
 // use std::net::Ipv4Addr;
 // use std::net::Ipv6Addr;
-use std::ops::Deref;
-use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::Arc;
 use std::time::Duration;
-use std::time::Instant;
-use std::time::SystemTime;
 
 #[derive(Clone, Copy, PartialOrd, PartialEq, Ord, Eq, Debug, Hash)]
 pub struct GnomeId(pub u64);
@@ -107,47 +140,85 @@ struct OngoingRequest {
     network_settings: Vec<u8>,
 }
 
+// How many rounds we wait between searches for new neighbors
+const DISCOVERY_ROUNDS: u16 = 1000;
+// and how many when we have less neighbors than we would like
+const DISCOVERY_ROUNDS_FEW: u16 = 25;
+// Up to how many times we double waiting period when low on bandwidth
+const DISCOVERY_MAX_BACKOFFS: u8 = 4;
+
 struct NeighborDiscovery {
     counter: u16,
     treshold: u16,
     attempts: u8,
     try_next: bool,
+    backoffs: u8,
     queried_neighbors: Vec<GnomeId>,
 }
 impl NeighborDiscovery {
     // Every new round we increment a counter.
     // Once counter reaches defined threashold
-    // function returns true.
-    // Function may also return true if recent
+    // function returns Started.
+    // Function may also return Started if recent
     // neighbor search ended with a failure, up to n-tries.
-    // If neither of above is true, then function returns false.
-    //
-    // TODO: modify treshold & attempts to depend on available bandwith
-    fn tick_and_check(&mut self) -> bool {
-        self.counter += 1;
-        let treshold_reached = self.counter >= self.treshold;
+    // Threashold is lower when we have few neighbors and
+    // we need more available bandwith to search when we have enough.
+    // If we should search but bandwith is scarce we back off
+    // and wait longer before next try.
+    // If neither of above is true, then function returns None.
+    fn tick_and_check(
+        &mut self,
+        neighbors_count: usize,
+        available_bandwith: u64,
+        assigned_bandwith: u64,
+    ) -> Option<DiscoveryStatus> {
+        let few_neighbors = neighbors_count < DEFAULT_NEIGHBORS_PER_GNOME;
+        self.treshold = if few_neighbors {
+            DISCOVERY_ROUNDS_FEW
+        } else {
+            DISCOVERY_ROUNDS
+        };
+        self.counter = self.counter.saturating_add(1);
+        let treshold_reached = self.counter >= self.treshold << self.backoffs;
+        let retry = self.try_next && self.attempts > 0;
+        if !treshold_reached && !retry {
+            return None;
+        }
+        let required_bandwith = if few_neighbors {
+            assigned_bandwith >> 3
+        } else {
+            assigned_bandwith >> 1
+        };
+        self.try_next = false;
+        if available_bandwith < required_bandwith {
+            self.counter = 0;
+            self.backoffs = (self.backoffs + 1).min(DISCOVERY_MAX_BACKOFFS);
+            return Some(DiscoveryStatus::BackedOff(available_bandwith));
+        }
+        self.backoffs = 0;
         if treshold_reached {
             self.counter = 0;
-            self.attempts = 3;
-            self.try_next = false;
-            true
-        } else if self.try_next && self.attempts > 0 {
-            self.attempts -= 1;
-            self.try_next = false;
-            true
+            self.attempts = if few_neighbors { 5 } else { 3 };
         } else {
-            false
+            self.attempts -= 1;
         }
+        Some(DiscoveryStatus::Started(neighbors_count))
+    }
+
+    // Recent search did not bring us a new neighbor
+    fn failed(&mut self) {
+        self.try_next = true;
     }
 }
 
 impl Default for NeighborDiscovery {
     fn default() -> Self {
         NeighborDiscovery {
-            counter: 1000,
-            treshold: 1000,
+            counter: DISCOVERY_ROUNDS,
+            treshold: DISCOVERY_ROUNDS,
             attempts: 3,
             try_next: true,
+            backoffs: 0,
             queried_neighbors: vec![],
         }
     }
@@ -237,14 +308,12 @@ pub struct Gnome {
     // swarm_diameter: SwarmTime,
     receiver: Receiver<ToGnome>,
     // band_receiver: Receiver<u64>,
+    // Unbounded, so control and consensus events are never lost,
+    // only cast data goes through bounded channels with overflow policies
     sender: Sender<GnomeToApp>,
     mgr_sender: Sender<GnomeToManager>,
     mgr_receiver: Receiver<ManagerToGnome>,
-    // TODO: make neighbors attrs into HashMap<GnomeId,Neighbor>
-    fast_neighbors: Vec<Neighbor>,
-    slow_neighbors: Vec<Neighbor>,
-    new_neighbors: Vec<Neighbor>,
-    refreshed_neighbors: Vec<Neighbor>,
+    neighbors: NeighborTable,
     header: Header,
     payload: Payload,
     my_proposal: Option<Proposal>,
@@ -258,13 +327,77 @@ pub struct Gnome {
     pending_conn_requests: VecDeque<ConnRequest>,
     ongoing_requests: HashMap<u8, OngoingRequest>,
     neighbor_discovery: NeighborDiscovery,
-    chill_out: (bool, Instant),
+    chill_out: (bool, Duration),
     chill_out_max: Duration,
-    data_converters: HashMap<(CastType, CastID), (Receiver<CastData>, Sender<WrappedMessage>)>,
+    data_converters: HashMap<(CastType, CastID), FromApp>,
     sign: fn(&str, SwarmTime, &mut Vec<u8>) -> Result<Vec<u8>, ()>,
     sha_hash: fn(&[u8]) -> u64,
     send_internal: Sender<InternalMsg>,
     recv_internal: Receiver<InternalMsg>,
+    equivocations: EquivocationDetector,
+    chain: ChainHistory,
+    // Neighbors that gave us a different founder than the one we accepted
+    // during presync, we do not take any registries from them
+    founder_disputed: HashSet<GnomeId>,
+    clock: Arc<dyn Clock>,
+    tracer: Option<Tracer>,
+    retention: NeighborRetention,
+    // Neighbors we want to drop, but wait until casts they source
+    // are subscribed from someone else
+    leaving: Vec<(GnomeId, DropReason)>,
+    swarm_queries: SwarmQueries,
+    token_buckets: TokenBuckets,
+    unicasts: Unicasts,
+    traffic_shares: TrafficShares,
+    // Bandwidth assigned by manager, to be applied in next step
+    new_bandwidth: Option<u64>,
+}
+
+// A gnome's gotta sleep
+const MIN_SLEEP_NSEC: u64 = 1 << 7; //128nsec min
+const MAX_SLEEP_NSEC: u64 = 1 << 26; //~64msec max
+
+// Everything gnome's main loop keeps between iterations,
+// so that a Gnome can also be driven step by step.
+pub struct JobState {
+    assigned_bandwidth: u64,
+    timer: Duration,
+    sleep_nsec: u64,
+    loops_with_no_reply: u8,
+    last_loop_time: Duration,
+    available_tokens: u64,
+    min_token_creation_time: Duration,
+    borrowed_tokens: u64,
+    band_mon: BandwidthMonitor,
+    neigh_drop_time_by_net: SwarmTime,
+    bandwidth_report_due: bool,
+}
+
+impl JobState {
+    fn use_tokens(&mut self, tokens_used: usize) {
+        update_tokens(
+            &mut self.available_tokens,
+            &mut self.borrowed_tokens,
+            tokens_used,
+        );
+        self.record_sent(tokens_used as u64);
+    }
+
+    // Data paid from neighbors' buckets is only counted as sent
+    fn record_sent(&mut self, tokens_sent: u64) {
+        self.bandwidth_report_due |= self.band_mon.update(tokens_sent);
+    }
+
+    // Tokens given to neighbors' buckets are no longer available for us
+    fn lend_tokens(&mut self, tokens: u64) {
+        self.available_tokens = self.available_tokens.saturating_sub(tokens);
+    }
+
+    fn set_bandwidth(&mut self, bandwidth: u64) {
+        self.assigned_bandwidth = bandwidth;
+        self.available_tokens = self.available_tokens.min(bandwidth);
+        self.min_token_creation_time = calculate_min_token_period(bandwidth);
+    }
 }
 
 impl Gnome {
@@ -285,6 +418,7 @@ impl Gnome {
     ) -> Self {
         // println!("DER size: {}", pub_key_bytes.len());
         let (send_internal, recv_internal) = channel();
+        let chain = ChainHistory::new(swarm.name.founder == id);
         // let (ipv6_network_settings, network_settings) = if network_settings.pub_ip.is_ipv4() {
         //     (
         //         NetworkSettings::new_not_natted(
@@ -313,10 +447,7 @@ impl Gnome {
             sender,
             mgr_sender,
             mgr_receiver,
-            fast_neighbors: Vec::with_capacity(DEFAULT_NEIGHBORS_PER_GNOME),
-            slow_neighbors: Vec::with_capacity(DEFAULT_NEIGHBORS_PER_GNOME),
-            new_neighbors: vec![],
-            refreshed_neighbors: Vec::with_capacity(DEFAULT_NEIGHBORS_PER_GNOME),
+            neighbors: NeighborTable::new(),
             header: Header::Sync,
             payload: Payload::KeepAlive(10240),
             my_proposal: None,
@@ -331,13 +462,25 @@ impl Gnome {
             pending_conn_requests: VecDeque::new(),
             ongoing_requests: HashMap::new(),
             neighbor_discovery: NeighborDiscovery::default(),
-            chill_out: (false, Instant::now()),
+            chill_out: (false, Duration::ZERO),
             chill_out_max: Duration::from_millis(14500),
             data_converters: HashMap::new(),
             sign,
             sha_hash,
             send_internal,
             recv_internal,
+            equivocations: EquivocationDetector::default(),
+            chain,
+            founder_disputed: HashSet::new(),
+            clock: Arc::new(SystemClock),
+            tracer: None,
+            retention: NeighborRetention::default(),
+            leaving: vec![],
+            swarm_queries: SwarmQueries::new(),
+            token_buckets: TokenBuckets::new(),
+            unicasts: Unicasts::new(),
+            traffic_shares: TrafficShares::default(),
+            new_bandwidth: None,
         }
     }
 
@@ -373,10 +516,16 @@ impl Gnome {
             sign,
             sha_hash,
         );
-        gnome.fast_neighbors = neighbors;
+        for neighbor in neighbors {
+            gnome.neighbors.insert(neighbor, NeighborStatus::Fast);
+        }
         gnome
     }
 
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
     //TODO: we should probably add identifiers to internal messages
     //      and have them progress on a step by step basis somehow
     fn serve_internal(&mut self) -> (bool, usize) {
@@ -386,7 +535,7 @@ impl Gnome {
         while let Ok(internal) = self.recv_internal.try_recv() {
             any_data_processed = true;
             match internal {
-                InternalMsg::SubscribeCast(is_bcast, id, origin) => {
+                InternalMsg::SubscribeCast(is_bcast, id, origin, with_history) => {
                     if origin.is_any() {
                         eprintln!("TODO: SubscribeCast origin is any");
                     }
@@ -394,18 +543,18 @@ impl Gnome {
                         if self.has_neighbor(source_id) {
                             source_id
                         } else {
-                            self.neighbor_with_highest_bandwith(None)
+                            self.best_neighbor(None)
                         }
                     } else {
-                        self.neighbor_with_highest_bandwith(None)
+                        self.best_neighbor(None)
                     };
                     eprintln!("Subscribing to Cast: {}(is bcast: {})", id.0, is_bcast);
                     let (send_n, recv_n) = channel();
-                    let (send_d, recv_d) = channel();
+                    let (send_d, recv_d) = app_channel();
                     // TODO: bandwith threshold should not be a fixed value
                     if self.send_neighbor_request(
                         source,
-                        NeighborRequest::SubscribeRequest(is_bcast, id),
+                        NeighborRequest::SubscribeRequest(is_bcast, id, with_history),
                     ) {
                         tokens_used += 48;
                         if is_bcast {
@@ -446,7 +595,7 @@ impl Gnome {
                     //         if let Some(source_id) = self.swarm.get_source(cast_id, is_bcast) {
                     //             source_id
                     //         } else {
-                    //             self.neighbor_with_highest_bandwith()
+                    //             self.best_neighbor()
                     //         };
 
                     //     // TODO: support multicast subscription
@@ -501,22 +650,44 @@ impl Gnome {
                 }
                 InternalMsg::FindNewCastSource(is_bcast, cast_id, old_source) => {
                     eprintln!("Looking for new casting source");
-                    let alt_sources = self.swarm.get_alt_sources(is_bcast, &cast_id, old_source);
-                    //TODO: pick alternative source
+                    let source = self.swarm.get_source(&cast_id, is_bcast);
+                    if source != Some(old_source) {
+                        // Someone we asked to be our new source could not,
+                        // current one stays until retention tries again
+                        eprintln!("{} is not a source of {}", old_source, cast_id.0);
+                        self.leaving.retain(|(l_id, _r)| Some(*l_id) != source);
+                        continue;
+                    }
+                    let mut alt_sources =
+                        self.swarm.get_alt_sources(is_bcast, &cast_id, old_source);
+                    if alt_sources.is_empty() {
+                        // Any other neighbor may have it,
+                        // he refuses if he receives it from us
+                        let n_id = self.best_neighbor(Some(old_source));
+                        if !n_id.is_any() {
+                            alt_sources.push(n_id);
+                        }
+                    }
                     if !alt_sources.is_empty() {
                         if let Some(new_source) = self.select_best_alternative(alt_sources) {
-                            let _ = self
-                                .send_internal
-                                .send(InternalMsg::SubscribeCast(is_bcast, cast_id, new_source));
+                            // We switch to new source once he confirms with Subscribed
+                            if self.send_neighbor_request(
+                                new_source,
+                                NeighborRequest::SubscribeRequest(is_bcast, cast_id, false),
+                            ) {
+                                tokens_used += 48;
+                            }
                         }
                     } else {
                         // TODO: we need to build extended logic here
-                        eprintln!("Unable to find alternative source.")
+                        eprintln!("Unable to find alternative source.");
+                        // Old source stays, retention can try again next round
+                        self.leaving.retain(|(l_id, _r)| *l_id != old_source);
                     }
                 }
                 InternalMsg::RequestOut(gnome_id, excl_opt, request) => {
                     let neighbor_id = if gnome_id.is_any() {
-                        self.neighbor_with_highest_bandwith(excl_opt)
+                        self.best_neighbor(excl_opt)
                     } else {
                         gnome_id
                     };
@@ -531,15 +702,15 @@ impl Gnome {
                         }
                     } else {
                         eprintln!("Unable to find a Neighbor to send out CustomRequest(F:{}, R:{}, S:{}, N:{})",
-                            self.fast_neighbors.len(),
-                            self.refreshed_neighbors.len(),
-                            self.slow_neighbors.len(),
-                            self.new_neighbors.len());
+                            self.neighbors.count(NeighborStatus::Fast),
+                            self.neighbors.count(NeighborStatus::Refreshed),
+                            self.neighbors.count(NeighborStatus::Slow),
+                            self.neighbors.count(NeighborStatus::New));
                     }
                 }
                 InternalMsg::ResponseOut(gnome_id, response) => {
                     let neighbor_id = if gnome_id.is_any() {
-                        self.neighbor_with_highest_bandwith(None)
+                        self.best_neighbor(None)
                     } else {
                         gnome_id
                     };
@@ -557,26 +728,11 @@ impl Gnome {
         (any_data_processed, tokens_used)
     }
 
-    fn serve_user_data(&self) -> bool {
+    fn serve_user_data(&mut self) -> bool {
         // Casting is only being sent internally, not via network
         let mut any_data_processed = false;
-        for ((c_type, c_id), (recv_d, send_m)) in &self.data_converters {
-            while let Ok(data) = recv_d.try_recv() {
-                // println!("user data: {:?}",data);
-                any_data_processed = true;
-                let message = match c_type {
-                    CastType::Broadcast => {
-                        WrappedMessage::Cast(CastMessage::new_broadcast(*c_id, data))
-                    }
-                    CastType::Multicast => {
-                        WrappedMessage::Cast(CastMessage::new_multicast(*c_id, data))
-                    }
-                    CastType::Unicast => {
-                        WrappedMessage::Cast(CastMessage::new_unicast(*c_id, data))
-                    }
-                };
-                let _r = send_m.send(message);
-            }
+        for from_app in self.data_converters.values_mut() {
+            any_data_processed |= from_app.serve();
         }
         any_data_processed
     }
@@ -596,7 +752,7 @@ impl Gnome {
                         self.swarm_time,
                         self.header,
                         self.neighborhood,
-                        self.fast_neighbors.len()
+                        self.neighbors.count(NeighborStatus::Fast)
                     );
                 }
                 ToGnome::AddData(data) => {
@@ -625,13 +781,20 @@ impl Gnome {
                     // println!("vvv USER vvv REQ {}", data);
                 }
                 ToGnome::Reconfigure(value, s_data) => {
+                    if value > MAX_USER_CONFIG_ID {
+                        eprintln!("Config id {} is reserved, sending back", value);
+                        let _ = self
+                            .sender
+                            .send(GnomeToApp::PolicyNotMetRcfg(value, s_data));
+                        return (false, false);
+                    }
                     let msg = Message {
                         swarm_time: SwarmTime(0),
                         neighborhood: Neighborhood(0),
                         header: Header::Reconfigure(value, self.id),
                         payload: Payload::Reconfigure(
                             Signature::Regular(self.id, vec![]),
-                            Configuration::from_bytes(s_data.clone().bytes()),
+                            Configuration::UserDefined(value, s_data.clone()),
                         ),
                     };
                     if !self.swarm.verify_policy(&msg) {
@@ -648,12 +811,14 @@ impl Gnome {
                     new_user_proposal = true;
                 }
                 ToGnome::SetFounder(f_id) => {
-                    self.swarm.set_founder(f_id);
-                    let _res = self.mgr_sender.send(GnomeToManager::FounderDetermined(
-                        self.swarm.id,
-                        self.swarm.name.clone(),
-                    ));
-                    eprintln!("Set founder to {}, {:?}", f_id, _res);
+                    if self.swarm.set_founder(f_id) {
+                        self.create_genesis();
+                        let _res = self.mgr_sender.send(GnomeToManager::FounderDetermined(
+                            self.swarm.id,
+                            self.swarm.name.clone(),
+                        ));
+                        eprintln!("Set founder to {}, {:?}", f_id, _res);
+                    }
                 }
                 ToGnome::AddNeighbor(neighbor) => {
                     eprintln!(
@@ -675,17 +840,11 @@ impl Gnome {
                     self.drop_neighbor(n_id);
                 }
                 ToGnome::ListNeighbors => {
-                    let mut n_ids = vec![];
-                    for n in &self.fast_neighbors {
-                        n_ids.push(n.id);
-                    }
-                    for n in &self.slow_neighbors {
-                        n_ids.push(n.id);
-                    }
-                    for n in &self.refreshed_neighbors {
-                        n_ids.push(n.id);
-                    }
-                    eprintln!("New neighbors len: {}", self.new_neighbors.len());
+                    let n_ids = self.neighbors.ids(&NeighborStatus::ACTIVE);
+                    eprintln!(
+                        "New neighbors len: {}",
+                        self.neighbors.count(NeighborStatus::New)
+                    );
                     eprintln!(
                         "{} Gnome sending neighbors to app (n#: {})",
                         self.swarm.id,
@@ -699,14 +858,8 @@ impl Gnome {
                 ToGnome::StartUnicast(gnome_id) => {
                     // println!("Received StartUnicast {:?}", gnome_id);
                     // let mut request_sent = false;
-                    let mut avail_ids: [CastID; 256] = [CastID(0); 256];
-                    for (added_ids, cast_id) in
-                        self.swarm.avail_unicast_ids().into_iter().enumerate()
-                    {
-                        avail_ids[added_ids] = cast_id;
-                    }
-                    let request =
-                        NeighborRequest::UnicastRequest(self.swarm.id, Box::new(avail_ids));
+                    let avail_ids = UnicastIDs::from_ids(self.swarm.avail_unicast_ids());
+                    let request = NeighborRequest::UnicastRequest(self.swarm.id, avail_ids);
                     self.send_internal
                         .send(InternalMsg::RequestOut(gnome_id, None, request))
                         .unwrap();
@@ -731,7 +884,7 @@ impl Gnome {
                     //     eprintln!("Unable to find gnome with id {}", gnome_id);
                     // }
                 }
-                ToGnome::StartBroadcast => {
+                ToGnome::StartBroadcast(descriptor) => {
                     eprintln!("Received StartBroadcast user request");
                     let cast_id_opt = self.swarm.next_broadcast_id();
                     if cast_id_opt.is_some() {
@@ -739,11 +892,64 @@ impl Gnome {
                             .push_front(Proposal::Config(Configuration::StartBroadcast(
                                 self.id,
                                 cast_id_opt.unwrap().to_owned(),
+                                descriptor,
                             )));
                         new_user_proposal = true;
                     }
                     // println!("vvv USER vvv REQ {}", data);
                 }
+                ToGnome::ListCasts => {
+                    let _ = self
+                        .sender
+                        .send(GnomeToApp::Casts(self.swarm.id, self.swarm.cast_listing()));
+                }
+                ToGnome::EndUnicast(c_id) => {
+                    if let Some(n_id) = self.unicasts.remove(c_id) {
+                        self.end_unicast(n_id, c_id, true);
+                    } else {
+                        eprintln!("No Unicast with id: {:?}", c_id);
+                    }
+                }
+                ToGnome::ReliableBroadcast(c_id) => {
+                    if !self.swarm.make_cast_reliable(true, &c_id, self.id) {
+                        eprintln!("Not an origin of Broadcast {}", c_id.0);
+                    }
+                }
+                ToGnome::ReliableMulticast(c_id) => {
+                    if !self.swarm.make_cast_reliable(false, &c_id, self.id) {
+                        eprintln!("Not an origin of Multicast {}", c_id.0);
+                    }
+                }
+                ToGnome::KeepBroadcastHistory(c_id, limit) => {
+                    if !self.swarm.keep_cast_history(true, &c_id, limit) {
+                        eprintln!("No Broadcast with id: {}", c_id.0);
+                    }
+                }
+                ToGnome::KeepMulticastHistory(c_id, limit) => {
+                    if !self.swarm.keep_cast_history(false, &c_id, limit) {
+                        eprintln!("No Multicast with id: {}", c_id.0);
+                    }
+                }
+                ToGnome::SetBroadcastOverflow(c_id, policy) => {
+                    if !self.swarm.set_cast_overflow_policy(true, &c_id, policy) {
+                        eprintln!("No Broadcast with id: {}", c_id.0);
+                    }
+                }
+                ToGnome::SetMulticastOverflow(c_id, policy) => {
+                    if !self.swarm.set_cast_overflow_policy(false, &c_id, policy) {
+                        eprintln!("No Multicast with id: {}", c_id.0);
+                    }
+                }
+                ToGnome::SetBroadcastTopics(c_id, topics) => {
+                    if !self.swarm.set_cast_topics(true, &c_id, topics) {
+                        eprintln!("No Broadcast with id: {}", c_id.0);
+                    }
+                }
+                ToGnome::SetMulticastTopics(c_id, topics) => {
+                    if !self.swarm.set_cast_topics(false, &c_id, topics) {
+                        eprintln!("No Multicast with id: {}", c_id.0);
+                    }
+                }
                 ToGnome::EndBroadcast(c_id) => {
                     eprintln!("Received EndBroadcast user request");
                     self.proposals
@@ -756,6 +962,15 @@ impl Gnome {
                         true,
                         c_id,
                         GnomeId::any(),
+                        false,
+                    ));
+                }
+                ToGnome::SubscribeBroadcastWithHistory(c_id) => {
+                    let _ = self.send_internal.send(InternalMsg::SubscribeCast(
+                        true,
+                        c_id,
+                        GnomeId::any(),
+                        true,
                     ));
                 }
                 ToGnome::UnsubscribeBroadcast(c_id) => {
@@ -771,13 +986,13 @@ impl Gnome {
                         .send(InternalMsg::SendToCastSource(true, c_id, c_data));
                 }
                 // ToGnome::StartMulticast(ids) => {
-                ToGnome::StartMulticast => {
+                ToGnome::StartMulticast(descriptor) => {
                     eprintln!("Received StartMulticast() user request",);
                     let cast_id_opt = self.swarm.next_multicast_id();
                     if let Some(cast_id) = cast_id_opt {
                         self.proposals
                             .push_front(Proposal::Config(Configuration::StartMulticast(
-                                self.id, cast_id,
+                                self.id, cast_id, descriptor,
                             )));
                         new_user_proposal = true;
                     }
@@ -795,6 +1010,15 @@ impl Gnome {
                         false,
                         c_id,
                         GnomeId::any(),
+                        false,
+                    ));
+                }
+                ToGnome::SubscribeMulticastWithHistory(c_id) => {
+                    let _ = self.send_internal.send(InternalMsg::SubscribeCast(
+                        false,
+                        c_id,
+                        GnomeId::any(),
+                        true,
                     ));
                 }
                 ToGnome::UnsubscribeMulticast(c_id) => {
@@ -822,7 +1046,7 @@ impl Gnome {
                 ToGnome::ChangeDiameter(new_value) => {
                     eprintln!("Received ChangeDiameter({})", new_value);
 
-                    if self.id == self.swarm.name.founder && new_value < 16 && new_value > 0 {
+                    if self.id == self.swarm.founder() && new_value < 16 && new_value > 0 {
                         self.proposals
                             .push_front(Proposal::Config(Configuration::ChangeDiameter(
                                 self.id, new_value,
@@ -830,13 +1054,78 @@ impl Gnome {
                         new_user_proposal = true;
                     } else {
                         eprintln!("Can not submit ChangeDiameter proposal");
-                        eprintln!(
-                            "My: {} – {} Swarm Founder",
-                            self.id, self.swarm.name.founder
-                        );
+                        eprintln!("My: {} – {} Swarm Founder", self.id, self.swarm.founder());
                     }
                     // println!("vvv USER vvv REQ {}", data);
                 }
+                ToGnome::TransferFounder(new_founder) => {
+                    eprintln!("Received TransferFounder({})", new_founder);
+                    if self.id == self.swarm.founder() {
+                        self.proposals.push_front(Proposal::Config(
+                            Configuration::TransferFounder(self.id, new_founder),
+                        ));
+                        new_user_proposal = true;
+                    } else {
+                        eprintln!("Can not submit TransferFounder proposal, not a founder");
+                    }
+                }
+                ToGnome::AddOwner(owner) => {
+                    eprintln!("Received AddOwner({})", owner);
+                    if self.id == self.swarm.founder() {
+                        self.proposals
+                            .push_front(Proposal::Config(Configuration::AddOwner(self.id, owner)));
+                        new_user_proposal = true;
+                    } else {
+                        eprintln!("Can not submit AddOwner proposal, not a founder");
+                    }
+                }
+                ToGnome::EndorseSuccession(candidate) => {
+                    let is_owner = self
+                        .swarm
+                        .capability_reg
+                        .get(&Capabilities::Owner)
+                        .map(|tree| tree.contains(&self.id))
+                        .unwrap_or(false);
+                    if !is_owner {
+                        eprintln!("Only Owners can endorse succession");
+                        return (false, false);
+                    }
+                    if !self.swarm.key_reg.has_key(self.id) {
+                        self.proposals
+                            .push_front(Proposal::Config(Configuration::InsertPubkey(
+                                self.id,
+                                self.pub_key_bytes.clone(),
+                            )));
+                        new_user_proposal = true;
+                    }
+                    let mut bytes = Endorsement::signed_bytes(&self.swarm, candidate);
+                    if let Ok(signature) = (self.sign)(&self.priv_key_pem, SwarmTime(0), &mut bytes)
+                    {
+                        let endorsement = Endorsement {
+                            owner: self.id,
+                            signature,
+                        };
+                        let _res = self
+                            .sender
+                            .send(GnomeToApp::SuccessionEndorsement(candidate, endorsement));
+                    } else {
+                        eprintln!("Failed to sign succession endorsement");
+                    }
+                }
+                ToGnome::ClaimFounder(endorsements) => {
+                    eprintln!(
+                        "Received ClaimFounder with {} endorsements",
+                        endorsements.len()
+                    );
+                    if has_quorum(&self.swarm, self.id, &endorsements) {
+                        self.proposals.push_front(Proposal::Config(
+                            Configuration::FounderSuccession(self.id, endorsements),
+                        ));
+                        new_user_proposal = true;
+                    } else {
+                        eprintln!("Can not submit FounderSuccession proposal, no quorum");
+                    }
+                }
                 ToGnome::RunningPolicies => {
                     let mut p_chunks = self.swarm.policy_chunks();
                     let mut policies = p_chunks.remove(0);
@@ -857,6 +1146,26 @@ impl Gnome {
                         .mgr_sender
                         .send(GnomeToManager::RunningCapabilities(policies));
                 }
+                ToGnome::StartTrace(path) => match Tracer::create(&path, self.clock.clone()) {
+                    Ok(tracer) => {
+                        eprintln!("{} tracing into {:?}", self.swarm.name, path);
+                        self.set_tracer(Some(tracer));
+                    }
+                    Err(err) => eprintln!("Unable to start trace {:?}: {}", path, err),
+                },
+                ToGnome::StopTrace => self.set_tracer(None),
+                ToGnome::SetNeighborRetention(retention) => {
+                    eprintln!("{} retention: {:?}", self.swarm.name, retention);
+                    self.retention = retention;
+                }
+                ToGnome::SetTrafficShares(shares) => {
+                    eprintln!("{} traffic shares: {:?}", self.swarm.name, shares);
+                    self.traffic_shares = shares;
+                }
+                ToGnome::ListNeighboringSwarms(depth) => {
+                    eprintln!("{} listing swarms {} hops away", self.swarm.name, depth);
+                    self.start_swarms_query(self.id, depth.max(1), vec![]);
+                }
                 ToGnome::RunningByteSets => {
                     let mut b_sets: Vec<(u8, ByteSet)> =
                         Vec::with_capacity(self.swarm.byteset_reg.len());
@@ -1170,6 +1479,9 @@ impl Gnome {
                         eprintln!("Gnome sent Custom Neighbor response to {g_id}: {res}");
                     }
                 }
+                ManagerToGnome::SetBandwidth(bandwidth) => {
+                    self.new_bandwidth = Some(bandwidth);
+                }
                 ManagerToGnome::Disconnect => {
                     // Gnome should send Disconnected automatically
                     // once he realizes he has no neighbors around
@@ -1188,14 +1500,74 @@ impl Gnome {
         }
         (any_data_processed, bye, tokens_used)
     }
-    fn get_neighboring_swarms(&self) -> HashSet<(GnomeId, SwarmName)> {
-        let mut neighboring_swarms = HashSet::new();
-        for n in &self.fast_neighbors {
-            for swarm_name in &n.member_of_swarms {
-                neighboring_swarms.insert((n.id, swarm_name.clone()));
+    // Swarms we and our neighbors belong to, as an answer to ListNeighboringSwarms
+    fn list_swarms(&self) -> Vec<SwarmName> {
+        let mut names = vec![self.swarm.name.clone()];
+        for (_n_id, swarm_name) in self.get_neighboring_swarms() {
+            if !swarm_name.founder.is_any() {
+                names.push(swarm_name);
+            }
+        }
+        names
+    }
+
+    // Ask every active neighbor except requester for swarms it knows of
+    fn start_swarms_query(&mut self, requester: GnomeId, depth: u8, names: Vec<SwarmName>) {
+        if !self.swarm_queries.start(requester, names) {
+            eprintln!("Still listing swarms for {}", requester);
+            return;
+        }
+        let deadline =
+            SwarmTime(self.swarm_time.0 + 2 * depth as u32 * self.swarm.diameter.0.max(1));
+        let request = NeighborRequest::ListNeighboringSwarms(depth);
+        for neighbor in self.neighbors.iter_mut(&NeighborStatus::ACTIVE) {
+            if neighbor.id != requester
+                && self.swarm_queries.insert(requester, neighbor.id, deadline)
+            {
+                neighbor.request_data(request.clone());
+            }
+        }
+        // There might be no one to ask
+        self.swarms_listed(requester, requester, vec![]);
+    }
+
+    // Page of swarms from queried neighbor
+    fn serve_neighboring_swarms(
+        &mut self,
+        queried: GnomeId,
+        page: u8,
+        total: u8,
+        names: Vec<SwarmName>,
+    ) {
+        let listings = self.swarm_queries.add_page(queried, page, total, names);
+        if listings.is_empty() && page >= total {
+            eprintln!("Unexpected NeighboringSwarms from {}", queried);
+        }
+        for (requester, names) in listings {
+            self.swarms_listed(requester, queried, names);
+        }
+    }
+
+    // Our app gets every neighbor's listing, remote requester gets
+    // all listings merged into one, with pages numbered anew
+    fn swarms_listed(&mut self, requester: GnomeId, queried: GnomeId, names: Vec<SwarmName>) {
+        if requester == self.id {
+            if queried != self.id {
+                let _ = self
+                    .sender
+                    .send(GnomeToApp::NeighboringSwarms(queried, names));
+            }
+            self.swarm_queries.gather(requester, vec![]);
+        } else if let Some(gathered) = self.swarm_queries.gather(requester, names) {
+            for response in swarm_pages(gathered) {
+                self.send_neighbor_response(requester, response);
             }
         }
-        for n in &self.refreshed_neighbors {
+    }
+
+    fn get_neighboring_swarms(&self) -> HashSet<(GnomeId, SwarmName)> {
+        let mut neighboring_swarms = HashSet::new();
+        for n in self.neighbors.iter(&NeighborStatus::RESPONSIVE) {
             for swarm_name in &n.member_of_swarms {
                 neighboring_swarms.insert((n.id, swarm_name.clone()));
             }
@@ -1214,7 +1586,8 @@ impl Gnome {
     // if there is only one neighbor we simply send back a failure message to originating
     // neighbor, since we do not have other neighbors to connect to.
     fn add_ongoing_request(&mut self, origin: GnomeId, network_settings: Vec<u8>) {
-        let neighbor_count = self.fast_neighbors.len() + self.slow_neighbors.len();
+        let neighbor_count =
+            self.neighbors.count(NeighborStatus::Fast) + self.neighbors.count(NeighborStatus::Slow);
         if neighbor_count < 2 {
             eprintln!("FCF> Not enough neighbors: {}", neighbor_count);
             self.send_neighbor_response(origin, NeighborResponse::ForwardConnectFailed);
@@ -1243,42 +1616,17 @@ impl Gnome {
         }
         eprintln!("Ongoing count: {}", id);
         let mut queried_neighbor: Option<GnomeId> = None;
-        for neigh in &mut self.fast_neighbors {
-            if neigh.id != origin {
-                queried_neighbor = Some(neigh.id);
-                neigh.request_data(NeighborRequest::ConnectRequest(
-                    id,
-                    origin,
-                    network_settings.clone(),
-                ));
-                break;
-            }
-        }
-        if queried_neighbor.is_none() {
-            for neigh in &mut self.refreshed_neighbors {
-                if neigh.id != origin {
-                    queried_neighbor = Some(neigh.id);
-                    neigh.request_data(NeighborRequest::ConnectRequest(
-                        id,
-                        origin,
-                        network_settings.clone(),
-                    ));
-                    break;
-                }
-            }
-        }
-        if queried_neighbor.is_none() {
-            for neigh in &mut self.slow_neighbors {
-                if neigh.id != origin {
-                    queried_neighbor = Some(neigh.id);
-                    neigh.request_data(NeighborRequest::ConnectRequest(
-                        id,
-                        origin,
-                        network_settings.clone(),
-                    ));
-                    break;
-                }
-            }
+        if let Some(neigh) = self
+            .neighbors
+            .iter_mut(&NeighborStatus::ACTIVE)
+            .find(|neigh| neigh.id != origin)
+        {
+            queried_neighbor = Some(neigh.id);
+            neigh.request_data(NeighborRequest::ConnectRequest(
+                id,
+                origin,
+                network_settings.clone(),
+            ));
         }
         let queried_neighbors = if queried_neighbor.is_none() {
             vec![]
@@ -1295,6 +1643,35 @@ impl Gnome {
         self.ongoing_requests.insert(id, or);
     }
 
+    fn record_accepted(
+        &mut self,
+        signature: Signature,
+        digest: u64,
+        is_block: bool,
+        bytes: Vec<u8>,
+    ) {
+        let (signer, sign) = match signature {
+            Signature::Regular(gid, sign) => (gid, sign),
+            Signature::Extended(gid, _pub_key, sign) => (gid, sign),
+        };
+        let proof = ChainProof {
+            round_start: self.round_start,
+            signer,
+            digest,
+            is_block,
+            signed: (bytes, sign),
+        };
+        if self.chain.append(proof, self.sha_hash) {
+            let req = NeighborRequest::ChainInfo(self.chain.summary());
+            for neighbor in self
+                .neighbors
+                .iter_mut(&[NeighborStatus::Fast, NeighborStatus::Slow])
+            {
+                neighbor.request_data(req.clone());
+            }
+        }
+    }
+
     fn notify_neighbors_about_new_swarm(&mut self, swarm_name: SwarmName, n_ids: Vec<GnomeId>) {
         eprintln!("Supposed to notify {} neighbors", n_ids.len());
         let req = NeighborRequest::SwarmJoinedInfo(swarm_name);
@@ -1326,13 +1703,7 @@ impl Gnome {
         //TODO: implement token bucket logic
         eprintln!("Send CN 3");
         let req = NeighborRequest::CreateNeighbor(self.id, swarm_name.clone());
-        for neighbor in &mut self.fast_neighbors {
-            neighbor.request_data(req.clone());
-        }
-        for neighbor in &mut self.slow_neighbors {
-            neighbor.request_data(req.clone());
-        }
-        for neighbor in &mut self.refreshed_neighbors {
+        for neighbor in self.neighbors.iter_mut(&NeighborStatus::ACTIVE) {
             neighbor.request_data(req.clone());
         }
     }
@@ -1340,20 +1711,7 @@ impl Gnome {
     fn bye_all(&mut self) {
         eprintln!("Sending bye to all {:?} Neighbors…", self.swarm.id);
         let bye = Message::bye();
-        let mut neighbors = std::mem::replace(&mut self.fast_neighbors, vec![]);
-        for mut neighbor in neighbors {
-            neighbor.send_out(bye.clone());
-        }
-        neighbors = std::mem::replace(&mut self.slow_neighbors, vec![]);
-        for mut neighbor in neighbors {
-            neighbor.send_out(bye.clone());
-        }
-        neighbors = std::mem::replace(&mut self.refreshed_neighbors, vec![]);
-        for mut neighbor in neighbors {
-            neighbor.send_out(bye.clone());
-        }
-        neighbors = std::mem::replace(&mut self.new_neighbors, vec![]);
-        for mut neighbor in neighbors {
+        for mut neighbor in self.neighbors.drain() {
             neighbor.send_out(bye.clone());
         }
     }
@@ -1365,20 +1723,8 @@ impl Gnome {
         // network_settings: Vec<NetworkSettings>,
         network_settings: Vec<u8>,
     ) -> Option<NeighborResponse> {
-        for neighbor in &self.fast_neighbors {
-            if neighbor.id == origin {
-                return Some(NeighborResponse::AlreadyConnected(id));
-            }
-        }
-        for neighbor in &self.refreshed_neighbors {
-            if neighbor.id == origin {
-                return Some(NeighborResponse::AlreadyConnected(id));
-            }
-        }
-        for neighbor in &self.slow_neighbors {
-            if neighbor.id == origin {
-                return Some(NeighborResponse::AlreadyConnected(id));
-            }
+        if self.has_neighbor(origin) {
+            return Some(NeighborResponse::AlreadyConnected(id));
         }
         // TODO: vary response depending on available bandwith
         // TODO: In some cases we need to split this procedure in two:
@@ -1423,29 +1769,17 @@ impl Gnome {
         let option_req = self.ongoing_requests.get_mut(&id);
         if let Some(v) = option_req {
             let mut neighbor_found = false;
-            for neighbor in &mut self.fast_neighbors {
-                if !v.queried_neighbors.contains(&neighbor.id) {
-                    neighbor_found = true;
-                    neighbor.request_data(NeighborRequest::ConnectRequest(
-                        id,
-                        v.origin,
-                        v.network_settings.clone(),
-                    ));
-                    break;
-                }
-            }
-            if !neighbor_found {
-                for neighbor in &mut self.slow_neighbors {
-                    if !v.queried_neighbors.contains(&neighbor.id) {
-                        neighbor_found = true;
-                        neighbor.request_data(NeighborRequest::ConnectRequest(
-                            id,
-                            v.origin,
-                            v.network_settings.clone(),
-                        ));
-                        break;
-                    }
-                }
+            if let Some(neighbor) = self
+                .neighbors
+                .iter_mut(&[NeighborStatus::Fast, NeighborStatus::Slow])
+                .find(|neighbor| !v.queried_neighbors.contains(&neighbor.id))
+            {
+                neighbor_found = true;
+                neighbor.request_data(NeighborRequest::ConnectRequest(
+                    id,
+                    v.origin,
+                    v.network_settings.clone(),
+                ));
             }
             if !neighbor_found {
                 eprintln!("FCF> Unable to find more neighbors for {}", id);
@@ -1509,8 +1843,7 @@ impl Gnome {
                 any_data_processed = true;
                 eprintln!("FCP> Sending response: {:?}", v.response);
                 let response = NeighborResponse::ForwardConnectResponse(v.response);
-                //todo: fix tokens used
-                tokens_used += 43 + response.len();
+                // Queued, so paid from neighbor's bucket
                 self.send_neighbor_response(v.origin, response);
                 // let mut response_sent = false;
                 // for neighbor in &mut self.fast_neighbors {
@@ -1593,7 +1926,6 @@ impl Gnome {
                     } else {
                         eprintln!("FCF> Unable to find more neighbors for {}", k);
                         let resp = NeighborResponse::ForwardConnectFailed;
-                        tokens_used += 43 + resp.len();
                         self.send_neighbor_response(v.origin, resp);
                         // let mut response_sent = false;
                         // for neighbor in &mut self.fast_neighbors {
@@ -1639,32 +1971,34 @@ impl Gnome {
     fn serve_neighbors_casts(&mut self) -> bool {
         // This only sends cast messages internaly, not via network
         let mut any_data_processed = false;
-        for neighbor in &mut self.fast_neighbors {
-            any_data_processed |= neighbor.try_recv_cast();
-        }
-        for neighbor in &mut self.slow_neighbors {
-            any_data_processed |= neighbor.try_recv_cast();
-        }
-        for neighbor in &mut self.refreshed_neighbors {
+        for neighbor in self.neighbors.iter_mut(&NeighborStatus::ACTIVE) {
             any_data_processed |= neighbor.try_recv_cast();
         }
         any_data_processed
     }
-    fn serve_sync_requests(&mut self, available_tokens: u64) -> (bool, usize) {
-        //TODO: implement token bucket logic
-        let mut tokens_used = 0;
+    // Responses are queued, so they are paid from neighbors' buckets
+    fn serve_sync_requests(&mut self, available_tokens: u64) -> bool {
         let mut any_data_processed = false;
         // TODO: in order to function we need to always have
         //       actual value of app_sync_hash at hand
         //       this should be provided by Manager and stored by Gnome or better Swarm
-        if self.new_neighbors.is_empty() {
-            return (any_data_processed, tokens_used);
+        if !self.neighbors.has_any(&[NeighborStatus::New]) {
+            return any_data_processed;
         }
         let message = self.prepare_message(available_tokens);
-        let mut processed_neighbors = vec![];
-        while let Some(mut neighbor) = self.new_neighbors.pop() {
+        for n_id in self.neighbors.ids(&[NeighborStatus::New]) {
+            let mut entry = if let Some(entry) = self.neighbors.take(n_id) {
+                entry
+            } else {
+                continue;
+            };
+            let neighbor = &mut entry.neighbor;
             // eprintln!("Serving Sync Swarm request");
             neighbor.try_recv_cast();
+            if self.token_buckets.available(n_id) == 0 {
+                self.neighbors.restore(entry);
+                continue;
+            }
             // eprintln!("SSReq 1");
             if let Some(NeighborRequest::SwarmSyncRequest(SwarmSyncRequestParams {
                 sync_key_reg,
@@ -1681,24 +2015,22 @@ impl Gnome {
                 // );
                 any_data_processed = true;
                 neighbor.swarm_time = message.swarm_time;
-                // TODO: use tokens when sending response immediately
-                tokens_used += self.send_sync_responses(
+                self.send_sync_responses(
                     // app_sync_hash,
                     sync_key_reg,
                     sync_capability,
                     sync_policy,
                     sync_broadcast,
                     sync_multicast,
-                    &mut neighbor,
+                    neighbor,
                 );
                 // self.fast_neighbors.push(neighbor);
                 // } else {
                 //     processed_neighbors.push(neighbor);
             }
-            processed_neighbors.push(neighbor);
+            self.neighbors.restore(entry);
         }
-        self.new_neighbors = processed_neighbors;
-        (any_data_processed, tokens_used)
+        any_data_processed
     }
 
     fn send_sync_responses(
@@ -1710,8 +2042,7 @@ impl Gnome {
         sync_broadcast: bool,
         sync_multicast: bool,
         neighbor: &mut Neighbor,
-    ) -> usize {
-        let mut tokens_used = 0;
+    ) {
         // println!("Serving some SyncRequest!");
         let b_count = self.swarm.broadcasts_count();
         let m_count = self.swarm.multicasts_count();
@@ -1722,9 +2053,11 @@ impl Gnome {
             //     self.chill_out_max,
             //     self.chill_out.1.elapsed()
             // );
-            (self.chill_out_max.checked_sub(self.chill_out.1.elapsed()))
-                .unwrap_or(Duration::ZERO)
-                .as_millis() as u16
+            (self
+                .chill_out_max
+                .checked_sub(self.clock.now().saturating_sub(self.chill_out.1)))
+            .unwrap_or(Duration::ZERO)
+            .as_millis() as u16
         } else {
             0
             // self.chill_out_max.as_millis() as u16
@@ -1747,13 +2080,6 @@ impl Gnome {
             (false, vec![], vec![])
         };
 
-        let key_size = if !first_key_batch.is_empty() {
-            8 + first_key_batch[0].1.len()
-        } else {
-            0
-        };
-        tokens_used += 54 + 26 + (first_key_batch.len() * key_size);
-
         let sync_response = SwarmSyncResponse {
             chill_phase,
             founder: self.swarm.name.founder,
@@ -1768,17 +2094,17 @@ impl Gnome {
             multicast_size: m_count,
             more_key_reg_messages: more_keys,
             key_reg_pairs: first_key_batch,
+            genesis: self.swarm.genesis(),
         };
         let response = NeighborResponse::SwarmSync(sync_response);
         eprintln!("SNR2");
         neighbor.start_new_round(self.swarm_time);
-        let _ = neighbor.send_out_cast(CastMessage::new_response(response));
+        neighbor.queue_cast(CastMessage::new_response(response));
         let mut i: u8 = 1;
         let total_batches = remaining_batches.len() as u8;
         while let Some(batch) = remaining_batches.pop() {
-            tokens_used += 45 + (batch.len() * key_size);
             let response = NeighborResponse::KeyRegistrySync(i, total_batches, batch);
-            let _ = neighbor.send_out_cast(CastMessage::new_response(response));
+            neighbor.queue_cast(CastMessage::new_response(response));
             i += 1;
         }
         if sync_capability {
@@ -1787,11 +2113,6 @@ impl Gnome {
             if total_chunks > 0 {
                 for i in 1..total_chunks + 1 {
                     let chunk = chunks.pop().unwrap();
-                    let mut bytes_size = 0;
-                    for (_c, ids) in &chunk {
-                        bytes_size += 2 + (8 * ids.len());
-                    }
-                    tokens_used += 44 + bytes_size;
                     let response = NeighborResponse::CapabilitySync(
                         i as u8,
                         // TODO: we have to limit maximum size of Capability registry!
@@ -1799,7 +2120,7 @@ impl Gnome {
                         total_chunks as u8,
                         chunk,
                     );
-                    let _ = neighbor.send_out_cast(CastMessage::new_response(response));
+                    neighbor.queue_cast(CastMessage::new_response(response));
                 }
             }
         }
@@ -1811,13 +2132,8 @@ impl Gnome {
             if total_chunks > 0 {
                 for i in 1..total_chunks + 1 {
                     let chunk = chunks.pop().unwrap();
-                    let mut bytes_size = 0;
-                    for (_p, req) in &chunk {
-                        bytes_size += 1 + (req.len() as usize);
-                    }
-                    tokens_used += 44 + bytes_size;
                     let response = NeighborResponse::PolicySync(i as u8, total_chunks as u8, chunk);
-                    let _ = neighbor.send_out_cast(CastMessage::new_response(response));
+                    neighbor.queue_cast(CastMessage::new_response(response));
                 }
             }
         }
@@ -1825,23 +2141,23 @@ impl Gnome {
             let b_casts = if b_count == 0 {
                 vec![]
             } else {
-                self.swarm.broadcast_ids()
+                self.swarm.cast_sync(true)
             };
-            tokens_used += 46 + b_casts.len();
             let response = NeighborResponse::BroadcastSync(1, 1, b_casts);
-            let _ = neighbor.send_out_cast(CastMessage::new_response(response));
+            neighbor.queue_cast(CastMessage::new_response(response));
         }
         if sync_multicast {
             let m_casts = if m_count == 0 {
                 vec![]
             } else {
-                self.swarm.multicast_ids()
+                self.swarm.cast_sync(false)
             };
-            tokens_used += 46 + m_casts.len();
             let response = NeighborResponse::MulticastSync(1, 1, m_casts);
-            let _ = neighbor.send_out_cast(CastMessage::new_response(response));
+            neighbor.queue_cast(CastMessage::new_response(response));
         }
-        tokens_used
+        // Newcomer will adopt our chain, so he can later detect forks
+        let request = NeighborRequest::ChainInfo(self.chain.summary());
+        neighbor.queue_cast(CastMessage::new_request(request));
     }
 
     fn serve_neighbors_requests(
@@ -1852,53 +2168,80 @@ impl Gnome {
     ) -> (bool, usize) {
         let mut any_data_processed = false;
         let mut tokens_used = 0;
-        let mut neighbors = if slow {
-            std::mem::take(&mut self.slow_neighbors)
+        let status = if slow {
+            NeighborStatus::Slow
         } else if refreshed {
-            std::mem::take(&mut self.refreshed_neighbors)
+            NeighborStatus::Refreshed
         } else {
-            std::mem::take(&mut self.fast_neighbors)
+            NeighborStatus::Fast
         };
         let mut pending_ongoing_requests = vec![];
-        for neighbor in &mut neighbors {
+        for n_id in self.neighbors.ids(&[status]) {
+            let mut entry = if let Some(entry) = self.neighbors.take(n_id) {
+                entry
+            } else {
+                continue;
+            };
+            let neighbor = &mut entry.neighbor;
+            // Requests wait until neighbor's bucket gets refilled
+            if self.token_buckets.available(n_id) == 0 {
+                self.neighbors.restore(entry);
+                continue;
+            }
             if let Some(request) = neighbor.requests.pop_back() {
                 any_data_processed = true;
                 match request {
                     NeighborRequest::UnicastRequest(_swarm_id, cast_ids) => {
-                        let (send_d, recv_d) = channel();
-                        for cast_id in cast_ids.deref() {
-                            if self.swarm.is_unicast_id_available(*cast_id) {
-                                self.swarm.insert_unicast(*cast_id);
-                                self.insert_originating_unicast(
-                                    *cast_id,
-                                    recv_d,
-                                    neighbor.sender.clone(),
-                                );
-                                neighbor.add_unicast(self.swarm.id, *cast_id);
-                                // neighbor.add_requested_data(NeighborResponse::Unicast(
-                                //     self.swarm.id,
-                                //     *cast_id,
-                                // ));
-                                let _res = self.sender.send(GnomeToApp::UnicastOrigin(
-                                    self.swarm.id,
-                                    *cast_id,
-                                    send_d,
-                                ));
-                                break;
-                            }
+                        let our_ids = UnicastIDs::from_ids(self.swarm.avail_unicast_ids());
+                        if let Some(cast_id) = our_ids.first_common(&cast_ids) {
+                            self.swarm.insert_unicast(cast_id);
+                            let send_d = self.unicasts.insert(cast_id, neighbor.id);
+                            neighbor.add_unicast(self.swarm.id, cast_id);
+                            let _res = self.sender.send(GnomeToApp::UnicastOrigin(
+                                self.swarm.id,
+                                cast_id,
+                                send_d,
+                            ));
+                        } else {
+                            eprintln!("No Unicast id available for {}", neighbor.id);
                         }
                     }
-                    NeighborRequest::ForwardConnectRequest(network_settings) => {
-                        eprintln!("FCR< {}", neighbor.id);
-                        pending_ongoing_requests.push((neighbor.id, network_settings));
+                    NeighborRequest::CastNack(is_bcast, cast_id, seqs) => {
+                        for (seq, data) in self.swarm.retransmit(is_bcast, &cast_id, &seqs) {
+                            neighbor.queue_cast(CastMessage::new_response(
+                                NeighborResponse::CastRepair(is_bcast, cast_id, seq, data),
+                            ));
+                        }
                     }
-                    NeighborRequest::ConnectRequest(id, gnome_id, network_settings) => {
+                    NeighborRequest::CastTopics(is_bcast, cast_id, topics) => {
+                        self.swarm
+                            .set_subscriber_topics(is_bcast, &cast_id, neighbor.id, topics);
+                    }
+                    NeighborRequest::PauseUnicast(cast_id) => {
+                        self.unicasts.set_paused(cast_id, neighbor.id, true);
+                    }
+                    NeighborRequest::ResumeUnicast(cast_id) => {
+                        self.unicasts.set_paused(cast_id, neighbor.id, false);
+                    }
+                    NeighborRequest::EndUnicast(cast_id) => {
+                        if neighbor.end_unicast(cast_id) {
+                            self.unicasts.remove(cast_id);
+                            self.swarm.remove_unicast(cast_id);
+                            let _ = self
+                                .sender
+                                .send(GnomeToApp::UnicastEnded(self.swarm.id, cast_id));
+                        }
+                    }
+                    NeighborRequest::ForwardConnectRequest(network_settings) => {
+                        eprintln!("FCR< {}", neighbor.id);
+                        pending_ongoing_requests.push((neighbor.id, network_settings));
+                    }
+                    NeighborRequest::ConnectRequest(id, gnome_id, network_settings) => {
                         eprintln!("ConnReq {}", gnome_id);
                         if let Some(response) =
                             self.serve_connect_request(id, neighbor.id, gnome_id, network_settings)
                         {
-                            tokens_used += 43 + response.len();
-                            self.send_neighbor_response(neighbor.id, response);
+                            neighbor.queue_cast(CastMessage::new_response(response));
                             // neighbor.add_requested_data(response);
                         }
                     }
@@ -1911,7 +2254,7 @@ impl Gnome {
                         // app_root_hash: _,
                     }) => {
                         // eprintln!("SSReq 2");
-                        tokens_used += self.send_sync_responses(
+                        self.send_sync_responses(
                             // app_sync_hash,
                             sync_key_reg,
                             sync_capability,
@@ -1932,9 +2275,69 @@ impl Gnome {
                             .mgr_sender
                             .send(GnomeToManager::NeighboringSwarms(self.swarm.id, swarms_set));
                     }
-                    NeighborRequest::SubscribeRequest(is_bcast, cast_id) => {
+                    NeighborRequest::ListNeighboringSwarms(depth) => {
+                        eprintln!("ListNeighboringSwarms({}) from {}", depth, neighbor.id);
+                        if depth > 1 {
+                            // Answered once swarms further away are gathered
+                            self.start_swarms_query(neighbor.id, depth - 1, self.list_swarms());
+                        } else {
+                            for response in swarm_pages(self.list_swarms()) {
+                                neighbor.queue_cast(CastMessage::new_response(response));
+                            }
+                        }
+                    }
+                    NeighborRequest::ChainInfo(links) => {
+                        // While merging we only follow the winner's chain
+                        let ignored = self
+                            .chain
+                            .merging_with()
+                            .is_some_and(|winner| winner != neighbor.id);
+                        if ignored {
+                            eprintln!("Ignoring ChainInfo from {} while merging", neighbor.id);
+                        } else if let Some((fork_point, g_ids)) =
+                            self.chain.compare(neighbor.id, &links, self.sha_hash)
+                        {
+                            eprintln!(
+                                "{} Fork detected @{} with {:?}",
+                                self.swarm.name, fork_point, g_ids
+                            );
+                            let _ = self
+                                .sender
+                                .send(GnomeToApp::ForkDetected(fork_point, g_ids.clone()));
+                            let _ = self.mgr_sender.send(GnomeToManager::ForkDetected(
+                                self.swarm.id,
+                                self.swarm.name.clone(),
+                                fork_point,
+                                g_ids,
+                            ));
+                            if self.chain.dispute(neighbor.id, fork_point, self.swarm_time) {
+                                let request = NeighborRequest::ChainProofRequest(fork_point);
+                                tokens_used += 43 + request.len();
+                                neighbor.request_data(request);
+                            }
+                        }
+                    }
+                    NeighborRequest::ChainProofRequest(fork_point) => {
+                        let proofs = self.chain.proofs_since(fork_point);
+                        let total_chunks = proofs.len() as u8;
+                        for (i, proof) in proofs.into_iter().enumerate() {
+                            let response =
+                                NeighborResponse::ChainProof(i as u8 + 1, total_chunks, proof);
+                            neighbor.queue_cast(CastMessage::new_response(response));
+                        }
+                    }
+                    NeighborRequest::SubscribeRequest(is_bcast, cast_id, with_history) => {
                         eprintln!("SubscribeRequest {}(is_bcast: {})", cast_id.0, is_bcast);
-                        if let Some(origin) = self.swarm.add_subscriber(
+                        if !self.swarm.may_subscribe(is_bcast, &cast_id, neighbor.id) {
+                            eprintln!("{} may not subscribe {}", neighbor.id, cast_id.0);
+                            let response = NeighborResponse::SubscribeDenied(is_bcast, cast_id);
+                            neighbor.queue_cast(CastMessage::new_response(response));
+                        } else if self.swarm.get_source(&cast_id, is_bcast) == Some(neighbor.id) {
+                            // Our source can not receive it from us
+                            let request = NeighborRequest::SourceDrained(is_bcast, cast_id);
+                            tokens_used += 43 + request.len();
+                            neighbor.request_data(request);
+                        } else if let Some(origin) = self.swarm.add_subscriber(
                             is_bcast,
                             &cast_id,
                             neighbor.id,
@@ -1942,8 +2345,14 @@ impl Gnome {
                         ) {
                             let response =
                                 NeighborResponse::Subscribed(is_bcast, cast_id, origin, None);
-                            tokens_used += 43 + response.len();
-                            self.send_neighbor_response(neighbor.id, response);
+                            neighbor.queue_cast(CastMessage::new_response(response));
+                            // Queued before any live data, since subscriber
+                            // was added in this very step
+                            if with_history {
+                                for message in self.swarm.cast_history(is_bcast, &cast_id) {
+                                    neighbor.queue_cast(message);
+                                }
+                            }
                             // neighbor.add_requested_data(NeighborResponse::Subscribed(
                             //     is_bcast, cast_id, origin, None,
                             // ));
@@ -1968,6 +2377,9 @@ impl Gnome {
                             neighbor.id,
                         ));
                     }
+                    NeighborRequest::CastDropped(is_bcast, cast_id, count) => {
+                        self.report_cast_drops(is_bcast, cast_id, count);
+                    }
                     NeighborRequest::SendToCastSource(is_bcast, cast_id, c_data) => {
                         //TODO
                         let _ = self
@@ -2033,14 +2445,8 @@ impl Gnome {
                     }
                 }
             }
+            self.neighbors.restore(entry);
         }
-        let _ = if slow {
-            std::mem::replace(&mut self.slow_neighbors, neighbors)
-        } else if refreshed {
-            std::mem::replace(&mut self.refreshed_neighbors, neighbors)
-        } else {
-            std::mem::replace(&mut self.fast_neighbors, neighbors)
-        };
 
         for (id, net_set) in pending_ongoing_requests {
             self.add_ongoing_request(id, net_set);
@@ -2049,12 +2455,30 @@ impl Gnome {
     }
 
     pub fn do_your_job(mut self, assigned_bandwidth: u64) {
+        let mut job = if let Some(job) = self.prepare(assigned_bandwidth, true) {
+            job
+        } else {
+            return;
+        };
+        while let Some(sleep_time) = self.step(&mut job) {
+            // A gnome's gotta sleep
+            self.clock.sleep(sleep_time);
+        }
+        // eprintln!("Gnome is done");
+    }
+
+    // Wait for neighbors and sync with swarm.
+    // Returns None if we were told to leave before that happened.
+    pub fn prepare(&mut self, assigned_bandwidth: u64, presync: bool) -> Option<JobState> {
         eprintln!(
             "Waiting for user/network to provide some Neighbors for {}...",
             self.swarm.name
         );
         let sleep_time = Duration::from_millis(128);
-        while self.fast_neighbors.is_empty() && self.slow_neighbors.is_empty() {
+        while !self
+            .neighbors
+            .has_any(&[NeighborStatus::Fast, NeighborStatus::Slow])
+        {
             // println!("in while");
             let _ = self.serve_user_requests();
             let (_data_processed, bye, _tokens_used) = self.serve_manager_requests();
@@ -2063,9 +2487,9 @@ impl Gnome {
                     self.swarm.id,
                     self.swarm.name.clone(),
                 ));
-                return;
+                return None;
             }
-            std::thread::sleep(sleep_time);
+            self.clock.sleep(sleep_time);
         }
         eprintln!("{} have neighbors!", self.swarm.name);
         self.notify_mgr_about_neighbors();
@@ -2077,289 +2501,384 @@ impl Gnome {
         // };
 
         eprintln!("Avail bandwith: {}", assigned_bandwidth);
-        // TODO: manager should send a message with available bandwith
-        //       and available network_buffer
-        self.presync_with_swarm(assigned_bandwidth);
-        let mut timer = Instant::now();
+        // Manager can change assigned bandwith with ManagerToGnome::SetBandwidth
+        // TODO: manager should also inform us about available network_buffer
+        if presync {
+            self.presync_with_swarm(assigned_bandwidth);
+        } else {
+            // All gnomes start together, there is nothing to sync with
+            let _ = self.sender.send(GnomeToApp::SwarmReady(
+                self.swarm.name.clone(),
+                self.id == self.swarm.name.founder,
+            ));
+            self.send_all(assigned_bandwidth);
+        }
         self.timeout_duration = Duration::from_secs(16);
+        let now = self.clock.now();
+        Some(JobState {
+            assigned_bandwidth,
+            timer: now,
+            sleep_nsec: 1 << 25,
+            loops_with_no_reply: 0,
+            last_loop_time: now,
+            available_tokens: assigned_bandwidth,
+            min_token_creation_time: calculate_min_token_period(assigned_bandwidth),
+            borrowed_tokens: 0,
+            band_mon: BandwidthMonitor::new(Duration::from_secs(1), self.clock.clone()),
+            neigh_drop_time_by_net: SwarmTime(0),
+            bandwidth_report_due: false,
+        })
+    }
 
-        // A gnome's gotta sleep
-        let min_sleep_nsec: u64 = 1 << 7; //128nsec min
-        let max_sleep_nsec: u64 = 1 << 26; //~64msec max
-        let mut sleep_nsec: u64 = 1 << 25;
+    // Single iteration of gnome's main loop.
+    // Returns how long we should sleep before next one,
+    // or None when it is time to quit.
+    pub fn step(&mut self, job: &mut JobState) -> Option<Duration> {
         // set was_loop_iteration_busy  to true if:
         // - we received a Sync message
         // - we received a Cast message (incl. Neighbor Req/Res)
         // - we received a ManagerRequest
         // - user has sent us a request
         // - we sent a Cast message
-        let mut was_loop_iteration_busy;
-        let mut loops_with_no_reply = 0;
-        // let mut tokens_used_in_iteration = 0;
-        let mut last_loop_time = SystemTime::now();
-        let mut available_tokens = assigned_bandwidth;
-        let min_token_creation_time = calculate_min_token_period(assigned_bandwidth);
-        let mut borrowed_tokens: u64 = 0;
-        let mut band_mon = BandwidthMonitor::new(Duration::from_secs(1));
-        let mut neigh_drop_time_by_net = SwarmTime(0);
-        loop {
-            //TODO: Gather bandwith usage stats
-            //
-            // At the start of every iteration read timestamp
-            // and substract from it one from previous iteration.
-            // time_step = new_timestamp - old_timestamp [sec]
-            // new_tokens = time_step * bandwith
-            // If value of network_buffer_used is > 0, decrease it by
-            // new_tokens.
-            //    (If new_tokens > network_buffer_used
-            //     new_tokens = network_buffer_used - prev_network_buffer_used
-            //     otherwise new_tokens = 0)
+        //TODO: Gather bandwith usage stats
+        //
+        // At the start of every iteration read timestamp
+        // and substract from it one from previous iteration.
+        // time_step = new_timestamp - old_timestamp [sec]
+        // new_tokens = time_step * bandwith
+        // If value of network_buffer_used is > 0, decrease it by
+        // new_tokens.
+        //    (If new_tokens > network_buffer_used
+        //     new_tokens = network_buffer_used - prev_network_buffer_used
+        //     otherwise new_tokens = 0)
 
-            // available_tokens = min(available_tokens + new_tokens,
-            //                        MAX_TOKENS)
-            // TODO: iterate over neighbors and
-            //   for each neighbor take some amount of tokens and inform him
-            //   how many he can use for sending messages.
-            //   Every neighbor returns number of unused tokens.
-            // Every neighbor should get at least 1500 tokens, so that
-            // he can send at least one message for sure.
-            // If available_tokens < 1500 we can try sending this smaller
-            //   amount, or better we wait until next iteration for
-            //   this number to increase.
+        // available_tokens = min(available_tokens + new_tokens,
+        //                        MAX_TOKENS)
+        // New tokens are split among neighbors' buckets,
+        // what does not fit into them stays in available_tokens.
+        // Data we serve to given neighbor (responses, casts) is paid
+        // from his bucket when it leaves his outbox,
+        // and we stop serving him once it is empty.
+        // Every neighbor can hold at least 1500 tokens, so that
+        // he can send at least one message for sure.
+        // Consensus messages are only paid from available_tokens.
 
-            let this_loop_time = SystemTime::now();
-            let last_loop_duration = this_loop_time.duration_since(last_loop_time).unwrap();
-            if last_loop_duration > min_token_creation_time {
-                last_loop_time = this_loop_time;
-                create_tokens(
-                    &mut available_tokens,
-                    &mut borrowed_tokens,
-                    last_loop_duration,
-                    assigned_bandwidth,
-                );
-                // tokens_used_in_iteration = 0;
-            }
-            was_loop_iteration_busy = false;
-            let sleep_time = Duration::from_nanos(sleep_nsec);
-            // println!("Sleeping for: {:?}", sleep_time);
-            std::thread::sleep(sleep_time);
-            let (mut break_the_loop, new_user_proposal) = self.serve_user_requests();
+        let this_loop_time = self.clock.now();
+        let last_loop_duration = this_loop_time.saturating_sub(job.last_loop_time);
+        if last_loop_duration > job.min_token_creation_time {
+            job.last_loop_time = this_loop_time;
+            let tokens_created = create_tokens(
+                &mut job.available_tokens,
+                &mut job.borrowed_tokens,
+                last_loop_duration,
+                job.assigned_bandwidth,
+            );
+            let tokens_left = self.token_buckets.refill(
+                &self.neighbors.ids(&NeighborStatus::ALL),
+                tokens_created,
+                job.assigned_bandwidth,
+            );
+            job.lend_tokens(tokens_created - tokens_left);
+        }
+        let mut was_loop_iteration_busy = false;
+        let (mut break_the_loop, new_user_proposal) = self.serve_user_requests();
+        //TODO: decide if we should serve below when no tokens available
+        let (mgr_busy, bye, tokens_used) = self.serve_manager_requests();
+        job.use_tokens(tokens_used);
+        if let Some(bandwidth) = self.new_bandwidth.take() {
+            eprintln!("{} bandwidth set to {}", self.swarm.name, bandwidth);
+            job.set_bandwidth(bandwidth);
+        }
+        if tokens_used > 0 {
+            eprintln!("Manager requests used {} byte tokens", tokens_used);
+        }
+        break_the_loop |= bye;
+        was_loop_iteration_busy |= mgr_busy;
+        was_loop_iteration_busy |= self.serve_user_data();
+        if self.chill_out.0 {
             //TODO: decide if we should serve below when no tokens available
-            let (mgr_busy, bye, tokens_used) = self.serve_manager_requests();
-            update_tokens(&mut available_tokens, &mut borrowed_tokens, tokens_used);
-            // tokens_used_in_iteration += tokens_used;
-            if tokens_used > 0 {
-                eprintln!("Manager requests used {} byte tokens", tokens_used);
-            }
-            break_the_loop |= bye;
-            was_loop_iteration_busy |= mgr_busy;
-            was_loop_iteration_busy |= self.serve_user_data();
-            if self.chill_out.0 {
-                //TODO: decide if we should serve below when no tokens available
-                let (was_busy, tokens_used) = self.serve_sync_requests(available_tokens);
-                was_loop_iteration_busy |= was_busy;
-                update_tokens(&mut available_tokens, &mut borrowed_tokens, tokens_used);
-                // tokens_used_in_iteration += tokens_used;
-            }
+            was_loop_iteration_busy |= self.serve_sync_requests(job.available_tokens);
+        }
+        //TODO: decide if we should serve below when no tokens available
+        let (was_busy, tokens_used) = self.serve_internal();
+        was_loop_iteration_busy |= was_busy;
+        job.use_tokens(tokens_used);
+        if self.neighbors.has_any(&[NeighborStatus::Refreshed]) {
             //TODO: decide if we should serve below when no tokens available
-            let (was_busy, tokens_used) = self.serve_internal();
+            let (was_busy, tokens_used) = self.serve_neighbors_requests(true, false);
             was_loop_iteration_busy |= was_busy;
-            update_tokens(&mut available_tokens, &mut borrowed_tokens, tokens_used);
-            // tokens_used_in_iteration += tokens_used;
-            if !self.refreshed_neighbors.is_empty() {
-                //TODO: decide if we should serve below when no tokens available
-                let (was_busy, tokens_used) = self.serve_neighbors_requests(true, false);
-                was_loop_iteration_busy |= was_busy;
-                update_tokens(&mut available_tokens, &mut borrowed_tokens, tokens_used);
-                // tokens_used_in_iteration += tokens_used;
-            }
-            if !self.fast_neighbors.is_empty() {
-                //TODO: decide if we should serve below when no tokens available
-                let (was_busy, tokens_used) = self.serve_neighbors_requests(false, false);
-                was_loop_iteration_busy |= was_busy;
-                update_tokens(&mut available_tokens, &mut borrowed_tokens, tokens_used);
-                // tokens_used_in_iteration += tokens_used;
-                // was_loop_iteration_busy |= self.serve_neighbors_requests(false, false);
-            }
-            if !self.slow_neighbors.is_empty() {
-                //TODO: decide if we should serve below when no tokens available
-                let (was_busy, tokens_used) = self.serve_neighbors_requests(false, true);
-                was_loop_iteration_busy |= was_busy;
-                update_tokens(&mut available_tokens, &mut borrowed_tokens, tokens_used);
-                // tokens_used_in_iteration += tokens_used;
-                // was_loop_iteration_busy |= self.serve_neighbors_requests(false, true);
-            }
+            job.use_tokens(tokens_used);
+        }
+        if self.neighbors.has_any(&[NeighborStatus::Fast]) {
             //TODO: decide if we should serve below when no tokens available
-            let (was_busy, tokens_used) = self.serve_ongoing_requests();
+            let (was_busy, tokens_used) = self.serve_neighbors_requests(false, false);
             was_loop_iteration_busy |= was_busy;
-            update_tokens(&mut available_tokens, &mut borrowed_tokens, tokens_used);
-            // tokens_used_in_iteration += tokens_used;
-            was_loop_iteration_busy |= self.serve_neighbors_casts();
-            // was_loop_iteration_busy |= self.swarm.serve_casts(available_tokens); // #5
-            let (was_busy, tokens_used) = self.swarm.serve_casts(available_tokens);
+            job.use_tokens(tokens_used);
+            // was_loop_iteration_busy |= self.serve_neighbors_requests(false, false);
+        }
+        if self.neighbors.has_any(&[NeighborStatus::Slow]) {
+            //TODO: decide if we should serve below when no tokens available
+            let (was_busy, tokens_used) = self.serve_neighbors_requests(false, true);
             was_loop_iteration_busy |= was_busy;
-            update_tokens(
-                &mut available_tokens,
-                &mut borrowed_tokens,
-                tokens_used as usize,
-            );
-            // let refr_new_proposal = self.try_recv_refreshed();
-            // print!(
-            //     "F:{}s:{},r:{},n:{}",
-            //     self.fast_neighbors.len(),
-            //     self.slow_neighbors.len(),
-            //     self.refreshed_neighbors.len(),
-            //     self.new_neighbors.len()
-            // );
-            let (
-                _have_responsive_neighbors,
-                slow_advance_to_next_turn,
-                slow_new_proposal,
-                slow_any_data_processed,
-            ) = self.try_recv(false, &mut break_the_loop);
-            was_loop_iteration_busy |= slow_any_data_processed;
-            let (
-                have_responsive_neighbors,
-                fast_advance_to_next_turn,
-                fast_new_proposal,
-                fast_any_data_processed,
-            ) = self.try_recv(true, &mut break_the_loop);
-            was_loop_iteration_busy |= fast_any_data_processed;
+            job.use_tokens(tokens_used);
+            // was_loop_iteration_busy |= self.serve_neighbors_requests(false, true);
+        }
+        //TODO: decide if we should serve below when no tokens available
+        let (was_busy, tokens_used) = self.serve_ongoing_requests();
+        was_loop_iteration_busy |= was_busy;
+        job.use_tokens(tokens_used);
+        was_loop_iteration_busy |= self.serve_neighbors_casts();
+        // was_loop_iteration_busy |= self.swarm.serve_casts(available_tokens); // #5
+        let mut room: HashMap<GnomeId, usize> = self
+            .neighbors
+            .iter(&NeighborStatus::ALL)
+            .map(|neighbor| (neighbor.id, neighbor.cast_room()))
+            .collect();
+        let mut outgoing = vec![];
+        // Casts are queued, so they are paid from neighbors' buckets
+        let (was_busy, _tokens_queued) =
+            self.swarm
+                .serve_casts(job.available_tokens, &mut room, &mut outgoing);
+        was_loop_iteration_busy |= was_busy;
+        // Unicast data is paid from neighbor's bucket once it leaves the outbox
+        let cast_count = outgoing.len();
+        for (n_id, cast_id) in self.unicasts.serve(&mut room, &mut outgoing) {
+            eprintln!("Unicast {} to {} ended by app", cast_id.0, n_id);
+            self.end_unicast(n_id, cast_id, true);
+        }
+        was_loop_iteration_busy |= outgoing.len() > cast_count;
+        // Origin keeps casting even when its own app does not keep up
+        for (is_bcast, cast_id) in self.swarm.take_overflowed_casts() {
+            if self.swarm.get_source(&cast_id, is_bcast) != Some(self.id) {
+                let _ = self
+                    .send_internal
+                    .send(InternalMsg::UnsubscribeCast(is_bcast, cast_id));
+            }
+        }
+        for (is_bcast, cast_id, count) in self.swarm.take_dropped_casts() {
+            let _ = self
+                .sender
+                .send(GnomeToApp::CastDataDropped(is_bcast, cast_id, count));
+            if self.swarm.get_source(&cast_id, is_bcast) != Some(self.id) {
+                self.report_cast_drops(is_bcast, cast_id, count);
+            }
+        }
+        for (n_id, request) in self.swarm.take_cast_nacks() {
+            outgoing.push((n_id, CastMessage::new_request(request)));
+        }
+        // Origin has no source to tell
+        for (n_id, request) in self.swarm.take_topic_updates() {
+            if let Some(neighbor) = self.neighbors.get_mut(n_id, &NeighborStatus::ALL) {
+                neighbor.queue_cast(CastMessage::new_request(request));
+            }
+        }
+        for (n_id, message) in outgoing {
+            if let Some(neighbor) = self.neighbors.get_mut(n_id, &NeighborStatus::ALL) {
+                neighbor.queue_cast(message);
+            } else {
+                eprintln!("Subscriber {} is no longer our neighbor", n_id);
+            }
+        }
+        let tokens_sent = self.flush_outboxes();
+        was_loop_iteration_busy |= tokens_sent > 0;
+        job.record_sent(tokens_sent);
+        if job.bandwidth_report_due {
+            job.bandwidth_report_due = false;
+            self.report_bandwidth(job);
+        }
+        // let refr_new_proposal = self.try_recv_refreshed();
+        // print!(
+        //     "F:{}s:{},r:{},n:{}",
+        //     self.fast_neighbors.len(),
+        //     self.slow_neighbors.len(),
+        //     self.refreshed_neighbors.len(),
+        //     self.new_neighbors.len()
+        // );
+        let (
+            _have_responsive_neighbors,
+            slow_advance_to_next_turn,
+            slow_new_proposal,
+            slow_any_data_processed,
+        ) = self.try_recv(false, &mut break_the_loop);
+        was_loop_iteration_busy |= slow_any_data_processed;
+        let (
+            have_responsive_neighbors,
+            fast_advance_to_next_turn,
+            fast_new_proposal,
+            fast_any_data_processed,
+        ) = self.try_recv(true, &mut break_the_loop);
+        was_loop_iteration_busy |= fast_any_data_processed;
 
-            // TODO: get rid of this old mechanism
-            //
-            // We have to send NoOp every 128msec in order to
-            // trigger token admission on socket side
-            // That is why we can not sleep for longer than 128msec
-            // if let Ok(band) = self.band_receiver.try_recv() {
-            //     if band == 0 {
-            //         // print!("R");
-            //         self.send_noop_from_a_neighbor();
-            //     } else {
-            //         assigned_bandwith = band;
-            //         eprintln!("Got bandwith: {}", assigned_bandwith);
-            //         min_token_creation_time = calculate_min_token_period(assigned_bandwith);
-            //     }
-            //     // println!("Avail bandwith: {}", available_bandwith);
-            //     //TODO make use of available_bandwith during multicasting setup
-            // }
-            let advance_to_next_turn = fast_advance_to_next_turn || slow_advance_to_next_turn;
-            let new_proposal = new_user_proposal || fast_new_proposal || slow_new_proposal;
+        // TODO: get rid of this old mechanism
+        //
+        // We have to send NoOp every 128msec in order to
+        // trigger token admission on socket side
+        // That is why we can not sleep for longer than 128msec
+        // if let Ok(band) = self.band_receiver.try_recv() {
+        //     if band == 0 {
+        //         // print!("R");
+        //         self.send_noop_from_a_neighbor();
+        //     } else {
+        //         assigned_bandwith = band;
+        //         eprintln!("Got bandwith: {}", assigned_bandwith);
+        //         min_token_creation_time = calculate_min_token_period(assigned_bandwith);
+        //     }
+        //     // println!("Avail bandwith: {}", available_bandwith);
+        //     //TODO make use of available_bandwith during multicasting setup
+        // }
+        let advance_to_next_turn = fast_advance_to_next_turn || slow_advance_to_next_turn;
+        let new_proposal = new_user_proposal || fast_new_proposal || slow_new_proposal;
 
-            // TODO: when round ends drop slow neighbors with a bye message
-            // Those neighbors will need to start over again
-            // if !new_proposal && !fast_advance_to_next_turn && !self.slow_neighbors.is_empty() {
-            //     eprint!("GSN ");
-            //     std::thread::sleep(Duration::from_nanos(sleep_nsec >> 1));
-            //     let (
-            //         _have_responsive_neighbors,
-            //         _slow_advance_to_next_turn,
-            //         slow_new_proposal,
-            //         slow_any_data_processed,
-            //     ) = self.try_recv(app_sync_hash, false);
-            //     was_loop_iteration_busy |= slow_any_data_processed;
-            //     new_proposal |= slow_new_proposal;
-            // }
+        // TODO: when round ends drop slow neighbors with a bye message
+        // Those neighbors will need to start over again
+        // if !new_proposal && !fast_advance_to_next_turn && !self.slow_neighbors.is_empty() {
+        //     eprint!("GSN ");
+        //     std::thread::sleep(Duration::from_nanos(sleep_nsec >> 1));
+        //     let (
+        //         _have_responsive_neighbors,
+        //         _slow_advance_to_next_turn,
+        //         slow_new_proposal,
+        //         slow_any_data_processed,
+        //     ) = self.try_recv(app_sync_hash, false);
+        //     was_loop_iteration_busy |= slow_any_data_processed;
+        //     new_proposal |= slow_new_proposal;
+        // }
 
-            // || refr_new_proposal;
-            // TODO: here we need to make use of self.chill_out attribute
-            // Following needs to be implemented for cases like (Forward)ConnectRequests.
-            // Need to find a way to always clear any data we have to send to our neighbors.
-            // Maybe we can send that data without updating state...
-            // Then only first message will pass sanity @ neighbor, following messages
-            // will fail sanity, but requested data should be served...done?
+        // || refr_new_proposal;
+        // TODO: here we need to make use of self.chill_out attribute
+        // Following needs to be implemented for cases like (Forward)ConnectRequests.
+        // Need to find a way to always clear any data we have to send to our neighbors.
+        // Maybe we can send that data without updating state...
+        // Then only first message will pass sanity @ neighbor, following messages
+        // will fail sanity, but requested data should be served...done?
 
-            // maybe self.send_immediate should no longer be...
+        // maybe self.send_immediate should no longer be...
 
-            // chill out mode may end abruptly in case new_proposal has been received
-            if new_proposal
-            // || advance_to_next_turn
-            //     && self.next_state.last_accepted_message.swarm_time == SwarmTime(0)
-            {
-                if self.chill_out.0 {
-                    // println!("Chill out is terminated abruptly");
-                    self.send_immediate = true;
-                }
-                self.chill_out.0 = false;
-            }
+        // chill out mode may end abruptly in case new_proposal has been received
+        if new_proposal
+        // || advance_to_next_turn
+        //     && self.next_state.last_accepted_message.swarm_time == SwarmTime(0)
+        {
             if self.chill_out.0 {
-                if self.chill_out.1.elapsed() >= self.chill_out_max {
-                    // If self.chill_out.1 reaches 0 self._chill_out.0 =false and it's time to work.
-                    // println!(
-                    //     "Chill out is over fast:{}, slow:{}, refr:{}",
-                    //     self.fast_neighbors.len(),
-                    //     self.slow_neighbors.len(),
-                    //     self.refreshed_neighbors.len()
-                    // );
-                    self.chill_out.0 = false;
-                    // When we end chill_out mode, we have to start new timer.
-                    // println!("Reset timer");
-                    self.send_immediate = true;
-                    timer = Instant::now();
-                    self.timeout_duration = Duration::from_millis(500);
+                // println!("Chill out is terminated abruptly");
+                self.send_immediate = true;
+            }
+            self.chill_out.0 = false;
+        }
+        if self.chill_out.0 {
+            if self.clock.now().saturating_sub(self.chill_out.1) >= self.chill_out_max {
+                // If self.chill_out.1 reaches 0 self._chill_out.0 =false and it's time to work.
+                // println!(
+                //     "Chill out is over fast:{}, slow:{}, refr:{}",
+                //     self.fast_neighbors.len(),
+                //     self.slow_neighbors.len(),
+                //     self.refreshed_neighbors.len()
+                // );
+                self.chill_out.0 = false;
+                // When we end chill_out mode, we have to start new timer.
+                // println!("Reset timer");
+                self.send_immediate = true;
+                job.timer = self.clock.now();
+                self.timeout_duration = Duration::from_millis(500);
+            } else {
+                if was_loop_iteration_busy {
+                    // print!("d ");
+                    job.sleep_nsec >>= 2;
+                    if job.sleep_nsec < MIN_SLEEP_NSEC {
+                        job.sleep_nsec = MIN_SLEEP_NSEC;
+                    }
                 } else {
-                    if was_loop_iteration_busy {
-                        // print!("d ");
-                        sleep_nsec >>= 2;
-                        if sleep_nsec < min_sleep_nsec {
-                            sleep_nsec = min_sleep_nsec;
-                        }
-                    } else {
-                        sleep_nsec <<= 1;
-                        // print!("i ");
-                        if sleep_nsec > max_sleep_nsec {
-                            sleep_nsec = max_sleep_nsec;
-                        }
+                    job.sleep_nsec <<= 1;
+                    // print!("i ");
+                    if job.sleep_nsec > MAX_SLEEP_NSEC {
+                        job.sleep_nsec = MAX_SLEEP_NSEC;
                     }
-                    continue;
                 }
+                return Some(Duration::from_nanos(job.sleep_nsec));
             }
+        }
 
-            //TODO: following conditional logic is a terrible mess, it begs for refactoring
-            let timeout = timer.elapsed() >= self.timeout_duration;
-            if advance_to_next_turn || self.send_immediate || timeout && have_responsive_neighbors {
-                loops_with_no_reply = 0;
-                self.update_state();
-                //TODO: calculate how many bytes on average we have
-                // available.
-                // Maybe substract from bandwith number of bytes used
-                // since round start divided by round time
-                // avail = bandwith - (used/time)
-                // to give neighbors a rough estimate of our capacity
-                if !new_proposal && !self.send_immediate {
-                    // println!("swap&send");
-                    self.swap_neighbors();
-                } else {
-                    // println!("konkat&send");
-                    self.concat_neighbors();
-                    // self.send_all(available_tokens); // always send!
-                }
-                //TODO: send average bandwith available
-                let average_available = assigned_bandwidth.saturating_sub(band_mon.average());
-                let tokens_used = self.send_all(average_available);
-                update_tokens(
-                    &mut available_tokens,
-                    &mut borrowed_tokens,
-                    tokens_used as usize,
-                );
-                if average_available <= assigned_bandwidth >> 3 {
-                    // we have used >=87.5% of bandwidth available
-                    // so we need to drop a neighbor
-                    if self.swarm_time - neigh_drop_time_by_net > SwarmTime(30)
-                        && self.neighbors_count() > 2
-                    {
-                        if let Some(dropped) = self.drop_any_neighbor() {
-                            eprintln!("Dropped {} due to high network usage", dropped.id);
-                            neigh_drop_time_by_net = self.swarm_time;
-                        }
-                    }
+        let timed_out = self.clock.now().saturating_sub(job.timer) >= self.timeout_duration;
+        match TurnEnd::decide(
+            advance_to_next_turn,
+            self.send_immediate,
+            timed_out,
+            have_responsive_neighbors,
+        ) {
+            TurnEnd::Now => self.end_turn(job, new_proposal),
+            TurnEnd::NoReply => break_the_loop |= self.turn_unanswered(job),
+            TurnEnd::Wait => {}
+        }
+
+        if break_the_loop {
+            let _ = self.mgr_sender.send(GnomeToManager::Disconnected(
+                self.swarm.id,
+                self.swarm.name.clone(),
+            ));
+            return None;
+        };
+
+        if was_loop_iteration_busy {
+            job.sleep_nsec >>= 2;
+            // print!("d ");
+            if job.sleep_nsec < MIN_SLEEP_NSEC {
+                job.sleep_nsec = MIN_SLEEP_NSEC;
+            }
+        } else {
+            job.sleep_nsec <<= 1;
+            // print!("i ");
+            if job.sleep_nsec > MAX_SLEEP_NSEC {
+                job.sleep_nsec = MAX_SLEEP_NSEC;
+            }
+        }
+        Some(Duration::from_nanos(job.sleep_nsec))
+    }
+    // Send our message for next turn and check if round is over
+    fn end_turn(&mut self, job: &mut JobState, new_proposal: bool) {
+        job.loops_with_no_reply = 0;
+        self.update_state();
+        //TODO: calculate how many bytes on average we have
+        // available.
+        // Maybe substract from bandwith number of bytes used
+        // since round start divided by round time
+        // avail = bandwith - (used/time)
+        // to give neighbors a rough estimate of our capacity
+        if !new_proposal && !self.send_immediate {
+            // println!("swap&send");
+            self.swap_neighbors();
+        } else {
+            // println!("konkat&send");
+            self.concat_neighbors();
+            // self.send_all(available_tokens); // always send!
+        }
+        //TODO: send average bandwith available
+        let average_available = job
+            .assigned_bandwidth
+            .saturating_sub(job.band_mon.average());
+        let tokens_used = self.send_all(average_available);
+        job.use_tokens(tokens_used as usize);
+        if average_available <= job.assigned_bandwidth >> 3 {
+            // we have used >=87.5% of bandwidth available
+            // so we need to drop a neighbor
+            if self.swarm_time - job.neigh_drop_time_by_net > SwarmTime(30)
+                && self.neighbors_count() > 2
+            {
+                if let Some(dropped) = self.drop_any_neighbor() {
+                    eprintln!("Dropped {} due to high network usage", dropped.id);
+                    self.neighbor_dropped(dropped.id, DropReason::Bandwith);
+                    job.neigh_drop_time_by_net = self.swarm_time;
                 }
-                band_mon.update(tokens_used);
-                self.send_immediate = false;
-                if self.check_if_new_round(available_tokens)
-                    && self.neighbor_discovery.tick_and_check()
-                    && average_available >= assigned_bandwidth >> 1
-                {
+            }
+        }
+        self.send_immediate = false;
+        if self.check_if_new_round(job.available_tokens) {
+            let discovery = self.neighbor_discovery.tick_and_check(
+                self.neighbors_count(),
+                average_available,
+                job.assigned_bandwidth,
+            );
+            if let Some(status) = discovery {
+                if let DiscoveryStatus::Started(_count) = status {
                     eprintln!("Gnome requesting PubIPs");
                     self.pending_conn_requests.push_front(ConnRequest {
                         conn_id: 0,
@@ -2371,76 +2890,37 @@ impl Gnome {
                         self.id,
                     ));
                 }
-                timer = Instant::now();
-                self.timeout_duration = Duration::from_millis(500);
-            } else if timeout && !have_responsive_neighbors {
-                loops_with_no_reply += 1;
-                if loops_with_no_reply >= 5 {
-                    loops_with_no_reply = 0;
-                    break_the_loop = true;
-                    if !self.slow_neighbors.is_empty() {
-                        eprintln!("Timed out multiple times, droping slow neighbors…");
-                        let slow = std::mem::take(&mut self.slow_neighbors);
-                        for neighbor in slow {
-                            self.drop_neighbor(neighbor.id);
-                        }
-                    }
-                }
+                self.discovery_status(status);
             }
+        }
+        job.timer = self.clock.now();
+        self.timeout_duration = Duration::from_millis(500);
+    }
 
-            if break_the_loop {
-                let _ = self.mgr_sender.send(GnomeToManager::Disconnected(
-                    self.swarm.id,
-                    self.swarm.name.clone(),
-                ));
-                break;
-            };
-
-            if was_loop_iteration_busy {
-                sleep_nsec >>= 2;
-                // print!("d ");
-                if sleep_nsec < min_sleep_nsec {
-                    sleep_nsec = min_sleep_nsec;
-                }
-            } else {
-                sleep_nsec <<= 1;
-                // print!("i ");
-                if sleep_nsec > max_sleep_nsec {
-                    sleep_nsec = max_sleep_nsec;
+    // Returns true when we have timed out too many times
+    // and should disconnect from swarm
+    fn turn_unanswered(&mut self, job: &mut JobState) -> bool {
+        job.loops_with_no_reply += 1;
+        if job.loops_with_no_reply < 5 {
+            return false;
+        }
+        job.loops_with_no_reply = 0;
+        if self.neighbors.has_any(&[NeighborStatus::Slow]) {
+            eprintln!("Timed out multiple times, droping slow neighbors…");
+            for n_id in self.neighbors.ids(&[NeighborStatus::Slow]) {
+                if self.drop_neighbor(n_id).is_some() {
+                    self.neighbor_dropped(n_id, DropReason::Unresponsive);
                 }
             }
-        } //loop
-          // eprintln!("Gnome is done");
+        }
+        true
     }
+
     pub fn has_any_neighbors(&self) -> bool {
-        if !self.refreshed_neighbors.is_empty() {
-            return true;
-        } else if !self.fast_neighbors.is_empty() {
-            return true;
-        } else if !self.new_neighbors.is_empty() {
-            return true;
-        } else if !self.slow_neighbors.is_empty() {
-            return true;
-        }
-        false
+        !self.neighbors.is_empty()
     }
     pub fn has_neighbor(&self, id: GnomeId) -> bool {
-        for neighbor in &self.slow_neighbors {
-            if neighbor.id == id {
-                return true;
-            }
-        }
-        for neighbor in &self.fast_neighbors {
-            if neighbor.id == id {
-                return true;
-            }
-        }
-        for neighbor in &self.refreshed_neighbors {
-            if neighbor.id == id {
-                return true;
-            }
-        }
-        false
+        self.neighbors.get(id, &NeighborStatus::ACTIVE).is_some()
     }
     fn get_shared_sender(
         &self,
@@ -2453,41 +2933,16 @@ impl Gnome {
             Receiver<WrappedMessage>,
         )>,
     > {
-        for neighbor in &self.fast_neighbors {
-            if neighbor.id == neighbor_id {
-                return Some(neighbor.get_shared_sender());
-            }
-        }
-        for neighbor in &self.refreshed_neighbors {
-            if neighbor.id == neighbor_id {
-                return Some(neighbor.get_shared_sender());
-            }
-        }
-        for neighbor in &self.slow_neighbors {
-            if neighbor.id == neighbor_id {
-                return Some(neighbor.get_shared_sender());
-            }
-        }
-        for neighbor in &self.new_neighbors {
-            if neighbor.id == neighbor_id {
-                return Some(neighbor.get_shared_sender());
-            }
-        }
-        None
+        self.neighbors
+            .get(neighbor_id, &NeighborStatus::ALL)
+            .map(|neighbor| neighbor.get_shared_sender())
     }
 
     fn collect_active_neighbor_ids(&self) -> HashSet<GnomeId> {
-        let mut n_ids = HashSet::new();
-        // for neighbor in &self.slow_neighbors {
-        //     }
-        // }
-        for neighbor in &self.fast_neighbors {
-            n_ids.insert(neighbor.id);
-        }
-        for neighbor in &self.refreshed_neighbors {
-            n_ids.insert(neighbor.id);
-        }
-        n_ids
+        self.neighbors
+            .iter(&NeighborStatus::RESPONSIVE)
+            .map(|neighbor| neighbor.id)
+            .collect()
     }
     fn notify_mgr_about_neighbors(&self) {
         let s_id = self.swarm.id;
@@ -2535,14 +2990,21 @@ impl Gnome {
                 n_ids,
             ));
         }
-        if self.chill_out.0 || (self.fast_neighbors.is_empty() && self.slow_neighbors.is_empty()) {
+        if self.tracer.is_some() {
+            neighbor.set_tracer(self.tracer.clone());
+        }
+        if self.chill_out.0
+            || !self
+                .neighbors
+                .has_any(&[NeighborStatus::Fast, NeighborStatus::Slow])
+        {
             eprintln!(
                 "{} ADD {} (chilling or no neighbors around)",
                 self.swarm.id, neighbor.id
             );
-            self.fast_neighbors.push(neighbor);
+            self.neighbors.insert(neighbor, NeighborStatus::Fast);
         } else {
-            self.new_neighbors.push(neighbor);
+            self.neighbors.insert(neighbor, NeighborStatus::New);
         }
         if !swarms_set.is_empty() {
             let _ = self
@@ -2550,12 +3012,28 @@ impl Gnome {
                 .send(GnomeToManager::NeighboringSwarms(self.swarm.id, swarms_set));
         }
     }
+    // Tracer is shared by all our neighbors, we record our own decisions with it too
+    fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        if let Some(old_tracer) = &self.tracer {
+            old_tracer.flush();
+        }
+        for neighbor in self.neighbors.iter_mut(&NeighborStatus::ALL) {
+            neighbor.set_tracer(tracer.clone());
+        }
+        self.tracer = tracer;
+        self.trace(TraceEvent::Accepted(
+            self.next_state.last_accepted_message.clone(),
+        ));
+    }
+
+    fn trace(&self, event: TraceEvent) {
+        if let Some(tracer) = &self.tracer {
+            tracer.record(self.id, &event);
+        }
+    }
+
     fn send_noop_from_a_neighbor(&self) {
-        if let Some(neighbor) = self.fast_neighbors.first() {
-            neighbor.send_no_op();
-        } else if let Some(neighbor) = self.refreshed_neighbors.first() {
-            neighbor.send_no_op();
-        } else if let Some(neighbor) = self.slow_neighbors.first() {
+        if let Some(neighbor) = self.neighbors.iter(&NeighborStatus::ACTIVE).next() {
             neighbor.send_no_op();
         }
     }
@@ -2565,41 +3043,14 @@ impl Gnome {
             //TODO: select best candidate to say farewell
             return None;
         }
-        if let Some(index) = self.fast_neighbors.iter().position(|x| x.id == neighbor_id) {
-            return Some(self.fast_neighbors.remove(index));
-        }
-        if let Some(index) = self.slow_neighbors.iter().position(|x| x.id == neighbor_id) {
-            return Some(self.slow_neighbors.remove(index));
-        }
-        if let Some(index) = self
-            .refreshed_neighbors
-            .iter()
-            .position(|x| x.id == neighbor_id)
-        {
-            return Some(self.refreshed_neighbors.remove(index));
-        }
-        if let Some(index) = self.new_neighbors.iter().position(|x| x.id == neighbor_id) {
-            return Some(self.new_neighbors.remove(index));
-        }
-        None
+        self.neighbors.remove(neighbor_id)
     }
     pub fn drop_any_neighbor(&mut self) -> Option<Neighbor> {
-        //TODO: first search
-        if let Some(index) = self.slow_neighbors.iter().position(|x| x.can_be_dropped()) {
-            return Some(self.slow_neighbors.remove(index));
-        }
-        if let Some(index) = self.fast_neighbors.iter().position(|x| x.can_be_dropped()) {
-            return Some(self.fast_neighbors.remove(index));
-        }
-        if let Some(index) = self
-            .refreshed_neighbors
-            .iter()
-            .position(|x| x.can_be_dropped())
-        {
-            return Some(self.refreshed_neighbors.remove(index));
-        }
-        if let Some(index) = self.new_neighbors.iter().position(|x| x.can_be_dropped()) {
-            return Some(self.new_neighbors.remove(index));
+        let candidate = self
+            .neighbors
+            .worst(&NeighborStatus::ALL, |x| x.can_be_dropped());
+        if let Some(neighbor_id) = candidate {
+            return self.neighbors.remove(neighbor_id);
         }
         None
     }
@@ -2619,28 +3070,36 @@ impl Gnome {
         // }
         let keep_alive = message.set_payload(Payload::KeepAlive(available_tokens));
         let keep_alive_len = 43 + keep_alive.len();
-        for neighbor in &mut self.fast_neighbors {
+        let now = self.clock.now();
+        let mut sent = vec![];
+        for neighbor in self.neighbors.iter_mut(&[NeighborStatus::Fast]) {
             if neighbor.header == message.header {
                 eprintln!("{} >>> {}", self.swarm.id, keep_alive);
                 // println!("Sending KA only");
                 neighbor.send_out(keep_alive.clone());
-                tokens_used += keep_alive_len;
+                sent.push((neighbor.id, keep_alive_len));
             } else {
                 eprintln!("{} >/> {}", self.swarm.id, message);
                 neighbor.send_out(message.clone());
-                tokens_used += message_len;
+                sent.push((neighbor.id, message_len));
             }
+            neighbor.mark_sent(now);
         }
-        for neighbor in &mut self.slow_neighbors {
+        for neighbor in self.neighbors.iter_mut(&[NeighborStatus::Slow]) {
             if neighbor.header == message.header {
                 eprintln!("{} >s> {}", self.swarm.id, keep_alive);
                 neighbor.send_out(keep_alive.clone());
-                tokens_used += keep_alive_len;
+                sent.push((neighbor.id, keep_alive_len));
             } else {
                 eprintln!("{} >S> {}", self.swarm.id, message);
                 neighbor.send_out(message.clone());
-                tokens_used += message_len;
+                sent.push((neighbor.id, message_len));
             }
+            neighbor.mark_sent(now);
+        }
+        for (n_id, bytes) in sent {
+            tokens_used += bytes;
+            self.neighbors.add_sent(n_id, bytes as u64);
         }
         tokens_used as u64
     }
@@ -2670,66 +3129,61 @@ impl Gnome {
     fn presync_with_swarm(&mut self, available_bandwith: u64) {
         eprintln!("{} In presync", self.swarm.id);
         // let mut remote_id = GnomeId(0);
-        let response_opt = if let Some(neighbor) = self.fast_neighbors.iter_mut().next() {
+        self.create_genesis();
+        // We ask a few neighbors for SwarmSync, but only first one sends us
+        // all registries. The rest is there to cross-check a founder.
+        let fast_count = self.neighbors.count(NeighborStatus::Fast);
+        let mut responses = vec![];
+        for (i, neighbor) in self
+            .neighbors
+            .iter_mut(&[NeighborStatus::Fast, NeighborStatus::Slow])
+            .take(PRESYNC_NEIGHBORS)
+            .enumerate()
+        {
+            let full_sync = i == 0;
             eprintln!(
-                "{} {} Sending SyncReq to {} ",
-                self.swarm.id, self.swarm.name, neighbor.id,
+                "{} {} Sending SyncReq to {} (full: {})",
+                self.swarm.id, self.swarm.name, neighbor.id, full_sync
             );
             let _ = neighbor.send_out_cast(CastMessage::new_request(
                 NeighborRequest::SwarmSyncRequest(SwarmSyncRequestParams {
-                    sync_key_reg: true,
-                    sync_capability: true,
-                    sync_policy: true,
-                    sync_broadcast: true,
-                    sync_multicast: true,
+                    sync_key_reg: full_sync,
+                    sync_capability: full_sync,
+                    sync_policy: full_sync,
+                    sync_broadcast: full_sync,
+                    sync_multicast: full_sync,
                     // app_root_hash,
                 }),
             ));
-            if let Ok(sync_response_option) = neighbor.recv_sync(Duration::from_secs(2)) {
-                eprintln!("Received SyncResponse: {:?}", sync_response_option);
+            let timeout = if i < fast_count {
+                Duration::from_secs(2)
+            } else {
+                Duration::from_secs(20)
+            };
+            if let Ok(Some(NeighborResponse::SwarmSync(sync_response))) =
+                neighbor.recv_sync(timeout, &*self.clock)
+            {
+                eprintln!("Received SyncResponse: {:?}", sync_response);
                 // TODO: not sure if reversing next_state update with start_new_round
                 // inside neighbor.recv_sync is fine
-                self.next_state.update(neighbor);
-                // remote_id = neighbor.id;
-                sync_response_option
-            } else {
-                eprintln!("No response received");
-                None
-            }
-        } else if let Some(neighbor) = self.slow_neighbors.iter_mut().next() {
-            eprintln!(
-                "SID-{} Slow {} Sending SwarmSyncRequest ",
-                self.swarm.id.0, neighbor.id,
-            );
-            let _ = neighbor.send_out_cast(CastMessage::new_request(
-                NeighborRequest::SwarmSyncRequest(SwarmSyncRequestParams {
-                    sync_key_reg: true,
-                    sync_capability: true,
-                    sync_policy: true,
-                    sync_broadcast: true,
-                    sync_multicast: true,
-                    // app_root_hash,
-                }),
-            ));
-            if let Ok(sync_response_option) = neighbor.recv_sync(Duration::from_secs(20)) {
-                self.next_state.update(neighbor);
-                // remote_id = neighbor.id;
-                sync_response_option
+                if let Some(view) = neighbor.take_view() {
+                    neighbor.trace(TraceEvent::Updated);
+                    self.next_state.update(&view);
+                }
+                responses.push((neighbor.id, full_sync, sync_response));
             } else {
-                None
+                eprintln!("No response received from {}", neighbor.id);
             }
-        } else {
+        }
+        if responses.is_empty() {
             eprintln!(
-                "SID-{} Not Sending SwarmSyncRequest - no neighbors",
+                "SID-{} No SwarmSyncResponse - no neighbors",
                 self.swarm.id.0
             );
-            None
-        };
-        // eprintln!("SID-{} Response opt: {:?}", self.swarm.id.0, response_opt);
-        // TODO: make use of capability_size, policy_size, b_cast_size, m_cast_size, more_keys_follow
-        // TODO: in case we join a swarm which has a *cast originating from our gnome
-        //       we need to initialize all necessary piping to be able to send through it
-        if let Some(NeighborResponse::SwarmSync(mut swarm_sync_response)) = response_opt {
+        }
+        let response_opt =
+            choose_sync_response(&mut self.swarm, &mut self.founder_disputed, responses);
+        if let Some((sync_source, full_sync, mut swarm_sync_response)) = response_opt {
             eprintln!(
                 "App sync ST: {} Diameter: {} Round: {} Key#: {}, chill: {}",
                 swarm_sync_response.swarm_time,
@@ -2765,13 +3219,31 @@ impl Gnome {
             if swarm_sync_response.chill_phase > 0 {
                 eprintln!("Into chill {}", swarm_sync_response.chill_phase);
                 self.chill_out.0 = true;
-                self.chill_out.1 = Instant::now() - self.chill_out_max
+                self.chill_out.1 = self.clock.now().saturating_sub(self.chill_out_max)
                     + Duration::from_millis(swarm_sync_response.chill_phase as u64);
             } else {
                 // eprintln!("no chill ");
                 self.chill_out.0 = false;
             }
-        } else if response_opt.is_none() {
+            if !full_sync {
+                // Neighbor that gave us all registries was outvoted,
+                // so we ask for them the one we trust
+                if let Some(neighbor) = self
+                    .neighbors
+                    .get_mut(sync_source, &[NeighborStatus::Fast, NeighborStatus::Slow])
+                {
+                    let _ = neighbor.send_out_cast(CastMessage::new_request(
+                        NeighborRequest::SwarmSyncRequest(SwarmSyncRequestParams {
+                            sync_key_reg: true,
+                            sync_capability: true,
+                            sync_policy: true,
+                            sync_broadcast: true,
+                            sync_multicast: true,
+                        }),
+                    ));
+                }
+            }
+        } else {
             // let synced = app_root_hash == 0;
             let _ = self.sender.send(GnomeToApp::SwarmReady(
                 self.swarm.name.clone(),
@@ -2804,38 +3276,30 @@ impl Gnome {
             // }
             // println!("Sync response: {}", response);
             self.send_all(available_bandwith);
+        }
+    }
+
+    fn create_genesis(&mut self) {
+        if self.id != self.swarm.name.founder || self.swarm.founder_verified() {
             return;
+        }
+        let mut bytes = self.swarm.name.as_bytes();
+        if let Ok(signature) = (self.sign)(&self.priv_key_pem, SwarmTime(0), &mut bytes) {
+            self.swarm.set_genesis(Genesis {
+                founder: self.id,
+                pub_key: self.pub_key_bytes.clone(),
+                signature,
+            });
         } else {
-            eprintln!("unexpected Response opt: {:?}", response_opt);
-            let _ = self.sender.send(GnomeToApp::SwarmReady(
-                self.swarm.name.clone(),
-                self.id == self.swarm.name.founder, //TODO: not sure if this is ok
-            ));
+            eprintln!("Failed to sign Genesis");
         }
     }
 
     fn swap_neighbors(&mut self) {
         // println!("Swapping neighbors");
-        // TODO: we do not want to drop slow neighbors,
-        //       only increase some counters to indicate what % of time
-        //       they could not keep up
-        //       user should decide to drop a neighbor
-        //       or set a policy to drop a neighbor when certain dgram loss threashold
-        //       is crossed
-        for neighbor in &mut self.slow_neighbors {
-            // TODO: here we should also check if given neighbor
-            //       is a source for any multicast and
-            //       maybe change it to some other neighbor
-            neighbor.add_timeout();
-        }
-        let fast_n = std::mem::take(&mut self.fast_neighbors);
-        for neighbor in fast_n {
-            self.slow_neighbors.push(neighbor);
-        }
-        self.fast_neighbors = std::mem::replace(
-            &mut self.refreshed_neighbors,
-            Vec::with_capacity(DEFAULT_NEIGHBORS_PER_GNOME),
-        );
+        // Slow neighbors are not dropped here, we only count
+        // what they missed and let NeighborRetention decide at round end
+        self.neighbors.end_turn();
         // TODO: if we have only slow_neighbors and no fast/refreshed
         //       neighbors - this is an indication that something is
         //       wrong with our network connection
@@ -2848,62 +3312,78 @@ impl Gnome {
     }
 
     fn get_other_neighbor_id_than(&self, n_ids: &Vec<GnomeId>) -> Option<GnomeId> {
-        for neighbor in &self.refreshed_neighbors {
-            if !n_ids.contains(&neighbor.id) {
-                return Some(neighbor.id);
-            }
-        }
-        for neighbor in &self.fast_neighbors {
-            if !n_ids.contains(&neighbor.id) {
-                return Some(neighbor.id);
-            }
-        }
         //TODO: maybe skip searching thru slow neighbors?
-        for neighbor in &self.slow_neighbors {
-            if !n_ids.contains(&neighbor.id) {
-                return Some(neighbor.id);
-            }
-        }
-        None
+        self.neighbors
+            .iter(&[
+                NeighborStatus::Refreshed,
+                NeighborStatus::Fast,
+                NeighborStatus::Slow,
+            ])
+            .find(|neighbor| !n_ids.contains(&neighbor.id))
+            .map(|neighbor| neighbor.id)
     }
-    fn send_neighbor_response(&mut self, neighbor_id: GnomeId, response: NeighborResponse) -> bool {
-        for neighbor in &mut self.refreshed_neighbors {
-            if neighbor.id == neighbor_id {
-                // neighbor.add_requested_data(response);
-                let _ = neighbor.send_out_cast(CastMessage::new_response(response));
-                return true;
+    // Consensus messages are sent right away, everything else waits in
+    // neighbors' outboxes and is sent as long as their buckets allow
+    // Returns tokens used
+    fn flush_outboxes(&mut self) -> u64 {
+        let mut sent = vec![];
+        for neighbor in self.neighbors.iter_mut(&NeighborStatus::ALL) {
+            let tokens = self.token_buckets.available(neighbor.id);
+            let tokens_used = neighbor.flush_outbox(tokens, &self.traffic_shares);
+            if tokens_used > 0 {
+                self.token_buckets.charge(neighbor.id, tokens_used);
+                sent.push((neighbor.id, tokens_used));
             }
         }
-        for neighbor in &mut self.fast_neighbors {
-            if neighbor.id == neighbor_id {
-                // neighbor.add_requested_data(response);
-                let _ = neighbor.send_out_cast(CastMessage::new_response(response));
-                return true;
-            }
+        let mut total_tokens_used = 0;
+        for (n_id, tokens_used) in sent {
+            total_tokens_used += tokens_used;
+            self.neighbors.add_sent(n_id, tokens_used);
         }
-        for neighbor in &mut self.slow_neighbors {
-            if neighbor.id == neighbor_id {
-                // neighbor.add_requested_data(response);
-                let _ = neighbor.send_out_cast(CastMessage::new_response(response));
-                return true;
-            }
+        total_tokens_used
+    }
+
+    fn report_bandwidth(&mut self, job: &JobState) {
+        let period_ms = job.band_mon.period_time().as_millis().max(1) as u64;
+        let usage = BandwidthUsage {
+            assigned: job.assigned_bandwidth,
+            average: job.band_mon.average() * 1000 / period_ms,
+            neighbors: self.neighbors.take_sent(),
+        };
+        let _ = self
+            .mgr_sender
+            .send(GnomeToManager::BandwidthUsage(self.swarm.id, usage.clone()));
+        let _ = self.sender.send(GnomeToApp::BandwidthUsage(usage));
+    }
+
+    fn send_neighbor_response(&mut self, neighbor_id: GnomeId, response: NeighborResponse) -> bool {
+        if let Some(neighbor) = self.neighbors.get_mut(neighbor_id, &NeighborStatus::ACTIVE) {
+            // neighbor.add_requested_data(response);
+            neighbor.queue_cast(CastMessage::new_response(response));
+            return true;
         }
         eprintln!("Failed to send response");
         false
     }
 
-    fn send_neighbor_request(&mut self, id: GnomeId, request: NeighborRequest) -> bool {
-        for neighbor in &mut self.fast_neighbors {
-            if neighbor.id == id {
-                neighbor.request_data(request);
-                return true;
-            }
+    // Capabilities have changed, so some subscribers may no longer
+    // fulfill Requirements of casts they receive.
+    // Every gnome applies the same changes and checks only its own
+    // subscribers, so SubscribeDenied is not forwarded down the subtree.
+    fn deny_unauthorized_subscribers(&mut self) {
+        for (sub_id, is_bcast, cast_id) in self.swarm.remove_unauthorized_subscribers() {
+            eprintln!("{} no longer may subscribe {}", sub_id, cast_id.0);
+            self.send_neighbor_response(
+                sub_id,
+                NeighborResponse::SubscribeDenied(is_bcast, cast_id),
+            );
         }
-        for neighbor in &mut self.refreshed_neighbors {
-            if neighbor.id == id {
-                neighbor.request_data(request);
-                return true;
-            }
+    }
+
+    fn send_neighbor_request(&mut self, id: GnomeId, request: NeighborRequest) -> bool {
+        if let Some(neighbor) = self.neighbors.get_mut(id, &NeighborStatus::RESPONSIVE) {
+            neighbor.request_data(request);
+            return true;
         }
         false
     }
@@ -2912,74 +3392,45 @@ impl Gnome {
         if alt_sources.is_empty() {
             return None;
         }
-        let mut curr_pick = alt_sources[0];
-        let mut curr_bandwith = 0;
-        for neighbor in &self.refreshed_neighbors {
-            let n_id = neighbor.id;
-            if alt_sources.contains(&n_id) {
-                let n_bandwith = neighbor.available_bandwith;
-                if n_bandwith > curr_bandwith {
-                    curr_pick = n_id;
-                    curr_bandwith = n_bandwith;
-                }
-            }
-        }
-        for neighbor in &self.fast_neighbors {
-            let n_id = neighbor.id;
-            if alt_sources.contains(&n_id) {
-                let n_bandwith = neighbor.available_bandwith;
-                if n_bandwith > curr_bandwith {
-                    curr_pick = n_id;
-                    curr_bandwith = n_bandwith;
-                }
-            }
-        }
-        Some(curr_pick)
+        self.neighbors
+            .best(
+                &[NeighborStatus::Refreshed, NeighborStatus::Fast],
+                |neighbor| alt_sources.contains(&neighbor.id),
+            )
+            .or(Some(alt_sources[0]))
     }
 
-    fn neighbor_with_highest_bandwith(&self, exclude: Option<GnomeId>) -> GnomeId {
-        // eprintln!("Searching among {} neighbors", self.fast_neighbors.len());
-        let mut gnome_id = GnomeId::any();
-        let mut curr_max_band = 0;
-        let mut found = false;
-        for neighbor in &self.fast_neighbors {
-            // eprintln!("N band: {}", neighbor.available_bandwith);
-            if neighbor.available_bandwith >= curr_max_band {
-                if exclude.is_some_and(|e| e == neighbor.id) {
-                    continue;
-                }
-                gnome_id = neighbor.id;
-                curr_max_band = neighbor.available_bandwith;
-                found = true;
+    // Drops downstream are passed on source by source until they reach origin
+    fn report_cast_drops(&mut self, is_bcast: bool, cast_id: CastID, count: u32) {
+        match self.swarm.get_source(&cast_id, is_bcast) {
+            Some(source) if source == self.id => {
+                let _ = self
+                    .sender
+                    .send(GnomeToApp::CastDownstreamFull(is_bcast, cast_id, count));
             }
-        }
-        if !found {
-            for neighbor in &self.refreshed_neighbors {
-                // eprintln!("N band: {}", neighbor.available_bandwith);
-                if neighbor.available_bandwith >= curr_max_band {
-                    if exclude.is_some_and(|e| e == neighbor.id) {
-                        continue;
-                    }
-                    gnome_id = neighbor.id;
-                    curr_max_band = neighbor.available_bandwith;
-                }
+            Some(source) => {
+                let request = NeighborRequest::CastDropped(is_bcast, cast_id, count);
+                self.send_neighbor_request(source, request);
             }
+            None => eprintln!("No source to report drops of {} to", cast_id.0),
         }
-        gnome_id
+    }
+
+    fn best_neighbor(&self, exclude: Option<GnomeId>) -> GnomeId {
+        self.neighbors
+            .best(&NeighborStatus::RESPONSIVE, |neighbor| {
+                exclude != Some(neighbor.id)
+            })
+            .unwrap_or(GnomeId::any())
     }
 
     fn neighbor_with_enough_bandwith(&self, min_bandwith: u64) -> Option<GnomeId> {
-        // eprintln!("Searching among {} neighbors", self.fast_neighbors.len());
-        for neighbor in &self.fast_neighbors {
-            eprintln!("N band: {}", neighbor.available_bandwith);
-            if neighbor.available_bandwith >= min_bandwith {
-                return Some(neighbor.id);
-            }
-        }
-        None
+        self.neighbors.best(&[NeighborStatus::Fast], |neighbor| {
+            neighbor.available_bandwith >= min_bandwith
+        })
     }
     fn concat_neighbors(&mut self) {
-        self.fast_neighbors.append(&mut self.refreshed_neighbors);
+        self.neighbors.merge_refreshed();
     }
 
     fn update_state(&mut self) {
@@ -3000,25 +3451,14 @@ impl Gnome {
         // a direct Subscribe message to selected neighbor, with optional
         // Unsubscribe message to current source. Source gnome should
         // respond with Subscribed message.
-        let (n_st, n_neigh, n_head, n_payload) = self.next_state.next_params();
-        // println!("Next params: {} {} {} {}", n_st, n_neigh, n_head, n_payload);
-        if let Some(sub) = n_st.0.checked_sub(self.swarm_time.0) {
-            if sub >= self.swarm.diameter.0 + self.swarm.diameter.0 {
-                eprintln!("Not updating neighborhood when catching up with swarm");
-            } else {
-                self.neighborhood = n_neigh;
-            }
-        } else {
-            self.neighborhood = n_neigh;
-        }
-        self.swarm_time = n_st;
-        self.header = n_head;
-
-        self.payload = n_payload;
-        if self.header == Header::Sync
-            && self.round_start > SwarmTime(0)
-            && self.neighborhood.0 <= 1
-        {
+        let (next, payload) = self
+            .next_state
+            .next_position(self.position(), self.swarm.diameter);
+        self.swarm_time = next.swarm_time;
+        self.neighborhood = next.neighborhood;
+        self.header = next.header;
+        self.payload = payload;
+        if next.can_propose() {
             if let Some(mut proposal) = self.proposals.pop_back() {
                 // We are submitting new proposal, so we have to reset NHood
                 self.neighborhood = Neighborhood(0);
@@ -3071,115 +3511,11 @@ impl Gnome {
                 self.next_state.header = self.header;
                 self.next_state.payload = self.payload.clone();
 
-                if self.header.is_reconfigure() {
-                    if let Payload::Reconfigure(
-                        _signature,
-                        Configuration::StartBroadcast(g_id, c_id),
-                    ) = &self.payload
-                    {
-                        self.next_state.change_config = ChangeConfig::AddBroadcast {
-                            id: *c_id,
-                            origin: *g_id,
-                            source: *g_id,
-                            filtered_neighbors: vec![],
-                            turn_ended: false,
-                        };
-                    } else if let Payload::Reconfigure(
-                        _signature,
-                        Configuration::StartMulticast(g_id, c_id),
-                    ) = &self.payload
-                    {
-                        self.next_state.change_config = ChangeConfig::AddMulticast {
-                            id: *c_id,
-                            origin: *g_id,
-                            source: *g_id,
-                            filtered_neighbors: vec![],
-                            turn_ended: false,
-                        };
-                        // }
-                    } else if let Payload::Reconfigure(
-                        _signature,
-                        Configuration::InsertPubkey(id, key),
-                    ) = &self.payload
-                    {
-                        self.next_state.change_config = ChangeConfig::InsertPubkey {
-                            id: *id,
-                            key: key.clone(),
-                            turn_ended: false,
-                        };
-                    } else if let Payload::Reconfigure(
-                        _sign,
-                        Configuration::EndBroadcast(_g_id, c_id),
-                    ) = &self.payload
-                    {
-                        self.next_state.change_config = ChangeConfig::RemoveBroadcast {
-                            id: *c_id,
-                            turn_ended: false,
-                        };
-                    } else if let Payload::Reconfigure(
-                        _sign,
-                        Configuration::EndMulticast(_g_id, c_id),
-                    ) = &self.payload
-                    {
-                        self.next_state.change_config = ChangeConfig::RemoveMulticast {
-                            id: *c_id,
-                            turn_ended: false,
-                        };
-                    } else if let Payload::Reconfigure(
-                        _sign,
-                        Configuration::ChangeDiameter(g_id, new_diameter),
-                    ) = &self.payload
-                    {
-                        // eprintln!("NSCK: SetDiameter({})", new_diameter);
-                        self.next_state.change_config = ChangeConfig::SetDiameter {
-                            originator: *g_id,
-                            new_value: SwarmTime(*new_diameter as u32),
-                            turn_ended: false,
-                        };
-                    } else if let Payload::Reconfigure(
-                        _sign,
-                        Configuration::SetRunningPolicy(g_id, policy, req),
-                    ) = &self.payload
-                    {
-                        self.next_state.change_config = ChangeConfig::SetPolicy {
-                            originator: *g_id,
-                            policy: *policy,
-                            req: req.clone(),
-                            turn_ended: false,
-                        };
-                    } else if let Payload::Reconfigure(
-                        _sign,
-                        Configuration::SetRunningCapability(g_id, cap, v_gids),
-                    ) = &self.payload
-                    {
-                        self.next_state.change_config = ChangeConfig::SetCapability {
-                            originator: *g_id,
-                            cap: *cap,
-                            members: v_gids.clone(),
-                            turn_ended: false,
-                        };
-                    } else if let Payload::Reconfigure(
-                        _sign,
-                        Configuration::SetRunningByteSet(g_id, b_id, bset),
-                    ) = &self.payload
-                    {
-                        self.next_state.change_config = ChangeConfig::SetByteSet {
-                            originator: *g_id,
-                            b_id: *b_id,
-                            bset: bset.clone(),
-                            turn_ended: false,
-                        };
-                    } else if let Payload::Reconfigure(
-                        _sign,
-                        Configuration::UserDefined(id, s_data),
-                    ) = &self.payload
+                if let Payload::Reconfigure(ref signature, ref config) = self.payload {
+                    if let Some(change) =
+                        ChangeConfig::from_configuration(config, self.id, signature.gnome_id())
                     {
-                        self.next_state.change_config = ChangeConfig::Custom {
-                            id: *id,
-                            signed_by: self.id,
-                            s_data: s_data.clone(),
-                            turn_ended: false,
-                        };
+                        self.next_state.change_config = change;
                     } else {
                         eprintln!("Not IMPLEMENTED for: {:?}", self.payload);
                     }
@@ -3190,39 +3526,38 @@ impl Gnome {
         }
     }
 
+    fn position(&self) -> RoundPosition {
+        RoundPosition {
+            swarm_time: self.swarm_time,
+            round_start: self.round_start,
+            neighborhood: self.neighborhood,
+            header: self.header,
+        }
+    }
+
     fn check_if_new_round(&mut self, available_tokens: u64) -> bool {
-        let all_gnomes_aware = self.neighborhood.0 as u32 >= self.swarm.diameter.0;
-        let finish_round =
-            self.swarm_time - self.round_start >= self.swarm.diameter + self.swarm.diameter;
-        if all_gnomes_aware || finish_round {
+        let outcome = self.position().outcome(self.swarm.diameter);
+        if outcome != TurnOutcome::Continue {
             // println!("New round");
-            if !self.slow_neighbors.is_empty() {
-                // TODO: we need to store it as gnome's attribute and allow for
-                //       user to change it (default is 87.5%)
-                // let drop_treshold: u8 = 7 * self.swarm_diameter.0 as u8;
-                let drop_treshold: u8 = 0;
-                let mut slow_neighbors = std::mem::take(&mut self.slow_neighbors);
-                while let Some(mut neighbor) = slow_neighbors.pop() {
-                    neighbor.shift_timeout();
-                    if neighbor.timeouts_count() < drop_treshold {
-                        self.slow_neighbors.push(neighbor);
-                    } else {
-                        eprintln!(
-                            "{} DROP {} as drop threshold exceeded",
-                            self.swarm.name, neighbor.id
-                        );
-                    }
-                }
-            }
-            for neighbor in &mut self.fast_neighbors {
+            for neighbor in self
+                .neighbors
+                .iter_mut(&[NeighborStatus::Fast, NeighborStatus::Slow])
+            {
                 neighbor.shift_timeout();
             }
+            self.apply_retention();
+            self.chain.expire(self.swarm_time);
+            for (requester, queried, names) in self.swarm_queries.expire(self.swarm_time) {
+                self.swarms_listed(requester, queried, names);
+            }
             let block_proposed = self.header.non_zero_block();
-            let reconfig = self.header.is_reconfigure();
-            if block_proposed || reconfig {
+            if outcome != TurnOutcome::Synced {
                 self.send_immediate = true;
-                if all_gnomes_aware {
+                if outcome == TurnOutcome::Accepted {
                     self.next_state.last_accepted_message = self.prepare_message(available_tokens);
+                    self.trace(TraceEvent::Accepted(
+                        self.next_state.last_accepted_message.clone(),
+                    ));
                     let payload =
                         std::mem::replace(&mut self.payload, Payload::KeepAlive(available_tokens));
                     if block_proposed {
@@ -3231,11 +3566,9 @@ impl Gnome {
                             if let Some((g_id, pub_key)) = signature.pubkey() {
                                 self.swarm.key_reg.insert(g_id, pub_key)
                             }
-                            let _res = self.sender.send(GnomeToApp::Block(
-                                block_id,
-                                data,
-                                signature.gnome_id(),
-                            ));
+                            let signer = signature.gnome_id();
+                            self.record_accepted(signature, block_id.0, true, data.clone().bytes());
+                            let _res = self.sender.send(GnomeToApp::Block(block_id, data, signer));
                             // println!("^^^ USER ^^^ NEW {} {:075}", block_id, data.0);
                         } else {
                             eprintln!("Can not send to user, payload not matching\n {:?}", payload);
@@ -3246,6 +3579,9 @@ impl Gnome {
                             if let Some((g_id, pub_key)) = signature.pubkey() {
                                 self.swarm.key_reg.insert(g_id, pub_key)
                             }
+                            let bytes = _conf.bytes();
+                            let digest = (self.sha_hash)(&bytes);
+                            self.record_accepted(signature, digest, false, bytes);
                             eprintln!(" c-k2: {:?}", _conf);
                         }
                         let change_config = std::mem::replace(
@@ -3257,10 +3593,12 @@ impl Gnome {
                             ChangeConfig::AddBroadcast {
                                 id,
                                 origin,
+                                descriptor,
                                 source,
                                 filtered_neighbors,
                                 ..
                             } => {
+                                self.swarm.set_cast_descriptor(true, id, origin, descriptor);
                                 let mut subscribers: HashMap<GnomeId, Sender<WrappedMessage>> =
                                     self.get_neighbor_ids_and_senders();
                                 // println!("origin: {}", origin);
@@ -3273,14 +3611,18 @@ impl Gnome {
                                 // println!("subs after filter: {:?}", subscribers);
                                 // println!("filtered neighbors: {:?}", filtered_neighbors);
                                 let (wrapped_message_sender, wrapped_message_receiver) = channel();
-                                let (cast_data_sender, cast_data_receiver) = channel();
+                                let (cast_data_sender, cast_data_receiver) = app_channel();
                                 if self.id == origin {
                                     eprintln!("I am origin!");
                                     let (internal_cast_data_sender, internal_cast_data_receiver) =
-                                        channel();
+                                        app_channel();
+                                    // Bounded, so that app's sender gets full
+                                    // when cast can not keep up
+                                    let (source_sender, source_receiver) =
+                                        sync_channel(MAX_CAST_BACKLOG);
                                     let b_cast = Multicast::new(
                                         origin,
-                                        (source, wrapped_message_receiver),
+                                        (source, source_receiver),
                                         filtered_neighbors,
                                         subscribers,
                                         Some(internal_cast_data_sender),
@@ -3289,7 +3631,7 @@ impl Gnome {
                                     self.insert_originating_broadcast(
                                         id,
                                         cast_data_receiver,
-                                        wrapped_message_sender,
+                                        source_sender,
                                     );
                                     self.swarm.insert_broadcast(id, b_cast);
                                     let _res = self.sender.send(GnomeToApp::BroadcastOrigin(
@@ -3327,10 +3669,13 @@ impl Gnome {
                             ChangeConfig::AddMulticast {
                                 id,
                                 origin,
+                                descriptor,
                                 source,
                                 filtered_neighbors,
                                 ..
                             } => {
+                                self.swarm
+                                    .set_cast_descriptor(false, id, origin, descriptor);
                                 let mut subscribers: HashMap<GnomeId, Sender<WrappedMessage>> =
                                     self.get_neighbor_ids_and_senders();
                                 // println!("origin: {}", origin);
@@ -3343,14 +3688,18 @@ impl Gnome {
                                 // println!("subs after filter: {:?}", subscribers);
                                 // println!("filtered neighbors: {:?}", filtered_neighbors);
                                 let (wrapped_message_sender, wrapped_message_receiver) = channel();
-                                let (cast_data_sender, cast_data_receiver) = channel();
+                                let (cast_data_sender, cast_data_receiver) = app_channel();
                                 if self.id == origin {
                                     eprintln!("I am multi origin!");
                                     let (internal_cast_data_sender, internal_cast_data_receiver) =
-                                        channel();
+                                        app_channel();
+                                    // Bounded, so that app's sender gets full
+                                    // when cast can not keep up
+                                    let (source_sender, source_receiver) =
+                                        sync_channel(MAX_CAST_BACKLOG);
                                     let m_cast = Multicast::new(
                                         origin,
-                                        (source, wrapped_message_receiver),
+                                        (source, source_receiver),
                                         filtered_neighbors,
                                         subscribers,
                                         Some(internal_cast_data_sender),
@@ -3359,7 +3708,7 @@ impl Gnome {
                                     self.insert_originating_multicast(
                                         id,
                                         cast_data_receiver,
-                                        wrapped_message_sender,
+                                        source_sender,
                                     );
                                     self.swarm.insert_multicast(id, m_cast);
                                     let _res = self.sender.send(GnomeToApp::MulticastOrigin(
@@ -3451,8 +3800,75 @@ impl Gnome {
                                     .sender
                                     .send(GnomeToApp::Reconfig(id, signed_by, s_data));
                             }
+                            ChangeConfig::RevokeOffender {
+                                reporter, evidence, ..
+                            } => {
+                                eprintln!(
+                                    "Received ChangeConfig::RevokeOffender({}) from {}",
+                                    evidence.offender, reporter
+                                );
+                                if evidence.verify(&self.swarm) {
+                                    let revoked = self.swarm.revoke_capabilities(evidence.offender);
+                                    let _res = self.sender.send(GnomeToApp::CapabilitiesRevoked(
+                                        evidence.offender,
+                                        revoked,
+                                    ));
+                                } else {
+                                    eprintln!("Equivocation evidence is not valid");
+                                }
+                            }
+                            ChangeConfig::TransferFounder {
+                                founder,
+                                new_founder,
+                                ..
+                            } => {
+                                eprintln!(
+                                    "Received ChangeConfig::TransferFounder({} -> {})",
+                                    founder, new_founder
+                                );
+                                if self.swarm.founder() == founder {
+                                    self.swarm.transfer_founder(new_founder);
+                                    let _res = self
+                                        .sender
+                                        .send(GnomeToApp::FounderChanged(founder, new_founder));
+                                } else {
+                                    eprintln!("{} is not a founder", founder);
+                                }
+                            }
+                            ChangeConfig::AddOwner { founder, owner, .. } => {
+                                eprintln!(
+                                    "Received ChangeConfig::AddOwner({}) from {}",
+                                    owner, founder
+                                );
+                                if self.swarm.founder() == founder {
+                                    self.swarm
+                                        .insert_capability(Capabilities::Owner, vec![owner]);
+                                } else {
+                                    eprintln!("{} is not a founder", founder);
+                                }
+                            }
+                            ChangeConfig::FounderSuccession {
+                                candidate,
+                                endorsements,
+                                ..
+                            } => {
+                                eprintln!(
+                                    "Received ChangeConfig::FounderSuccession({})",
+                                    candidate
+                                );
+                                if has_quorum(&self.swarm, candidate, &endorsements) {
+                                    let founder = self.swarm.founder();
+                                    self.swarm.transfer_founder(candidate);
+                                    let _res = self
+                                        .sender
+                                        .send(GnomeToApp::FounderChanged(founder, candidate));
+                                } else {
+                                    eprintln!("No quorum for succession of {}", candidate);
+                                }
+                            }
                             ChangeConfig::None => {}
                         }
+                        self.deny_unauthorized_subscribers();
                     }
                     let my_proposal = self.my_proposal.take();
                     if let Some(my_proposed_data) = my_proposal {
@@ -3546,7 +3962,7 @@ impl Gnome {
                     self.payload = Payload::KeepAlive(available_tokens);
                     self.chill_out.0 = true;
                     // TODO: probably we can merge chillout and timeout into one
-                    self.chill_out.1 = Instant::now();
+                    self.chill_out.1 = self.clock.now();
                 }
             // println!("We have got a Reconfig to parse");
             } else {
@@ -3555,6 +3971,9 @@ impl Gnome {
                 eprintln!("{} Sync swarm time {}", self.swarm.id, self.swarm_time);
                 self.round_start = self.swarm_time;
                 self.next_state.last_accepted_message = self.prepare_message(available_tokens);
+                self.trace(TraceEvent::Accepted(
+                    self.next_state.last_accepted_message.clone(),
+                ));
                 // println!("set N-0");
                 self.neighborhood = Neighborhood(0);
                 // println!("--------round start to: {}", self.swarm_time);
@@ -3609,38 +4028,47 @@ impl Gnome {
                 } else {
                     // eprintln!("Starting to chill… 2");
                     self.chill_out.0 = true;
-                    self.chill_out.1 = Instant::now();
+                    self.chill_out.1 = self.clock.now();
                 }
                 // At start of new round
                 // Flush awaiting neighbors
                 // We ignore msgs from new neighbors until start of next round
 
-                if !self.new_neighbors.is_empty() {
-                    let new_neighbors = std::mem::replace(
-                        &mut self.new_neighbors,
-                        Vec::with_capacity(DEFAULT_NEIGHBORS_PER_GNOME),
-                    );
-
+                if self.neighbors.has_any(&[NeighborStatus::New]) {
                     let msg = self.prepare_message(available_tokens);
-                    for mut neighbor in new_neighbors {
+                    let new_ids = self.neighbors.ids(&[NeighborStatus::New]);
+                    for neighbor in self.neighbors.iter_mut(&[NeighborStatus::New]) {
                         let _ = neighbor.try_recv(
                             self.next_state.last_accepted_message.clone(),
                             &mut self.swarm,
                         );
                         eprintln!("{} ADD {}", self.swarm.id, neighbor.id);
                         neighbor.send_out(msg.clone());
-                        self.fast_neighbors.push(neighbor);
+                        neighbor.mark_sent(self.clock.now());
+                    }
+                    for n_id in new_ids {
+                        self.neighbors.set_status(n_id, NeighborStatus::Fast);
                     }
                 }
             }
 
+            self.trace(TraceEvent::TurnEnded(
+                true,
+                self.header,
+                self.payload.clone(),
+            ));
             self.next_state
                 .reset_for_next_turn(true, self.header, self.payload.clone());
-            for neighbor in &mut self.fast_neighbors {
+            self.equivocations.prune(SwarmTime(
+                self.round_start
+                    .0
+                    .saturating_sub(self.swarm.diameter.0 + self.swarm.diameter.0),
+            ));
+            for neighbor in self.neighbors.iter_mut(&[NeighborStatus::Fast]) {
                 eprintln!("SNR4");
                 neighbor.start_new_round(self.swarm_time);
             }
-            for neighbor in &mut self.slow_neighbors {
+            for neighbor in self.neighbors.iter_mut(&[NeighborStatus::Slow]) {
                 eprintln!("SNR5");
                 neighbor.start_new_round(self.swarm_time);
             }
@@ -3652,6 +4080,11 @@ impl Gnome {
             }
             true
         } else {
+            self.trace(TraceEvent::TurnEnded(
+                false,
+                self.header,
+                self.payload.clone(),
+            ));
             self.next_state
                 .reset_for_next_turn(false, self.header, self.payload.clone());
             false
@@ -3660,29 +4093,26 @@ impl Gnome {
 
     fn try_recv(&mut self, fast: bool, break_the_loop: &mut bool) -> (bool, bool, bool, bool) {
         let mut any_data_processed = false;
-        let n_len = if fast {
-            self.fast_neighbors.len()
+        let status = if fast {
+            NeighborStatus::Fast
         } else {
-            self.slow_neighbors.len()
+            NeighborStatus::Slow
         };
-        if n_len == 0 {
+        let loop_ids = self.neighbors.ids(&[status]);
+        if loop_ids.is_empty() {
             return (false, false, false, any_data_processed);
         }
         let mut looped = false;
         let mut new_proposal_received = false;
-        let loop_neighbors = if fast {
-            std::mem::replace(
-                &mut self.fast_neighbors,
-                Vec::with_capacity(DEFAULT_NEIGHBORS_PER_GNOME),
-            )
-        } else {
-            std::mem::replace(
-                &mut self.slow_neighbors,
-                Vec::with_capacity(DEFAULT_NEIGHBORS_PER_GNOME),
-            )
-        };
-        for mut neighbor in loop_neighbors {
+        for n_id in loop_ids {
+            let mut entry = if let Some(entry) = self.neighbors.take(n_id) {
+                entry
+            } else {
+                continue;
+            };
+            let neighbor = &mut entry.neighbor;
             looped = true;
+            let mut misbehaved = false;
             while let Some(response) = neighbor.user_responses.pop_back() {
                 any_data_processed = true;
                 match response {
@@ -3693,16 +4123,50 @@ impl Gnome {
                         NeighborResponse::AlreadyConnected(id) => {
                             self.skip_neighbor(id);
                         }
+                        NeighborResponse::CastRepair(is_bcast, cast_id, seq, data) => {
+                            self.swarm.repair_cast(is_bcast, cast_id, seq, data);
+                        }
+                        NeighborResponse::SubscribeDenied(is_bcast, cast_id) => {
+                            // Our subscribers get SourceDrained, those still
+                            // allowed will find another source
+                            if self.swarm.get_source(&cast_id, is_bcast) == Some(neighbor.id) {
+                                let _ = self
+                                    .send_internal
+                                    .send(InternalMsg::UnsubscribeCast(is_bcast, cast_id));
+                                let _ = self
+                                    .sender
+                                    .send(GnomeToApp::SubscribeDenied(is_bcast, cast_id));
+                            }
+                        }
+                        NeighborResponse::Unicast(swarm_id, cast_id) => {
+                            if self.swarm.is_unicast_id_available(cast_id) {
+                                self.swarm.insert_unicast(cast_id);
+                                let send_d = self.unicasts.insert(cast_id, neighbor.id);
+                                let _res = self
+                                    .sender
+                                    .send(GnomeToApp::UnicastOrigin(swarm_id, cast_id, send_d));
+                            } else {
+                                eprintln!("Unicast id {} already taken", cast_id.0);
+                                neighbor.end_unicast(cast_id);
+                                neighbor.queue_cast(CastMessage::new_request(
+                                    NeighborRequest::EndUnicast(cast_id),
+                                ));
+                            }
+                        }
                         NeighborResponse::ForwardConnectResponse(net_set) => {
                             eprintln!("FCP< ForwardConnResponse: {:?}", net_set);
+                            self.discovery_status(DiscoveryStatus::Found(neighbor.id));
                             // for ns in net_set {
                             let _ = self.net_settings_send.send(net_set);
                             // }
                         }
+                        NeighborResponse::NeighboringSwarms(page, total, names) => {
+                            self.serve_neighboring_swarms(neighbor.id, page, total, names);
+                        }
                         NeighborResponse::ForwardConnectFailed => {
                             eprintln!("FCF<");
-                            // TODO build querying mechanism
-                            // TODO inform gnome's mechanism to ask another neighbor
+                            self.neighbor_discovery.failed();
+                            self.discovery_status(DiscoveryStatus::Failed(neighbor.id));
                         }
                         // TODO make use af app_sync_hash
                         // We should notify application layer about received
@@ -3711,10 +4175,20 @@ impl Gnome {
                         // if it is different we need to sync,
                         // if we have just joined then this means we are behind
                         NeighborResponse::SwarmSync(mut swarm_sync_response) => {
+                            if self.founder_disputed.contains(&neighbor.id) {
+                                eprintln!("Ignoring SwarmSync from disputed {}", neighbor.id);
+                                continue;
+                            }
+                            if let Some(genesis) = swarm_sync_response.genesis.take() {
+                                self.swarm.set_genesis(genesis);
+                            }
                             if swarm_sync_response.chill_phase > 0 {
                                 eprintln!("Into chill {}", swarm_sync_response.chill_phase);
                                 self.chill_out.0 = true;
-                                self.chill_out.1 = Instant::now() - self.chill_out_max
+                                self.chill_out.1 = self
+                                    .clock
+                                    .now()
+                                    .saturating_sub(self.chill_out_max)
                                     + Duration::from_millis(swarm_sync_response.chill_phase as u64);
                             } else {
                                 // eprintln!("no chill ");
@@ -3728,11 +4202,13 @@ impl Gnome {
                             self.round_start = swarm_sync_response.round_start;
                             self.swarm.set_founder(swarm_sync_response.founder);
                             self.swarm.swarm_type = swarm_sync_response.swarm_type;
-                            self.swarm.key_reg = KeyRegistry::from(&mut vec![
-                                swarm_sync_response.key_reg_size,
-                                0,
-                                0,
-                            ]);
+                            if self.chain.merging_with() != Some(neighbor.id) {
+                                self.swarm.key_reg = KeyRegistry::from(&mut vec![
+                                    swarm_sync_response.key_reg_size,
+                                    0,
+                                    0,
+                                ]);
+                            }
                             self.next_state.swarm_time = swarm_sync_response.swarm_time;
                             while let Some((id, pubkey)) = swarm_sync_response.key_reg_pairs.pop() {
                                 self.swarm.key_reg.insert(id, pubkey);
@@ -3750,9 +4226,30 @@ impl Gnome {
                         }
                         NeighborResponse::Subscribed(is_bcast, cast_id, origin, source) => {
                             let source = source.unwrap();
-                            let (send_d, recv_d) = channel();
+                            let (send_d, recv_d) = app_channel();
                             let (send_m, recv_m) = channel();
-                            let activated = if is_bcast {
+                            let activated = if let Some(old_source) = self
+                                .swarm
+                                .get_source(&cast_id, is_bcast)
+                                .filter(|old_source| *old_source != source)
+                            {
+                                // Cast moves to this neighbor, we keep our subscribers
+                                // and app's receiver, only source changes
+                                if is_bcast {
+                                    neighbor.activate_broadcast(cast_id, send_m);
+                                } else {
+                                    neighbor.activate_multicast(cast_id, send_m);
+                                }
+                                eprintln!(
+                                    "Cast {} moved from {} to {}",
+                                    cast_id.0, old_source, source
+                                );
+                                self.swarm.set_source(&cast_id, is_bcast, (source, recv_m));
+                                let request =
+                                    NeighborRequest::UnsubscribeRequest(is_bcast, cast_id);
+                                self.send_neighbor_request(old_source, request);
+                                continue;
+                            } else if is_bcast {
                                 self.activate_broadcast_at_neighbor(source, cast_id, send_m)
                             } else {
                                 false
@@ -3782,36 +4279,99 @@ impl Gnome {
                                 }
                             }
                         }
+                        NeighborResponse::KeyRegistrySync(..)
+                        | NeighborResponse::CapabilitySync(..)
+                        | NeighborResponse::PolicySync(..)
+                        | NeighborResponse::ChainProof(..)
+                            if self.founder_disputed.contains(&neighbor.id) =>
+                        {
+                            eprintln!("Ignoring registry sync from disputed {}", neighbor.id);
+                        }
                         NeighborResponse::KeyRegistrySync(chunk_no, total_chunks, mut pairs) => {
                             // TODO: we need to preserve key registry order!
                             while let Some((gnome_id, pubkey)) = pairs.pop() {
                                 self.swarm.key_reg.insert(gnome_id, pubkey);
                             }
                         }
-                        NeighborResponse::CapabilitySync(chunk_no, total_chunks, mut pairs) => {
+                        NeighborResponse::CapabilitySync(_chunk_no, _total_chunks, mut pairs) => {
                             // TODO: we also need to cover case when a capability
                             //       is split into two or more chunks
-                            while let Some((capability, ids)) = pairs.pop() {
-                                self.swarm.insert_capability(capability, ids);
+                            if let Some(merge) = self.chain.merge_mut(neighbor.id) {
+                                merge.sync_capabilities(pairs);
+                            } else {
+                                while let Some((capability, ids)) = pairs.pop() {
+                                    self.swarm.insert_capability(capability, ids);
+                                }
+                            }
+                        }
+                        NeighborResponse::PolicySync(chunk_no, total_chunks, mut pairs) => {
+                            if let Some(merge) = self.chain.merge_mut(neighbor.id) {
+                                merge.sync_policies(pairs);
+                                if chunk_no == total_chunks {
+                                    eprintln!(
+                                        "{} Merge with {} done",
+                                        self.swarm.name, neighbor.id
+                                    );
+                                    if let Some((old, new)) = self
+                                        .chain
+                                        .end_merge()
+                                        .and_then(|merge| merge.finish(&mut self.swarm))
+                                    {
+                                        let _res =
+                                            self.sender.send(GnomeToApp::FounderChanged(old, new));
+                                    }
+                                }
+                            } else {
+                                while let Some((policy, req)) = pairs.pop() {
+                                    self.swarm.policy_reg.insert(policy, req);
+                                }
                             }
                         }
-                        NeighborResponse::PolicySync(_chunk_no, _total_chunks, mut pairs) => {
-                            while let Some((policy, req)) = pairs.pop() {
-                                self.swarm.policy_reg.insert(policy, req);
+                        NeighborResponse::ChainProof(chunk_no, total_chunks, proof) => {
+                            if self
+                                .chain
+                                .add_proof(neighbor.id, chunk_no, total_chunks, proof)
+                            {
+                                let resolution = self.chain.resolve(
+                                    neighbor.id,
+                                    &self.swarm,
+                                    self.sha_hash,
+                                    self.swarm_time,
+                                );
+                                if resolution == Resolution::Misbehaved {
+                                    misbehaved = true;
+                                } else if let Resolution::RolledBack(rolled_back) = resolution {
+                                    eprintln!("{} Merging with {}", self.swarm.name, neighbor.id);
+                                    let _ =
+                                        self.sender.send(GnomeToApp::BlocksRolledBack(rolled_back));
+                                    neighbor.request_data(NeighborRequest::SwarmSyncRequest(
+                                        SwarmSyncRequestParams {
+                                            sync_key_reg: true,
+                                            sync_capability: true,
+                                            sync_policy: true,
+                                            sync_broadcast: true,
+                                            sync_multicast: true,
+                                        },
+                                    ));
+                                }
                             }
                         }
                         NeighborResponse::BroadcastSync(chunk_no, total_chunks, mut pairs) => {
-                            while let Some((c_id, origin)) = pairs.pop() {
+                            while let Some((c_id, origin, descriptor)) = pairs.pop() {
+                                self.swarm
+                                    .set_cast_descriptor(true, c_id, origin, descriptor);
                                 self.send_internal
-                                    .send(InternalMsg::SubscribeCast(true, c_id, origin))
+                                    .send(InternalMsg::SubscribeCast(true, c_id, origin, false))
                                     .unwrap();
                             }
                         }
                         NeighborResponse::MulticastSync(chunk_no, total_chunks, mut pairs) => {
-                            while let Some((_c_id, _origin)) = pairs.pop() {
+                            while let Some((c_id, origin, descriptor)) = pairs.pop() {
+                                self.swarm
+                                    .set_cast_descriptor(false, c_id, origin, descriptor);
                                 // TODO: do we really want to subscribe to all multicasts?
                                 // self.send_internal
-                                //     .send(InternalMsg::SubscribeCast(false, c_id, origin))
+                                //     .send(InternalMsg::SubscribeCast(false, c_id, origin, false))
                                 //     .unwrap();
                             }
                         }
@@ -3829,6 +4389,12 @@ impl Gnome {
                 self.next_state.last_accepted_message.clone(),
                 &mut self.swarm,
             );
+            let drop_me = drop_me || misbehaved;
+            for (round_start, signature, bytes) in neighbor.take_verified_payloads() {
+                if let Some(evidence) = self.equivocations.observe(round_start, signature, bytes) {
+                    self.report_equivocation(evidence);
+                }
+            }
             // println!("snd {}", pass);
             //     "{} srv:{} pass:{} new:{}",
             //     neighbor.id, served, sanity_passed, new_proposal
@@ -3839,34 +4405,37 @@ impl Gnome {
             if served {
                 // println!("Served!");
                 any_data_processed = true;
+                neighbor.mark_responded(self.clock.now());
                 if sanity_passed {
                     //TODO: this is wacky
                     if self.round_start.0 == 0 {
                         self.next_state.swarm_time = neighbor.swarm_time;
                     }
-                    self.next_state.update(&mut neighbor);
+                    if let Some(view) = neighbor.take_view() {
+                        neighbor.trace(TraceEvent::Updated);
+                        self.next_state.update(&view);
+                    }
                 }
                 if !drop_me {
-                    self.refreshed_neighbors.push(neighbor);
+                    entry.status = NeighborStatus::Refreshed;
+                    self.neighbors.restore(entry);
                 } else {
-                    eprintln!("{} Dropping a neighbor {}", self.swarm.name, neighbor.id);
+                    eprintln!("{} Dropping a neighbor {}", self.swarm.name, n_id);
+                    self.neighbor_dropped(n_id, DropReason::Misbehaved);
                     if !self.has_any_neighbors() {
                         *break_the_loop = true;
                     }
                 }
             } else if !drop_me {
-                if fast {
-                    self.fast_neighbors.push(neighbor);
-                } else {
-                    self.slow_neighbors.push(neighbor);
-                }
+                self.neighbors.restore(entry);
             } else {
-                eprintln!("{} Dropping  neighbor {}", self.swarm.name, neighbor.id);
+                eprintln!("{} Dropping  neighbor {}", self.swarm.name, n_id);
+                self.neighbor_dropped(n_id, DropReason::Misbehaved);
             }
         }
-        let refreshed_empty = self.refreshed_neighbors.is_empty();
-        let fast_empty = self.fast_neighbors.is_empty();
-        let slow_empty = self.slow_neighbors.is_empty();
+        let refreshed_empty = !self.neighbors.has_any(&[NeighborStatus::Refreshed]);
+        let fast_empty = !self.neighbors.has_any(&[NeighborStatus::Fast]);
+        let slow_empty = !self.neighbors.has_any(&[NeighborStatus::Slow]);
         let have_responsive_neighbors = !refreshed_empty || !fast_empty;
         if refreshed_empty && fast_empty && slow_empty {
             eprintln!("Can not advance with no neighbors around\n\n\n\n");
@@ -3892,6 +4461,112 @@ impl Gnome {
         }
     }
 
+    fn apply_retention(&mut self) {
+        self.neighbors.end_round();
+        let leaving = std::mem::take(&mut self.leaving);
+        for (n_id, reason) in leaving {
+            if self.swarm.casts_sourced_by(n_id).is_empty() {
+                self.retire_neighbor(n_id, reason);
+            } else {
+                self.leaving.push((n_id, reason));
+            }
+        }
+        for (n_id, reason) in self.neighbors.judge(&self.retention) {
+            if self.leaving.iter().any(|(l_id, _r)| *l_id == n_id) {
+                continue;
+            }
+            let active = self.neighbors.iter(&NeighborStatus::ACTIVE).count();
+            if active <= self.retention.min_neighbors {
+                eprintln!(
+                    "{} keeping {} ({:?}), only {} neighbors left",
+                    self.swarm.name, n_id, reason, active
+                );
+                break;
+            }
+            let casts = self.swarm.casts_sourced_by(n_id);
+            if casts.is_empty() {
+                self.retire_neighbor(n_id, reason);
+                continue;
+            }
+            for (is_bcast, cast_id) in casts {
+                eprintln!("Migrating cast {} away from {}", cast_id.0, n_id);
+                let _ = self
+                    .send_internal
+                    .send(InternalMsg::FindNewCastSource(is_bcast, cast_id, n_id));
+            }
+            self.leaving.push((n_id, reason));
+        }
+    }
+
+    fn retire_neighbor(&mut self, n_id: GnomeId, reason: DropReason) {
+        eprintln!(
+            "{} DROP {} {:?} {:?} score: {:?}",
+            self.swarm.name,
+            n_id,
+            reason,
+            self.neighbors.metrics(n_id),
+            self.neighbors.score(n_id)
+        );
+        self.neighbors.remove(n_id);
+        self.neighbor_dropped(n_id, reason);
+    }
+
+    // Casts we were receiving from dropped neighbor need a new source
+    fn neighbor_dropped(&mut self, n_id: GnomeId, reason: DropReason) {
+        self.leaving.retain(|(l_id, _r)| *l_id != n_id);
+        for (is_bcast, cast_id) in self.swarm.casts_sourced_by(n_id) {
+            eprintln!("Migrating cast {} away from {}", cast_id.0, n_id);
+            let _ = self
+                .send_internal
+                .send(InternalMsg::FindNewCastSource(is_bcast, cast_id, n_id));
+        }
+        for cast_id in self.unicasts.remove_neighbor(n_id) {
+            self.swarm.remove_unicast(cast_id);
+            let _ = self
+                .sender
+                .send(GnomeToApp::UnicastEnded(self.swarm.id, cast_id));
+        }
+        self.chain.neighbor_dropped(n_id);
+        let _ = self.sender.send(GnomeToApp::NeighborDropped(n_id, reason));
+    }
+
+    // Both sides of a unicast are closed, neighbor is told to do the same
+    fn end_unicast(&mut self, n_id: GnomeId, cast_id: CastID, notify: bool) {
+        self.swarm.remove_unicast(cast_id);
+        if let Some(neighbor) = self.neighbors.get_mut(n_id, &NeighborStatus::ALL) {
+            neighbor.end_unicast(cast_id);
+            if notify {
+                neighbor.queue_cast(CastMessage::new_request(NeighborRequest::EndUnicast(
+                    cast_id,
+                )));
+            }
+        }
+    }
+
+    fn report_equivocation(&mut self, evidence: Equivocation) {
+        eprintln!(
+            "{} signed two different proposals for {}",
+            evidence.offender, evidence.round_start
+        );
+        let _ = self.sender.send(GnomeToApp::EquivocationDetected(
+            evidence.offender,
+            evidence.round_start,
+        ));
+        if evidence.len_in_bytes() > MAX_EVIDENCE_LEN {
+            eprintln!(
+                "Evidence too big to be proposed: {}",
+                evidence.len_in_bytes()
+            );
+            return;
+        }
+        let config = Configuration::ReportEquivocation(self.id, evidence);
+        if self.swarm.check_config_policy(&self.id, config.as_ct()) {
+            self.proposals.push_back(Proposal::Config(config));
+        } else {
+            eprintln!("Not allowed to report equivocation");
+        }
+    }
+
     // We send a query to a single neighbor asking to provide us with a new neighbor.
     // We need to track what neighbors have been queried so that we do not query the
     // same neighbor over again, if all our neighbors have been asked we simply clean
@@ -3903,83 +4578,70 @@ impl Gnome {
     fn query_for_new_neighbors(&mut self, network_settings: Vec<u8>) {
         eprintln!("In query_for_new_neighbors");
         let request = NeighborRequest::ForwardConnectRequest(network_settings);
-        let mut request_sent = false;
-        for neighbor in &mut self.fast_neighbors {
-            if !self
-                .neighbor_discovery
-                .queried_neighbors
-                .contains(&neighbor.id)
-            {
-                neighbor.request_data(request.clone());
-                request_sent = true;
-                eprintln!("FCR> {:?} to {}", request, neighbor.id);
-                self.neighbor_discovery.queried_neighbors.push(neighbor.id);
-                break;
-            }
-        }
-        if !request_sent {
-            for neighbor in &mut self.slow_neighbors {
-                if !self
-                    .neighbor_discovery
-                    .queried_neighbors
-                    .contains(&neighbor.id)
-                {
-                    eprintln!("FCR> {:?} to {}", request, neighbor.id);
-                    neighbor.request_data(request);
-                    request_sent = true;
-                    self.neighbor_discovery.queried_neighbors.push(neighbor.id);
-                    break;
-                }
+        let queried = &mut self.neighbor_discovery.queried_neighbors;
+        // Neighbors that are members of many swarms are more likely to know
+        // gnomes outside of our neighborhood, among them we ask best one
+        let target = self
+            .neighbors
+            .iter(&[NeighborStatus::Fast, NeighborStatus::Slow])
+            .filter(|neighbor| !queried.contains(&neighbor.id))
+            .map(|neighbor| {
+                (
+                    neighbor.member_of_swarms.len(),
+                    self.neighbors.score(neighbor.id),
+                    neighbor.id,
+                )
+            })
+            .max_by_key(|(swarms, score, _id)| (*swarms, *score));
+        if let Some((_swarms, _score, n_id)) = target {
+            if let Some(neighbor) = self.neighbors.get_mut(n_id, &NeighborStatus::ALL) {
+                eprintln!("FCR> {:?} to {}", request, n_id);
+                neighbor.request_data(request);
             }
-        }
-        if !request_sent {
-            self.neighbor_discovery.queried_neighbors = vec![];
+            queried.push(n_id);
+            self.discovery_status(DiscoveryStatus::Querying(n_id));
+        } else {
+            queried.clear();
+            self.neighbor_discovery.failed();
+            self.discovery_status(DiscoveryStatus::Failed(self.id));
         }
     }
-    fn get_neighbor_ids_and_senders(&self) -> HashMap<GnomeId, Sender<WrappedMessage>> {
-        let mut ids = HashMap::new();
-        for neighbor in &self.fast_neighbors {
-            ids.insert(neighbor.id, neighbor.sender.clone());
-        }
-        for neighbor in &self.slow_neighbors {
-            ids.insert(neighbor.id, neighbor.sender.clone());
-        }
-        for neighbor in &self.refreshed_neighbors {
-            ids.insert(neighbor.id, neighbor.sender.clone());
-        }
-        for neighbor in &self.new_neighbors {
-            ids.insert(neighbor.id, neighbor.sender.clone());
-        }
-        ids
+
+    fn discovery_status(&self, status: DiscoveryStatus) {
+        eprintln!("{} discovery: {:?}", self.swarm.name, status);
+        let _ = self
+            .mgr_sender
+            .send(GnomeToManager::DiscoveryStatus(self.swarm.id, status));
     }
 
-    fn insert_originating_unicast(
-        &mut self,
-        id: CastID,
-        recv_d: Receiver<CastData>,
-        send_n: Sender<WrappedMessage>,
-    ) {
-        self.data_converters
-            .insert((CastType::Unicast, id), (recv_d, send_n));
+    fn get_neighbor_ids_and_senders(&self) -> HashMap<GnomeId, Sender<WrappedMessage>> {
+        self.neighbors
+            .iter(&NeighborStatus::ALL)
+            .map(|neighbor| (neighbor.id, neighbor.sender.clone()))
+            .collect()
     }
 
     fn insert_originating_broadcast(
         &mut self,
         id: CastID,
         recv_d: Receiver<CastData>,
-        send_n: Sender<WrappedMessage>,
+        send_n: SyncSender<WrappedMessage>,
     ) {
-        self.data_converters
-            .insert((CastType::Broadcast, id), (recv_d, send_n));
+        self.data_converters.insert(
+            (CastType::Broadcast, id),
+            FromApp::new(CastType::Broadcast, id, recv_d, send_n),
+        );
     }
     fn insert_originating_multicast(
         &mut self,
         id: CastID,
         recv_d: Receiver<CastData>,
-        send_n: Sender<WrappedMessage>,
+        send_n: SyncSender<WrappedMessage>,
     ) {
-        self.data_converters
-            .insert((CastType::Multicast, id), (recv_d, send_n));
+        self.data_converters.insert(
+            (CastType::Multicast, id),
+            FromApp::new(CastType::Multicast, id, recv_d, send_n),
+        );
     }
 
     fn remove_originating_broadcast(&mut self, id: CastID) {
@@ -3995,29 +4657,9 @@ impl Gnome {
         cast_id: CastID,
         sender: Sender<WrappedMessage>,
     ) -> bool {
-        for neighbor in &mut self.fast_neighbors {
-            if neighbor.id == id {
-                neighbor.activate_broadcast(cast_id, sender);
-                return true;
-            }
-        }
-        for neighbor in &mut self.refreshed_neighbors {
-            if neighbor.id == id {
-                neighbor.activate_broadcast(cast_id, sender);
-                return true;
-            }
-        }
-        for neighbor in &mut self.slow_neighbors {
-            if neighbor.id == id {
-                neighbor.activate_broadcast(cast_id, sender);
-                return true;
-            }
-        }
-        for neighbor in &mut self.new_neighbors {
-            if neighbor.id == id {
-                neighbor.activate_broadcast(cast_id, sender);
-                return true;
-            }
+        if let Some(neighbor) = self.neighbors.get_mut(id, &NeighborStatus::ALL) {
+            neighbor.activate_broadcast(cast_id, sender);
+            return true;
         }
         false
     }
@@ -4027,46 +4669,23 @@ impl Gnome {
         cast_id: CastID,
         sender: Sender<WrappedMessage>,
     ) -> bool {
-        for neighbor in &mut self.fast_neighbors {
-            if neighbor.id == id {
-                neighbor.activate_multicast(cast_id, sender);
-                return true;
-            }
-        }
-        for neighbor in &mut self.refreshed_neighbors {
-            if neighbor.id == id {
-                neighbor.activate_multicast(cast_id, sender);
-                return true;
-            }
-        }
-        for neighbor in &mut self.slow_neighbors {
-            if neighbor.id == id {
-                neighbor.activate_multicast(cast_id, sender);
-                return true;
-            }
-        }
-        for neighbor in &mut self.new_neighbors {
-            if neighbor.id == id {
-                neighbor.activate_multicast(cast_id, sender);
-                return true;
-            }
+        if let Some(neighbor) = self.neighbors.get_mut(id, &NeighborStatus::ALL) {
+            neighbor.activate_multicast(cast_id, sender);
+            return true;
         }
         false
     }
     fn neighbors_count(&self) -> usize {
-        self.refreshed_neighbors.len()
-            + self.fast_neighbors.len()
-            + self.slow_neighbors.len()
-            + self.new_neighbors.len()
+        self.neighbors.len()
     }
 }
 
-fn create_tokens(
+pub fn create_tokens(
     available_tokens: &mut u64,
     borrowed_tokens: &mut u64,
     time_period: Duration,
     available_bandwith: u64,
-) {
+) -> u64 {
     let last_loop_duration_ms = time_period.as_millis() as u64;
     let mut tokens_created = available_bandwith * last_loop_duration_ms / 1000;
     if *borrowed_tokens > 0 {
@@ -4083,6 +4702,7 @@ fn create_tokens(
     //     "Tokens borrowed: {}, available: {}",
     //     borrowed_tokens, available_tokens
     // );
+    tokens_created
 }
 fn update_tokens(available_tokens: &mut u64, borrowed_tokens: &mut u64, tokens_used: usize) {
     let tokens_used = tokens_used as u64;
@@ -4097,7 +4717,7 @@ fn update_tokens(available_tokens: &mut u64, borrowed_tokens: &mut u64, tokens_u
         *borrowed_tokens += tokens_used;
     }
 }
-fn calculate_min_token_period(available_bandwith: u64) -> Duration {
+pub fn calculate_min_token_period(available_bandwith: u64) -> Duration {
     if available_bandwith >= 1000000000 {
         Duration::from_nanos(1)
     } else if available_bandwith >= 100000000 {
@@ -10,6 +10,7 @@ use crate::Neighbor;
 // use crate::PortAllocationRule;
 use crate::SwarmID;
 use crate::SwarmName;
+use crate::SwarmTime;
 
 #[derive(Debug)]
 pub enum GnomeToManager {
@@ -24,4 +25,34 @@ pub enum GnomeToManager {
     ProvidePublicAddress(SwarmID, u8, GnomeId),
     SwarmBusy(SwarmID, bool),
     Disconnected(SwarmID, SwarmName),
+    ForkDetected(SwarmID, SwarmName, SwarmTime, Vec<GnomeId>),
+    DiscoveryStatus(SwarmID, DiscoveryStatus),
+    BandwidthUsage(SwarmID, BandwidthUsage),
+}
+
+// How much of its bandwith a gnome has been using
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BandwidthUsage {
+    // Bytes per second gnome is allowed to send
+    pub assigned: u64,
+    // Bytes per second gnome has been sending on average
+    pub average: u64,
+    // Bytes sent to each neighbor since previous report
+    pub neighbors: Vec<(GnomeId, u64)>,
+}
+
+// What gnome is doing to find new neighbors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveryStatus {
+    // Search started while we have given number of neighbors
+    Started(usize),
+    // Search postponed, we only have given bandwith available
+    BackedOff(u64),
+    // We asked given neighbor to introduce us to another gnome
+    Querying(GnomeId),
+    // Given neighbor found us a gnome to connect with
+    Found(GnomeId),
+    // Given neighbor could not help us, our own id means
+    // there was no one left to ask
+    Failed(GnomeId),
 }
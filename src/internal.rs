@@ -1,7 +1,7 @@
 use crate::{CastData, CastID, GnomeId, NeighborRequest, NeighborResponse};
 
 pub enum InternalMsg {
-    SubscribeCast(bool, CastID, GnomeId),
+    SubscribeCast(bool, CastID, GnomeId, bool), // last one is for history
     UnsubscribeCast(bool, CastID),
     RequestOut(GnomeId, Option<GnomeId>, NeighborRequest),
     ResponseOut(GnomeId, NeighborResponse),
@@ -1,5 +1,12 @@
+mod app_channel;
 mod band_mon;
 mod capabilities;
+mod cast_descriptor;
+mod cast_history;
+mod chain;
+mod clock;
+mod equivocation;
+mod genesis;
 mod gnome;
 mod gnome_to_manager;
 mod internal;
@@ -7,6 +14,10 @@ mod key_registry;
 mod manager_to_gnome;
 mod policy;
 mod requirement;
+mod succession;
+mod swarm_discovery;
+mod token_bucket;
+mod trace;
 use crate::gnome::Gnome;
 pub use crate::gnome::GnomeId;
 mod message;
@@ -14,6 +25,11 @@ mod multicast;
 mod swarm;
 pub use crate::capabilities::CapabiLeaf;
 pub use crate::capabilities::Capabilities;
+pub use crate::chain::ChainLink;
+pub use crate::chain::ChainProof;
+use crate::clock::Clock;
+pub use crate::equivocation::Equivocation;
+pub use crate::genesis::Genesis;
 // pub use crate::gnome::Nat;
 // pub use crate::gnome::NetworkSettings;
 // pub use crate::gnome::PortAllocationRule;
@@ -23,34 +39,61 @@ pub use crate::neighbor::SwarmSyncRequestParams;
 pub use crate::neighbor::SwarmSyncResponse;
 pub use crate::policy::Policy;
 pub use crate::requirement::Requirement;
+pub use crate::succession::Endorsement;
 pub use crate::swarm::ByteSet;
 pub use crate::swarm::Swarm;
 pub use crate::swarm::SwarmID;
 pub use crate::swarm::SwarmName;
 pub use crate::swarm::SwarmTime;
 pub use crate::swarm::SwarmType;
+pub use crate::trace::read_trace;
+pub use crate::trace::replay;
+pub use crate::trace::ReplayedTurn;
+pub use crate::trace::TraceEvent;
+pub use crate::trace::TraceRecord;
+use crate::trace::Tracer;
 pub use data::CastData;
 pub use data::SyncData;
+pub use gnome_to_manager::BandwidthUsage;
+pub use gnome_to_manager::DiscoveryStatus;
 pub use gnome_to_manager::GnomeToManager;
 pub use manager_to_gnome::ManagerToGnome;
 pub use message::BlockID;
 pub use message::Configuration;
+pub use message::MAX_USER_CONFIG_ID;
 pub use message::{Header, Message, Payload, Signature, WrappedMessage};
 pub use neighbor::NeighborRequest;
 mod neighbor;
+mod neighbor_table;
+pub use crate::app_channel::OverflowPolicy;
+pub use crate::app_channel::APP_CHANNEL_CAPACITY;
+pub use crate::cast_descriptor::CastDescriptor;
+pub use crate::cast_history::HistoryLimit;
 pub use crate::neighbor::Neighbor;
 pub use crate::neighbor::NeighborResponse;
 pub use crate::neighbor::Neighborhood;
+pub use crate::neighbor_table::DropReason;
+pub use crate::neighbor_table::NeighborRetention;
+pub use crate::outbox::TrafficShares;
+pub use crate::topic_filter::TopicFilter;
+pub use crate::unicast::UnicastIDs;
 pub use multicast::CastContent;
 pub use multicast::CastMessage;
 pub use multicast::CastType;
 mod data;
 mod next_state;
+mod outbox;
+mod reliable_cast;
+mod topic_filter;
+mod unicast;
 use crate::next_state::NextState;
 use std::fmt;
+use std::path::PathBuf;
 use std::sync::mpsc::Receiver;
-use std::sync::mpsc::Sender;
+use std::sync::mpsc::SyncSender;
 
+#[cfg(test)]
+mod simulator;
 #[cfg(test)]
 mod tests;
 
@@ -59,6 +102,7 @@ mod tests;
 
 const DEFAULT_NEIGHBORS_PER_GNOME: usize = 3;
 const DEFAULT_SWARM_DIAMETER: SwarmTime = SwarmTime(7); //Max value is 15!
+const PRESYNC_NEIGHBORS: usize = 3; // to cross-check a founder
 
 #[derive(Debug)]
 pub enum ToGnome {
@@ -72,22 +116,43 @@ pub enum ToGnome {
     Disconnect,
     Status,
     StartUnicast(GnomeId),
-    StartMulticast,
-    StartBroadcast,
+    EndUnicast(CastID),
+    StartMulticast(CastDescriptor),
+    StartBroadcast(CastDescriptor),
+    ListCasts,
     EndBroadcast(CastID),
     EndMulticast(CastID),
+    ReliableBroadcast(CastID), // As origin, number messages so they can be repaired
+    ReliableMulticast(CastID),
     SubscribeBroadcast(CastID),
     SubscribeMulticast(CastID),
+    SubscribeBroadcastWithHistory(CastID), // Recent data is delivered before live data
+    SubscribeMulticastWithHistory(CastID),
+    KeepBroadcastHistory(CastID, HistoryLimit),
+    KeepMulticastHistory(CastID, HistoryLimit),
     UnsubscribeBroadcast(CastID),
     UnsubscribeMulticast(CastID),
+    SetBroadcastTopics(CastID, TopicFilter),
+    SetMulticastTopics(CastID, TopicFilter),
+    SetBroadcastOverflow(CastID, OverflowPolicy), // What to do when app does not keep up
+    SetMulticastOverflow(CastID, OverflowPolicy),
     SendToBCastSource(CastID, CastData),
     SendToMCastSource(CastID, CastData),
     SwarmNeighbors(SwarmName),
     ChangeDiameter(u8),
+    TransferFounder(GnomeId),
+    AddOwner(GnomeId),
+    EndorseSuccession(GnomeId),
+    ClaimFounder(Vec<Endorsement>),
     Reconfigure(u8, SyncData),
     RunningPolicies,
     RunningCapabilities,
     RunningByteSets,
+    StartTrace(PathBuf),
+    StopTrace,
+    SetNeighborRetention(NeighborRetention),
+    ListNeighboringSwarms(u8),
+    SetTrafficShares(TrafficShares),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Ord, Eq, Hash)]
@@ -98,11 +163,13 @@ pub enum GnomeToApp {
     Block(BlockID, SyncData, GnomeId),
     DataInquiry(GnomeId, NeighborRequest),
     Listing(Vec<BlockID>),
-    UnicastOrigin(SwarmID, CastID, Sender<CastData>),
+    UnicastOrigin(SwarmID, CastID, SyncSender<CastData>),
     Unicast(SwarmID, CastID, Receiver<CastData>),
-    MulticastOrigin(SwarmID, CastID, Sender<CastData>, Receiver<CastData>),
+    UnicastEnded(SwarmID, CastID), // by neighbor or because he was dropped
+    // Sender is full when cast can not keep up, try_send tells that
+    MulticastOrigin(SwarmID, CastID, SyncSender<CastData>, Receiver<CastData>),
     Multicast(SwarmID, CastID, Receiver<CastData>),
-    BroadcastOrigin(SwarmID, CastID, Sender<CastData>, Receiver<CastData>),
+    BroadcastOrigin(SwarmID, CastID, SyncSender<CastData>, Receiver<CastData>),
     Broadcast(SwarmID, CastID, Receiver<CastData>),
     Neighbors(SwarmID, Vec<GnomeId>),
     NewNeighbor(SwarmName, Neighbor),
@@ -115,6 +182,21 @@ pub enum GnomeToApp {
     Reconfig(u8, GnomeId, SyncData),
     PolicyNotMet(SyncData),
     PolicyNotMetRcfg(u8, SyncData),
+    EquivocationDetected(GnomeId, SwarmTime),
+    CapabilitiesRevoked(GnomeId, Vec<Capabilities>),
+    ForkDetected(SwarmTime, Vec<GnomeId>),
+    BlocksRolledBack(Vec<BlockID>),
+    FounderChanged(GnomeId, GnomeId),
+    SuccessionEndorsement(GnomeId, Endorsement),
+    NeighborDropped(GnomeId, DropReason),
+    NeighboringSwarms(GnomeId, Vec<SwarmName>),
+    BandwidthUsage(BandwidthUsage),
+    Casts(SwarmID, Vec<(bool, CastID, GnomeId, CastDescriptor)>), // (is_broadcast, id, origin, ..)
+    SubscribeDenied(bool, CastID),                                // (is_broadcast, id)
+    // App did not keep up and missed this many messages of a cast
+    CastDataDropped(bool, CastID, u32), // (is_broadcast, id, count)
+    // Origin only, subscribers have dropped this many messages of our cast
+    CastDownstreamFull(bool, CastID, u32), // (is_broadcast, id, count)
 }
 
 impl fmt::Debug for GnomeToApp {
@@ -150,6 +232,9 @@ impl fmt::Debug for GnomeToApp {
             GnomeToApp::UnicastOrigin(_sid, _cid, _sdata) => {
                 write!(f, "Unicast source {:?}", _cid)
             }
+            GnomeToApp::UnicastEnded(_sid, c_id) => {
+                write!(f, "UnicastEnded({})", c_id.0)
+            }
             GnomeToApp::Multicast(_sid, _cid, _rdata) => {
                 write!(f, "Multicast {:?}", _cid)
             }
@@ -187,6 +272,53 @@ impl fmt::Debug for GnomeToApp {
             GnomeToApp::PolicyNotMetRcfg(cfg_id, _s_data) => {
                 write!(f, "PolicyNotMetRcfg({})", cfg_id)
             }
+            GnomeToApp::EquivocationDetected(g_id, round_start) => {
+                write!(f, "EquivocationDetected({} @{})", g_id, round_start)
+            }
+            GnomeToApp::CapabilitiesRevoked(g_id, caps) => {
+                write!(f, "CapabilitiesRevoked({}: {:?})", g_id, caps)
+            }
+            GnomeToApp::ForkDetected(fork_point, g_ids) => {
+                write!(f, "ForkDetected(@{} with {:?})", fork_point, g_ids)
+            }
+            GnomeToApp::BlocksRolledBack(block_ids) => {
+                write!(f, "BlocksRolledBack({:?})", block_ids)
+            }
+            GnomeToApp::FounderChanged(old, new) => {
+                write!(f, "FounderChanged({} -> {})", old, new)
+            }
+            GnomeToApp::SuccessionEndorsement(candidate, endorsement) => {
+                write!(
+                    f,
+                    "SuccessionEndorsement({} by {})",
+                    candidate, endorsement.owner
+                )
+            }
+            GnomeToApp::NeighborDropped(g_id, reason) => {
+                write!(f, "NeighborDropped({}: {:?})", g_id, reason)
+            }
+            GnomeToApp::NeighboringSwarms(g_id, names) => {
+                write!(f, "NeighboringSwarms({}: {} swarms)", g_id, names.len())
+            }
+            GnomeToApp::BandwidthUsage(usage) => write!(f, "{:?}", usage),
+            GnomeToApp::Casts(_sid, casts) => write!(f, "Casts: {:?}", casts),
+            GnomeToApp::SubscribeDenied(is_bcast, c_id) => {
+                write!(f, "SubscribeDenied({}, bcast: {})", c_id.0, is_bcast)
+            }
+            GnomeToApp::CastDataDropped(is_bcast, c_id, count) => {
+                write!(
+                    f,
+                    "CastDataDropped({}, bcast: {}): {}",
+                    c_id.0, is_bcast, count
+                )
+            }
+            GnomeToApp::CastDownstreamFull(is_bcast, c_id, count) => {
+                write!(
+                    f,
+                    "CastDownstreamFull({}, bcast: {}): {}",
+                    c_id.0, is_bcast, count
+                )
+            }
         }
     }
 }
@@ -11,5 +11,6 @@ pub enum ManagerToGnome {
     SetRunningCapability(Capabilities, Vec<GnomeId>),
     SetRunningByteSet(u8, ByteSet),
     SendCustom(bool, GnomeId, u8, CastData), // bool = is_neighbor_request
+    SetBandwidth(u64),                       // bytes per second
     Disconnect,
 }
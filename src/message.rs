@@ -1,3 +1,4 @@
+use crate::cast_descriptor::CastDescriptor;
 use crate::data::SyncData;
 use crate::neighbor::Neighborhood;
 use crate::ByteSet;
@@ -5,6 +6,8 @@ use crate::Capabilities;
 // use crate::swarm::PubKey;
 use crate::CastID;
 use crate::CastMessage;
+use crate::Endorsement;
+use crate::Equivocation;
 use crate::GnomeId;
 use crate::Policy;
 use crate::Requirement;
@@ -126,14 +129,18 @@ impl Payload {
     }
 }
 
+// Config ids above this one (237..=255) are reserved for system configurations.
+// Apps may only reconfigure a swarm with UserDefined ids 0..=MAX_USER_CONFIG_ID.
+pub const MAX_USER_CONFIG_ID: u8 = 236;
+
 // #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 // #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Configuration {
-    StartBroadcast(GnomeId, CastID),
+    StartBroadcast(GnomeId, CastID, CastDescriptor),
     ChangeBroadcastOrigin(GnomeId, CastID),
     EndBroadcast(GnomeId, CastID),
-    StartMulticast(GnomeId, CastID),
+    StartMulticast(GnomeId, CastID, CastDescriptor),
     ChangeMulticastOrigin(GnomeId, CastID),
     EndMulticast(GnomeId, CastID),
     CreateGroup,
@@ -144,16 +151,20 @@ pub enum Configuration {
     SetRunningPolicy(GnomeId, Policy, Requirement),
     SetRunningCapability(GnomeId, Capabilities, Vec<GnomeId>),
     SetRunningByteSet(GnomeId, u8, ByteSet),
+    ReportEquivocation(GnomeId, Equivocation),
+    TransferFounder(GnomeId, GnomeId),
+    AddOwner(GnomeId, GnomeId),
+    FounderSuccession(GnomeId, Vec<Endorsement>),
     UserDefined(u8, SyncData),
 }
 
 impl Configuration {
     pub fn header_byte(&self) -> u8 {
         match *self {
-            Self::StartBroadcast(_gid, _cid) => 254,
+            Self::StartBroadcast(_gid, _cid, ref _desc) => 254,
             Self::ChangeBroadcastOrigin(_gid, _cid) => 253,
             Self::EndBroadcast(_gid, _cid) => 252,
-            Self::StartMulticast(_gid, _cid) => 251,
+            Self::StartMulticast(_gid, _cid, ref _desc) => 251,
             Self::ChangeMulticastOrigin(_gid, _cid) => 250,
             Self::EndMulticast(_gid, _cid) => 249,
             Self::CreateGroup => 248,
@@ -164,6 +175,10 @@ impl Configuration {
             Self::SetRunningPolicy(_gid, _p, ref _r) => 243,
             Self::SetRunningCapability(_gid, _p, ref _r) => 242,
             Self::SetRunningByteSet(_gid, _bid, ref _bs) => 241,
+            Self::ReportEquivocation(_gid, ref _ev) => 240,
+            Self::TransferFounder(_gid, _new_gid) => 239,
+            Self::AddOwner(_gid, _new_gid) => 238,
+            Self::FounderSuccession(_gid, ref _endorsements) => 237,
             Self::UserDefined(other, ref _s_data) => other,
         }
     }
@@ -175,10 +190,10 @@ impl Configuration {
     }
     pub fn as_gid(&self, g_id: GnomeId) -> GnomeId {
         match *self {
-            Self::StartBroadcast(gid, _cid) => gid,
+            Self::StartBroadcast(gid, _cid, ref _desc) => gid,
             Self::ChangeBroadcastOrigin(gid, _cid) => gid,
             Self::EndBroadcast(gid, _cid) => gid,
-            Self::StartMulticast(gid, _cid) => gid,
+            Self::StartMulticast(gid, _cid, ref _desc) => gid,
             Self::ChangeMulticastOrigin(gid, _cid) => gid,
             Self::EndMulticast(g_id, _cid) => g_id,
             Self::CreateGroup => g_id,
@@ -189,16 +204,20 @@ impl Configuration {
             Self::SetRunningPolicy(gid, _p, ref _r) => gid,
             Self::SetRunningCapability(gid, _p, ref _r) => gid,
             Self::SetRunningByteSet(gid, _id, ref _bs) => gid,
+            Self::ReportEquivocation(gid, ref _ev) => gid,
+            Self::TransferFounder(gid, _new_gid) => gid,
+            Self::AddOwner(gid, _new_gid) => gid,
+            Self::FounderSuccession(gid, ref _endorsements) => gid,
             Self::UserDefined(_other, ref _s_data) => g_id,
         }
     }
 
     pub fn len(&self) -> usize {
         match self {
-            Self::StartBroadcast(_gid, _cid) => 10,
+            Self::StartBroadcast(_gid, _cid, desc) => 10 + desc.len_in_bytes(),
             Self::ChangeBroadcastOrigin(_gid, _cid) => 10,
             Self::EndBroadcast(_gid, _cid) => 2,
-            Self::StartMulticast(_gid, _cid) => 10,
+            Self::StartMulticast(_gid, _cid, desc) => 10 + desc.len_in_bytes(),
             Self::ChangeMulticastOrigin(_gid, _cid) => 10,
             Self::EndMulticast(_gid, _cid) => 2,
             Self::CreateGroup => 1,
@@ -209,6 +228,12 @@ impl Configuration {
             Self::SetRunningPolicy(_gid, _pol, req) => 11 + req.len() as usize,
             Self::SetRunningCapability(_gid, _cap, v_gids) => 11 + (8 * v_gids.len()) as usize,
             Self::SetRunningByteSet(_gid, _id, bset) => 11 + bset.len_in_bytes(),
+            Self::ReportEquivocation(_gid, evidence) => 9 + evidence.len_in_bytes(),
+            Self::TransferFounder(_gid, _new_gid) => 17,
+            Self::AddOwner(_gid, _new_gid) => 17,
+            Self::FounderSuccession(_gid, endorsements) => {
+                10 + endorsements.iter().map(|e| e.len_in_bytes()).sum::<usize>()
+            }
             Self::UserDefined(_other, ref s_data) => s_data.len() + 1,
         }
     }
@@ -216,13 +241,35 @@ impl Configuration {
     // pub fn as_u32(&self) -> u32 {
     //     (self.header_byte() as u32) << 24
     // }
-    pub fn from_bytes(mut value: Vec<u8>) -> Configuration {
+    // Only for bytes we produced ourselves, use try_from_bytes for received ones
+    pub fn from_bytes(value: Vec<u8>) -> Configuration {
+        Self::try_from_bytes(value).expect("Malformed Configuration bytes")
+    }
+
+    // None when bytes are too short for given config id
+    pub fn try_from_bytes(mut value: Vec<u8>) -> Option<Configuration> {
         // println!("From bytes: {:?}", value);
-        let header_byte = value[0];
-        match header_byte {
+        let header_byte = *value.first()?;
+        let min_len = match header_byte {
+            246..=248 => 1,
+            245 | 240 => 9,
+            239 | 238 => 17,
+            241 => 13,
+            242 => 12,
+            243 => 11,
+            237..=254 => 10,
+            _other => 1,
+        };
+        if value.len() < min_len {
+            return None;
+        }
+        let config = match header_byte {
             254 => {
                 let gnome_id = u64::from_be_bytes(value[1..9].try_into().unwrap());
-                Self::StartBroadcast(GnomeId(gnome_id), CastID(value[9]))
+                let cast_id = CastID(value[9]);
+                value.drain(0..10);
+                let descriptor = CastDescriptor::from(&mut value);
+                Self::StartBroadcast(GnomeId(gnome_id), cast_id, descriptor)
             } //TODO: need source for this
             253 => {
                 let gnome_id = u64::from_be_bytes(value[1..9].try_into().unwrap());
@@ -234,7 +281,10 @@ impl Configuration {
             }
             251 => {
                 let gnome_id = u64::from_be_bytes(value[1..9].try_into().unwrap());
-                Self::StartMulticast(GnomeId(gnome_id), CastID(value[9]))
+                let cast_id = CastID(value[9]);
+                value.drain(0..10);
+                let descriptor = CastDescriptor::from(&mut value);
+                Self::StartMulticast(GnomeId(gnome_id), cast_id, descriptor)
             }
             250 => {
                 let gnome_id = u64::from_be_bytes(value[1..9].try_into().unwrap());
@@ -270,6 +320,9 @@ impl Configuration {
                 let cap = Capabilities::from(value[0]);
                 let how_many = u16::from_be_bytes([value[1], value[2]]);
                 value.drain(0..3);
+                if value.len() < 8 * how_many as usize {
+                    return None;
+                }
                 let mut v_gids = Vec::with_capacity(how_many as usize);
                 for _i in 0..how_many {
                     let b1 = value.remove(0);
@@ -293,6 +346,9 @@ impl Configuration {
                 let tpe = value[1];
                 let how_many = u16::from_be_bytes([value[2], value[3]]);
                 value.drain(0..4);
+                if value.len() < tpe as usize * how_many as usize {
+                    return None;
+                }
                 let bset = if tpe == 1 {
                     let mut hs = HashSet::with_capacity(how_many as usize);
                     for _i in 0..how_many {
@@ -310,12 +366,42 @@ impl Configuration {
                     }
                     ByteSet::Pairs(hs)
                 } else {
-                    panic!("Unexpected tpe: {}", tpe);
+                    return None;
                 };
                 Self::SetRunningByteSet(GnomeId(gnome_id), b_id, bset)
             }
-            other => Self::UserDefined(other, SyncData::new(value[1..].into()).unwrap()),
-        }
+            240 => {
+                let gnome_id = u64::from_be_bytes(value[1..9].try_into().unwrap());
+                value.drain(0..9);
+                let evidence = Equivocation::from(&mut value).unwrap_or_else(|| {
+                    eprintln!("Malformed Equivocation evidence");
+                    Equivocation::malformed(GnomeId(gnome_id))
+                });
+                Self::ReportEquivocation(GnomeId(gnome_id), evidence)
+            }
+            239 => {
+                let gnome_id = u64::from_be_bytes(value[1..9].try_into().unwrap());
+                let new_gnome_id = u64::from_be_bytes(value[9..17].try_into().unwrap());
+                Self::TransferFounder(GnomeId(gnome_id), GnomeId(new_gnome_id))
+            }
+            238 => {
+                let gnome_id = u64::from_be_bytes(value[1..9].try_into().unwrap());
+                let new_gnome_id = u64::from_be_bytes(value[9..17].try_into().unwrap());
+                Self::AddOwner(GnomeId(gnome_id), GnomeId(new_gnome_id))
+            }
+            237 => {
+                let gnome_id = u64::from_be_bytes(value[1..9].try_into().unwrap());
+                let count = value[9];
+                value.drain(0..10);
+                let mut endorsements = Vec::with_capacity(count as usize);
+                for _i in 0..count {
+                    endorsements.push(Endorsement::from(&mut value)?);
+                }
+                Self::FounderSuccession(GnomeId(gnome_id), endorsements)
+            }
+            other => Self::UserDefined(other, SyncData::new(value[1..].into()).ok()?),
+        };
+        Some(config)
     }
     pub fn bytes(&self) -> Vec<u8> {
         let mut result_vec = vec![];
@@ -330,13 +416,14 @@ impl Configuration {
     pub fn content_bytes(&self, with_gnome_id: bool) -> Vec<u8> {
         let mut content_bytes = vec![];
         match *self {
-            Self::StartBroadcast(gid, cid) => {
+            Self::StartBroadcast(gid, cid, ref descriptor) => {
                 if with_gnome_id {
                     for b in gid.0.to_be_bytes() {
                         content_bytes.push(b);
                     }
                 }
                 content_bytes.push(cid.0);
+                descriptor.append_bytes_to(&mut content_bytes);
             }
             Self::ChangeBroadcastOrigin(gid, cid) => {
                 if with_gnome_id {
@@ -354,13 +441,14 @@ impl Configuration {
                 }
                 content_bytes.push(cid.0);
             }
-            Self::StartMulticast(gid, cid) => {
+            Self::StartMulticast(gid, cid, ref descriptor) => {
                 if with_gnome_id {
                     for b in gid.0.to_be_bytes() {
                         content_bytes.push(b);
                     }
                 }
                 content_bytes.push(cid.0);
+                descriptor.append_bytes_to(&mut content_bytes);
             }
             Self::ChangeMulticastOrigin(gid, cid) => {
                 if with_gnome_id {
@@ -454,6 +542,35 @@ impl Configuration {
 
                 content_bytes.append(&mut bset.bytes());
             }
+            Self::ReportEquivocation(gid, ref evidence) => {
+                if with_gnome_id {
+                    for b in gid.0.to_be_bytes() {
+                        content_bytes.push(b);
+                    }
+                }
+                evidence.append_bytes_to(&mut content_bytes);
+            }
+            Self::TransferFounder(gid, new_gid) | Self::AddOwner(gid, new_gid) => {
+                if with_gnome_id {
+                    for b in gid.0.to_be_bytes() {
+                        content_bytes.push(b);
+                    }
+                }
+                for b in new_gid.0.to_be_bytes() {
+                    content_bytes.push(b);
+                }
+            }
+            Self::FounderSuccession(gid, ref endorsements) => {
+                if with_gnome_id {
+                    for b in gid.0.to_be_bytes() {
+                        content_bytes.push(b);
+                    }
+                }
+                content_bytes.push(endorsements.len() as u8);
+                for endorsement in endorsements {
+                    endorsement.append_bytes_to(&mut content_bytes);
+                }
+            }
             Self::UserDefined(_other, ref sync_data) => {
                 content_bytes.append(&mut sync_data.clone().bytes())
             }
@@ -620,7 +737,7 @@ impl Message {
             header: Header::Reconfigure(255 as ConfigType, GnomeId(0)),
             payload: Payload::Reconfigure(
                 Signature::Regular(GnomeId(0), vec![]),
-                Configuration::StartBroadcast(GnomeId(0), CastID(0)),
+                Configuration::StartBroadcast(GnomeId(0), CastID(0), CastDescriptor::default()),
             ),
         }
     }
@@ -1,8 +1,15 @@
 use std::{
-    collections::HashMap,
-    sync::mpsc::{Receiver, Sender},
+    collections::{HashMap, VecDeque},
+    sync::mpsc::{Receiver, Sender, SyncSender},
 };
 
+use crate::app_channel::Delivery;
+use crate::app_channel::OverflowPolicy;
+use crate::app_channel::ToApp;
+use crate::cast_history::CastHistory;
+use crate::cast_history::HistoryLimit;
+use crate::reliable_cast::ReliableCast;
+use crate::topic_filter::TopicFilter;
 use crate::{CastData, CastID, GnomeId, NeighborRequest, NeighborResponse, WrappedMessage};
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -15,6 +22,8 @@ pub enum CastType {
 #[derive(Clone, Debug)]
 pub enum CastContent {
     Data(CastData),
+    Sequenced(u32, CastData), // Data of a cast in reliable mode
+    History(CastData),        // Recent data for a new subscriber, not forwarded
     Request(NeighborRequest),
     Response(NeighborResponse),
 }
@@ -22,6 +31,8 @@ impl CastContent {
     pub fn len(&self) -> usize {
         match self {
             Self::Data(d) => d.len(),
+            Self::Sequenced(_s, d) => 4 + d.len(),
+            Self::History(d) => d.len(),
             Self::Request(nreq) => nreq.len(),
             Self::Response(nresp) => nresp.len(),
         }
@@ -47,10 +58,11 @@ impl CastMessage {
         self.id
     }
     pub fn get_data(self) -> Option<CastData> {
-        if let CastContent::Data(dat) = self.content {
-            Some(dat)
-        } else {
-            None
+        match self.content {
+            CastContent::Data(dat) | CastContent::Sequenced(_, dat) | CastContent::History(dat) => {
+                Some(dat)
+            }
+            _ => None,
         }
     }
     pub fn get_request(&self) -> Option<NeighborRequest> {
@@ -102,6 +114,20 @@ impl CastMessage {
             content: CastContent::Data(data),
         }
     }
+    pub fn new_sequenced(c_type: CastType, id: CastID, seq: u32, data: CastData) -> Self {
+        CastMessage {
+            c_type,
+            id,
+            content: CastContent::Sequenced(seq, data),
+        }
+    }
+    pub fn new_history(c_type: CastType, id: CastID, data: CastData) -> Self {
+        CastMessage {
+            c_type,
+            id,
+            content: CastContent::History(data),
+        }
+    }
     pub fn is_unicast(&self) -> bool {
         matches!(self.c_type, CastType::Unicast)
     }
@@ -121,7 +147,20 @@ pub struct Multicast {
     source: (GnomeId, Receiver<WrappedMessage>),
     alt_sources: Vec<GnomeId>,
     subscribers: HashMap<GnomeId, Sender<WrappedMessage>>,
-    to_app: Option<Sender<CastData>>,
+    to_app: Option<ToApp>,
+    // App could not keep up and wants to leave
+    overflowed: bool,
+    // Messages app has missed since last asked
+    dropped: u32,
+    reliable: Option<ReliableCast>,
+    repaired: VecDeque<CastMessage>,
+    nack: Option<(GnomeId, Vec<u32>)>,
+    // What our app wants, and what subscribers want for their subtrees
+    topics: TopicFilter,
+    sub_topics: HashMap<GnomeId, TopicFilter>,
+    // What we last asked our source for
+    interest_sent: TopicFilter,
+    history: Option<CastHistory>,
 }
 
 impl Multicast {
@@ -130,14 +169,23 @@ impl Multicast {
         source: (GnomeId, Receiver<WrappedMessage>),
         alt_sources: Vec<GnomeId>,
         subscribers: HashMap<GnomeId, Sender<WrappedMessage>>,
-        to_app: Option<Sender<CastData>>,
+        to_app: Option<SyncSender<CastData>>,
     ) -> Self {
         Self {
             origin,
             source,
             alt_sources,
             subscribers,
-            to_app,
+            to_app: to_app.map(|sender| ToApp::new(sender, OverflowPolicy::default())),
+            overflowed: false,
+            dropped: 0,
+            reliable: None,
+            repaired: VecDeque::new(),
+            nack: None,
+            topics: TopicFilter::All,
+            sub_topics: HashMap::new(),
+            interest_sent: TopicFilter::All,
+            history: None,
         }
     }
     // pub fn subscribers(&self) -> Vec<(GnomeId,Sender<Message>)> {
@@ -158,6 +206,8 @@ impl Multicast {
     }
     pub fn set_source(&mut self, source: (GnomeId, Receiver<WrappedMessage>)) {
         self.source = source;
+        // New source sends everything until told otherwise
+        self.interest_sent = TopicFilter::All;
     }
 
     pub fn add_subscriber(&mut self, subscriber: (GnomeId, Sender<WrappedMessage>)) {
@@ -165,12 +215,65 @@ impl Multicast {
     }
 
     pub fn remove_subscriber(&mut self, subscriber: &GnomeId) -> Option<Sender<WrappedMessage>> {
+        self.sub_topics.remove(subscriber);
         self.subscribers.remove(subscriber)
     }
 
+    pub fn set_topics(&mut self, topics: TopicFilter) {
+        self.topics = topics;
+    }
+
+    pub fn set_subscriber_topics(&mut self, subscriber: GnomeId, topics: TopicFilter) {
+        if self.subscribers.contains_key(&subscriber) {
+            self.sub_topics.insert(subscriber, topics);
+        }
+    }
+
+    // Topics wanted by us and our subtree
+    fn interest(&self) -> TopicFilter {
+        let mut interest = if self.to_app.is_some() {
+            self.topics.clone()
+        } else {
+            TopicFilter::nothing()
+        };
+        for sub_id in self.subscribers.keys() {
+            if let Some(topics) = self.sub_topics.get(sub_id) {
+                interest = interest.union(topics);
+            } else {
+                return TopicFilter::All;
+            }
+        }
+        interest
+    }
+
+    // When our interest has changed our source should know about it
+    pub fn take_interest_update(&mut self) -> Option<(GnomeId, TopicFilter)> {
+        let interest = self.interest();
+        if interest == self.interest_sent {
+            return None;
+        }
+        self.interest_sent = interest.clone();
+        Some((self.source.0, interest))
+    }
+
     pub fn dont_send_to_app(&mut self) {
         self.to_app = None;
     }
+
+    pub fn set_overflow_policy(&mut self, policy: OverflowPolicy) {
+        if let Some(to_app) = &mut self.to_app {
+            to_app.set_policy(policy);
+        }
+    }
+
+    // Whether app has overflowed with Unsubscribe policy since last asked
+    pub fn take_overflowed(&mut self) -> bool {
+        std::mem::replace(&mut self.overflowed, false)
+    }
+
+    pub fn take_dropped(&mut self) -> u32 {
+        std::mem::take(&mut self.dropped)
+    }
     pub fn get_alt_sources(&mut self, old_source: GnomeId) -> Vec<GnomeId> {
         let prev_sources = std::mem::replace(&mut self.alt_sources, vec![]);
         for alt_s in prev_sources {
@@ -182,39 +285,199 @@ impl Multicast {
         self.alt_sources.clone()
     }
 
-    pub fn serve(&mut self, available_tokens: u64) -> (bool, u64) {
+    // Origin numbers messages from now on, others switch
+    // to reliable mode once they receive a numbered message
+    pub fn make_reliable(&mut self) {
+        if self.reliable.is_none() {
+            self.reliable = Some(ReliableCast::new());
+        }
+    }
+
+    pub fn retransmit(&self, seqs: &[u32]) -> Vec<(u32, CastData)> {
+        if let Some(reliable) = &self.reliable {
+            reliable.retransmit(seqs)
+        } else {
+            vec![]
+        }
+    }
+
+    // Repairs are served like any other message from source
+    pub fn repair(&mut self, message: CastMessage) {
+        self.repaired.push_back(message);
+    }
+
+    // Who to ask for which missing messages
+    pub fn take_nack(&mut self) -> Option<(GnomeId, Vec<u32>)> {
+        self.nack.take()
+    }
+
+    pub fn keep_history(&mut self, limit: HistoryLimit) {
+        if let Some(history) = &mut self.history {
+            history.set_limit(limit);
+        } else {
+            self.history = Some(CastHistory::new(limit));
+        }
+    }
+
+    pub fn history(&self) -> Vec<CastData> {
+        if let Some(history) = &self.history {
+            history.entries()
+        } else {
+            vec![]
+        }
+    }
+
+    // Reliable casts are forwarded whole, otherwise
+    // subscribers could not tell filtered from lost
+    fn wants(&self, sub_id: &GnomeId, message: &CastMessage) -> bool {
+        if let CastContent::History(_data) = &message.content {
+            return false;
+        }
+        if let (Some(topics), CastContent::Data(data)) =
+            (self.sub_topics.get(sub_id), &message.content)
+        {
+            topics.matches(data)
+        } else {
+            true
+        }
+    }
+
+    // In reliable mode a message may release several held ones,
+    // or nothing when it came out of order
+    fn in_order(&mut self, message: CastMessage) -> Vec<CastMessage> {
+        let CastMessage {
+            c_type,
+            id,
+            content,
+        } = message;
+        match content {
+            CastContent::Sequenced(seq, data) => {
+                self.reliable
+                    .get_or_insert_with(ReliableCast::new)
+                    .receive(seq, data);
+            }
+            CastContent::Data(data) if self.reliable.is_some() => {
+                let reliable = self.reliable.as_mut().unwrap();
+                let seq = reliable.stamp();
+                reliable.receive(seq, data);
+            }
+            other => {
+                return vec![CastMessage {
+                    c_type,
+                    id,
+                    content: other,
+                }]
+            }
+        }
+        let reliable = self.reliable.as_mut().unwrap();
+        let mut ready = reliable.take_in_order();
+        if let Some((attempt, missing)) = reliable.nack() {
+            let from = if attempt == 0 || self.alt_sources.is_empty() {
+                self.source.0
+            } else {
+                self.alt_sources[(attempt as usize - 1) % self.alt_sources.len()]
+            };
+            self.nack = Some((from, missing));
+        }
+        ready.append(&mut reliable.take_in_order());
+        ready
+            .into_iter()
+            .map(|(seq, data)| CastMessage::new_sequenced(c_type, id, seq, data))
+            .collect()
+    }
+
+    // Next message is taken from source only when every subscriber
+    // has room for it in his outbox, otherwise it waits in the channel.
+    // Messages for subscribers are put into outgoing, Gnome queues them.
+    pub fn serve(
+        &mut self,
+        available_tokens: u64,
+        room: &mut HashMap<GnomeId, usize>,
+        outgoing: &mut Vec<(GnomeId, CastMessage)>,
+    ) -> (bool, u64) {
         let mut any_data_processed = false;
         let mut tokens_used = 0;
         // let mut tokens_remaining = available_tokens;
-        while let Ok(WrappedMessage::Cast(msg)) = self.source.1.try_recv() {
+        if let Some(to_app) = &mut self.to_app {
+            if !to_app.flush() {
+                eprintln!("User not interested in bcast.");
+                self.to_app = None;
+            }
+        }
+        loop {
+            if self
+                .to_app
+                .as_ref()
+                .is_some_and(|to_app| to_app.is_blocked())
+            {
+                break;
+            }
+            if self
+                .subscribers
+                .keys()
+                .any(|sub_id| room.get(sub_id).copied().unwrap_or(0) == 0)
+            {
+                break;
+            }
+            let msg = if let Some(msg) = self.repaired.pop_front() {
+                msg
+            } else if let Ok(WrappedMessage::Cast(msg)) = self.source.1.try_recv() {
+                msg
+            } else {
+                break;
+            };
             eprintln!(
                 "Received a casting msg: {:?}(sub len: {})",
                 msg,
                 self.subscribers.len()
             );
-            let m_size = msg.len() as u64;
-            for sender in self.subscribers.values() {
-                any_data_processed = true;
-                tokens_used += m_size;
-                // tokens_remaining = tokens_remaining.saturating_sub(m_size);
-                // println!("Wrapped send: {:?}", msg);
-                let res = sender.send(WrappedMessage::Cast(msg.clone()));
-                eprintln!("WMSR: {:?}", res);
-            }
-            // TODO: we can Unsubscribe from a cast when to_app is None
-            //       and subscribers.len()>0 when we want to save bandwith
-            if let Some(sender) = &self.to_app {
-                let res = sender.send(msg.get_data().unwrap());
-                if res.is_err() {
-                    //TODO: unsubscribe - or we can keep this cast
-                    // since we are not forwarding to anyone it does
-                    // not cost us bandwith, only some CPU cycles
-                    // if !any_data_processed {
-                    // }
-                    eprintln!("User not interested in bcast.");
-                    self.to_app = None;
-                } else {
-                    any_data_processed = true;
+            any_data_processed = true;
+            for msg in self.in_order(msg) {
+                let m_size = msg.len() as u64;
+                for sub_id in self.subscribers.keys() {
+                    if !self.wants(sub_id, &msg) {
+                        continue;
+                    }
+                    tokens_used += m_size;
+                    // tokens_remaining = tokens_remaining.saturating_sub(m_size);
+                    // println!("Wrapped send: {:?}", msg);
+                    if let Some(room) = room.get_mut(sub_id) {
+                        *room = room.saturating_sub(1);
+                    }
+                    outgoing.push((*sub_id, msg.clone()));
+                }
+                // TODO: we can Unsubscribe from a cast when to_app is None
+                //       and subscribers.len()>0 when we want to save bandwith
+                let is_history = matches!(msg.content, CastContent::History(_));
+                let data = msg.get_data().unwrap();
+                if let (Some(history), false) = (&mut self.history, is_history) {
+                    history.record(data.clone());
+                }
+                if !self.topics.matches(&data) {
+                    continue;
+                }
+                if let Some(to_app) = &mut self.to_app {
+                    match to_app.send(data) {
+                        Delivery::Sent | Delivery::Held => {}
+                        Delivery::Dropped => {
+                            eprintln!("User too slow, dropping cast data");
+                            self.dropped += 1;
+                        }
+                        Delivery::Unsubscribe => {
+                            eprintln!("User too slow, no longer sending to it");
+                            self.to_app = None;
+                            self.overflowed = true;
+                        }
+                        Delivery::Gone => {
+                            //TODO: unsubscribe - or we can keep this cast
+                            // since we are not forwarding to anyone it does
+                            // not cost us bandwith, only some CPU cycles
+                            // if !any_data_processed {
+                            // }
+                            eprintln!("User not interested in bcast.");
+                            self.to_app = None;
+                        }
+                    }
                 }
             }
             // if tokens_remaining == 0 {
@@ -1,33 +1,54 @@
+use crate::app_channel::app_channel;
+use crate::app_channel::Delivery;
+use crate::app_channel::ToApp;
+use crate::clock::recv_timeout;
 use crate::message::Header;
 use crate::message::Payload;
 use crate::message::WrappedMessage;
 use crate::multicast::CastMessage;
 use crate::multicast::CastType;
+use crate::next_state::NeighborView;
+use crate::next_state::RoundPosition;
+use crate::outbox::Outbox;
+use crate::outbox::TrafficShares;
 use crate::policy::Policy;
 use crate::requirement::Requirement;
+use crate::topic_filter::TopicFilter;
+use crate::unicast::UnicastIDs;
 use crate::Capabilities;
 use crate::CastContent;
 use crate::CastData;
+use crate::CastDescriptor;
 use crate::CastID;
+use crate::ChainLink;
+use crate::ChainProof;
+use crate::Clock;
+use crate::Genesis;
 use crate::GnomeId;
 use crate::GnomeToApp;
 use crate::Message;
+use crate::OverflowPolicy;
 use crate::Signature;
 use crate::Swarm;
 use crate::SwarmID;
 use crate::SwarmName;
 use crate::SwarmTime;
 use crate::SwarmType;
+use crate::TraceEvent;
+use crate::Tracer;
 // use crate::SyncData;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt::Display;
 
 use std::collections::VecDeque;
-use std::sync::mpsc::channel;
 use std::sync::mpsc::SendError;
 use std::sync::mpsc::{Receiver, Sender};
 use std::time::Duration;
 
+// After that many payloads failing verification a neighbor is dropped
+const MAX_VERIFY_FAILURES: u32 = 3;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Neighborhood(pub u8);
 
@@ -68,13 +89,25 @@ pub struct Neighbor {
     pub requested_data: VecDeque<NeighborResponse>,
     gnome_header: Header,
     gnome_neighborhood: Neighborhood,
-    active_unicasts: HashMap<CastID, Sender<CastData>>,
+    active_unicasts: HashMap<CastID, ToApp>,
+    // Unicasts we have asked neighbor to pause, since our app is full
+    paused_unicasts: HashSet<CastID>,
     active_broadcasts: HashMap<CastID, Sender<WrappedMessage>>,
     active_multicasts: HashMap<CastID, Sender<WrappedMessage>>,
     pub available_bandwith: u64,
     pub member_of_swarms: Vec<SwarmName>,
     timeouts: [u8; 8],
+    // When we sent our last message that neighbor has not yet answered
+    sent_at: Option<Duration>,
+    // Smoothed time from our message to neighbor's next one
+    pub rtt: Option<Duration>,
+    pub verify_failures: u32,
+    outbox: Outbox,
     pub new_message_recieved: bool,
+    // Payloads that passed signature verification, with round_start
+    // they were verified against, waiting to be checked for equivocation
+    verified_payloads: VecDeque<(SwarmTime, Signature, Vec<u8>)>,
+    tracer: Option<Tracer>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -108,47 +141,71 @@ pub struct SwarmSyncResponse {
     pub multicast_size: u8,
     pub more_key_reg_messages: bool,
     pub key_reg_pairs: Vec<(GnomeId, Vec<u8>)>,
+    pub genesis: Option<Genesis>,
 }
 impl SwarmSyncResponse {
     pub fn len(&self) -> usize {
+        let genesis_len = if let Some(genesis) = &self.genesis {
+            1 + genesis.len_in_bytes()
+        } else {
+            1
+        };
         if self.key_reg_pairs.is_empty() {
-            26
+            26 + genesis_len
         } else {
             let e_len = self.key_reg_pairs[0].1.len() + 8;
-            26 + self.key_reg_pairs.len() * e_len
+            26 + genesis_len + self.key_reg_pairs.len() * e_len
         }
     }
 }
 //TODO: Move all upper layer Requests Responses into Custom wrap
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum NeighborRequest {
-    UnicastRequest(SwarmID, Box<[CastID; 256]>),
+    UnicastRequest(SwarmID, UnicastIDs),
+    EndUnicast(CastID),
+    // Our app is not reading given unicast, stop sending until resumed
+    PauseUnicast(CastID),
+    ResumeUnicast(CastID),
     ForwardConnectRequest(Vec<u8>),
     // ConnectRequest(u8, GnomeId, Vec<NetworkSettings>),
     ConnectRequest(u8, GnomeId, Vec<u8>),
     SwarmSyncRequest(SwarmSyncRequestParams),
-    SubscribeRequest(bool, CastID),
-    UnsubscribeRequest(bool, CastID), // We send this when no longer interested in bcast
+    SubscribeRequest(bool, CastID, bool), // last one is for history
+    UnsubscribeRequest(bool, CastID),     // We send this when no longer interested in bcast
     SendToCastSource(bool, CastID, CastData), // We send this to our subscribers to indicate they
-    SourceDrained(bool, CastID),      // We send this to our subscribers to indicate they
+    SourceDrained(bool, CastID),          // We send this to our subscribers to indicate they
     // have to find another source for given cast, give them some time to do so
+    CastNack(bool, CastID, Vec<u32>), // Sequence numbers of reliable cast we are missing
+    CastTopics(bool, CastID, TopicFilter), // What we and our subscribers want from a cast
+    CastDropped(bool, CastID, u32),   // Messages dropped downstream, passed on up to origin
     CreateNeighbor(GnomeId, SwarmName),
     SwarmJoinedInfo(SwarmName),
-    //TODO: implement ListNeighboringSwarms,
+    ChainInfo(Vec<ChainLink>),
+    ChainProofRequest(SwarmTime), // proofs of all links since fork point
+    ListNeighboringSwarms(u8),    // how many hops away to look for swarms
     Custom(u8, CastData),
 }
 impl NeighborRequest {
     pub fn len(&self) -> usize {
         match self {
-            NeighborRequest::UnicastRequest(_s, cbox) => 2 + cbox.len(),
+            NeighborRequest::UnicastRequest(_s, _ids) => 34,
+            NeighborRequest::EndUnicast(_c) => 2,
+            NeighborRequest::PauseUnicast(_c) => 2,
+            NeighborRequest::ResumeUnicast(_c) => 2,
             NeighborRequest::ForwardConnectRequest(ns) => 1 + ns.len(),
             NeighborRequest::ConnectRequest(_n, _g, ns) => 3 + ns.len(),
             NeighborRequest::SwarmSyncRequest(ssp) => 1 + ssp.len(),
-            NeighborRequest::SubscribeRequest(_b, _c) => 3,
+            NeighborRequest::SubscribeRequest(_b, _c, _h) => 4,
             NeighborRequest::UnsubscribeRequest(_b, _c) => 3,
             NeighborRequest::SourceDrained(_b, _c) => 3,
+            NeighborRequest::CastNack(_b, _c, seqs) => 3 + 4 * seqs.len(),
+            NeighborRequest::CastTopics(_b, _c, topics) => 3 + topics.len_in_bytes(),
+            NeighborRequest::CastDropped(_b, _c, _n) => 7,
             NeighborRequest::CreateNeighbor(_g, sn) => 17 + sn.name.len(),
             NeighborRequest::SwarmJoinedInfo(sn) => 9 + sn.name.len(),
+            NeighborRequest::ChainInfo(links) => 2 + links.len() * 20,
+            NeighborRequest::ChainProofRequest(_r) => 5,
+            NeighborRequest::ListNeighboringSwarms(_d) => 2,
             NeighborRequest::SendToCastSource(_is, _id, cd) => 2 + cd.len(),
             NeighborRequest::Custom(_b, cd) => 2 + cd.len(),
         }
@@ -157,8 +214,18 @@ impl NeighborRequest {
 impl NeighborResponse {
     pub fn len(&self) -> usize {
         match self {
-            NeighborResponse::BroadcastSync(_b, _t, cvec) => 3 + (9 * cvec.len()),
-            NeighborResponse::MulticastSync(_b, _t, mvec) => 3 + (9 * mvec.len()),
+            NeighborResponse::BroadcastSync(_b, _t, cvec) => {
+                3 + cvec
+                    .iter()
+                    .map(|(_c, _g, desc)| 9 + desc.len_in_bytes())
+                    .sum::<usize>()
+            }
+            NeighborResponse::MulticastSync(_b, _t, mvec) => {
+                3 + mvec
+                    .iter()
+                    .map(|(_c, _g, desc)| 9 + desc.len_in_bytes())
+                    .sum::<usize>()
+            }
             NeighborResponse::Unicast(_s, _c) => 3,
             NeighborResponse::ForwardConnectResponse(ns) => 1 + ns.len(),
             NeighborResponse::ForwardConnectFailed => 1,
@@ -194,6 +261,16 @@ impl NeighborResponse {
                     12
                 }
             }
+            NeighborResponse::NeighboringSwarms(_p, _t, names) => {
+                let mut total_len = 3;
+                for name in names {
+                    total_len += 9 + name.name.len();
+                }
+                total_len
+            }
+            NeighborResponse::CastRepair(_b, _c, _s, cdata) => 6 + cdata.len(),
+            NeighborResponse::SubscribeDenied(_b, _c) => 3,
+            NeighborResponse::ChainProof(_b, _t, proof) => 3 + proof.len_in_bytes(),
             NeighborResponse::Custom(_b, cdata) => 2 + cdata.len(),
         }
     }
@@ -201,8 +278,8 @@ impl NeighborResponse {
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum NeighborResponse {
-    BroadcastSync(u8, u8, Vec<(CastID, GnomeId)>),
-    MulticastSync(u8, u8, Vec<(CastID, GnomeId)>),
+    BroadcastSync(u8, u8, Vec<(CastID, GnomeId, CastDescriptor)>),
+    MulticastSync(u8, u8, Vec<(CastID, GnomeId, CastDescriptor)>),
     Unicast(SwarmID, CastID),
     // ForwardConnectResponse(Vec<NetworkSettings>),
     ForwardConnectResponse(Vec<u8>),
@@ -215,7 +292,10 @@ pub enum NeighborResponse {
     CapabilitySync(u8, u8, Vec<(Capabilities, Vec<GnomeId>)>),
     PolicySync(u8, u8, Vec<(Policy, Requirement)>),
     Subscribed(bool, CastID, GnomeId, Option<GnomeId>),
-    //TODO: implement NeighboringSwarms(u8, u8, Vec<SwarmName>),
+    NeighboringSwarms(u8, u8, Vec<SwarmName>),
+    CastRepair(bool, CastID, u32, CastData),
+    SubscribeDenied(bool, CastID),
+    ChainProof(u8, u8, ChainProof),
     Custom(u8, CastData),
 }
 
@@ -255,12 +335,19 @@ impl Neighbor {
             gnome_header: Header::Sync,
             gnome_neighborhood: Neighborhood(0),
             active_unicasts: HashMap::new(),
+            paused_unicasts: HashSet::new(),
             active_broadcasts: HashMap::new(),
             active_multicasts: HashMap::new(),
             available_bandwith: 1024,
             member_of_swarms,
             timeouts: [0; 8],
+            sent_at: None,
+            rtt: None,
+            verify_failures: 0,
+            outbox: Outbox::new(),
             new_message_recieved: false,
+            verified_payloads: VecDeque::new(),
+            tracer: None,
         }
     }
     pub fn get_shared_sender(
@@ -305,12 +392,45 @@ impl Neighbor {
         self.gnome_header = neighbor.gnome_header;
         self.gnome_neighborhood = neighbor.gnome_neighborhood;
         self.active_unicasts = neighbor.active_unicasts;
+        self.paused_unicasts = neighbor.paused_unicasts;
         self.active_broadcasts = neighbor.active_broadcasts;
         self.available_bandwith = neighbor.available_bandwith;
+        self.timeouts = neighbor.timeouts;
+        self.rtt = neighbor.rtt;
+        self.verify_failures = neighbor.verify_failures;
+        self.outbox = neighbor.outbox;
         self.new_message_recieved = neighbor.new_message_recieved;
     }
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+        // Replay needs to know what we verify signatures against
+        self.trace(TraceEvent::RoundStarted(self.round_start));
+    }
+
+    pub fn trace(&self, event: TraceEvent) {
+        if let Some(tracer) = &self.tracer {
+            tracer.record(self.id, &event);
+        }
+    }
+
+    // Latest message from this Neighbor, unless we have already seen it
+    pub fn take_view(&mut self) -> Option<NeighborView> {
+        if !self.new_message_recieved {
+            return None;
+        }
+        self.new_message_recieved = false;
+        Some(NeighborView {
+            id: self.id,
+            swarm_time: self.swarm_time,
+            neighborhood: self.neighborhood,
+            header: self.header,
+            payload: self.payload.clone(),
+        })
+    }
+
     pub fn start_new_round(&mut self, swarm_time: SwarmTime) {
         // eprintln!("\n\nN{} Starting new round @{}", self.id, swarm_time);
+        self.trace(TraceEvent::RoundStarted(swarm_time));
         self.round_start = swarm_time;
         self.header = Header::Sync;
         self.payload = Payload::KeepAlive(self.available_bandwith);
@@ -318,9 +438,12 @@ impl Neighbor {
         self.neighborhood = Neighborhood(0);
     }
 
-    pub fn recv(&mut self, timeout: Duration) -> Option<Message> {
-        let recv_result = self.receiver.recv_timeout(timeout);
-        if let Ok(response) = recv_result {
+    pub fn recv(&mut self, timeout: Duration, clock: &dyn Clock) -> Option<Message> {
+        let recv_result = recv_timeout(&self.receiver, timeout, clock);
+        if let Some(response) = recv_result {
+            if self.tracer.is_some() {
+                self.trace(TraceEvent::Received(response.clone()));
+            }
             // TODO: we should update Neighbor state according to
             self.header = response.header;
             self.payload = response.payload.clone();
@@ -331,9 +454,18 @@ impl Neighbor {
         }
         None
     }
-    pub fn recv_sync(&mut self, timeout: Duration) -> Result<Option<NeighborResponse>, String> {
-        let recv_result = self.cast_receiver.recv_timeout(timeout);
-        if let Ok(CastMessage {
+    pub fn recv_sync(
+        &mut self,
+        timeout: Duration,
+        clock: &dyn Clock,
+    ) -> Result<Option<NeighborResponse>, String> {
+        let recv_result = recv_timeout(&self.cast_receiver, timeout, clock);
+        if self.tracer.is_some() {
+            if let Some(c_msg) = &recv_result {
+                self.trace(TraceEvent::from_cast(true, c_msg));
+            }
+        }
+        if let Some(CastMessage {
             c_type,
             id,
             content,
@@ -379,8 +511,12 @@ impl Neighbor {
 
     pub fn try_recv_cast(&mut self) -> bool {
         let mut any_data_processed = false;
+        self.resume_unicasts();
         while let Ok(c_msg @ CastMessage { c_type, id, .. }) = self.cast_receiver.try_recv() {
             any_data_processed = true;
+            if self.tracer.is_some() {
+                self.trace(TraceEvent::from_cast(true, &c_msg));
+            }
             match c_type {
                 CastType::Broadcast => {
                     if let Some(sender) = self.active_broadcasts.get(&id) {
@@ -408,10 +544,17 @@ impl Neighbor {
                             }
                         }
                         _ => {
-                            if let Some(sender) = self.active_unicasts.get(&id) {
-                                let _ = sender.send(c_msg.get_data().unwrap());
+                            let delivery = if let Some(to_app) = self.active_unicasts.get_mut(&id) {
+                                to_app.send(c_msg.get_data().unwrap())
                             } else {
                                 eprintln!("Could not find Unicast with id: {:?}", id);
+                                continue;
+                            };
+                            // Data is held for app, neighbor stops sending more
+                            if delivery == Delivery::Held && self.paused_unicasts.insert(id) {
+                                self.queue_cast(CastMessage::new_request(
+                                    NeighborRequest::PauseUnicast(id),
+                                ));
                             }
                         }
                     }
@@ -481,6 +624,9 @@ impl Neighbor {
         ) = self.receiver.try_recv()
         {
             eprintln!("{}  <  {}", self.id, message);
+            if self.tracer.is_some() {
+                self.trace(TraceEvent::Received(message.clone()));
+            }
 
             if message.swarm_time.0 < last_accepted_message.swarm_time.0 {
                 eprintln!("Old message, ignoring");
@@ -520,10 +666,16 @@ impl Neighbor {
                 eprintln!("N{} verify_payload ST: {}", self.id, self.round_start);
                 if !self.verify_payload(self.round_start, swarm, &signature, &mut bytes) {
                     eprintln!("Verification failed {}", self.round_start);
-                    // TODO: maybe it's too drastic of a measure?
-                    drop_me = true;
+                    self.verify_failures += 1;
+                    // Before MAX_VERIFY_FAILURES a failure only lowers his score
+                    drop_me = self.verify_failures >= MAX_VERIFY_FAILURES;
                     return (message_recvd, false, new_proposal, drop_me);
                 } else {
+                    self.verified_payloads.push_back((
+                        self.round_start,
+                        signature.clone(),
+                        bytes.clone(),
+                    ));
                     message.pack(r_st, r_n, r_header, is_config, Some((signature, bytes)));
                 }
                 if !swarm.verify_policy(&message) {
@@ -646,13 +798,23 @@ impl Neighbor {
         (message_recvd, sanity_passed, new_proposal, drop_me)
     }
 
+    pub fn take_verified_payloads(&mut self) -> VecDeque<(SwarmTime, Signature, Vec<u8>)> {
+        std::mem::take(&mut self.verified_payloads)
+    }
+
     fn serve_neighbor_response(&mut self, response: NeighborResponse) {
         match response {
             NeighborResponse::Unicast(swarm_id, cast_id) => {
-                let (sender, receiver) = channel();
-                self.active_unicasts.insert(cast_id, sender);
+                let (sender, receiver) = app_channel();
+                self.active_unicasts
+                    .insert(cast_id, ToApp::new(sender, OverflowPolicy::Block));
                 self.user_responses
                     .push_front(GnomeToApp::Unicast(swarm_id, cast_id, receiver));
+                // Gnome sets up sending side
+                self.user_responses
+                    .push_front(GnomeToApp::ToGnome(NeighborResponse::Unicast(
+                        swarm_id, cast_id,
+                    )));
             }
             NeighborResponse::ForwardConnectResponse(ref _network_settings) => {
                 //TODO send this to networking
@@ -723,6 +885,22 @@ impl Neighbor {
                     ),
                 ));
             }
+            NeighborResponse::NeighboringSwarms(_page, _total, ref _names) => {
+                self.user_responses
+                    .push_front(GnomeToApp::ToGnome(response));
+            }
+            NeighborResponse::CastRepair(_is_bcast, _cast_id, _seq, ref _data) => {
+                self.user_responses
+                    .push_front(GnomeToApp::ToGnome(response));
+            }
+            NeighborResponse::SubscribeDenied(_is_bcast, _cast_id) => {
+                self.user_responses
+                    .push_front(GnomeToApp::ToGnome(response));
+            }
+            NeighborResponse::ChainProof(_chunk_no, _total, ref _proof) => {
+                self.user_responses
+                    .push_front(GnomeToApp::ToGnome(response));
+            }
             NeighborResponse::Custom(id, data) => self
                 .user_responses
                 .push_front(GnomeToApp::Custom(false, id, self.id, data)),
@@ -754,6 +932,23 @@ impl Neighbor {
         self.timeouts.iter().sum()
     }
 
+    pub fn mark_sent(&mut self, now: Duration) {
+        if self.sent_at.is_none() {
+            self.sent_at = Some(now);
+        }
+    }
+
+    pub fn mark_responded(&mut self, now: Duration) {
+        if let Some(sent_at) = self.sent_at.take() {
+            let sample = now.saturating_sub(sent_at);
+            self.rtt = Some(if let Some(rtt) = self.rtt {
+                (rtt * 7 + sample) / 8
+            } else {
+                sample
+            });
+        }
+    }
+
     // fn send_casting(&self, message: CastMessage) {
     //     if message.is_broadcast() {
     //         if let Some(sender) = self.active_broadcasts.get(&message.id()) {
@@ -768,96 +963,24 @@ impl Neighbor {
         neighborhood: &Neighborhood,
         header: &Header,
     ) -> bool {
-        if self.swarm_time > *swarm_time {
-            eprintln!(
-                "Received a message with older swarm_time {} than previous {}!",
-                swarm_time, self.swarm_time
-            );
-            return false;
-        }
-        // A neighbor can not announce a number greater than the number
-        // we announced to him, plus one
-        let hood_inc_limited = if self.gnome_header == *header {
-            // println!("{} <= {}", neighborhood.0, self.gnome_neighborhood.0 + 1);
-            neighborhood.0 <= self.gnome_neighborhood.0 + 1
-        } else {
-            true
+        let current = RoundPosition {
+            swarm_time: self.swarm_time,
+            round_start: self.round_start,
+            neighborhood: self.neighborhood,
+            header: self.header,
         };
-        if self.header == *header {
-            // println!("same header");
-            // A gnome can not stay at the same neighborhood number for more than
-            // 2 turns
-            let hood_increased = if let Some(prev_neighborhood) = self.prev_neighborhood {
-                // println!(
-                //     "{} new: {:?} > pprev: {:?}",
-                //     swarm_time, neighborhood, prev_neighborhood
-                // );
-                if neighborhood.0 == 0
-                    && u32::from(self.neighborhood.0 + 1) >= self.swarm_diameter.0
-                {
-                    true
-                } else {
-                    neighborhood.0 > prev_neighborhood.0 || neighborhood.0 > self.neighborhood.0
-                }
-            } else {
-                // A gnome can not backtrack by announcing a smaller neighborhood
-                // number than before
-                // println!(
-                //     "{} current: {:?} <= new: {:?}",
-                //     swarm_time, self.neighborhood, neighborhood
-                // );
-                // self.neighborhood.0 < neighborhood.0
-                true
-            };
-            if !hood_increased {
-                eprintln!(
-                    "{} fail hood_increased prev:{:?} curr:{} recv:{} ",
-                    swarm_time, self.prev_neighborhood, self.neighborhood, neighborhood
-                );
-            }
-            if !hood_inc_limited {
-                eprintln!(
-                    "{} fail hood_inc_limited neighbor {} <= {} gnome",
-                    swarm_time,
-                    neighborhood.0,
-                    self.gnome_neighborhood.0 + 1
-                );
-            }
-            hood_increased && hood_inc_limited
-        } else {
-            // TODO: Need to think this through
-            let no_backdating =
-                *swarm_time - self.round_start < (self.swarm_diameter + self.swarm_diameter);
-            if !no_backdating {
-                eprintln!(
-                    "backdating: {}-{}<{}",
-                    swarm_time, self.round_start, self.swarm_diameter
-                );
-            }
-            if let Header::Block(id) = self.header {
-                match header {
-                    Header::Block(new_id) => new_id >= &id && no_backdating && hood_inc_limited,
-                    Header::Reconfigure(_ct, _gid) => false,
-                    Header::Sync => false,
-                }
-            } else if let Header::Reconfigure(ct, gid) = self.header {
-                match header {
-                    Header::Block(_id) => no_backdating && hood_inc_limited,
-                    Header::Reconfigure(new_ct, new_gid) => {
-                        //TODO: not sure if this is ok
-                        if *new_ct > ct || *new_gid > gid {
-                            no_backdating
-                        } else {
-                            no_backdating && hood_inc_limited
-                        }
-                    }
-                    Header::Sync => false,
-                }
-            } else {
-                // Backdating check
-                no_backdating && hood_inc_limited
-            }
-        }
+        let next = RoundPosition {
+            swarm_time: *swarm_time,
+            neighborhood: *neighborhood,
+            header: *header,
+            ..current
+        };
+        current.may_move_to(
+            &next,
+            self.prev_neighborhood,
+            (self.gnome_header, self.gnome_neighborhood),
+            self.swarm_diameter,
+        )
     }
 
     pub fn send_no_op(&self) {
@@ -865,6 +988,9 @@ impl Neighbor {
     }
     pub fn send_out_cast(&mut self, message: CastMessage) -> Result<(), SendError<WrappedMessage>> {
         // println!("Sending: {:?}", message);
+        if self.tracer.is_some() {
+            self.trace(TraceEvent::from_cast(false, &message));
+        }
         let _res = self.sender.send(WrappedMessage::Cast(message));
         // if let Some(err) = _res.err() {
         //     eprintln!("Unable to send cast: {:?}", err);
@@ -872,10 +998,32 @@ impl Neighbor {
         _res
     }
 
+    // Cast will be sent once Gnome decides there is bandwith for it
+    pub fn queue_cast(&mut self, message: CastMessage) {
+        self.outbox.push(message);
+    }
+
+    pub fn cast_room(&self) -> usize {
+        self.outbox.cast_room()
+    }
+
+    // Send queued casts that fit into given tokens, returns tokens used
+    pub fn flush_outbox(&mut self, tokens: u64, shares: &TrafficShares) -> u64 {
+        let mut tokens_used = 0;
+        for message in self.outbox.schedule(tokens, shares) {
+            tokens_used += message.len() as u64;
+            let _ = self.send_out_cast(message);
+        }
+        tokens_used
+    }
+
     pub fn send_out(&mut self, message: Message) {
         self.gnome_header = message.header;
         // println!("new gn: {}", message.neighborhood.0);
         self.gnome_neighborhood = message.neighborhood;
+        if self.tracer.is_some() {
+            self.trace(TraceEvent::Sent(message.clone()));
+        }
         let _ = self.sender.send(WrappedMessage::Regular(message));
     }
 
@@ -898,15 +1046,37 @@ impl Neighbor {
     // }
 
     pub fn add_unicast(&mut self, swarm_id: SwarmID, cast_id: CastID) {
-        let (sender, receiver) = channel();
-        self.active_unicasts.insert(cast_id, sender);
+        let (sender, receiver) = app_channel();
+        self.active_unicasts
+            .insert(cast_id, ToApp::new(sender, OverflowPolicy::Block));
         self.user_responses
             .push_front(GnomeToApp::Unicast(swarm_id, cast_id, receiver));
-        let _ = self.send_out_cast(CastMessage::new_response(NeighborResponse::Unicast(
+        self.queue_cast(CastMessage::new_response(NeighborResponse::Unicast(
             swarm_id, cast_id,
         )));
     }
 
+    pub fn end_unicast(&mut self, cast_id: CastID) -> bool {
+        self.paused_unicasts.remove(&cast_id);
+        self.active_unicasts.remove(&cast_id).is_some()
+    }
+
+    // Once app has read all held data of a paused unicast, neighbor may go on
+    fn resume_unicasts(&mut self) {
+        let mut resumed = vec![];
+        for id in &self.paused_unicasts {
+            if let Some(to_app) = self.active_unicasts.get_mut(id) {
+                if to_app.flush() && !to_app.is_blocked() {
+                    resumed.push(*id);
+                }
+            }
+        }
+        for id in resumed {
+            self.paused_unicasts.remove(&id);
+            self.queue_cast(CastMessage::new_request(NeighborRequest::ResumeUnicast(id)));
+        }
+    }
+
     pub fn request_data(&mut self, request: NeighborRequest) {
         let _ = self.send_out_cast(CastMessage::new_request(request));
     }
@@ -917,5 +1087,6 @@ impl Neighbor {
             && self.user_responses.is_empty()
             && self.requests.is_empty()
             && self.requested_data.is_empty()
+            && self.outbox.is_empty()
     }
 }
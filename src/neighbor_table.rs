@@ -0,0 +1,416 @@
+use crate::GnomeId;
+use crate::Neighbor;
+use crate::DEFAULT_NEIGHBORS_PER_GNOME;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+// Where given neighbor stands within current turn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeighborStatus {
+    // Responded during previous turn
+    Fast,
+    // Did not respond during previous turn
+    Slow,
+    // Already responded during current turn
+    Refreshed,
+    // Waits for next round to start, we ignore its messages until then
+    New,
+}
+
+impl NeighborStatus {
+    // Neighbors that responded recently
+    pub const RESPONSIVE: [NeighborStatus; 2] = [NeighborStatus::Fast, NeighborStatus::Refreshed];
+    // Neighbors taking part in consensus
+    pub const ACTIVE: [NeighborStatus; 3] = [
+        NeighborStatus::Fast,
+        NeighborStatus::Refreshed,
+        NeighborStatus::Slow,
+    ];
+    pub const ALL: [NeighborStatus; 4] = [
+        NeighborStatus::Fast,
+        NeighborStatus::Refreshed,
+        NeighborStatus::Slow,
+        NeighborStatus::New,
+    ];
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NeighborMetrics {
+    pub turns_served: u32,
+    pub turns_missed: u32,
+    pub served_in_round: bool,
+    // Rounds in a row during which neighbor did not serve a single turn
+    pub rounds_missed: u8,
+    // Bytes we have sent to neighbor since last usage report
+    pub bytes_sent: u64,
+}
+
+impl NeighborMetrics {
+    pub fn loss_permille(&self) -> u16 {
+        let turns = self.turns_served as u64 + self.turns_missed as u64;
+        (self.turns_missed as u64 * 1000)
+            .checked_div(turns)
+            .unwrap_or(0) as u16
+    }
+}
+
+// When a Gnome should let go of neighbors that do not keep up,
+// checked at the end of every round
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NeighborRetention {
+    // Drop after missing more than that many turns out of a thousand
+    pub max_loss_permille: u16,
+    // Loss ratio is meaningless until we have seen that many turns
+    pub min_turns: u32,
+    // Drop after that many rounds in a row without a response
+    pub max_missed_rounds: u8,
+    // Never drop below that many neighbors
+    pub min_neighbors: usize,
+}
+
+impl Default for NeighborRetention {
+    fn default() -> Self {
+        NeighborRetention {
+            max_loss_permille: 875,
+            min_turns: 8,
+            max_missed_rounds: 2,
+            min_neighbors: 1,
+        }
+    }
+}
+
+impl NeighborRetention {
+    pub fn judge(&self, metrics: &NeighborMetrics) -> Option<DropReason> {
+        if metrics.rounds_missed >= self.max_missed_rounds {
+            return Some(DropReason::MissedRounds(metrics.rounds_missed));
+        }
+        let loss = metrics.loss_permille();
+        if metrics.turns_served + metrics.turns_missed >= self.min_turns
+            && loss > self.max_loss_permille
+        {
+            return Some(DropReason::Loss(loss));
+        }
+        None
+    }
+}
+
+// Why a Gnome decided to drop a neighbor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DropReason {
+    // Permille of turns it missed
+    Loss(u16),
+    MissedRounds(u8),
+    // Sent us something that failed verification or policy check
+    Misbehaved,
+    // We were running out of bandwith
+    Bandwith,
+    // We timed out waiting for anyone too many times
+    Unresponsive,
+}
+
+#[derive(Debug)]
+pub struct NeighborEntry {
+    pub neighbor: Neighbor,
+    pub status: NeighborStatus,
+    pub metrics: NeighborMetrics,
+}
+
+impl NeighborEntry {
+    // Out of 1000, anything we hold against a neighbor lowers it:
+    // slow responses, turns it missed, timeouts in recent rounds
+    // and payloads that failed verification
+    pub fn score(&self) -> u32 {
+        let neighbor = &self.neighbor;
+        let mut penalty: u64 = 0;
+        if let Some(rtt) = neighbor.rtt {
+            penalty += rtt.as_millis().min(1000) as u64 / 4;
+        }
+        penalty += self.metrics.loss_permille() as u64 / 4;
+        penalty += 25 * neighbor.timeouts_count() as u64;
+        penalty += 100 * neighbor.verify_failures as u64;
+        1000u64.saturating_sub(penalty) as u32
+    }
+}
+
+// DefaultHasher has fixed keys, so iteration order is the same every run,
+// simulator depends on that to reproduce a history from a seed
+type FixedState = BuildHasherDefault<DefaultHasher>;
+
+// All neighbors of a Gnome keyed by their GnomeId.
+// Methods taking a slice of statuses only look at neighbors
+// with one of those and return them in order of that slice.
+#[derive(Debug, Default)]
+pub struct NeighborTable {
+    entries: HashMap<GnomeId, NeighborEntry, FixedState>,
+}
+
+impl NeighborTable {
+    pub fn new() -> Self {
+        NeighborTable {
+            entries: HashMap::with_capacity_and_hasher(
+                DEFAULT_NEIGHBORS_PER_GNOME,
+                FixedState::default(),
+            ),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn count(&self, status: NeighborStatus) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.status == status)
+            .count()
+    }
+
+    pub fn has_any(&self, statuses: &[NeighborStatus]) -> bool {
+        self.entries
+            .values()
+            .any(|entry| statuses.contains(&entry.status))
+    }
+
+    pub fn metrics(&self, id: GnomeId) -> Option<NeighborMetrics> {
+        self.entries.get(&id).map(|entry| entry.metrics)
+    }
+
+    pub fn score(&self, id: GnomeId) -> Option<u32> {
+        self.entries.get(&id).map(|entry| entry.score())
+    }
+
+    pub fn get(&self, id: GnomeId, statuses: &[NeighborStatus]) -> Option<&Neighbor> {
+        self.entries
+            .get(&id)
+            .filter(|entry| statuses.contains(&entry.status))
+            .map(|entry| &entry.neighbor)
+    }
+
+    pub fn get_mut(&mut self, id: GnomeId, statuses: &[NeighborStatus]) -> Option<&mut Neighbor> {
+        self.entries
+            .get_mut(&id)
+            .filter(|entry| statuses.contains(&entry.status))
+            .map(|entry| &mut entry.neighbor)
+    }
+
+    // Returns a neighbor we had under the same id
+    pub fn insert(&mut self, neighbor: Neighbor, status: NeighborStatus) -> Option<Neighbor> {
+        let entry = NeighborEntry {
+            neighbor,
+            status,
+            metrics: NeighborMetrics::default(),
+        };
+        self.entries
+            .insert(entry.neighbor.id, entry)
+            .map(|old| old.neighbor)
+    }
+
+    pub fn remove(&mut self, id: GnomeId) -> Option<Neighbor> {
+        self.take(id).map(|entry| entry.neighbor)
+    }
+
+    // Take an entry out while Gnome needs both itself and a neighbor mutably,
+    // it has to be put back with restore
+    pub fn take(&mut self, id: GnomeId) -> Option<NeighborEntry> {
+        self.entries.remove(&id)
+    }
+
+    pub fn restore(&mut self, entry: NeighborEntry) {
+        self.entries.insert(entry.neighbor.id, entry);
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = Neighbor> + '_ {
+        self.entries.drain().map(|(_id, entry)| entry.neighbor)
+    }
+
+    pub fn add_sent(&mut self, id: GnomeId, bytes: u64) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.metrics.bytes_sent += bytes;
+        }
+    }
+
+    // Bytes sent to every neighbor since previous call
+    pub fn take_sent(&mut self) -> Vec<(GnomeId, u64)> {
+        let mut sent: Vec<(GnomeId, u64)> = self
+            .entries
+            .values_mut()
+            .map(|entry| {
+                let bytes = std::mem::take(&mut entry.metrics.bytes_sent);
+                (entry.neighbor.id, bytes)
+            })
+            .collect();
+        sent.sort();
+        sent
+    }
+
+    pub fn set_status(&mut self, id: GnomeId, status: NeighborStatus) -> bool {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.status = status;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn ids(&self, statuses: &[NeighborStatus]) -> Vec<GnomeId> {
+        self.iter(statuses).map(|neighbor| neighbor.id).collect()
+    }
+
+    pub fn iter(&self, statuses: &[NeighborStatus]) -> impl Iterator<Item = &Neighbor> {
+        self.select(statuses)
+            .into_iter()
+            .map(|entry| &entry.neighbor)
+    }
+
+    // Highest scored neighbor passing given filter,
+    // self reported bandwith settles a draw
+    pub fn best<F>(&self, statuses: &[NeighborStatus], filter: F) -> Option<GnomeId>
+    where
+        F: Fn(&Neighbor) -> bool,
+    {
+        let mut best: Option<(u32, u64, GnomeId)> = None;
+        for entry in self.select(statuses) {
+            if !filter(&entry.neighbor) {
+                continue;
+            }
+            let key = (
+                entry.score(),
+                entry.neighbor.available_bandwith,
+                entry.neighbor.id,
+            );
+            if best.is_none_or(|(score, band, _id)| (key.0, key.1) > (score, band)) {
+                best = Some(key);
+            }
+        }
+        best.map(|(_score, _band, id)| id)
+    }
+
+    // Lowest scored neighbor passing given filter
+    pub fn worst<F>(&self, statuses: &[NeighborStatus], filter: F) -> Option<GnomeId>
+    where
+        F: Fn(&Neighbor) -> bool,
+    {
+        let mut worst: Option<(u32, u64, GnomeId)> = None;
+        for entry in self.select(statuses) {
+            if !filter(&entry.neighbor) {
+                continue;
+            }
+            let key = (
+                entry.score(),
+                entry.neighbor.available_bandwith,
+                entry.neighbor.id,
+            );
+            if worst.is_none_or(|(score, band, _id)| (key.0, key.1) < (score, band)) {
+                worst = Some(key);
+            }
+        }
+        worst.map(|(_score, _band, id)| id)
+    }
+
+    fn select(&self, statuses: &[NeighborStatus]) -> Vec<&NeighborEntry> {
+        let mut selected: Vec<&NeighborEntry> = self
+            .entries
+            .values()
+            .filter(|entry| statuses.contains(&entry.status))
+            .collect();
+        selected.sort_by_key(|entry| rank(statuses, entry.status));
+        selected
+    }
+
+    pub fn iter_mut(&mut self, statuses: &[NeighborStatus]) -> impl Iterator<Item = &mut Neighbor> {
+        let mut selected: Vec<&mut NeighborEntry> = self
+            .entries
+            .values_mut()
+            .filter(|entry| statuses.contains(&entry.status))
+            .collect();
+        selected.sort_by_key(|entry| rank(statuses, entry.status));
+        selected.into_iter().map(|entry| &mut entry.neighbor)
+    }
+
+    // Neighbors that responded during this turn become fast ones,
+    // those that did not become slow
+    pub fn end_turn(&mut self) {
+        for entry in self.entries.values_mut() {
+            match entry.status {
+                NeighborStatus::Refreshed => {
+                    entry.status = NeighborStatus::Fast;
+                    entry.metrics.turns_served += 1;
+                    entry.metrics.served_in_round = true;
+                }
+                NeighborStatus::Fast => {
+                    entry.status = NeighborStatus::Slow;
+                    entry.metrics.turns_missed += 1;
+                }
+                NeighborStatus::Slow => {
+                    // TODO: here we should also check if given neighbor
+                    //       is a source for any multicast and
+                    //       maybe change it to some other neighbor
+                    entry.neighbor.add_timeout();
+                    entry.metrics.turns_missed += 1;
+                }
+                NeighborStatus::New => {}
+            }
+        }
+    }
+
+    // Turn ended early, neighbors that responded join fast ones
+    // and those still silent stay where they are
+    pub fn merge_refreshed(&mut self) {
+        for entry in self.entries.values_mut() {
+            if entry.status == NeighborStatus::Refreshed {
+                entry.status = NeighborStatus::Fast;
+                entry.metrics.turns_served += 1;
+                entry.metrics.served_in_round = true;
+            }
+        }
+    }
+
+    // Active neighbors that did not respond during whole round
+    // get another missed round on their record
+    pub fn end_round(&mut self) {
+        for entry in self.entries.values_mut() {
+            match entry.status {
+                NeighborStatus::New => {}
+                NeighborStatus::Refreshed => entry.metrics.rounds_missed = 0,
+                NeighborStatus::Fast | NeighborStatus::Slow => {
+                    if entry.metrics.served_in_round {
+                        entry.metrics.rounds_missed = 0;
+                    } else {
+                        entry.metrics.rounds_missed = entry.metrics.rounds_missed.saturating_add(1);
+                    }
+                }
+            }
+            entry.metrics.served_in_round = false;
+        }
+    }
+
+    // Neighbors retention wants us to drop, worst scored first
+    pub fn judge(&self, retention: &NeighborRetention) -> Vec<(GnomeId, DropReason)> {
+        let mut condemned: Vec<(u32, GnomeId, DropReason)> = self
+            .select(&NeighborStatus::ACTIVE)
+            .into_iter()
+            .filter_map(|entry| {
+                retention
+                    .judge(&entry.metrics)
+                    .map(|reason| (entry.score(), entry.neighbor.id, reason))
+            })
+            .collect();
+        condemned.sort_by_key(|(score, _id, _reason)| *score);
+        condemned
+            .into_iter()
+            .map(|(_score, id, reason)| (id, reason))
+            .collect()
+    }
+}
+
+fn rank(statuses: &[NeighborStatus], status: NeighborStatus) -> usize {
+    statuses
+        .iter()
+        .position(|s| *s == status)
+        .unwrap_or(statuses.len())
+}
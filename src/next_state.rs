@@ -3,11 +3,13 @@ use crate::message::Payload;
 use crate::neighbor::Neighborhood;
 use crate::ByteSet;
 use crate::Capabilities;
+use crate::CastDescriptor;
 use crate::CastID;
 use crate::Configuration;
+use crate::Endorsement;
+use crate::Equivocation;
 use crate::GnomeId;
 use crate::Message;
-use crate::Neighbor;
 use crate::Policy;
 use crate::Requirement;
 use crate::SwarmTime;
@@ -24,6 +26,7 @@ pub enum ChangeConfig {
     AddBroadcast {
         id: CastID,
         origin: GnomeId,
+        descriptor: CastDescriptor,
         source: GnomeId,
         filtered_neighbors: Vec<GnomeId>,
         turn_ended: bool,
@@ -35,6 +38,7 @@ pub enum ChangeConfig {
     AddMulticast {
         id: CastID,
         origin: GnomeId,
+        descriptor: CastDescriptor,
         source: GnomeId,
         filtered_neighbors: Vec<GnomeId>,
         turn_ended: bool,
@@ -72,6 +76,26 @@ pub enum ChangeConfig {
         s_data: SyncData,
         turn_ended: bool,
     },
+    RevokeOffender {
+        reporter: GnomeId,
+        evidence: Equivocation,
+        turn_ended: bool,
+    },
+    TransferFounder {
+        founder: GnomeId,
+        new_founder: GnomeId,
+        turn_ended: bool,
+    },
+    AddOwner {
+        founder: GnomeId,
+        owner: GnomeId,
+        turn_ended: bool,
+    },
+    FounderSuccession {
+        candidate: GnomeId,
+        endorsements: Vec<Endorsement>,
+        turn_ended: bool,
+    },
 }
 
 impl ChangeConfig {
@@ -108,9 +132,143 @@ impl ChangeConfig {
             Self::Custom {
                 ref mut turn_ended, ..
             } => *turn_ended = true,
+            Self::RevokeOffender {
+                ref mut turn_ended, ..
+            } => *turn_ended = true,
+            Self::TransferFounder {
+                ref mut turn_ended, ..
+            } => *turn_ended = true,
+            Self::AddOwner {
+                ref mut turn_ended, ..
+            } => *turn_ended = true,
+            Self::FounderSuccession {
+                ref mut turn_ended, ..
+            } => *turn_ended = true,
         }
     }
 
+    // What should change once given configuration gets accepted.
+    // Source is a neighbor we got it from (or ourselves),
+    // signer is whoever signed the configuration.
+    // Configurations that name an originator have to be signed by him.
+    pub fn from_configuration(
+        config: &Configuration,
+        source: GnomeId,
+        signer: GnomeId,
+    ) -> Option<Self> {
+        let originator = match *config {
+            Configuration::StartBroadcast(origin, _, _)
+            | Configuration::EndBroadcast(origin, _)
+            | Configuration::StartMulticast(origin, _, _)
+            | Configuration::EndMulticast(origin, _)
+            | Configuration::ChangeDiameter(origin, _)
+            | Configuration::SetRunningPolicy(origin, _, _)
+            | Configuration::SetRunningCapability(origin, _, _)
+            | Configuration::SetRunningByteSet(origin, _, _)
+            | Configuration::ReportEquivocation(origin, _)
+            | Configuration::TransferFounder(origin, _)
+            | Configuration::AddOwner(origin, _)
+            | Configuration::FounderSuccession(origin, _) => Some(origin),
+            _ => None,
+        };
+        if let Some(originator) = originator {
+            if originator != signer {
+                eprintln!(
+                    "Config {} from {} but signed by {}",
+                    config.header_byte(),
+                    originator,
+                    signer
+                );
+                return None;
+            }
+        }
+        let change = match config {
+            Configuration::StartBroadcast(origin, id, descriptor) => Self::AddBroadcast {
+                id: *id,
+                origin: *origin,
+                descriptor: descriptor.clone(),
+                source,
+                filtered_neighbors: vec![],
+                turn_ended: false,
+            },
+            Configuration::EndBroadcast(_origin, id) => Self::RemoveBroadcast {
+                id: *id,
+                turn_ended: false,
+            },
+            Configuration::StartMulticast(origin, id, descriptor) => Self::AddMulticast {
+                id: *id,
+                origin: *origin,
+                descriptor: descriptor.clone(),
+                source,
+                filtered_neighbors: vec![],
+                turn_ended: false,
+            },
+            Configuration::EndMulticast(_origin, id) => Self::RemoveMulticast {
+                id: *id,
+                turn_ended: false,
+            },
+            Configuration::InsertPubkey(id, key) => Self::InsertPubkey {
+                id: *id,
+                key: key.clone(),
+                turn_ended: false,
+            },
+            Configuration::ChangeDiameter(originator, diameter) => Self::SetDiameter {
+                originator: *originator,
+                new_value: SwarmTime(*diameter as u32),
+                turn_ended: false,
+            },
+            Configuration::SetRunningPolicy(originator, policy, req) => Self::SetPolicy {
+                originator: *originator,
+                policy: *policy,
+                req: req.clone(),
+                turn_ended: false,
+            },
+            Configuration::SetRunningCapability(originator, cap, members) => Self::SetCapability {
+                originator: *originator,
+                cap: *cap,
+                members: members.clone(),
+                turn_ended: false,
+            },
+            Configuration::SetRunningByteSet(originator, b_id, bset) => Self::SetByteSet {
+                originator: *originator,
+                b_id: *b_id,
+                bset: bset.clone(),
+                turn_ended: false,
+            },
+            Configuration::ReportEquivocation(reporter, evidence) => Self::RevokeOffender {
+                reporter: *reporter,
+                evidence: evidence.clone(),
+                turn_ended: false,
+            },
+            Configuration::TransferFounder(founder, new_founder) => Self::TransferFounder {
+                founder: *founder,
+                new_founder: *new_founder,
+                turn_ended: false,
+            },
+            Configuration::AddOwner(founder, owner) => Self::AddOwner {
+                founder: *founder,
+                owner: *owner,
+                turn_ended: false,
+            },
+            Configuration::FounderSuccession(candidate, endorsements) => Self::FounderSuccession {
+                candidate: *candidate,
+                endorsements: endorsements.clone(),
+                turn_ended: false,
+            },
+            Configuration::UserDefined(id, s_data) => Self::Custom {
+                id: *id,
+                signed_by: signer,
+                s_data: s_data.clone(),
+                turn_ended: false,
+            },
+            other => {
+                eprintln!("Unhandled config: {:?}", other);
+                return None;
+            }
+        };
+        Some(change)
+    }
+
     pub fn add_filtered_neighbor(&mut self, gnome_id: GnomeId) {
         if let Self::AddBroadcast {
             ref mut filtered_neighbors,
@@ -137,16 +295,188 @@ impl ChangeConfig {
     }
 }
 
+// Latest message a Neighbor has sent us,
+// this is all NextState needs to know about him.
+#[derive(Clone, Debug)]
+pub struct NeighborView {
+    pub id: GnomeId,
+    pub swarm_time: SwarmTime,
+    pub neighborhood: Neighborhood,
+    pub header: Header,
+    pub payload: Payload,
+}
+
+// Where our Gnome is within current round.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RoundPosition {
+    pub swarm_time: SwarmTime,
+    pub round_start: SwarmTime,
+    pub neighborhood: Neighborhood,
+    pub header: Header,
+}
+
+// What happens with a round once a turn is over.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TurnOutcome {
+    // Round goes on
+    Continue,
+    // Every gnome in swarm is aware of our proposal, we accept it
+    Accepted,
+    // Round took twice the swarm diameter and our proposal
+    // has not reached everyone, it is dropped
+    TimedOut,
+    // Round without any proposal is over, swarm time gets synced
+    Synced,
+}
+
+// Whether our Gnome should end current turn now.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TurnEnd {
+    // Keep waiting for neighbors
+    Wait,
+    // Send our message for next turn
+    Now,
+    // Turn timed out and none of our neighbors has answered
+    NoReply,
+}
+
+impl TurnEnd {
+    // A turn ends once our neighbors have moved on, we have something to send
+    // right away, or it has timed out and there is someone to send to.
+    pub fn decide(
+        advance: bool,
+        send_immediate: bool,
+        timed_out: bool,
+        have_responsive_neighbors: bool,
+    ) -> Self {
+        if advance || send_immediate || timed_out && have_responsive_neighbors {
+            TurnEnd::Now
+        } else if timed_out {
+            TurnEnd::NoReply
+        } else {
+            TurnEnd::Wait
+        }
+    }
+}
+
+impl RoundPosition {
+    // Neighborhood tells how many turns in a row
+    // all of our neighbors had the same header as we do.
+    // Once it reaches swarm diameter, every gnome in the swarm
+    // has seen our header.
+    pub fn outcome(&self, diameter: SwarmTime) -> TurnOutcome {
+        let all_gnomes_aware = self.neighborhood.0 as u32 >= diameter.0;
+        let finish_round = self.swarm_time - self.round_start >= diameter + diameter;
+        if !all_gnomes_aware && !finish_round {
+            TurnOutcome::Continue
+        } else if !self.header.non_zero_block() && !self.header.is_reconfigure() {
+            TurnOutcome::Synced
+        } else if all_gnomes_aware {
+            TurnOutcome::Accepted
+        } else {
+            TurnOutcome::TimedOut
+        }
+    }
+
+    // We can only put in our own proposal when swarm is idle
+    // and it is early enough in a round for it to win.
+    pub fn can_propose(&self) -> bool {
+        self.header == Header::Sync && self.round_start > SwarmTime(0) && self.neighborhood.0 <= 1
+    }
+
+    // Whether a Neighbor we last saw at this position may announce next one.
+    // We also need neighborhood he announced a turn before, if he kept
+    // the same header, and header with neighborhood we last sent him.
+    pub fn may_move_to(
+        &self,
+        next: &RoundPosition,
+        prev_neighborhood: Option<Neighborhood>,
+        ours: (Header, Neighborhood),
+        diameter: SwarmTime,
+    ) -> bool {
+        if self.swarm_time > next.swarm_time {
+            eprintln!(
+                "Received a message with older swarm_time {} than previous {}!",
+                next.swarm_time, self.swarm_time
+            );
+            return false;
+        }
+        // A neighbor can not announce a number greater than the number
+        // we announced to him, plus one
+        let hood_inc_limited = ours.0 != next.header || next.neighborhood.0 <= ours.1 .0 + 1;
+        if !hood_inc_limited {
+            eprintln!(
+                "{} fail hood_inc_limited neighbor {} <= {} gnome",
+                next.swarm_time,
+                next.neighborhood.0,
+                ours.1 .0 + 1
+            );
+        }
+        if self.header == next.header {
+            // A gnome can not stay at the same neighborhood number for more than
+            // 2 turns, unless his round is over and he starts from 0
+            let hood_increased = if let Some(prev) = prev_neighborhood {
+                (next.neighborhood.0 == 0 && u32::from(self.neighborhood.0 + 1) >= diameter.0)
+                    || next.neighborhood.0 > prev.0
+                    || next.neighborhood.0 > self.neighborhood.0
+            } else {
+                true
+            };
+            if !hood_increased {
+                eprintln!(
+                    "{} fail hood_increased prev:{:?} curr:{} recv:{} ",
+                    next.swarm_time, prev_neighborhood, self.neighborhood, next.neighborhood
+                );
+            }
+            return hood_increased && hood_inc_limited;
+        }
+        let no_backdating = next.swarm_time - self.round_start < diameter + diameter;
+        if !no_backdating {
+            eprintln!(
+                "backdating: {}-{}<{}",
+                next.swarm_time, self.round_start, diameter
+            );
+        }
+        // A header can only be replaced by a higher one, except for Block
+        // that ends a round with a Reconfigure
+        match (self.header, next.header) {
+            (Header::Block(id), Header::Block(new_id)) => {
+                new_id >= id && no_backdating && hood_inc_limited
+            }
+            (Header::Block(_id), _other) => false,
+            (Header::Reconfigure(ct, gid), Header::Reconfigure(new_ct, new_gid))
+                if new_ct > ct || new_gid > gid =>
+            {
+                no_backdating
+            }
+            (Header::Reconfigure(_ct, _gid), Header::Sync) => false,
+            _other => no_backdating && hood_inc_limited,
+        }
+    }
+}
+
+// Round state machine of a Gnome.
+//
+// A round is a sequence of turns. In each turn we collect latest messages
+// of our neighbors with update, then next_position tells us what to send in
+// next turn, and RoundPosition::outcome whether that round is over.
+// After that reset_for_next_turn starts a new turn, or a new round.
+//
+// Within a turn:
+// - highest header wins (Sync < Reconfigure < Block), a Neighbor with
+//   higher header than ours makes us adopt his proposal, and we start
+//   counting neighborhood from 0,
+// - a Neighbor with the same header but lower neighborhood
+//   brings our neighborhood down to his,
+// - messages older than last accepted one are ignored,
+// - neighborhood only grows when all neighbors that spoke to us
+//   had the same header as we do.
 #[derive(Debug)]
 pub struct NextState {
     pub neighborhood: Neighborhood,
     pub swarm_time: SwarmTime,
     pub swarm_time_max: SwarmTime,
     pub change_config: ChangeConfig,
-    // pub block_id: BlockID,
-    // pub last_accepted_block: BlockID,
-    // pub last_accepted_reconf: Option<Configuration>,
-    // pub data: Data,
     pub header: Header,
     pub last_accepted_message: Message,
     pub payload: Payload,
@@ -161,10 +491,6 @@ impl NextState {
             swarm_time: SwarmTime(0),
             swarm_time_max: SwarmTime(u32::MAX),
             change_config: ChangeConfig::None,
-            // block_id: BlockID(0),
-            // last_accepted_block: BlockID(0),
-            // last_accepted_reconf: None,
-            // data: Data(0),
             header: Header::Sync,
             all_neighbors_same_header: true,
             last_accepted_message: Message::block(),
@@ -173,267 +499,54 @@ impl NextState {
         }
     }
 
-    // TODO: I have to redesign this barely functioning logic,
-    // but I am not yet ready to do so - don't know how.
-    //
-    // We have a lot of stuff going on here:
-    // - neighbors come and go
-    // - there is local neighbor state
-    // - there is our gnome's state
-    // - and there are incoming messages from neighbors that can come out of order
-    //   or not at all
-    //
-    // We should only update from a neighbor if he has a new message: DONE
-    // We should not increase Neighborhood if no message was received this turn: DONE?
-    pub fn update(&mut self, neighbor: &mut Neighbor) {
-        if !neighbor.new_message_recieved {
-            return;
-        }
+    pub fn update(&mut self, neighbor: &NeighborView) {
         self.turn_update_with_a_message = true;
-        neighbor.new_message_recieved = false;
-        // println!("Update {:?}", neighbor);
-        let neighbor_st = neighbor.swarm_time;
-
-        if neighbor_st < self.last_accepted_message.swarm_time {
+        if neighbor.swarm_time < self.last_accepted_message.swarm_time {
             return;
         }
-        if neighbor_st < self.swarm_time_max && neighbor_st < self.swarm_time {
-            self.swarm_time = neighbor_st;
+        if neighbor.swarm_time < self.swarm_time_max && neighbor.swarm_time < self.swarm_time {
+            self.swarm_time = neighbor.swarm_time;
         }
-
-        // let block_id_received = match neighbor.header {
-        //     Header::Sync => BlockID(0),
-        //     Header::Reconfigure => BlockID(0),
-        //     Header::Block(b_id) => b_id,
-        // };
-        // let data_received = match neighbor.payload {
-        //     Payload::Block(_b_id, data) => data,
-        //     Payload::Reconfigure(config) => Data(config.as_u32()),
-        //     _ => Data(0),
-        // };
-        // println!("id, data: {} {}", block_id_received, data_received);
-
-        // if self.block_id != block_id_received {
-        //     self.all_neighbors_same_header = false;
-        //     // println!("fols");
-        // }
         if self.header != neighbor.header {
             self.all_neighbors_same_header = false;
-            // println!("fols");
         }
 
-        // println!("{} > {}", block_id_received, self.block_id);
-        // if block_id_received > self.block_id
-        //     || (block_id_received == self.block_id && data_received.0 > self.data.0)
         if neighbor.header > self.header {
-            // println!("N {} > {}", neighbor.header, self.header);
-            // println!("P: {:?}", neighbor.payload);
-            // self.block_id = block_id_received;
             self.header = neighbor.header;
             self.payload = neighbor.payload.clone();
             self.neighborhood = Neighborhood(0);
-            // } else if block_id_received == self.block_id
             if self.header.is_reconfigure() {
-                // TODO: make use of signature
                 if let Payload::Reconfigure(ref signature, ref config) = self.payload {
-                    match config {
-                        Configuration::StartBroadcast(origin, id) => {
-                            if signature.gnome_id() == *origin {
-                                self.change_config = ChangeConfig::AddBroadcast {
-                                    id: *id,
-                                    origin: *origin,
-                                    source: neighbor.id,
-                                    filtered_neighbors: vec![],
-                                    turn_ended: false,
-                                };
-                            } else {
-                                eprintln!(
-                                    "StartBroadcast from {} but signed by {}",
-                                    origin,
-                                    signature.gnome_id()
-                                );
-                            }
-                        }
-                        Configuration::EndBroadcast(origin, id) => {
-                            if signature.gnome_id() == *origin {
-                                self.change_config = ChangeConfig::RemoveBroadcast {
-                                    id: *id,
-                                    turn_ended: false,
-                                };
-                            } else {
-                                eprintln!(
-                                    "EndBroadcast from {} but signed by {}",
-                                    origin,
-                                    signature.gnome_id()
-                                );
-                            }
-                        }
-                        Configuration::EndMulticast(origin, id) => {
-                            if signature.gnome_id() == *origin {
-                                self.change_config = ChangeConfig::RemoveMulticast {
-                                    id: *id,
-                                    turn_ended: false,
-                                };
-                            } else {
-                                eprintln!(
-                                    "EndMulticast from {} but signed by {}",
-                                    origin,
-                                    signature.gnome_id()
-                                );
-                            }
-                        }
-                        Configuration::StartMulticast(origin, id) => {
-                            if signature.gnome_id() == *origin {
-                                self.change_config = ChangeConfig::AddMulticast {
-                                    id: *id,
-                                    origin: *origin,
-                                    source: neighbor.id,
-                                    filtered_neighbors: vec![],
-                                    turn_ended: false,
-                                };
-                            } else {
-                                eprintln!(
-                                    "StartMulticast from {} but signed by {}",
-                                    origin,
-                                    signature.gnome_id()
-                                );
-                            }
-                        }
-                        Configuration::InsertPubkey(id, key) => {
-                            // if signature.gnome_id() == *id {
-                            self.change_config = ChangeConfig::InsertPubkey {
-                                id: *id,
-                                key: key.clone(),
-                                turn_ended: false,
-                            };
-                            // } else {
-                            //     eprintln!(
-                            //         "InsertPubkey from {} but signed by {}",
-                            //         id,
-                            //         signature.gnome_id()
-                            //     );
-                            // }
-                        }
-                        Configuration::ChangeDiameter(originator, diameter) => {
-                            if signature.gnome_id() == *originator {
-                                self.change_config = ChangeConfig::SetDiameter {
-                                    originator: *originator,
-                                    new_value: SwarmTime(*diameter as u32),
-                                    turn_ended: false,
-                                };
-                            } else {
-                                eprintln!(
-                                    "SetDiameter from {} but signed by {}",
-                                    originator,
-                                    signature.gnome_id()
-                                );
-                            }
-                        }
-                        Configuration::SetRunningPolicy(originator, pol, req) => {
-                            if signature.gnome_id() == *originator {
-                                self.change_config = ChangeConfig::SetPolicy {
-                                    originator: *originator,
-                                    policy: *pol,
-                                    req: req.clone(),
-                                    turn_ended: false,
-                                };
-                            } else {
-                                eprintln!(
-                                    "SetPolicy from {} but signed by {}",
-                                    originator,
-                                    signature.gnome_id()
-                                );
-                            }
-                        }
-                        Configuration::SetRunningCapability(originator, cap, v_gids) => {
-                            if signature.gnome_id() == *originator {
-                                self.change_config = ChangeConfig::SetCapability {
-                                    originator: *originator,
-                                    cap: *cap,
-                                    members: v_gids.clone(),
-                                    turn_ended: false,
-                                };
-                            } else {
-                                eprintln!(
-                                    "SetPolicy from {} but signed by {}",
-                                    originator,
-                                    signature.gnome_id()
-                                );
-                            }
-                        }
-                        Configuration::SetRunningByteSet(originator, b_id, bset) => {
-                            if signature.gnome_id() == *originator {
-                                self.change_config = ChangeConfig::SetByteSet {
-                                    originator: *originator,
-                                    b_id: *b_id,
-                                    bset: bset.clone(),
-                                    turn_ended: false,
-                                };
-                            } else {
-                                eprintln!(
-                                    "SetPolicy from {} but signed by {}",
-                                    originator,
-                                    signature.gnome_id()
-                                );
-                            }
-                        }
-                        Configuration::UserDefined(id, s_data) => {
-                            self.change_config = ChangeConfig::Custom {
-                                id: *id,
-                                signed_by: signature.gnome_id(),
-                                s_data: s_data.clone(),
-                                turn_ended: false,
-                            };
-                        }
-                        other => {
-                            println!("Unhandled config: {:?}", other);
-                        }
+                    if let Some(change) =
+                        ChangeConfig::from_configuration(config, neighbor.id, signature.gnome_id())
+                    {
+                        self.change_config = change;
                     }
                 }
             }
         } else if neighbor.header == self.header && self.neighborhood.0 > neighbor.neighborhood.0 {
-            // println!("nhood downgraded from {}", self.neighborhood);
             self.neighborhood = neighbor.neighborhood;
-            // println!("nhood downgraded to {}", self.neighborhood);
-            if neighbor.header.is_reconfigure() {
-                if let Payload::Reconfigure(ref _signature, ref config) = neighbor.payload {
-                    match config {
-                        Configuration::StartBroadcast(g_id, _c_id) => {
-                            if neighbor.id.0 != g_id.0 {
-                                self.change_config.add_filtered_neighbor(neighbor.id);
-                            }
-                        }
-                        Configuration::StartMulticast(g_id, _c_id) => {
-                            if neighbor.id.0 != g_id.0 {
-                                self.change_config
-                                    .add_filtered_neighbor_multicast(neighbor.id);
-                            }
-                        }
-                        _ => {
-                            println!("Unhandled config update");
-                        }
+            if !neighbor.header.is_reconfigure() {
+                return;
+            }
+            if let Payload::Reconfigure(ref _signature, ref config) = neighbor.payload {
+                // Neighbor is closer to origin than we are,
+                // so he should be our source of casted data
+                match config {
+                    Configuration::StartBroadcast(g_id, _c_id, _desc) if neighbor.id != *g_id => {
+                        self.change_config.add_filtered_neighbor(neighbor.id);
+                    }
+                    Configuration::StartMulticast(g_id, _c_id, _desc) if neighbor.id != *g_id => {
+                        self.change_config
+                            .add_filtered_neighbor_multicast(neighbor.id);
                     }
+                    // Only cast origins matter for who we take data from
+                    _other => {}
                 }
             }
-            // TODO: handle reconfig
-            // } else {
-            //     println!("Unhandled next_state {:?} update: {:?}", self, neighbor);
         }
-        // println!("Po wszystkim: {} {}", self.block_id, self.data);
     }
 
-    // fn next_swarm_time(&mut self) {
-    //     let old_prev_st = self.prev_swarm_time;
-    //     self.prev_swarm_time = self.swarm_time;
-    //     self.swarm_time = if self.swarm_time.0 == u32::MAX {
-    //         SwarmTime(0)
-    //     } else if old_prev_st == self.prev_swarm_time {
-    //         self.swarm_time.inc()
-    //     } else {
-    //         self.swarm_time
-    //     };
-    //     self.swarm_time_max = self.swarm_time.inc();
-    // }
     fn next_swarm_time(&mut self) {
         self.swarm_time = if self.swarm_time.0 == u32::MAX {
             SwarmTime(0)
@@ -443,111 +556,62 @@ impl NextState {
         self.swarm_time_max = self.swarm_time.inc();
     }
 
-    // pub fn next_params(&self) -> (SwarmTime, Neighborhood, BlockID, Data) {
-    pub fn next_params(&self) -> (SwarmTime, Neighborhood, Header, Payload) {
-        // if self.all_neighbors_same_header {
-        // println!("next_params 1 {}", self.swarm_time);
+    // Where we should be in next turn, given where we are now.
+    // When swarm is ahead of us by two diameters or more, we are catching up
+    // and our neighborhood stays as it was.
+    pub fn next_position(
+        &self,
+        current: RoundPosition,
+        diameter: SwarmTime,
+    ) -> (RoundPosition, Payload) {
+        let swarm_time = self.swarm_time.inc();
+        let catching_up = swarm_time
+            .0
+            .checked_sub(current.swarm_time.0)
+            .map(|ahead| ahead >= diameter.0 + diameter.0)
+            .unwrap_or(false);
+        let neighborhood = if catching_up {
+            eprintln!("Not updating neighborhood when catching up with swarm");
+            current.neighborhood
+        } else {
+            self.get_next_nhood()
+        };
         (
-            self.swarm_time.inc(),
-            self.get_next_nhood(),
-            self.header,
+            RoundPosition {
+                swarm_time,
+                round_start: current.round_start,
+                neighborhood,
+                header: self.header,
+            },
             self.payload.clone(),
         )
-        // } else {
-        //     // println!("next_params 2 {}", self.swarm_time);
-        //     (
-        //         self.swarm_time.inc(),
-        //         self.neighborhood,
-        //         self.header,
-        //         self.payload.clone(),
-        //     )
-        // }
     }
 
     fn get_next_nhood(&self) -> Neighborhood {
-        if self.all_neighbors_same_header {
-            if self.turn_update_with_a_message {
-                //     print!(">inc<");
-                self.neighborhood.inc()
-            } else {
-                //     print!(">no inc<");
-                self.neighborhood
-            }
+        if self.all_neighbors_same_header && self.turn_update_with_a_message {
+            self.neighborhood.inc()
         } else {
             self.neighborhood
         }
     }
 
-    // pub fn reset_for_next_turn(&mut self, new_round: bool, block_id: BlockID, data: Data) {
+    // Gnome has decided what to send in next turn,
+    // he may have put in his own proposal or started a new round.
     pub fn reset_for_next_turn(&mut self, new_round: bool, header: Header, payload: Payload) {
-        // println!("reset {}", self.swarm_time);
         self.next_swarm_time();
         self.turn_update_with_a_message = false;
-        // self.block_id = block_id;
-        // self.data = data;
         self.header = header;
         self.payload = payload.clone();
         self.all_neighbors_same_header = true;
         self.change_config.end_turn();
         self.neighborhood = if new_round {
-            self.change_config = if header.is_reconfigure() {
-                if let Payload::Reconfigure(_signature, config) = payload {
-                    match config {
-                        Configuration::StartBroadcast(origin, id) => ChangeConfig::AddBroadcast {
-                            id,
-                            origin,
-                            source: origin,
-                            filtered_neighbors: vec![],
-                            turn_ended: false,
-                        },
-                        Configuration::StartMulticast(origin, id) => ChangeConfig::AddMulticast {
-                            id,
-                            origin,
-                            source: origin,
-                            filtered_neighbors: vec![],
-                            turn_ended: false,
-                        },
-                        Configuration::ChangeDiameter(g_id, new_diameter) => {
-                            ChangeConfig::SetDiameter {
-                                originator: g_id,
-                                new_value: SwarmTime(new_diameter as u32),
-                                turn_ended: false,
-                            }
-                        }
-                        Configuration::SetRunningPolicy(g_id, pol, req) => {
-                            ChangeConfig::SetPolicy {
-                                originator: g_id,
-                                policy: pol,
-                                req: req,
-                                turn_ended: false,
-                            }
-                        }
-                        Configuration::SetRunningCapability(g_id, cap, members) => {
-                            ChangeConfig::SetCapability {
-                                originator: g_id,
-                                cap,
-                                members,
-                                turn_ended: false,
-                            }
-                        }
-                        Configuration::SetRunningByteSet(g_id, b_id, bset) => {
-                            ChangeConfig::SetByteSet {
-                                originator: g_id,
-                                b_id,
-                                bset,
-                                turn_ended: false,
-                            }
-                        }
-                        _ => {
-                            // TODO: handle other configs
-                            println!("Unhandled config in reset_for_next_turn");
-                            ChangeConfig::None
-                        }
-                    }
-                } else {
-                    println!("Unexpected payload in reset_for_next_turn");
-                    ChangeConfig::None
-                }
+            self.change_config = if let Payload::Reconfigure(signature, config) = payload {
+                ChangeConfig::from_configuration(
+                    &config,
+                    signature.gnome_id(),
+                    signature.gnome_id(),
+                )
+                .unwrap_or(ChangeConfig::None)
             } else {
                 ChangeConfig::None
             };
@@ -556,6 +620,5 @@ impl NextState {
         } else {
             self.neighborhood.inc()
         };
-        // println!("bid: {}", self.block_id);
     }
 }
@@ -0,0 +1,105 @@
+use crate::CastContent;
+use crate::CastMessage;
+use std::collections::VecDeque;
+
+// How many cast messages can wait for a single neighbor,
+// casts are not read from their sources until there is room
+pub const MAX_CAST_BACKLOG: usize = 8;
+
+// Consensus Messages are never queued, they are sent out right away.
+// Everything else waits in neighbor's Outbox until Gnome decides
+// it can be sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrafficClass {
+    // Neighbor requests and responses, including swarm sync
+    Sync,
+    // Data of broadcasts, multicasts and unicasts
+    Cast,
+}
+
+impl TrafficClass {
+    pub fn of(message: &CastMessage) -> Self {
+        match message.content {
+            CastContent::Data(_) | CastContent::Sequenced(_, _) | CastContent::History(_) => {
+                TrafficClass::Cast
+            }
+            CastContent::Request(_) | CastContent::Response(_) => TrafficClass::Sync,
+        }
+    }
+}
+
+// How neighbor's tokens are split between Sync and Cast traffic.
+// Values are weights, whatever one class leaves unused the other can take.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrafficShares {
+    pub sync: u8,
+    pub cast: u8,
+}
+
+impl Default for TrafficShares {
+    fn default() -> Self {
+        TrafficShares { sync: 3, cast: 1 }
+    }
+}
+
+#[derive(Debug)]
+pub struct Outbox {
+    sync: VecDeque<CastMessage>,
+    cast: VecDeque<CastMessage>,
+}
+
+impl Outbox {
+    pub fn new() -> Self {
+        Outbox {
+            sync: VecDeque::new(),
+            cast: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, message: CastMessage) {
+        match TrafficClass::of(&message) {
+            TrafficClass::Sync => self.sync.push_back(message),
+            TrafficClass::Cast => self.cast.push_back(message),
+        }
+    }
+
+    pub fn cast_room(&self) -> usize {
+        MAX_CAST_BACKLOG.saturating_sub(self.cast.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sync.is_empty() && self.cast.is_empty()
+    }
+
+    // Take messages that can be sent with given tokens.
+    // First each class spends its share, then what is left
+    // goes to whichever class still has something to send.
+    // A message is taken as long as there are any tokens left,
+    // so that messages bigger than neighbor's bucket are not stuck.
+    pub fn schedule(&mut self, tokens: u64, shares: &TrafficShares) -> Vec<CastMessage> {
+        let mut scheduled = vec![];
+        let weights = shares.sync as u64 + shares.cast as u64;
+        let sync_share = (tokens * shares.sync as u64)
+            .checked_div(weights)
+            .unwrap_or(tokens / 2);
+        let sync_left = take(&mut self.sync, sync_share, &mut scheduled);
+        let cast_left = take(&mut self.cast, tokens - sync_share, &mut scheduled);
+        let left = sync_left + cast_left;
+        let left = take(&mut self.sync, left, &mut scheduled);
+        take(&mut self.cast, left, &mut scheduled);
+        scheduled
+    }
+}
+
+// Returns how many tokens were not used
+fn take(queue: &mut VecDeque<CastMessage>, mut tokens: u64, taken: &mut Vec<CastMessage>) -> u64 {
+    while tokens > 0 {
+        if let Some(message) = queue.pop_front() {
+            tokens = tokens.saturating_sub(message.len() as u64);
+            taken.push(message);
+        } else {
+            break;
+        }
+    }
+    tokens
+}
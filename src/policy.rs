@@ -1,3 +1,5 @@
+use crate::message::MAX_USER_CONFIG_ID;
+
 #[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, PartialOrd, Ord)]
 pub enum Policy {
     Default,
@@ -13,6 +15,11 @@ pub enum Policy {
     ModifyGroup,
     InsertPubkey,
     DataWithFirstByte(u8),
+    ReportEquivocation,
+    TransferFounder,
+    AddOwner,
+    FounderSuccession,
+    // Only 0..=MAX_USER_CONFIG_ID, higher bytes belong to system policies
     UserDefined(u8),
 }
 
@@ -36,6 +43,10 @@ impl Policy {
                 let byte = bytes.drain(0..1).next().unwrap();
                 Policy::DataWithFirstByte(byte)
             }
+            242 => Policy::ReportEquivocation,
+            241 => Policy::TransferFounder,
+            240 => Policy::AddOwner,
+            239 => Policy::FounderSuccession,
             other => Policy::UserDefined(other),
         }
     }
@@ -58,6 +69,10 @@ impl Policy {
                 bytes.push(243);
                 bytes.push(*byte);
             }
+            Policy::ReportEquivocation => bytes.push(242),
+            Policy::TransferFounder => bytes.push(241),
+            Policy::AddOwner => bytes.push(240),
+            Policy::FounderSuccession => bytes.push(239),
             Policy::UserDefined(other) => bytes.push(*other),
         }
     }
@@ -77,6 +92,10 @@ impl Policy {
             Policy::ModifyGroup => "ModifyGroup".to_string(),
             Policy::InsertPubkey => "InsertPubkey".to_string(),
             Policy::DataWithFirstByte(b) => format!("DataWithFirstByte({})", b),
+            Policy::ReportEquivocation => "ReportEquivocation".to_string(),
+            Policy::TransferFounder => "TransferFounder".to_string(),
+            Policy::AddOwner => "AddOwner".to_string(),
+            Policy::FounderSuccession => "FounderSuccession".to_string(),
             Policy::UserDefined(o) => format!("UserDefined({})", o),
         }
     }
@@ -116,7 +135,12 @@ impl PolIter {
         for i in 0..=255 {
             items.push(Policy::DataWithFirstByte(i));
         }
-        for i in 0..=242 {
+        items.push(Policy::ReportEquivocation);
+        items.push(Policy::TransferFounder);
+        items.push(Policy::AddOwner);
+        items.push(Policy::FounderSuccession);
+        // UserDefined policies mirror user config ids, system ones are listed above
+        for i in 0..=MAX_USER_CONFIG_ID {
             items.push(Policy::UserDefined(i));
         }
         PolIter { items }
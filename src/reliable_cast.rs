@@ -0,0 +1,133 @@
+use crate::CastData;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+
+// How many delivered messages are kept for repairing subscribers,
+// and how many out of order messages are held waiting for a repair
+pub const RETRANSMIT_BUFFER: usize = 64;
+// When a gap is still there after this many messages we ask again,
+// each next time another source
+const NACK_RETRY_AFTER: u8 = 8;
+// Gaps that could not be repaired with this many NACKs are skipped
+const MAX_NACKS: u8 = 4;
+
+// Sequence numbering and reordering for casts in reliable mode.
+// Origin numbers every message, everyone else delivers them in order,
+// keeps a window of delivered messages for repairs and
+// asks for missing ones with a CastNack.
+// Only gaps followed by another message can be noticed.
+pub struct ReliableCast {
+    // Given out by origin
+    next_seq: u32,
+    // Next sequence number to deliver
+    expected: Option<u32>,
+    delivered: VecDeque<(u32, CastData)>,
+    held: BTreeMap<u32, CastData>,
+    // Messages received since we last asked for current gap
+    waiting: u8,
+    nacks_sent: u8,
+}
+
+impl ReliableCast {
+    pub fn new() -> Self {
+        ReliableCast {
+            next_seq: 0,
+            expected: None,
+            delivered: VecDeque::new(),
+            held: BTreeMap::new(),
+            waiting: 0,
+            nacks_sent: 0,
+        }
+    }
+
+    // Used only by origin
+    pub fn stamp(&mut self) -> u32 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    pub fn receive(&mut self, seq: u32, data: CastData) {
+        // We join a cast with whatever comes first
+        let expected = *self.expected.get_or_insert(seq);
+        if seq < expected {
+            return;
+        }
+        self.held.insert(seq, data);
+        if self.has_gap() {
+            self.waiting = self.waiting.saturating_add(1);
+            if self.held.len() > RETRANSMIT_BUFFER {
+                self.skip_gap();
+            }
+        }
+    }
+
+    // Messages that can now be delivered, in order
+    pub fn take_in_order(&mut self) -> Vec<(u32, CastData)> {
+        let mut ready = vec![];
+        let mut expected = if let Some(expected) = self.expected {
+            expected
+        } else {
+            return ready;
+        };
+        while let Some(data) = self.held.remove(&expected) {
+            if self.delivered.len() == RETRANSMIT_BUFFER {
+                self.delivered.pop_front();
+            }
+            self.delivered.push_back((expected, data.clone()));
+            ready.push((expected, data));
+            expected += 1;
+        }
+        if !ready.is_empty() {
+            self.waiting = 0;
+            self.nacks_sent = 0;
+        }
+        self.expected = Some(expected);
+        ready
+    }
+
+    // Which attempt this is and what is missing, when it is time to ask.
+    // After too many attempts the gap is skipped.
+    pub fn nack(&mut self) -> Option<(u8, Vec<u32>)> {
+        if !self.has_gap() || (self.nacks_sent > 0 && self.waiting < NACK_RETRY_AFTER) {
+            return None;
+        }
+        if self.nacks_sent >= MAX_NACKS {
+            self.skip_gap();
+            return None;
+        }
+        let attempt = self.nacks_sent;
+        self.nacks_sent += 1;
+        self.waiting = 0;
+        let first_held = *self.held.keys().next().unwrap();
+        let missing = (self.expected.unwrap()..first_held)
+            .take(RETRANSMIT_BUFFER)
+            .collect();
+        Some((attempt, missing))
+    }
+
+    pub fn retransmit(&self, seqs: &[u32]) -> Vec<(u32, CastData)> {
+        self.delivered
+            .iter()
+            .filter(|(seq, _data)| seqs.contains(seq))
+            .cloned()
+            .collect()
+    }
+
+    fn has_gap(&self) -> bool {
+        !self.held.is_empty() && !self.held.contains_key(&self.expected.unwrap())
+    }
+
+    fn skip_gap(&mut self) {
+        if let Some(first_held) = self.held.keys().next() {
+            eprintln!(
+                "Giving up on casts {}..{}",
+                self.expected.unwrap(),
+                first_held
+            );
+            self.expected = Some(*first_held);
+        }
+        self.waiting = 0;
+        self.nacks_sent = 0;
+    }
+}
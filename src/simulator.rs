@@ -0,0 +1,575 @@
+use crate::clock::Clock;
+use crate::gnome::Gnome;
+use crate::gnome::JobState;
+use crate::CastMessage;
+use crate::GnomeId;
+use crate::GnomeToApp;
+use crate::GnomeToManager;
+use crate::ManagerToGnome;
+use crate::Message;
+use crate::Neighbor;
+use crate::Swarm;
+use crate::SwarmID;
+use crate::SwarmName;
+use crate::SwarmTime;
+use crate::ToGnome;
+use crate::WrappedMessage;
+use crate::DEFAULT_SWARM_DIAMETER;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Deterministic in-process swarm.
+// Every Gnome is driven step by step from a single thread, all of them
+// share one ManualClock, and every message travels through a link
+// with a latency drawn from a seeded generator.
+// Same seed and same requests always give the same event streams.
+// Links can be made faulty with set_faults and partition.
+
+const BANDWIDTH: u64 = 1 << 20;
+// Virtual time consumed by a single step of a gnome
+const STEP_COST: Duration = Duration::from_micros(50);
+const MIN_LATENCY: Duration = Duration::from_millis(2);
+const MAX_JITTER_USEC: u64 = 1000;
+
+// Xorshift is good enough to shuffle latencies
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.max(1))
+    }
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.0 = x;
+        x
+    }
+}
+
+// Faults are set separately for each kind of traffic
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Traffic {
+    Sync,
+    Cast,
+}
+
+// Probabilities are given per mille
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Faults {
+    pub loss: u64,
+    pub duplicate: u64,
+    // Reordered message ignores link's ordering
+    // and can be delayed by up to reorder_window
+    pub reorder: u64,
+    pub reorder_window: Duration,
+    // Added to every message
+    pub delay: Duration,
+    pub partitioned: bool,
+}
+
+enum Event {
+    Step(usize),
+    Deliver(usize, Box<WrappedMessage>),
+}
+
+struct SimGnome {
+    id: GnomeId,
+    gnome: Gnome,
+    job: Option<JobState>,
+    // Key of this gnome's next Step in the queue
+    wake_up: Option<(Duration, u64)>,
+    finished: bool,
+    to_gnome: Sender<ToGnome>,
+    from_gnome: Receiver<GnomeToApp>,
+    events: Vec<(Duration, GnomeToApp)>,
+    // App does not read its events while stalled
+    app_stalled: bool,
+    mgr_receiver: Receiver<GnomeToManager>,
+    mgr_events: Vec<(Duration, GnomeToManager)>,
+    mgr_sender: Sender<ManagerToGnome>,
+    _net_receiver: Receiver<Vec<u8>>,
+}
+
+// One direction of a connection between two gnomes
+struct Link {
+    from: usize,
+    to: usize,
+    outbox: Receiver<WrappedMessage>,
+    inbox: Sender<Message>,
+    cast_inbox: Sender<CastMessage>,
+    last_delivery: Duration,
+}
+
+type SharedSender = Sender<(
+    SwarmName,
+    Sender<Message>,
+    Sender<CastMessage>,
+    Receiver<WrappedMessage>,
+)>;
+
+type SharedReceiver = Receiver<(
+    SwarmName,
+    Sender<Message>,
+    Sender<CastMessage>,
+    Receiver<WrappedMessage>,
+)>;
+
+// Time only moves when someone calls set or advance
+pub struct ManualClock {
+    now: Mutex<Duration>,
+}
+
+impl ManualClock {
+    pub fn new(start: Duration) -> Self {
+        ManualClock {
+            now: Mutex::new(start),
+        }
+    }
+    pub fn set(&self, now: Duration) {
+        *self.now.lock().unwrap() = now;
+    }
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Duration {
+        *self.now.lock().unwrap()
+    }
+    fn sleep(&self, _duration: Duration) {}
+}
+
+pub struct Simulator {
+    clock: Arc<ManualClock>,
+    gnomes: Vec<SimGnome>,
+    links: Vec<Link>,
+    queue: BTreeMap<(Duration, u64), Event>,
+    seq: u64,
+    rng: Rng,
+    // (from, to, traffic)
+    faults: HashMap<(GnomeId, GnomeId, Traffic), Faults>,
+    sent: u64,
+    lost: u64,
+    _shared_receiver: SharedReceiver,
+}
+
+impl Simulator {
+    // Every gnome is a neighbor of every other gnome
+    pub fn new(size: usize, seed: u64) -> Self {
+        let mut pairs = vec![];
+        for a in 0..size {
+            for b in a + 1..size {
+                pairs.push((a, b));
+            }
+        }
+        Simulator::with_links(size, seed, &pairs)
+    }
+
+    // First gnome is always swarm's founder
+    pub fn with_links(size: usize, seed: u64, pairs: &[(usize, usize)]) -> Self {
+        let clock = Arc::new(ManualClock::new(Duration::from_secs(1)));
+        let (shared_sender, shared_receiver) = channel();
+        let ids: Vec<GnomeId> = (0..size).map(|i| GnomeId(i as u64 + 1)).collect();
+        let mut neighbors: Vec<Vec<Neighbor>> = (0..size).map(|_| vec![]).collect();
+        let mut links = vec![];
+        for (a, b) in pairs {
+            let (a_to_b, a_neighbor, b_to_a, b_neighbor) =
+                connect((*a, ids[*a]), (*b, ids[*b]), &shared_sender);
+            neighbors[*a].push(b_neighbor);
+            neighbors[*b].push(a_neighbor);
+            links.push(a_to_b);
+            links.push(b_to_a);
+        }
+        let name = SwarmName::new(ids[0], "/sim".to_string()).unwrap();
+        let mut gnomes = Vec::with_capacity(size);
+        for (i, neighbors) in neighbors.into_iter().enumerate() {
+            let id = ids[i];
+            let (swarm, request_receiver) =
+                Swarm::new(name.clone(), SwarmID(0), |g, k, t, b, s| {
+                    fake_verify(g, k, t, b, s)
+                });
+            let to_gnome = swarm.sender.clone();
+            let (app_sender, from_gnome) = channel();
+            let (mgr_sender, mgr_receiver) = channel();
+            let (to_mgr_receiver_sender, to_mgr_receiver) = channel();
+            let (net_sender, net_receiver) = channel();
+            let mut gnome = Gnome::new_with_neighbors(
+                id,
+                fake_key(id).into_bytes(),
+                fake_key(id),
+                swarm,
+                app_sender,
+                request_receiver,
+                mgr_sender,
+                to_mgr_receiver,
+                neighbors,
+                net_sender,
+                |k, t, b| fake_sign(k, t, b),
+                fake_hash,
+            );
+            gnome.set_clock(clock.clone());
+            gnomes.push(SimGnome {
+                id,
+                gnome,
+                job: None,
+                wake_up: None,
+                finished: false,
+                to_gnome,
+                from_gnome,
+                events: vec![],
+                app_stalled: false,
+                mgr_receiver,
+                mgr_events: vec![],
+                mgr_sender: to_mgr_receiver_sender,
+                _net_receiver: net_receiver,
+            });
+        }
+        let mut sim = Simulator {
+            clock,
+            gnomes,
+            links,
+            queue: BTreeMap::new(),
+            seq: 0,
+            rng: Rng::new(seed),
+            faults: HashMap::new(),
+            sent: 0,
+            lost: 0,
+            _shared_receiver: shared_receiver,
+        };
+        let start = sim.now();
+        for i in 0..size {
+            let offset = Duration::from_micros(sim.rng.next() % 1000);
+            sim.wake_up(i, start + offset);
+        }
+        sim
+    }
+
+    pub fn now(&self) -> Duration {
+        self.clock.now()
+    }
+
+    pub fn id(&self, gnome: usize) -> GnomeId {
+        self.gnomes[gnome].id
+    }
+
+    pub fn request(&self, gnome: usize, request: ToGnome) {
+        let _ = self.gnomes[gnome].to_gnome.send(request);
+    }
+
+    pub fn manager_request(&self, gnome: usize, request: ManagerToGnome) {
+        let _ = self.gnomes[gnome].mgr_sender.send(request);
+    }
+
+    pub fn stall_app(&mut self, gnome: usize, stalled: bool) {
+        self.gnomes[gnome].app_stalled = stalled;
+    }
+
+    pub fn events(&self, gnome: usize) -> &[(Duration, GnomeToApp)] {
+        &self.gnomes[gnome].events
+    }
+
+    pub fn manager_events(&self, gnome: usize) -> &[(Duration, GnomeToManager)] {
+        &self.gnomes[gnome].mgr_events
+    }
+
+    // How many messages gnomes have sent to each other
+    pub fn sent(&self) -> u64 {
+        self.sent
+    }
+
+    // Fresh Swarm that knows keys of all our gnomes,
+    // so that it can verify their messages (e.g. when replaying a trace)
+    pub fn swarm(&self) -> Swarm {
+        let name = SwarmName::new(self.id(0), "/sim".to_string()).unwrap();
+        let (mut swarm, _request_receiver) =
+            Swarm::new(name, SwarmID(0), |g, k, t, b, s| fake_verify(g, k, t, b, s));
+        for gnome in &self.gnomes {
+            swarm
+                .key_reg
+                .insert(gnome.id, fake_key(gnome.id).into_bytes());
+        }
+        swarm
+    }
+
+    // What given gnome's signature of bytes is, e.g. to forge evidence
+    pub fn sign(&self, gnome: usize, time: SwarmTime, bytes: &[u8]) -> Vec<u8> {
+        signature(fake_key(self.id(gnome)).as_bytes(), time, bytes)
+    }
+
+    // How many messages were dropped by faulty links
+    pub fn lost(&self) -> u64 {
+        self.lost
+    }
+
+    // Faults apply only to messages sent from given gnome to the other one
+    pub fn set_faults(&mut self, from: GnomeId, to: GnomeId, traffic: Traffic, faults: Faults) {
+        self.faults.insert((from, to, traffic), faults);
+    }
+
+    // Cut both directions and both kinds of traffic between two gnomes
+    pub fn partition(&mut self, a: GnomeId, b: GnomeId) {
+        for (from, to) in [(a, b), (b, a)] {
+            for traffic in [Traffic::Sync, Traffic::Cast] {
+                self.faults
+                    .entry((from, to, traffic))
+                    .or_default()
+                    .partitioned = true;
+            }
+        }
+    }
+
+    pub fn heal(&mut self, a: GnomeId, b: GnomeId) {
+        for (from, to) in [(a, b), (b, a)] {
+            for traffic in [Traffic::Sync, Traffic::Cast] {
+                if let Some(faults) = self.faults.get_mut(&(from, to, traffic)) {
+                    faults.partitioned = false;
+                }
+            }
+        }
+    }
+
+    pub fn run_for(&mut self, duration: Duration) {
+        let deadline = self.now() + duration;
+        self.run_until(deadline, |_| false);
+    }
+
+    // Returns true if condition was met before deadline
+    pub fn run_until(
+        &mut self,
+        deadline: Duration,
+        condition: impl Fn(&Simulator) -> bool,
+    ) -> bool {
+        while let Some((&(time, seq), _e)) = self.queue.iter().next() {
+            if condition(self) {
+                return true;
+            }
+            if time > deadline {
+                break;
+            }
+            let event = self.queue.remove(&(time, seq)).unwrap();
+            self.clock.set(time.max(self.now()));
+            match event {
+                Event::Step(i) => {
+                    self.gnomes[i].wake_up = None;
+                    self.step(i)
+                }
+                Event::Deliver(l, message) => self.deliver(l, *message),
+            }
+        }
+        self.clock.set(deadline.max(self.now()));
+        condition(self)
+    }
+
+    fn step(&mut self, i: usize) {
+        let now = self.now();
+        let sim_gnome = &mut self.gnomes[i];
+        let sleep = if let Some(job) = sim_gnome.job.as_mut() {
+            sim_gnome.gnome.step(job)
+        } else {
+            sim_gnome.job = sim_gnome.gnome.prepare(BANDWIDTH, false);
+            sim_gnome.job.as_ref().map(|_j| Duration::ZERO)
+        };
+        while !sim_gnome.app_stalled {
+            if let Ok(event) = sim_gnome.from_gnome.try_recv() {
+                sim_gnome.events.push((now, event));
+            } else {
+                break;
+            }
+        }
+        while let Ok(event) = sim_gnome.mgr_receiver.try_recv() {
+            sim_gnome.mgr_events.push((now, event));
+        }
+        if let Some(sleep) = sleep {
+            self.wake_up(i, now + STEP_COST + sleep);
+        } else {
+            self.gnomes[i].finished = true;
+        }
+        for l in 0..self.links.len() {
+            if self.links[l].from != i {
+                continue;
+            }
+            while let Ok(message) = self.links[l].outbox.try_recv() {
+                self.send(l, message);
+            }
+        }
+    }
+
+    fn send(&mut self, l: usize, message: WrappedMessage) {
+        let traffic = match &message {
+            WrappedMessage::Regular(_m) => Traffic::Sync,
+            WrappedMessage::Cast(_m) => Traffic::Cast,
+            WrappedMessage::NoOp => return,
+        };
+        self.sent += 1;
+        let from = self.gnomes[self.links[l].from].id;
+        let to = self.gnomes[self.links[l].to].id;
+        let faults = self
+            .faults
+            .get(&(from, to, traffic))
+            .copied()
+            .unwrap_or_default();
+        if faults.partitioned || self.chance(faults.loss) {
+            self.lost += 1;
+            return;
+        }
+        let now = self.now();
+        let jitter = Duration::from_micros(self.rng.next() % MAX_JITTER_USEC);
+        let mut at = now + MIN_LATENCY + jitter + faults.delay;
+        if self.chance(faults.reorder) {
+            let window = faults.reorder_window.as_micros() as u64 + 1;
+            at += Duration::from_micros(self.rng.next() % window);
+        } else {
+            // Links keep messages in order
+            at = at.max(self.links[l].last_delivery);
+            self.links[l].last_delivery = at;
+        }
+        if self.chance(faults.duplicate) {
+            let again = at + Duration::from_micros(self.rng.next() % MAX_JITTER_USEC);
+            self.schedule(again, Event::Deliver(l, Box::new(message.clone())));
+        }
+        self.schedule(at, Event::Deliver(l, Box::new(message)));
+    }
+
+    fn chance(&mut self, per_mille: u64) -> bool {
+        per_mille > 0 && self.rng.next() % 1000 < per_mille
+    }
+
+    // Like a gnome waiting on a socket, receiver wakes up
+    // as soon as something arrives
+    fn deliver(&mut self, l: usize, message: WrappedMessage) {
+        let link = &self.links[l];
+        let to = link.to;
+        match message {
+            WrappedMessage::Regular(message) => {
+                let _ = link.inbox.send(message);
+            }
+            WrappedMessage::Cast(message) => {
+                let _ = link.cast_inbox.send(message);
+            }
+            WrappedMessage::NoOp => return,
+        }
+        let now = self.now();
+        self.wake_up(to, now + STEP_COST);
+    }
+
+    // Reschedule gnome's next step, but only to an earlier time
+    fn wake_up(&mut self, i: usize, at: Duration) {
+        if let Some(key) = self.gnomes[i].wake_up {
+            if key.0 <= at {
+                return;
+            }
+            self.queue.remove(&key);
+        } else if self.gnomes[i].finished {
+            return;
+        }
+        self.gnomes[i].wake_up = Some((at, self.seq));
+        self.schedule(at, Event::Step(i));
+    }
+
+    fn schedule(&mut self, at: Duration, event: Event) {
+        self.queue.insert((at, self.seq), event);
+        self.seq += 1;
+    }
+}
+
+// Returns both directions of a connection and a Neighbor
+// representing each side to the other one
+fn connect(
+    (a_idx, a): (usize, GnomeId),
+    (b_idx, b): (usize, GnomeId),
+    shared_sender: &SharedSender,
+) -> (Link, Neighbor, Link, Neighbor) {
+    let (a_inbox, a_receiver) = channel();
+    let (a_cast_inbox, a_cast_receiver) = channel();
+    let (a_sender, a_outbox) = channel();
+    let (b_inbox, b_receiver) = channel();
+    let (b_cast_inbox, b_cast_receiver) = channel();
+    let (b_sender, b_outbox) = channel();
+    // Neighbor b lives inside gnome a
+    let neighbor_b = Neighbor::from_id_channel_time(
+        b,
+        a_receiver,
+        a_cast_receiver,
+        a_sender,
+        shared_sender.clone(),
+        SwarmTime(0),
+        DEFAULT_SWARM_DIAMETER,
+        vec![],
+    );
+    let neighbor_a = Neighbor::from_id_channel_time(
+        a,
+        b_receiver,
+        b_cast_receiver,
+        b_sender,
+        shared_sender.clone(),
+        SwarmTime(0),
+        DEFAULT_SWARM_DIAMETER,
+        vec![],
+    );
+    (
+        Link {
+            from: a_idx,
+            to: b_idx,
+            outbox: a_outbox,
+            inbox: b_inbox,
+            cast_inbox: b_cast_inbox,
+            last_delivery: Duration::ZERO,
+        },
+        neighbor_a,
+        Link {
+            from: b_idx,
+            to: a_idx,
+            outbox: b_outbox,
+            inbox: a_inbox,
+            cast_inbox: a_cast_inbox,
+            last_delivery: Duration::ZERO,
+        },
+        neighbor_b,
+    )
+}
+
+// Keys are not secret here, a gnome's private key is the same
+// as his public key, and signature is just a hash.
+fn fake_key(id: GnomeId) -> String {
+    format!("key-{}", id.0)
+}
+
+fn fake_sign(priv_key: &str, time: SwarmTime, bytes: &mut [u8]) -> Result<Vec<u8>, ()> {
+    Ok(signature(priv_key.as_bytes(), time, bytes))
+}
+
+fn fake_verify(
+    gnome_id: GnomeId,
+    pub_key: &[u8],
+    time: SwarmTime,
+    bytes: &mut [u8],
+    sig: &[u8],
+) -> bool {
+    pub_key == fake_key(gnome_id).as_bytes() && signature(pub_key, time, bytes) == sig
+}
+
+fn signature(key: &[u8], time: SwarmTime, bytes: &[u8]) -> Vec<u8> {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    time.0.hash(&mut hasher);
+    bytes.hash(&mut hasher);
+    hasher.finish().to_be_bytes().to_vec()
+}
+
+fn fake_hash(bytes: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,92 @@
+use crate::Capabilities;
+use crate::GnomeId;
+use crate::Swarm;
+use crate::SwarmTime;
+use std::collections::HashSet;
+
+// Owner's signature stating that given candidate should become
+// a founder in place of current one (for example when founder's key was lost).
+// It is bound to current founder, so it can not be replayed
+// after founder has changed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endorsement {
+    pub owner: GnomeId,
+    pub signature: Vec<u8>,
+}
+
+impl Endorsement {
+    pub fn signed_bytes(swarm: &Swarm, candidate: GnomeId) -> Vec<u8> {
+        let mut bytes = swarm.name.as_bytes();
+        bytes.extend(swarm.founder().0.to_be_bytes());
+        bytes.extend(candidate.0.to_be_bytes());
+        bytes
+    }
+
+    pub fn len_in_bytes(&self) -> usize {
+        10 + self.signature.len()
+    }
+
+    pub fn append_bytes_to(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.owner.0.to_be_bytes());
+        bytes.extend((self.signature.len() as u16).to_be_bytes());
+        bytes.extend(&self.signature);
+    }
+
+    // None when bytes are too short, bytes are left untouched then
+    pub fn from(bytes: &mut Vec<u8>) -> Option<Self> {
+        if bytes.len() < 10 {
+            return None;
+        }
+        let sign_len = u16::from_be_bytes([bytes[8], bytes[9]]) as usize;
+        if bytes.len() < 10 + sign_len {
+            return None;
+        }
+        let owner = GnomeId(u64::from_be_bytes(
+            bytes.drain(0..8).collect::<Vec<u8>>().try_into().unwrap(),
+        ));
+        let signature = bytes.drain(2..2 + sign_len).collect();
+        bytes.drain(0..2);
+        Some(Endorsement { owner, signature })
+    }
+}
+
+// Succession is accepted when more than half of current Owners
+// have endorsed given candidate.
+pub fn has_quorum(swarm: &Swarm, candidate: GnomeId, endorsements: &[Endorsement]) -> bool {
+    let owners = if let Some(tree) = swarm.capability_reg.get(&Capabilities::Owner) {
+        tree.id_vec()
+    } else {
+        eprintln!("No Owners to endorse succession");
+        return false;
+    };
+    let bytes = Endorsement::signed_bytes(swarm, candidate);
+    let mut endorsed_by = HashSet::new();
+    for endorsement in endorsements {
+        if !owners.contains(&endorsement.owner) || endorsed_by.contains(&endorsement.owner) {
+            continue;
+        }
+        let pub_key = if let Some(key) = swarm.key_reg.get(endorsement.owner) {
+            key
+        } else {
+            eprintln!("No pubkey for endorsing Owner {}", endorsement.owner);
+            continue;
+        };
+        let mut signed = bytes.clone();
+        if (swarm.verify)(
+            endorsement.owner,
+            &pub_key,
+            SwarmTime(0),
+            &mut signed,
+            &endorsement.signature,
+        ) {
+            endorsed_by.insert(endorsement.owner);
+        }
+    }
+    eprintln!(
+        "Succession of {} endorsed by {}/{} Owners",
+        candidate,
+        endorsed_by.len(),
+        owners.len()
+    );
+    endorsed_by.len() * 2 > owners.len()
+}
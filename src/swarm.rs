@@ -1,4 +1,5 @@
 // use crate::capabilities::CapabiLeaf;
+use crate::genesis::Genesis;
 use crate::DEFAULT_SWARM_DIAMETER;
 // use crate::gnome::NetworkSettings;
 use crate::gnome_to_manager::GnomeToManager;
@@ -6,19 +7,25 @@ use crate::key_registry::KeyRegistry;
 use crate::manager_to_gnome::ManagerToGnome;
 use crate::message::Header;
 use crate::message::Payload;
+use crate::multicast::CastMessage;
+use crate::multicast::CastType;
 use crate::multicast::Multicast;
 use crate::policy::Policy;
 use crate::requirement::Requirement;
+use crate::topic_filter::TopicFilter;
 use crate::CapabiLeaf;
 use crate::Capabilities;
+use crate::CastDescriptor;
 use crate::Gnome;
 use crate::GnomeId;
 use crate::GnomeToApp;
+use crate::HistoryLimit;
 use crate::Message;
 use crate::Neighbor;
+use crate::OverflowPolicy;
 use crate::Signature;
 use crate::ToGnome;
-use crate::{CastID, WrappedMessage};
+use crate::{CastData, CastID, NeighborRequest, WrappedMessage};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt;
@@ -30,7 +37,7 @@ use std::str::FromStr;
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::thread::spawn;
 
-#[derive(PartialEq, PartialOrd, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, PartialOrd, Eq, Ord, Hash, Clone, Copy, Debug)]
 pub struct SwarmTime(pub u32);
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -242,12 +249,16 @@ pub struct Swarm {
     active_unicasts: HashSet<CastID>,
     active_broadcasts: HashMap<CastID, Multicast>,
     active_multicasts: HashMap<CastID, Multicast>,
+    // Every cast running in this swarm, whether we take part or not
+    cast_descriptors: HashMap<(bool, CastID), (GnomeId, CastDescriptor)>,
     pub key_reg: KeyRegistry,
     pub capability_reg: HashMap<Capabilities, CapabiLeaf>,
     pub policy_reg: HashMap<Policy, Requirement>,
     pub byteset_reg: HashMap<u8, ByteSet>,
     pub verify: fn(GnomeId, &Vec<u8>, SwarmTime, &mut Vec<u8>, &[u8]) -> bool,
     last_accepted_pubkey_chunk: (u8, u8),
+    // Once set it was verified, and founder can not be changed
+    genesis: Option<Genesis>,
     // TODO: This struct (or SwarmManifesto and/or attrs) should be provided by the user,
     // or some other mean like another Swarm functioning as a swarm catalogue,
     // and we only need to define Traits that particular attributes should be bounded to.
@@ -294,32 +305,33 @@ pub struct Swarm {
 }
 
 impl Swarm {
-    pub fn join(
+    pub fn new(
         name: SwarmName,
-        // TODO: allow for swarm_diameter customization
-        // Each swarm may have different diameter, accepted values 1-15,
-        // where 1 means each Gnome has active communication channel to every other Gnome.
-        // diameter: SwarmTime,
         id: SwarmID,
-        gnome_id: GnomeId,
-        pub_key_der: Vec<u8>,
-        priv_key_pem: String,
-        neighbors: Option<Vec<Neighbor>>,
-        mgr_sender: Sender<GnomeToManager>,
-        mgr_receiver: Receiver<ManagerToGnome>,
-        // band_receiver: Receiver<u64>,
-        // net_settings_send: Sender<NetworkSettings>,
-        net_settings_send: Sender<Vec<u8>>,
-        assigned_bandwidth: u64,
         verify: fn(GnomeId, &Vec<u8>, SwarmTime, &mut Vec<u8>, &[u8]) -> bool,
-        sign: fn(&str, SwarmTime, &mut Vec<u8>) -> Result<Vec<u8>, ()>,
-        sha_hash: fn(&[u8]) -> u64,
-    ) -> (Sender<ToGnome>, Receiver<GnomeToApp>) {
+    ) -> (Swarm, Receiver<ToGnome>) {
         let (sender, request_receiver) = channel::<ToGnome>();
-        let (response_sender, receiver) = channel::<GnomeToApp>();
         let mut policy_reg = HashMap::new();
         let diameter = DEFAULT_SWARM_DIAMETER;
         policy_reg.insert(Policy::Default, Requirement::Has(Capabilities::Founder));
+        // Evidence of equivocation verifies itself, anyone can report it
+        policy_reg.insert(Policy::ReportEquivocation, Requirement::None);
+        policy_reg.insert(
+            Policy::TransferFounder,
+            Requirement::Has(Capabilities::Founder),
+        );
+        policy_reg.insert(Policy::AddOwner, Requirement::Has(Capabilities::Founder));
+        // Owners need their pubkey known for endorsements to be verified
+        policy_reg.insert(
+            Policy::InsertPubkey,
+            Requirement::Or(
+                Box::new(Requirement::Has(Capabilities::Founder)),
+                Box::new(Requirement::Has(Capabilities::Owner)),
+            ),
+        );
+        // Quorum of Owners is checked when succession is applied,
+        // candidate does not need to be an Owner
+        policy_reg.insert(Policy::FounderSuccession, Requirement::None);
         let mut capability_reg = HashMap::new();
         if !name.founder.is_any() {
             let mut ct = CapabiLeaf::create();
@@ -338,17 +350,46 @@ impl Swarm {
             id,
             swarm_type: SwarmType::Catalog,
             diameter,
-            sender: sender.clone(),
+            sender,
             active_unicasts: HashSet::new(),
             active_broadcasts: HashMap::new(),
             active_multicasts: HashMap::new(),
+            cast_descriptors: HashMap::new(),
             verify,
             key_reg: KeyRegistry::new8(),
             capability_reg,
             policy_reg,
             byteset_reg: HashMap::new(),
             last_accepted_pubkey_chunk: (0, 0),
+            genesis: None,
         };
+        (swarm, request_receiver)
+    }
+
+    pub fn join(
+        name: SwarmName,
+        // TODO: allow for swarm_diameter customization
+        // Each swarm may have different diameter, accepted values 1-15,
+        // where 1 means each Gnome has active communication channel to every other Gnome.
+        // diameter: SwarmTime,
+        id: SwarmID,
+        gnome_id: GnomeId,
+        pub_key_der: Vec<u8>,
+        priv_key_pem: String,
+        neighbors: Option<Vec<Neighbor>>,
+        mgr_sender: Sender<GnomeToManager>,
+        mgr_receiver: Receiver<ManagerToGnome>,
+        // band_receiver: Receiver<u64>,
+        // net_settings_send: Sender<NetworkSettings>,
+        net_settings_send: Sender<Vec<u8>>,
+        assigned_bandwidth: u64,
+        verify: fn(GnomeId, &Vec<u8>, SwarmTime, &mut Vec<u8>, &[u8]) -> bool,
+        sign: fn(&str, SwarmTime, &mut Vec<u8>) -> Result<Vec<u8>, ()>,
+        sha_hash: fn(&[u8]) -> u64,
+    ) -> (Sender<ToGnome>, Receiver<GnomeToApp>) {
+        let (response_sender, receiver) = channel::<GnomeToApp>();
+        let (swarm, request_receiver) = Swarm::new(name, id, verify);
+        let sender = swarm.sender.clone();
         let gnome = if let Some(neighbors) = neighbors {
             // println!("PubKey {} {}", pub_key_pem, pub_key_pem.len());
             Gnome::new_with_neighbors(
@@ -455,6 +496,9 @@ impl Swarm {
     pub fn insert_unicast(&mut self, cast_id: CastID) {
         self.active_unicasts.insert(cast_id);
     }
+    pub fn remove_unicast(&mut self, cast_id: CastID) -> bool {
+        self.active_unicasts.remove(&cast_id)
+    }
     pub fn insert_multicast(&mut self, cast_id: CastID, multicast: Multicast) {
         self.active_multicasts.insert(cast_id, multicast);
     }
@@ -499,6 +543,7 @@ impl Swarm {
         is_bcast: bool,
         cast_id: &CastID,
     ) -> Option<(GnomeId, Vec<GnomeId>)> {
+        self.cast_descriptors.remove(&(is_bcast, *cast_id));
         if is_bcast {
             if let Some(cast) = self.active_broadcasts.remove(cast_id) {
                 Some((cast.source(), cast.subscribers()))
@@ -516,6 +561,12 @@ impl Swarm {
         eprintln!("Insert capability {:?}: {:?}", cap, id_list);
         if cap == Capabilities::Founder {
             eprintln!("Looks like this Cap {:?}is a Founder one…", cap);
+            // Once verified, founder changes only with signed TransferFounder
+            // or succession, never with sync
+            if self.genesis.is_some() {
+                eprintln!("Founder is verified, ignoring");
+                return;
+            }
             if let Some(gnome_id) = id_list.pop() {
                 let mut tree = CapabiLeaf::create();
                 eprintln!("overwrite Founder");
@@ -586,23 +637,266 @@ impl Swarm {
         }
     }
 
-    pub fn serve_casts(&mut self, available_tokens: u64) -> (bool, u64) {
+    pub fn serve_casts(
+        &mut self,
+        available_tokens: u64,
+        room: &mut HashMap<GnomeId, usize>,
+        outgoing: &mut Vec<(GnomeId, CastMessage)>,
+    ) -> (bool, u64) {
         //Castings are not served in case we have no tokens available
         let mut any_data_processed = false;
         let mut total_tokens_used = 0;
         if available_tokens == 0 {
             return (any_data_processed, total_tokens_used);
         }
-        let (was_busy, used_tokens) = self.serve_broadcasts(available_tokens);
+        let (was_busy, used_tokens) = self.serve_broadcasts(available_tokens, room, outgoing);
         total_tokens_used += used_tokens;
         any_data_processed |= was_busy;
-        let (was_busy, used_tokens) = self.serve_multicasts(available_tokens);
+        let (was_busy, used_tokens) = self.serve_multicasts(available_tokens, room, outgoing);
         total_tokens_used += used_tokens;
         any_data_processed |= was_busy;
-        // let (was_busy, tokens_used) = self.serve_unicasts(tokens_remaining);
+        // Unicasts go to a single neighbor, so Gnome serves them
         (any_data_processed, total_tokens_used)
     }
 
+    fn cast_mut(&mut self, is_bcast: bool, cast_id: &CastID) -> Option<&mut Multicast> {
+        if is_bcast {
+            self.active_broadcasts.get_mut(cast_id)
+        } else {
+            self.active_multicasts.get_mut(cast_id)
+        }
+    }
+
+    pub fn set_cast_overflow_policy(
+        &mut self,
+        is_bcast: bool,
+        cast_id: &CastID,
+        policy: OverflowPolicy,
+    ) -> bool {
+        if let Some(cast) = self.cast_mut(is_bcast, cast_id) {
+            cast.set_overflow_policy(policy);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Casts whose app has overflowed with Unsubscribe policy,
+    // and that we do not forward to anyone
+    pub fn take_overflowed_casts(&mut self) -> Vec<(bool, CastID)> {
+        let mut overflowed = vec![];
+        for (is_bcast, casts) in [
+            (true, &mut self.active_broadcasts),
+            (false, &mut self.active_multicasts),
+        ] {
+            for (c_id, cast) in casts.iter_mut() {
+                if cast.take_overflowed() && cast.subscribers().is_empty() {
+                    overflowed.push((is_bcast, *c_id));
+                }
+            }
+        }
+        overflowed.sort();
+        overflowed
+    }
+
+    // Casts whose app has missed some data, with how many messages
+    pub fn take_dropped_casts(&mut self) -> Vec<(bool, CastID, u32)> {
+        let mut dropped = vec![];
+        for (is_bcast, casts) in [
+            (true, &mut self.active_broadcasts),
+            (false, &mut self.active_multicasts),
+        ] {
+            for (c_id, cast) in casts.iter_mut() {
+                let count = cast.take_dropped();
+                if count > 0 {
+                    dropped.push((is_bcast, *c_id, count));
+                }
+            }
+        }
+        dropped.sort();
+        dropped
+    }
+
+    pub fn keep_cast_history(
+        &mut self,
+        is_bcast: bool,
+        cast_id: &CastID,
+        limit: HistoryLimit,
+    ) -> bool {
+        if let Some(cast) = self.cast_mut(is_bcast, cast_id) {
+            cast.keep_history(limit);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Recent data of a cast, oldest first, as messages for a new subscriber
+    pub fn cast_history(&self, is_bcast: bool, cast_id: &CastID) -> Vec<CastMessage> {
+        let (c_type, cast) = if is_bcast {
+            (CastType::Broadcast, self.active_broadcasts.get(cast_id))
+        } else {
+            (CastType::Multicast, self.active_multicasts.get(cast_id))
+        };
+        if let Some(cast) = cast {
+            cast.history()
+                .into_iter()
+                .map(|data| CastMessage::new_history(c_type, *cast_id, data))
+                .collect()
+        } else {
+            vec![]
+        }
+    }
+
+    // Only origin can decide a cast should be reliable
+    pub fn make_cast_reliable(
+        &mut self,
+        is_bcast: bool,
+        cast_id: &CastID,
+        origin: GnomeId,
+    ) -> bool {
+        if let Some(cast) = self.cast_mut(is_bcast, cast_id) {
+            if cast.origin() == origin {
+                cast.make_reliable();
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn retransmit(
+        &mut self,
+        is_bcast: bool,
+        cast_id: &CastID,
+        seqs: &[u32],
+    ) -> Vec<(u32, CastData)> {
+        if let Some(cast) = self.cast_mut(is_bcast, cast_id) {
+            cast.retransmit(seqs)
+        } else {
+            vec![]
+        }
+    }
+
+    pub fn repair_cast(&mut self, is_bcast: bool, cast_id: CastID, seq: u32, data: CastData) {
+        let c_type = if is_bcast {
+            CastType::Broadcast
+        } else {
+            CastType::Multicast
+        };
+        if let Some(cast) = self.cast_mut(is_bcast, &cast_id) {
+            cast.repair(CastMessage::new_sequenced(c_type, cast_id, seq, data));
+        }
+    }
+
+    pub fn set_cast_topics(
+        &mut self,
+        is_bcast: bool,
+        cast_id: &CastID,
+        topics: TopicFilter,
+    ) -> bool {
+        if let Some(cast) = self.cast_mut(is_bcast, cast_id) {
+            cast.set_topics(topics);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn set_subscriber_topics(
+        &mut self,
+        is_bcast: bool,
+        cast_id: &CastID,
+        sub_id: GnomeId,
+        topics: TopicFilter,
+    ) {
+        if let Some(cast) = self.cast_mut(is_bcast, cast_id) {
+            cast.set_subscriber_topics(sub_id, topics);
+        }
+    }
+
+    // CastTopics to be sent to sources of casts whose subtree interest has changed
+    pub fn take_topic_updates(&mut self) -> Vec<(GnomeId, NeighborRequest)> {
+        let mut updates = vec![];
+        for (is_bcast, casts) in [
+            (true, &mut self.active_broadcasts),
+            (false, &mut self.active_multicasts),
+        ] {
+            for (cast_id, cast) in casts.iter_mut() {
+                if let Some((n_id, topics)) = cast.take_interest_update() {
+                    updates.push((
+                        n_id,
+                        NeighborRequest::CastTopics(is_bcast, *cast_id, topics),
+                    ));
+                }
+            }
+        }
+        updates
+    }
+
+    // CastNacks to be sent and neighbors to send them to
+    pub fn take_cast_nacks(&mut self) -> Vec<(GnomeId, NeighborRequest)> {
+        let mut nacks = vec![];
+        for (is_bcast, casts) in [
+            (true, &mut self.active_broadcasts),
+            (false, &mut self.active_multicasts),
+        ] {
+            for (cast_id, cast) in casts.iter_mut() {
+                if let Some((n_id, seqs)) = cast.take_nack() {
+                    nacks.push((n_id, NeighborRequest::CastNack(is_bcast, *cast_id, seqs)));
+                }
+            }
+        }
+        nacks
+    }
+
+    pub fn set_cast_descriptor(
+        &mut self,
+        is_bcast: bool,
+        cast_id: CastID,
+        origin: GnomeId,
+        descriptor: CastDescriptor,
+    ) {
+        self.cast_descriptors
+            .insert((is_bcast, cast_id), (origin, descriptor));
+    }
+
+    pub fn cast_descriptor(&self, is_bcast: bool, cast_id: &CastID) -> Option<&CastDescriptor> {
+        self.cast_descriptors
+            .get(&(is_bcast, *cast_id))
+            .map(|(_origin, descriptor)| descriptor)
+    }
+
+    // (is_broadcast, id, origin, descriptor) of all known casts
+    pub fn cast_listing(&self) -> Vec<(bool, CastID, GnomeId, CastDescriptor)> {
+        let mut listing: Vec<(bool, CastID, GnomeId, CastDescriptor)> = self
+            .cast_descriptors
+            .iter()
+            .map(|((is_bcast, c_id), (origin, descriptor))| {
+                (*is_bcast, *c_id, *origin, descriptor.clone())
+            })
+            .collect();
+        listing.sort_by_key(|(is_bcast, c_id, _origin, _descriptor)| (!*is_bcast, *c_id));
+        listing
+    }
+
+    // Casts we take part in, for syncing a new neighbor
+    pub fn cast_sync(&self, is_bcast: bool) -> Vec<(CastID, GnomeId, CastDescriptor)> {
+        let ids = if is_bcast {
+            self.broadcast_ids()
+        } else {
+            self.multicast_ids()
+        };
+        ids.into_iter()
+            .map(|(c_id, origin)| {
+                let descriptor = self
+                    .cast_descriptor(is_bcast, &c_id)
+                    .cloned()
+                    .unwrap_or_default();
+                (c_id, origin, descriptor)
+            })
+            .collect()
+    }
+
     pub fn broadcasts_count(&self) -> u8 {
         self.active_broadcasts.len() as u8
     }
@@ -661,6 +955,44 @@ impl Swarm {
         }
     }
 
+    // Whether given gnome fulfills Requirement from cast's descriptor
+    pub fn may_subscribe(&self, is_bcast: bool, cast_id: &CastID, gnome_id: GnomeId) -> bool {
+        if let Some(descriptor) = self.cast_descriptor(is_bcast, cast_id) {
+            descriptor.requirement.is_fullfilled(
+                &gnome_id,
+                &self.capability_reg,
+                &self.byteset_reg,
+                None,
+                None,
+            )
+        } else {
+            true
+        }
+    }
+
+    // Remove subscribers that no longer fulfill their cast's Requirement,
+    // returns (subscriber, is_broadcast, cast_id) of each one removed
+    pub fn remove_unauthorized_subscribers(&mut self) -> Vec<(GnomeId, bool, CastID)> {
+        let mut unauthorized = vec![];
+        for (is_bcast, casts) in [
+            (true, &self.active_broadcasts),
+            (false, &self.active_multicasts),
+        ] {
+            for (c_id, cast) in casts.iter() {
+                for sub_id in cast.subscribers() {
+                    if !self.may_subscribe(is_bcast, c_id, sub_id) {
+                        unauthorized.push((sub_id, is_bcast, *c_id));
+                    }
+                }
+            }
+        }
+        unauthorized.sort();
+        for (sub_id, is_bcast, c_id) in &unauthorized {
+            self.remove_subscriber(*is_bcast, c_id, *sub_id);
+        }
+        unauthorized
+    }
+
     pub fn get_alt_sources(
         &mut self,
         is_bcast: bool,
@@ -702,16 +1034,78 @@ impl Swarm {
                 )
         }
     }
-    pub fn set_founder(&mut self, gnome_id: GnomeId) {
+    pub fn set_founder(&mut self, gnome_id: GnomeId) -> bool {
+        if let Some(genesis) = &self.genesis {
+            if genesis.founder != gnome_id {
+                eprintln!(
+                    "Swarm {} refusing to change verified founder {} to: {}",
+                    self.name.name, genesis.founder, gnome_id
+                );
+                return false;
+            }
+        }
         eprintln!(
             "Swarm {} setting founder from: {} to: {}",
             self.name.name, self.name.founder, gnome_id
         );
+        // Founder capability could have been transferred since swarm was founded
+        if self.name.founder != gnome_id
+            || !self.capability_reg.contains_key(&Capabilities::Founder)
+        {
+            let mut tree = CapabiLeaf::create();
+            tree.insert(gnome_id);
+            self.capability_reg.insert(Capabilities::Founder, tree);
+        }
         self.name.founder = gnome_id;
+        true
+    }
+    // SwarmName's founder is part of swarm's identity, while current founder
+    // is whoever holds Founder capability
+    pub fn founder(&self) -> GnomeId {
+        if let Some(tree) = self.capability_reg.get(&Capabilities::Founder) {
+            if let Some(gnome_id) = tree.id_vec().first() {
+                return *gnome_id;
+            }
+        }
+        self.name.founder
+    }
+    pub fn transfer_founder(&mut self, gnome_id: GnomeId) {
+        eprintln!(
+            "Swarm {} transferring founder from: {} to: {}",
+            self.name.name,
+            self.founder(),
+            gnome_id
+        );
         let mut tree = CapabiLeaf::create();
         tree.insert(gnome_id);
         self.capability_reg.insert(Capabilities::Founder, tree);
     }
+    pub fn set_genesis(&mut self, genesis: Genesis) -> bool {
+        if self.genesis.is_some() {
+            return self.genesis == Some(genesis);
+        }
+        if !genesis.verify(self) {
+            eprintln!("Swarm {} Genesis verification failed", self.name.name);
+            return false;
+        }
+        self.set_founder(genesis.founder);
+        self.genesis = Some(genesis);
+        true
+    }
+    pub fn genesis(&self) -> Option<Genesis> {
+        self.genesis.clone()
+    }
+    pub fn founder_verified(&self) -> bool {
+        self.genesis.is_some()
+    }
+    // Founder is not a part of synced state, so we keep it
+    pub fn clear_capabilities(&mut self) {
+        let founder = self.capability_reg.remove(&Capabilities::Founder);
+        self.capability_reg.clear();
+        if let Some(tree) = founder {
+            self.capability_reg.insert(Capabilities::Founder, tree);
+        }
+    }
     pub fn check_data_policy(
         &self,
         gnome_id: &GnomeId,
@@ -775,6 +1169,20 @@ impl Swarm {
         self.capability_reg.insert(cap, c_tree);
     }
 
+    // Removes given gnome from every capability tree.
+    // Returns capabilities that were revoked.
+    pub fn revoke_capabilities(&mut self, gnome_id: GnomeId) -> Vec<Capabilities> {
+        eprintln!("Swarm is revoking capabilities of {}", gnome_id);
+        let mut revoked = vec![];
+        for (cap, tree) in self.capability_reg.iter_mut() {
+            if tree.contains(&gnome_id) {
+                tree.remove(gnome_id);
+                revoked.push(*cap);
+            }
+        }
+        revoked
+    }
+
     pub fn set_byteset(&mut self, b_id: u8, bset: ByteSet) {
         eprintln!("Swarm is setting capability: {:?}", b_id);
 
@@ -886,16 +1294,26 @@ impl Swarm {
             247 => Policy::DeleteGroup,
             246 => Policy::ModifyGroup,
             245 => Policy::InsertPubkey,
+            240 => Policy::ReportEquivocation,
+            239 => Policy::TransferFounder,
+            238 => Policy::AddOwner,
+            237 => Policy::FounderSuccession,
+            // 241..=243 and 255 have no policy of their own
             other => Policy::UserDefined(other),
         }
     }
 
-    fn serve_broadcasts(&mut self, available_tokens: u64) -> (bool, u64) {
+    fn serve_broadcasts(
+        &mut self,
+        available_tokens: u64,
+        room: &mut HashMap<GnomeId, usize>,
+        outgoing: &mut Vec<(GnomeId, CastMessage)>,
+    ) -> (bool, u64) {
         let mut any_data_processed = false;
         let mut total_tokens_used = 0;
         let mut tokens_remaining = available_tokens;
         for bcast in self.active_broadcasts.values_mut() {
-            let (was_busy, used_tokens) = bcast.serve(tokens_remaining);
+            let (was_busy, used_tokens) = bcast.serve(tokens_remaining, room, outgoing);
             any_data_processed |= was_busy;
             total_tokens_used += used_tokens;
             // if tokens_remaining == 0 {
@@ -906,12 +1324,17 @@ impl Swarm {
         }
         (any_data_processed, total_tokens_used)
     }
-    fn serve_multicasts(&mut self, available_tokens: u64) -> (bool, u64) {
+    fn serve_multicasts(
+        &mut self,
+        available_tokens: u64,
+        room: &mut HashMap<GnomeId, usize>,
+        outgoing: &mut Vec<(GnomeId, CastMessage)>,
+    ) -> (bool, u64) {
         let mut any_data_processed = false;
         let mut total_tokens_used = 0;
         let mut tokens_remaining = available_tokens;
         for mcast in self.active_multicasts.values_mut() {
-            let (was_busy, used_tokens) = mcast.serve(tokens_remaining);
+            let (was_busy, used_tokens) = mcast.serve(tokens_remaining, room, outgoing);
             any_data_processed |= was_busy;
             total_tokens_used += used_tokens;
             // if tokens_remaining == 0 {
@@ -922,12 +1345,6 @@ impl Swarm {
         }
         (any_data_processed, total_tokens_used)
     }
-    // fn serve_unicasts(&mut self) {
-    //     for (c_id, c_recv) in self.active_unicasts.items() {
-    //         bcast.serve();
-    //     }
-    // }
-
     fn all_possible_cast_ids(&self, for_unicast: bool) -> HashSet<CastID> {
         let mut ids = HashSet::new();
         // Unicast ids 255 & 254 reserved for NeighborRequest NeighborResponse
@@ -983,6 +1400,23 @@ impl Swarm {
         }
         source
     }
+    // Casts we receive from given neighbor, (is_broadcast, id)
+    pub fn casts_sourced_by(&self, gnome_id: GnomeId) -> Vec<(bool, CastID)> {
+        let mut casts: Vec<(bool, CastID)> = self
+            .active_broadcasts
+            .iter()
+            .filter(|(_c_id, bcast)| bcast.source() == gnome_id)
+            .map(|(c_id, _bcast)| (true, *c_id))
+            .chain(
+                self.active_multicasts
+                    .iter()
+                    .filter(|(_c_id, mcast)| mcast.source() == gnome_id)
+                    .map(|(c_id, _mcast)| (false, *c_id)),
+            )
+            .collect();
+        casts.sort();
+        casts
+    }
     pub fn set_source(
         &mut self,
         cast_id: &CastID,
@@ -0,0 +1,132 @@
+use crate::GnomeId;
+use crate::NeighborResponse;
+use crate::SwarmName;
+use crate::SwarmTime;
+use std::collections::HashMap;
+
+// How many bytes of SwarmNames we put into a single NeighboringSwarms response
+pub const MAX_SWARMS_PAGE_LEN: usize = 1024;
+
+// Split a listing of swarms into NeighboringSwarms responses,
+// pages are numbered from 1 and each one carries total page count
+pub fn swarm_pages(mut names: Vec<SwarmName>) -> Vec<NeighborResponse> {
+    names.sort_by(|a, b| (a.founder, &a.name).cmp(&(b.founder, &b.name)));
+    names.dedup();
+    let mut pages = vec![];
+    let mut page = vec![];
+    let mut page_len = 0;
+    for name in names {
+        let name_len = 9 + name.name.len();
+        if page_len + name_len > MAX_SWARMS_PAGE_LEN && !page.is_empty() {
+            pages.push(std::mem::take(&mut page));
+            page_len = 0;
+        }
+        page_len += name_len;
+        page.push(name);
+    }
+    if !page.is_empty() || pages.is_empty() {
+        pages.push(page);
+    }
+    let total = pages.len() as u8;
+    pages
+        .into_iter()
+        .enumerate()
+        .map(|(i, page)| NeighborResponse::NeighboringSwarms(i as u8 + 1, total, page))
+        .collect()
+}
+
+// ListNeighboringSwarms requests we have sent out and are waiting on.
+// Queries are kept for every requester and neighbor we asked on his behalf,
+// a neighbor is asked only once and his answer goes to everyone waiting on it.
+// Answers for a remote requester are gathered and sent back as a single listing.
+pub struct SwarmQueries {
+    // Pages received so far and until when we wait for the rest,
+    // these include swarms from further away so it can take a while
+    pending: HashMap<(GnomeId, GnomeId), (Vec<SwarmName>, SwarmTime)>,
+    gathered: HashMap<GnomeId, Vec<SwarmName>>,
+}
+
+impl SwarmQueries {
+    pub fn new() -> Self {
+        SwarmQueries {
+            pending: HashMap::new(),
+            gathered: HashMap::new(),
+        }
+    }
+
+    // Returns false if given requester is still waiting for previous query
+    pub fn start(&mut self, requester: GnomeId, names: Vec<SwarmName>) -> bool {
+        if self.gathered.contains_key(&requester) {
+            return false;
+        }
+        self.gathered.insert(requester, names);
+        true
+    }
+
+    // Returns false if given neighbor has already been asked for someone else
+    pub fn insert(&mut self, requester: GnomeId, queried: GnomeId, deadline: SwarmTime) -> bool {
+        let asked = self.pending.keys().any(|(_r, q)| *q == queried);
+        self.pending
+            .entry((requester, queried))
+            .or_insert((vec![], deadline));
+        !asked
+    }
+
+    // Requesters that have now received whole listing from queried neighbor
+    pub fn add_page(
+        &mut self,
+        queried: GnomeId,
+        page: u8,
+        total: u8,
+        names: Vec<SwarmName>,
+    ) -> Vec<(GnomeId, Vec<SwarmName>)> {
+        let mut done = vec![];
+        for ((requester, q), (listed, _deadline)) in self.pending.iter_mut() {
+            if *q == queried {
+                listed.extend(names.iter().cloned());
+                if page >= total {
+                    done.push((*requester, std::mem::take(listed)));
+                }
+            }
+        }
+        for (requester, _names) in &done {
+            self.pending.remove(&(*requester, queried));
+        }
+        done.sort_by_key(|(requester, _names)| *requester);
+        done
+    }
+
+    // Listings we will not wait for any longer, (requester, queried, names)
+    pub fn expire(&mut self, now: SwarmTime) -> Vec<(GnomeId, GnomeId, Vec<SwarmName>)> {
+        let mut expired: Vec<(GnomeId, GnomeId)> = self
+            .pending
+            .iter()
+            .filter(|(_key, (_names, deadline))| *deadline < now)
+            .map(|(key, _value)| *key)
+            .collect();
+        expired.sort();
+        expired
+            .into_iter()
+            .map(|(requester, queried)| {
+                let (names, _deadline) = self.pending.remove(&(requester, queried)).unwrap();
+                (requester, queried, names)
+            })
+            .collect()
+    }
+
+    // Adds a listing to what requester will receive,
+    // returns all of it once we are not waiting for anyone else
+    pub fn gather(
+        &mut self,
+        requester: GnomeId,
+        mut names: Vec<SwarmName>,
+    ) -> Option<Vec<SwarmName>> {
+        if let Some(gathered) = self.gathered.get_mut(&requester) {
+            gathered.append(&mut names);
+        }
+        if self.pending.keys().any(|(r, _q)| *r == requester) {
+            return None;
+        }
+        self.gathered.remove(&requester)
+    }
+}
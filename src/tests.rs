@@ -1,202 +1,2312 @@
-use super::neighbor::Neighborhood;
+use super::app_channel::app_channel;
+use super::app_channel::Delivery;
+use super::app_channel::ToApp;
+use super::chain::ChainHistory;
+use super::chain::Merge;
+use super::chain::Resolution;
+use super::chain::CHAIN_INFO_INTERVAL;
+use super::chain::MERGE_TIMEOUT;
+use super::genesis::choose_sync_response;
+use super::multicast::Multicast;
+use super::neighbor_table::NeighborStatus;
+use super::neighbor_table::NeighborTable;
+use super::next_state::ChangeConfig;
+use super::next_state::NeighborView;
+use super::next_state::RoundPosition;
+use super::next_state::TurnEnd;
+use super::next_state::TurnOutcome;
+use super::outbox::Outbox;
+use super::outbox::TrafficClass;
+use super::reliable_cast::ReliableCast;
+use super::simulator::Faults;
+use super::simulator::ManualClock;
+use super::simulator::Simulator;
+use super::simulator::Traffic;
+use super::swarm_discovery::swarm_pages;
+use super::swarm_discovery::SwarmQueries;
+use super::swarm_discovery::MAX_SWARMS_PAGE_LEN;
+use super::token_bucket::TokenBuckets;
+use super::token_bucket::MIN_NEIGHBOR_TOKENS;
+use super::unicast::UNICAST_BUFFER;
 use super::*;
-use std::thread;
-
-use std::{
-    sync::mpsc::{channel, Receiver, Sender},
-    time::Duration,
-};
-
-struct TestManager {
-    manager: Manager,
-    swarm_id: SwarmID,
-    swarm_name: String,
-    neighbors: Vec<(GnomeId, Sender<Message>)>,
-}
-
-impl TestManager {
-    pub fn join_a_swarm(
-        swarm_name: &str,
-        neighbors_count: u64,
-    ) -> (TestManager, Sender<ToGnome>, Receiver<GnomeToApp>) {
-        let (send, _recv) = channel();
-        let mut manager = Manager::new(GnomeId(0), None, send);
-        let mut neighbors = Vec::with_capacity(neighbors_count as usize);
-        let mut mgr_neighbors: Vec<Neighbor> = Vec::with_capacity(neighbors_count as usize);
-        for i in 1..neighbors_count + 1 {
-            let (neighbor, sender, _receiver) = build_a_neighbor(GnomeId(i));
-            mgr_neighbors.push(neighbor);
-            neighbors.push((GnomeId(i), sender));
-        }
-
-        let (swarm_id, (req_sender, resp_receiver)) = manager
-            .join_a_swarm(swarm_name.to_string(), None, Some(mgr_neighbors))
-            .unwrap();
-        let manager = TestManager {
-            manager,
-            swarm_id,
-            swarm_name: swarm_name.to_string(),
-            neighbors,
-        };
-        (manager, req_sender, resp_receiver)
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::TrySendError;
+use std::time::Duration;
+
+fn blocks(sim: &Simulator, gnome: usize) -> Vec<(BlockID, GnomeId)> {
+    sim.events(gnome)
+        .iter()
+        .filter_map(|(_t, event)| {
+            if let GnomeToApp::Block(b_id, _data, signer) = event {
+                Some((*b_id, *signer))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn everyone_has_blocks(sim: &Simulator, size: usize, count: usize) -> bool {
+    (0..size).all(|g| blocks(sim, g).len() >= count)
+}
+
+#[test]
+fn gnomes_start_ready() {
+    let mut sim = Simulator::new(3, 1);
+    sim.run_for(Duration::from_millis(100));
+    for g in 0..3 {
+        let ready = sim.events(g).iter().any(|(_t, event)| {
+            matches!(event, GnomeToApp::SwarmReady(_name, founder) if *founder == (g == 0))
+        });
+        assert!(ready, "Gnome {} did not become ready", g);
+    }
+}
+
+#[test]
+fn gnome_message_exchange() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 7);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::AddData(SyncData::new(vec![1, 2, 3]).unwrap()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(
+        sim.run_until(deadline, |s| everyone_has_blocks(s, size, 1)),
+        "Block was not accepted by every gnome"
+    );
+    let expected = blocks(&sim, 0);
+    assert_eq!(expected[0].1, sim.id(0));
+    for g in 1..size {
+        assert_eq!(blocks(&sim, g), expected);
+    }
+}
+
+#[test]
+fn only_founder_adds_data_by_default() {
+    let mut sim = Simulator::new(2, 5);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(1, ToGnome::AddData(SyncData::new(vec![7, 7, 7]).unwrap()));
+    let deadline = sim.now() + Duration::from_secs(30);
+    assert!(sim.run_until(deadline, |s| s
+        .events(1)
+        .iter()
+        .any(|(_t, event)| matches!(event, GnomeToApp::PolicyNotMet(_d)))));
+    assert!(blocks(&sim, 0).is_empty());
+}
+
+#[test]
+fn founder_cannot_reconfigure_with_reserved_id() {
+    let mut sim = Simulator::new(2, 5);
+    sim.run_for(Duration::from_secs(1));
+    let reserved = MAX_USER_CONFIG_ID + 3;
+    sim.request(
+        0,
+        ToGnome::Reconfigure(reserved, SyncData::new(vec![2; 8]).unwrap()),
+    );
+    let deadline = sim.now() + Duration::from_secs(30);
+    assert!(sim.run_until(deadline, |s| s.events(0).iter().any(
+        |(_t, event)| matches!(event, GnomeToApp::PolicyNotMetRcfg(id, _d) if *id == reserved)
+    )));
+    assert!(!sim.events(1).iter().any(|(_t, event)| matches!(
+        event,
+        GnomeToApp::Reconfig(id, _g, _c) if *id == reserved
+    )));
+}
+
+#[test]
+fn block_reaches_gnomes_without_direct_link() {
+    let size = 4;
+    let mut sim = Simulator::with_links(size, 3, &[(0, 1), (1, 2), (2, 3)]);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::AddData(SyncData::new(vec![4, 4, 4]).unwrap()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| everyone_has_blocks(s, size, 1)));
+    let expected = blocks(&sim, 0);
+    for g in 1..size {
+        assert_eq!(blocks(&sim, g), expected);
+    }
+}
+
+#[test]
+fn same_seed_same_history() {
+    let run = |seed: u64| {
+        let mut sim = Simulator::new(3, seed);
+        sim.run_for(Duration::from_secs(1));
+        sim.request(0, ToGnome::AddData(SyncData::new(vec![5, 5, 5]).unwrap()));
+        sim.request(0, ToGnome::AddData(SyncData::new(vec![6, 6, 6]).unwrap()));
+        sim.run_for(Duration::from_secs(30));
+        assert_eq!(blocks(&sim, 2).len(), 2);
+        (0..3)
+            .map(|g| {
+                sim.events(g)
+                    .iter()
+                    .map(|(t, event)| format!("{:?} {:?}", t, event))
+                    .collect::<Vec<String>>()
+            })
+            .collect::<Vec<Vec<String>>>()
+    };
+    let first = run(11);
+    assert_eq!(first, run(11));
+}
+
+#[test]
+fn block_accepted_despite_message_loss() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 1);
+    let lossy = Faults {
+        loss: 30,
+        ..Faults::default()
+    };
+    sim.set_faults(sim.id(0), sim.id(1), Traffic::Sync, lossy);
+    sim.set_faults(sim.id(1), sim.id(0), Traffic::Sync, lossy);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::AddData(SyncData::new(vec![8, 8, 8]).unwrap()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| everyone_has_blocks(s, size, 1)));
+    assert!(sim.lost() > 0);
+    let expected = blocks(&sim, 0);
+    for g in 1..size {
+        assert_eq!(blocks(&sim, g), expected);
     }
+}
 
-    pub fn status(&self) {
-        self.manager.print_status(&self.swarm_id);
+#[test]
+fn duplicates_are_harmless() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 2);
+    let twice = Faults {
+        duplicate: 100,
+        ..Faults::default()
+    };
+    for to in 1..size {
+        sim.set_faults(sim.id(0), sim.id(to), Traffic::Sync, twice);
+        sim.set_faults(sim.id(0), sim.id(to), Traffic::Cast, twice);
     }
+    sim.run_for(Duration::from_secs(1));
+    sim.request(
+        0,
+        ToGnome::AddData(SyncData::new(vec![12, 12, 12]).unwrap()),
+    );
+    sim.request(
+        0,
+        ToGnome::AddData(SyncData::new(vec![13, 13, 13]).unwrap()),
+    );
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| everyone_has_blocks(s, size, 2)));
+    let expected = blocks(&sim, 0);
+    for g in 1..size {
+        assert_eq!(blocks(&sim, g), expected);
+    }
+}
 
-    pub fn turn(&mut self, mut messages: Vec<Option<Message>>) {
-        for (_id, sender) in &mut self.neighbors {
-            if let Some(Some(message)) = messages.pop() {
-                let _ = sender.send(message);
+#[test]
+fn block_accepted_despite_reordering() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 3);
+    let shuffled = Faults {
+        reorder: 200,
+        reorder_window: Duration::from_millis(1),
+        ..Faults::default()
+    };
+    for from in 0..size {
+        for to in 0..size {
+            if from != to {
+                sim.set_faults(sim.id(from), sim.id(to), Traffic::Sync, shuffled);
             }
         }
     }
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::AddData(SyncData::new(vec![9, 9, 9]).unwrap()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| everyone_has_blocks(s, size, 1)));
+}
+
+#[test]
+fn isolated_gnome_disconnects() {
+    let mut sim = Simulator::new(3, 19);
+    sim.run_for(Duration::from_secs(1));
+    sim.partition(sim.id(2), sim.id(0));
+    sim.partition(sim.id(2), sim.id(1));
+    // Chill out has to end before anyone notices
+    let deadline = sim.now() + Duration::from_secs(30);
+    assert!(sim.run_until(deadline, |s| s.manager_events(2).iter().any(
+        |(_t, event)| matches!(event, GnomeToManager::Disconnected(_s_id, _s_name))
+    )));
+}
+
+#[test]
+fn short_partition_heals() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 23);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(
+        0,
+        ToGnome::AddData(SyncData::new(vec![11, 11, 11]).unwrap()),
+    );
+    sim.partition(sim.id(0), sim.id(1));
+    sim.run_for(Duration::from_millis(200));
+    sim.heal(sim.id(0), sim.id(1));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| everyone_has_blocks(s, size, 1)));
+}
+
+#[test]
+fn tokens_are_created_with_time() {
+    let mut available = 0;
+    let mut borrowed = 0;
+    gnome::create_tokens(
+        &mut available,
+        &mut borrowed,
+        Duration::from_millis(10),
+        1000,
+    );
+    assert_eq!(available, 10);
+    gnome::create_tokens(&mut available, &mut borrowed, Duration::from_secs(5), 1000);
+    assert_eq!(available, 1000);
+
+    let mut available = 0;
+    let mut borrowed = 300;
+    gnome::create_tokens(
+        &mut available,
+        &mut borrowed,
+        Duration::from_millis(200),
+        1000,
+    );
+    assert_eq!((available, borrowed), (0, 100));
+    gnome::create_tokens(
+        &mut available,
+        &mut borrowed,
+        Duration::from_millis(200),
+        1000,
+    );
+    assert_eq!((available, borrowed), (100, 0));
+
+    assert_eq!(
+        gnome::calculate_min_token_period(1_000_000_000),
+        Duration::from_nanos(1)
+    );
+    assert_eq!(
+        gnome::calculate_min_token_period(1 << 20),
+        Duration::from_micros(1)
+    );
+    assert_eq!(
+        gnome::calculate_min_token_period(1024),
+        Duration::from_millis(1)
+    );
+}
+
+#[test]
+fn neighbors_get_fair_share_of_tokens() {
+    let (a, b, c) = (GnomeId(1), GnomeId(2), GnomeId(3));
+    let mut buckets = TokenBuckets::default();
+    // New neighbors start with empty buckets, never smaller than minimum,
+    // what does not fit into them is returned
+    assert_eq!(buckets.refill(&[a, b], 0, 1000), 0);
+    assert_eq!(buckets.available(a), 0);
+    assert_eq!(buckets.refill(&[a, b], 4000, 1000), 1000);
+    assert_eq!(buckets.available(a), MIN_NEIGHBOR_TOKENS);
+
+    // A chatty neighbor uses up his bucket, but not the others'
+    buckets.charge(a, 5000);
+    assert_eq!(buckets.available(a), 0);
+    assert_eq!(buckets.available(b), MIN_NEIGHBOR_TOKENS);
+
+    // Tokens others have no room for go to the one that needs them,
+    // what does not fit anywhere is returned
+    assert_eq!(buckets.refill(&[a, b], 1000, 1000), 0);
+    assert_eq!(buckets.available(a), 1000);
+    assert_eq!(buckets.refill(&[a, b], 1000, 1000), 500);
+    assert_eq!(buckets.available(a), MIN_NEIGHBOR_TOKENS);
+
+    // Equal shares when everyone is hungry, gone neighbors are forgotten
+    let mut buckets = TokenBuckets::new();
+    buckets.refill(&[a, b, c], 30_000, 30_000);
+    buckets.charge(a, 10_000);
+    buckets.charge(c, 10_000);
+    assert_eq!(buckets.refill(&[a, c], 3000, 30_000), 0);
+    assert_eq!(buckets.available(a), 1500);
+    assert_eq!(buckets.available(b), 0);
+    assert_eq!(buckets.available(c), 1500);
+}
+
+#[test]
+fn outbox_serves_sync_before_casts() {
+    let classes = |messages: Vec<CastMessage>| -> Vec<TrafficClass> {
+        messages.iter().map(TrafficClass::of).collect()
+    };
+    // Both kinds of messages are 100 bytes long
+    let cast = || CastMessage::new_broadcast(CastID(1), CastData::new(vec![0; 53]).unwrap());
+    let sync = || {
+        CastMessage::new_response(NeighborResponse::Custom(
+            0,
+            CastData::new(vec![0; 51]).unwrap(),
+        ))
+    };
+    let (s, c) = (TrafficClass::Sync, TrafficClass::Cast);
+    let mut outbox = Outbox::new();
+    for _i in 0..3 {
+        outbox.push(cast());
+        outbox.push(sync());
+    }
+    let shares = TrafficShares::default();
+    assert_eq!(classes(outbox.schedule(400, &shares)), vec![s, s, s, c]);
+    // Tokens left unused by sync go to casts
+    assert_eq!(classes(outbox.schedule(250, &shares)), vec![c, c]);
+    assert!(outbox.is_empty());
+
+    let casts_only = TrafficShares { sync: 0, cast: 1 };
+    outbox.push(sync());
+    outbox.push(cast());
+    assert_eq!(classes(outbox.schedule(100, &casts_only)), vec![c]);
+    assert!(outbox.schedule(0, &casts_only).is_empty());
+    // Message bigger than what is left is still sent
+    assert_eq!(classes(outbox.schedule(1, &casts_only)), vec![s]);
+}
+
+#[test]
+fn bandwidth_monitor_averages_full_periods() {
+    let clock = std::sync::Arc::new(ManualClock::new(Duration::ZERO));
+    let mut band_mon = band_mon::BandwidthMonitor::new(Duration::from_secs(1), clock.clone());
+    band_mon.update(1600);
+    assert_eq!(band_mon.average(), 0);
+    clock.advance(Duration::from_secs(1));
+    band_mon.update(1600);
+    assert_eq!(band_mon.average(), 200);
+    // Going back in time should not panic
+    clock.set(Duration::ZERO);
+    band_mon.update(1600);
+    assert_eq!(band_mon.average(), 200);
+}
+
+#[test]
+fn neighbor_recv_does_not_block_on_manual_clock() {
+    let (shared_sender, _shared_receiver) = std::sync::mpsc::channel();
+    let (_sender, receiver) = std::sync::mpsc::channel();
+    let (_cast_sender, cast_receiver) = std::sync::mpsc::channel();
+    let (sender, _outbox) = std::sync::mpsc::channel();
+    let mut neighbor = Neighbor::from_id_channel_time(
+        GnomeId(1),
+        receiver,
+        cast_receiver,
+        sender,
+        shared_sender,
+        SwarmTime(0),
+        DEFAULT_SWARM_DIAMETER,
+        vec![],
+    );
+    // Nobody moves manual time while we wait, so we do not block
+    let clock = ManualClock::new(Duration::ZERO);
+    assert!(neighbor.recv(Duration::from_secs(30), &clock).is_none());
+    assert!(neighbor.recv_sync(Duration::from_secs(2), &clock).is_err());
+    assert_eq!(clock.now(), Duration::ZERO);
+}
+
+#[test]
+fn chill_out_holds_until_new_proposal() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 29);
+    sim.run_for(Duration::from_secs(1));
+    // First round is over and everyone is chilling out
+    let sent = sim.sent();
+    sim.run_for(Duration::from_secs(10));
+    assert_eq!(sim.sent(), sent);
+
+    let requested_at = sim.now();
+    sim.request(
+        0,
+        ToGnome::AddData(SyncData::new(vec![14, 14, 14]).unwrap()),
+    );
+    let deadline = requested_at + Duration::from_secs(1);
+    assert!(sim.run_until(deadline, |s| everyone_has_blocks(s, size, 1)));
+}
+
+#[test]
+fn chill_out_ends_after_max_time() {
+    let mut sim = Simulator::new(3, 31);
+    sim.run_for(Duration::from_secs(1));
+    let sent = sim.sent();
+    sim.run_for(Duration::from_secs(15));
+    assert!(sim.sent() > sent);
+}
+
+#[test]
+fn replayed_trace_matches_gnome_decisions() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 37);
+    let path = std::env::temp_dir().join(format!("swarm-trace-{}", std::process::id()));
+    sim.request(1, ToGnome::StartTrace(path.clone()));
+    sim.run_for(Duration::from_secs(1));
+    sim.request(
+        0,
+        ToGnome::AddData(SyncData::new(vec![15, 15, 15]).unwrap()),
+    );
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| everyone_has_blocks(s, size, 1)));
+    sim.request(1, ToGnome::StopTrace);
+    sim.run_for(Duration::from_millis(200));
+    let records = read_trace(&path).unwrap();
+    let _ = std::fs::remove_file(&path);
+    assert!(records
+        .iter()
+        .any(|record| matches!(record.event, TraceEvent::Received(_))));
+
+    let turns = replay(&records, &mut sim.swarm());
+    assert!(!turns.is_empty());
+    for turn in &turns {
+        if !turn.new_round {
+            assert_eq!(turn.winner, turn.chosen, "{}", turn);
+        }
+    }
+    let (block_id, _signer) = blocks(&sim, 1)[0];
+    assert!(turns
+        .iter()
+        .any(|turn| turn.winner == Header::Block(block_id) && turn.won_by.is_some()));
+}
+
+fn view(id: u64, swarm_time: u32, neighborhood: u8, header: Header) -> NeighborView {
+    NeighborView {
+        id: GnomeId(id),
+        swarm_time: SwarmTime(swarm_time),
+        neighborhood: Neighborhood(neighborhood),
+        header,
+        payload: Payload::KeepAlive(0),
+    }
+}
 
-    pub fn finish(self) {
-        self.manager.finish();
+fn position(swarm_time: u32, round_start: u32, neighborhood: u8, header: Header) -> RoundPosition {
+    RoundPosition {
+        swarm_time: SwarmTime(swarm_time),
+        round_start: SwarmTime(round_start),
+        neighborhood: Neighborhood(neighborhood),
+        header,
     }
 }
 
-fn build_a_neighbor(
-    id: GnomeId,
-) -> (
-    Neighbor,
-    Sender<Message>,
-    Sender<CastMessage>,
-    Receiver<WrappedMessage>,
-) {
-    let (mocked_remote_sender, receiver) = channel::<Message>();
-    let (sender, mocked_remote_receiver) = channel::<WrappedMessage>();
-    let (mocked_cast_sender, cast_remote_receiver) = channel::<CastMessage>();
-    (
-        Neighbor::from_id_channel_time(
-            id,
-            receiver,
-            cast_remote_receiver,
-            sender,
-            SwarmTime(0),
-            DEFAULT_SWARM_DIAMETER,
-        ),
-        mocked_remote_sender,
-        mocked_cast_sender,
-        mocked_remote_receiver,
+fn start_broadcast(origin: u64, signer: u64) -> Payload {
+    Payload::Reconfigure(
+        Signature::Regular(GnomeId(signer), vec![]),
+        Configuration::StartBroadcast(GnomeId(origin), CastID(1), CastDescriptor::default()),
     )
 }
 
 #[test]
-fn gnome_message_exchange() {
-    let (mut manager, req_sender, resp_receiver) =
-        TestManager::join_a_swarm("gnome message exchange", 1);
-
-    println!("Neighbors sent Proposal!");
-    manager.turn(vec![Some(Message {
-        swarm_time: SwarmTime(0),
-        neighborhood: Neighborhood(0),
-        header: Header::Sync,
-        payload: Payload::KeepAlive,
-    })]);
-
-    thread::sleep(Duration::from_millis(100));
-    let msg_res = resp_receiver.try_recv();
-    assert!(msg_res.is_err(), "User received unexpected message!");
-    let _ = req_sender.send(ToGnome::AddData(SyncData(1)));
-
-    manager.turn(vec![Some(Message {
-        swarm_time: SwarmTime(1),
-        neighborhood: Neighborhood(1),
-        header: Header::Sync,
-        payload: Payload::KeepAlive,
-    })]);
-
-    for i in 2..7 {
-        thread::sleep(Duration::from_millis(100));
-        manager.turn(vec![Some(Message {
-            swarm_time: SwarmTime(i),
-            neighborhood: Neighborhood(i as u8),
-            header: Header::Sync,
-            payload: Payload::KeepAlive,
-        })]);
-    }
-
-    thread::sleep(Duration::from_millis(100));
-    manager.turn(vec![Some(Message {
-        swarm_time: SwarmTime(7),
-        neighborhood: Neighborhood(0),
-        header: Header::Sync,
-        payload: Payload::KeepAlive,
-    })]);
-
-    for i in 0..7 {
-        thread::sleep(Duration::from_millis(100));
-        manager.turn(vec![Some(Message {
-            swarm_time: SwarmTime(8 + i),
-            neighborhood: Neighborhood(i as u8),
-            header: message::Header::Block(BlockID(1)),
-            payload: Payload::KeepAlive,
-        })]);
-    }
-
-    let rcvd = resp_receiver.recv();
-    assert!(rcvd.is_ok(), "User received invalid response!");
-
-    let unwrapped = rcvd.unwrap();
-    assert_eq!(
-        unwrapped,
-        GnomeToApp::Block(BlockID(1), SyncData(1), GnomeId(1)),
-        "User received unexpected response!"
-    );
-
-    thread::sleep(Duration::from_millis(100));
-    manager.turn(vec![Some(Message {
-        swarm_time: SwarmTime(15),
-        neighborhood: Neighborhood(0),
-        header: Header::Sync,
-        payload: Payload::Request(NeighborRequest::ListingRequest(SwarmTime(0))),
-    })]);
-
-    println!("Trying receive another message...");
-    let rcvd = resp_receiver.recv();
-    assert!(rcvd.is_ok(), "User did not receive ListingRequest!");
-
-    let unwrapped = rcvd.unwrap();
-    let g_id = GnomeId(1);
-    let n_request = NeighborRequest::ListingRequest(SwarmTime(0));
-    assert_eq!(
-        unwrapped,
-        GnomeToApp::DataInquiry(g_id, n_request),
-        "User received unexpected inquiry!"
-    );
-    let _ = req_sender.send(ToGnome::SendData(
-        g_id,
-        n_request,
-        NeighborResponse::Listing(1, vec![BlockID(1)]),
+fn highest_proposal_wins_regardless_of_order() {
+    for order in [[3, 7], [7, 3]] {
+        let mut next_state = NextState::new();
+        next_state.reset_for_next_turn(true, Header::Sync, Payload::KeepAlive(0));
+        for (i, b_id) in order.iter().enumerate() {
+            next_state.update(&view(i as u64 + 1, 1, 0, Header::Block(BlockID(*b_id))));
+        }
+        assert_eq!(next_state.header, Header::Block(BlockID(7)));
+        assert_eq!(next_state.neighborhood, Neighborhood(0));
+    }
+
+    let mut next_state = NextState::new();
+    next_state.update(&view(1, 1, 0, Header::Block(BlockID(1))));
+    next_state.update(&view(2, 1, 0, Header::Reconfigure(254, GnomeId(2))));
+    assert_eq!(next_state.header, Header::Block(BlockID(1)));
+}
+
+#[test]
+fn lower_neighborhood_of_same_proposal_pulls_us_down() {
+    let mut next_state = NextState::new();
+    next_state.reset_for_next_turn(false, Header::Block(BlockID(1)), Payload::KeepAlive(0));
+    next_state.neighborhood = Neighborhood(4);
+    next_state.update(&view(1, 1, 6, Header::Block(BlockID(1))));
+    assert_eq!(next_state.neighborhood, Neighborhood(4));
+    next_state.update(&view(2, 1, 2, Header::Block(BlockID(1))));
+    assert_eq!(next_state.neighborhood, Neighborhood(2));
+    next_state.update(&view(3, 1, 0, Header::Sync));
+    assert_eq!(next_state.neighborhood, Neighborhood(2));
+}
+
+#[test]
+fn messages_older_than_accepted_one_are_ignored() {
+    let mut next_state = NextState::new();
+    next_state.last_accepted_message.swarm_time = SwarmTime(10);
+    next_state.update(&view(1, 5, 0, Header::Block(BlockID(9))));
+    assert_eq!(next_state.header, Header::Sync);
+    next_state.update(&view(1, 10, 0, Header::Block(BlockID(9))));
+    assert_eq!(next_state.header, Header::Block(BlockID(9)));
+}
+
+#[test]
+fn neighborhood_grows_only_when_neighbors_agree() {
+    let diameter = DEFAULT_SWARM_DIAMETER;
+    let current = position(20, 14, 2, Header::Sync);
+    let mut next_state = NextState::new();
+    next_state.reset_for_next_turn(false, Header::Sync, Payload::KeepAlive(0));
+    next_state.swarm_time = SwarmTime(20);
+    next_state.neighborhood = Neighborhood(2);
+
+    // Nobody said anything
+    let (next, _payload) = next_state.next_position(current, diameter);
+    assert_eq!(next.neighborhood, Neighborhood(2));
+    assert_eq!(next.swarm_time, SwarmTime(21));
+
+    next_state.update(&view(1, 20, 2, Header::Sync));
+    next_state.update(&view(2, 20, 3, Header::Sync));
+    let (next, _payload) = next_state.next_position(current, diameter);
+    assert_eq!(next.neighborhood, Neighborhood(3));
+
+    next_state.update(&view(3, 20, 0, Header::Block(BlockID(5))));
+    let (next, _payload) = next_state.next_position(current, diameter);
+    assert_eq!(next.neighborhood, Neighborhood(0));
+    assert_eq!(next.header, Header::Block(BlockID(5)));
+
+    // First turn of a round does not count
+    next_state.reset_for_next_turn(true, Header::Sync, Payload::KeepAlive(0));
+    next_state.update(&view(1, 21, 0, Header::Sync));
+    let (next, _payload) = next_state.next_position(current, diameter);
+    assert_eq!(next.neighborhood, Neighborhood(0));
+}
+
+#[test]
+fn catching_up_keeps_neighborhood() {
+    let diameter = SwarmTime(7);
+    let mut next_state = NextState::new();
+    next_state.reset_for_next_turn(false, Header::Sync, Payload::KeepAlive(0));
+    next_state.update(&view(1, 100, 3, Header::Sync));
+    next_state.swarm_time = SwarmTime(100);
+    next_state.neighborhood = Neighborhood(3);
+
+    let (next, _payload) = next_state.next_position(position(10, 7, 1, Header::Sync), diameter);
+    assert_eq!(next.swarm_time, SwarmTime(101));
+    assert_eq!(next.neighborhood, Neighborhood(1));
+
+    let (next, _payload) = next_state.next_position(position(95, 91, 1, Header::Sync), diameter);
+    assert_eq!(next.neighborhood, Neighborhood(4));
+}
+
+#[test]
+fn round_ends_when_everyone_is_aware_or_time_is_up() {
+    let diameter = SwarmTime(3);
+    let block = Header::Block(BlockID(1));
+    let reconf = Header::Reconfigure(254, GnomeId(1));
+    assert_eq!(
+        position(12, 10, 2, block).outcome(diameter),
+        TurnOutcome::Continue
+    );
+    assert_eq!(
+        position(12, 10, 3, block).outcome(diameter),
+        TurnOutcome::Accepted
+    );
+    assert_eq!(
+        position(12, 10, 3, reconf).outcome(diameter),
+        TurnOutcome::Accepted
+    );
+    assert_eq!(
+        position(16, 10, 2, block).outcome(diameter),
+        TurnOutcome::TimedOut
+    );
+    assert_eq!(
+        position(16, 10, 2, Header::Sync).outcome(diameter),
+        TurnOutcome::Synced
+    );
+    assert_eq!(
+        position(12, 10, 3, Header::Block(BlockID(0))).outcome(diameter),
+        TurnOutcome::Synced
+    );
+    // Swarm time wrapped around
+    assert_eq!(
+        position(1, u32::MAX, 0, block).outcome(diameter),
+        TurnOutcome::Continue
+    );
+}
+
+#[test]
+fn proposals_only_early_in_idle_round() {
+    assert!(position(12, 10, 1, Header::Sync).can_propose());
+    assert!(!position(12, 10, 2, Header::Sync).can_propose());
+    assert!(!position(2, 0, 0, Header::Sync).can_propose());
+    assert!(!position(12, 10, 0, Header::Block(BlockID(3))).can_propose());
+}
+
+#[test]
+fn neighbor_moves_are_checked_against_his_last_position() {
+    let diameter = SwarmTime(3);
+    let block = Header::Block(BlockID(7));
+    let reconf = Header::Reconfigure(254, GnomeId(2));
+    let ours = (Header::Sync, Neighborhood(1));
+    let last = position(12, 10, 1, Header::Sync);
+
+    // Time can not go back
+    assert!(!last.may_move_to(&position(11, 10, 2, Header::Sync), None, ours, diameter));
+    // Neighborhood can grow by one over what we have sent
+    assert!(last.may_move_to(&position(13, 10, 2, Header::Sync), None, ours, diameter));
+    assert!(!last.may_move_to(&position(13, 10, 3, Header::Sync), None, ours, diameter));
+    // Same neighborhood three turns in a row
+    let prev = Some(Neighborhood(1));
+    assert!(!last.may_move_to(&position(13, 10, 1, Header::Sync), prev, ours, diameter));
+    // Higher header, unless it comes too late in a round
+    assert!(last.may_move_to(&position(13, 10, 0, block), None, ours, diameter));
+    assert!(!last.may_move_to(&position(16, 10, 0, block), None, ours, diameter));
+    // Block is never replaced by a lower header
+    let last = position(12, 10, 1, block);
+    assert!(!last.may_move_to(&position(13, 10, 0, reconf), None, ours, diameter));
+    assert!(!last.may_move_to(&position(13, 10, 0, Header::Sync), None, ours, diameter));
+    let last = position(12, 10, 1, reconf);
+    assert!(last.may_move_to(&position(13, 10, 0, block), None, ours, diameter));
+    assert!(!last.may_move_to(&position(13, 10, 0, Header::Sync), None, ours, diameter));
+}
+
+#[test]
+fn turn_ends_when_neighbors_move_on_or_time_runs_out() {
+    assert_eq!(TurnEnd::decide(false, false, false, true), TurnEnd::Wait);
+    assert_eq!(TurnEnd::decide(true, false, false, false), TurnEnd::Now);
+    assert_eq!(TurnEnd::decide(false, true, false, false), TurnEnd::Now);
+    assert_eq!(TurnEnd::decide(false, false, true, true), TurnEnd::Now);
+    assert_eq!(TurnEnd::decide(false, false, true, false), TurnEnd::NoReply);
+}
+
+#[test]
+fn adopted_reconfiguration_sets_change_config() {
+    let header = Header::Reconfigure(254, GnomeId(5));
+    let mut next_state = NextState::new();
+    let mut proposal = view(2, 1, 0, header);
+    proposal.payload = start_broadcast(5, 6);
+    next_state.update(&proposal);
+    assert_eq!(next_state.header, header);
+    assert!(matches!(next_state.change_config, ChangeConfig::None));
+
+    proposal.payload = start_broadcast(5, 5);
+    let mut next_state = NextState::new();
+    next_state.update(&proposal);
+    assert!(matches!(
+        next_state.change_config,
+        ChangeConfig::AddBroadcast {
+            origin: GnomeId(5),
+            source: GnomeId(2),
+            ..
+        }
+    ));
+
+    // Neighbor closer to origin gets filtered out as a source
+    next_state.neighborhood = Neighborhood(2);
+    let mut closer = view(3, 1, 1, header);
+    closer.payload = start_broadcast(5, 5);
+    next_state.update(&closer);
+    if let ChangeConfig::AddBroadcast {
+        ref filtered_neighbors,
+        ..
+    } = next_state.change_config
+    {
+        assert_eq!(filtered_neighbors, &vec![GnomeId(3)]);
+    } else {
+        panic!("Expected AddBroadcast");
+    }
+
+    let custom = Configuration::UserDefined(7, SyncData::new(vec![1]).unwrap());
+    assert!(matches!(
+        ChangeConfig::from_configuration(&custom, GnomeId(2), GnomeId(9)),
+        Some(ChangeConfig::Custom {
+            signed_by: GnomeId(9),
+            ..
+        })
     ));
+    assert!(
+        ChangeConfig::from_configuration(&Configuration::CreateGroup, GnomeId(2), GnomeId(2))
+            .is_none()
+    );
+}
+
+#[test]
+fn own_proposal_at_round_start_sets_change_config() {
+    let mut next_state = NextState::new();
+    let payload = Payload::Reconfigure(
+        Signature::Regular(GnomeId(4), vec![]),
+        Configuration::UserDefined(9, SyncData::new(vec![1, 2]).unwrap()),
+    );
+    next_state.reset_for_next_turn(true, Header::Reconfigure(9, GnomeId(4)), payload);
+    assert!(matches!(
+        next_state.change_config,
+        ChangeConfig::Custom {
+            id: 9,
+            signed_by: GnomeId(4),
+            ..
+        }
+    ));
+    next_state.reset_for_next_turn(true, Header::Sync, Payload::KeepAlive(0));
+    assert!(matches!(next_state.change_config, ChangeConfig::None));
+}
+
+fn idle_neighbor(id: u64) -> Neighbor {
+    let (shared_sender, _shared_receiver) = std::sync::mpsc::channel();
+    let (_sender, receiver) = std::sync::mpsc::channel();
+    let (_cast_sender, cast_receiver) = std::sync::mpsc::channel();
+    let (sender, _outbox) = std::sync::mpsc::channel();
+    Neighbor::from_id_channel_time(
+        GnomeId(id),
+        receiver,
+        cast_receiver,
+        sender,
+        shared_sender,
+        SwarmTime(0),
+        DEFAULT_SWARM_DIAMETER,
+        vec![],
+    )
+}
+
+#[test]
+fn neighbor_table_swaps_statuses_at_turn_end() {
+    let mut table = NeighborTable::new();
+    table.insert(idle_neighbor(1), NeighborStatus::Fast);
+    table.insert(idle_neighbor(2), NeighborStatus::Fast);
+    table.insert(idle_neighbor(3), NeighborStatus::Slow);
+    table.insert(idle_neighbor(4), NeighborStatus::New);
+    assert!(table.set_status(GnomeId(2), NeighborStatus::Refreshed));
+    assert!(!table.set_status(GnomeId(5), NeighborStatus::Refreshed));
+
+    // Lookups respect statuses and come out in order we asked for
+    assert!(table.get(GnomeId(4), &NeighborStatus::ACTIVE).is_none());
+    assert!(table.get(GnomeId(4), &NeighborStatus::ALL).is_some());
+    assert_eq!(
+        table.ids(&[NeighborStatus::Slow, NeighborStatus::Refreshed]),
+        vec![GnomeId(3), GnomeId(2)]
+    );
+
+    table.end_turn();
+    assert_eq!(table.ids(&[NeighborStatus::Fast]), vec![GnomeId(2)]);
+    let mut slow = table.ids(&[NeighborStatus::Slow]);
+    slow.sort();
+    assert_eq!(slow, vec![GnomeId(1), GnomeId(3)]);
+    assert_eq!(table.ids(&[NeighborStatus::New]), vec![GnomeId(4)]);
+    let metrics = table.metrics(GnomeId(2)).unwrap();
+    assert_eq!((metrics.turns_served, metrics.turns_missed), (1, 0));
+    let metrics = table.metrics(GnomeId(1)).unwrap();
+    assert_eq!((metrics.turns_served, metrics.turns_missed), (0, 1));
+
+    // Turn cut short only promotes those that responded
+    table.set_status(GnomeId(3), NeighborStatus::Refreshed);
+    table.merge_refreshed();
+    assert_eq!(table.count(NeighborStatus::Fast), 2);
+    assert_eq!(table.ids(&[NeighborStatus::Slow]), vec![GnomeId(1)]);
+
+    assert!(table.remove(GnomeId(1)).is_some());
+    assert!(table.remove(GnomeId(1)).is_none());
+    assert_eq!(table.len(), 3);
+}
+
+#[test]
+fn neighbors_are_ranked_by_score() {
+    let mut table = NeighborTable::new();
+    let mut quick = idle_neighbor(1);
+    quick.available_bandwith = 100;
+    quick.mark_sent(Duration::from_millis(0));
+    quick.mark_responded(Duration::from_millis(40));
+    quick.mark_sent(Duration::from_millis(100));
+    quick.mark_responded(Duration::from_millis(120));
+    // Smoothed towards the newer sample
+    assert_eq!(quick.rtt, Some(Duration::from_micros(37_500)));
+    let mut lagging = idle_neighbor(2);
+    lagging.available_bandwith = 10_000;
+    lagging.mark_sent(Duration::from_millis(0));
+    lagging.mark_responded(Duration::from_millis(800));
+    let mut forger = idle_neighbor(3);
+    forger.available_bandwith = 5_000;
+    forger.verify_failures = 2;
+    let mut fresh = idle_neighbor(4);
+    fresh.available_bandwith = 200;
+    table.insert(quick, NeighborStatus::Fast);
+    table.insert(lagging, NeighborStatus::Fast);
+    table.insert(forger, NeighborStatus::Fast);
+    table.insert(fresh, NeighborStatus::Refreshed);
+
+    assert_eq!(table.score(GnomeId(1)), Some(991));
+    assert_eq!(table.score(GnomeId(2)), Some(800));
+    assert_eq!(table.score(GnomeId(3)), Some(800));
+    assert_eq!(table.score(GnomeId(4)), Some(1000));
+    assert_eq!(
+        table.best(&NeighborStatus::ALL, |_n| true),
+        Some(GnomeId(4))
+    );
+    // Self reported bandwith only settles a draw
+    assert_eq!(
+        table.best(&[NeighborStatus::Fast], |n| n.id != GnomeId(1)),
+        Some(GnomeId(2))
+    );
+    assert_eq!(
+        table.worst(&NeighborStatus::ALL, |_n| true),
+        Some(GnomeId(3))
+    );
+
+    // Missing turns costs up to a quarter of the score
+    table.end_turn();
+    table.end_turn();
+    assert_eq!(table.metrics(GnomeId(1)).unwrap().turns_missed, 2);
+    assert_eq!(table.score(GnomeId(1)), Some(991 - 250 - 25));
+    assert_eq!(table.score(GnomeId(4)), Some(1000 - 125));
+}
+
+#[test]
+fn neighbor_is_dropped_after_repeated_verification_failures() {
+    let sim = Simulator::new(2, 107);
+    let mut swarm = sim.swarm();
+    let (shared_sender, _shared_receiver) = channel();
+    let (to_neighbor, receiver) = channel();
+    let (_cast_sender, cast_receiver) = channel();
+    let (sender, _outbox) = channel();
+    let neighbor = Neighbor::from_id_channel_time(
+        sim.id(1),
+        receiver,
+        cast_receiver,
+        sender,
+        shared_sender,
+        SwarmTime(0),
+        DEFAULT_SWARM_DIAMETER,
+        vec![],
+    );
+    let id = neighbor.id;
+    let mut table = NeighborTable::new();
+    table.insert(neighbor, NeighborStatus::Fast);
+    let score = table.score(id).unwrap();
+    for i in 1..4 {
+        let data = SyncData::new(vec![i, i, i]).unwrap();
+        let forged = Message::new(
+            SwarmTime(i as u32),
+            Header::Block(BlockID(i as u64)),
+            Payload::Block(
+                BlockID(i as u64),
+                Signature::Regular(sim.id(1), vec![0; 8]),
+                data,
+            ),
+            Neighborhood(0),
+        );
+        to_neighbor.send(forged).unwrap();
+        let neighbor = table.get_mut(id, &[NeighborStatus::Fast]).unwrap();
+        let (served, sanity_passed, _new_proposal, drop_me) =
+            neighbor.try_recv(Message::block(), &mut swarm);
+        assert!(served);
+        assert!(!sanity_passed);
+        assert_eq!(drop_me, i == 3);
+        assert!(table.score(id).unwrap() < score);
+    }
+    assert_eq!(table.score(id), Some(score - 300));
+}
+
+#[test]
+fn retention_judges_loss_and_missed_rounds() {
+    let retention = NeighborRetention {
+        max_loss_permille: 500,
+        min_turns: 4,
+        max_missed_rounds: 2,
+        min_neighbors: 1,
+    };
+    let mut table = NeighborTable::new();
+    table.insert(idle_neighbor(1), NeighborStatus::Fast);
+    table.insert(idle_neighbor(2), NeighborStatus::Fast);
+    table.insert(idle_neighbor(3), NeighborStatus::New);
+
+    // Neighbor 1 serves every other turn, neighbor 2 never does
+    for turn in 0..4 {
+        if turn % 2 == 0 {
+            table.set_status(GnomeId(1), NeighborStatus::Refreshed);
+        }
+        table.end_turn();
+    }
+    table.end_round();
+    assert_eq!(table.metrics(GnomeId(1)).unwrap().loss_permille(), 500);
+    assert_eq!(table.metrics(GnomeId(2)).unwrap().rounds_missed, 1);
+    assert_eq!(
+        table.judge(&retention),
+        vec![(GnomeId(2), DropReason::Loss(1000))]
+    );
+
+    // Not enough turns seen yet to judge loss
+    let patient = NeighborRetention {
+        min_turns: 10,
+        ..retention
+    };
+    assert!(table.judge(&patient).is_empty());
+
+    // Silent for a second round in a row
+    table.end_turn();
+    table.end_round();
+    assert_eq!(
+        table.judge(&patient),
+        vec![(GnomeId(2), DropReason::MissedRounds(2))]
+    );
+    assert_eq!(table.metrics(GnomeId(1)).unwrap().rounds_missed, 1);
+    // Neighbor that responded in last turn of a round did not miss it
+    table.set_status(GnomeId(1), NeighborStatus::Refreshed);
+    table.end_round();
+    assert_eq!(table.metrics(GnomeId(1)).unwrap().rounds_missed, 0);
+    assert_eq!(table.metrics(GnomeId(3)).unwrap().rounds_missed, 0);
+}
+
+#[test]
+fn neighboring_swarms_are_listed_in_pages() {
+    let names: Vec<SwarmName> = (0..100)
+        .map(|i| SwarmName::new(GnomeId(i), format!("/swarm/{:02}", i)).unwrap())
+        .collect();
+    let pages = swarm_pages(names.clone());
+    let count = pages.len();
+    assert!(count > 1);
+    let mut listed = vec![];
+    for (i, page) in pages.into_iter().enumerate() {
+        assert!(page.len() <= 3 + MAX_SWARMS_PAGE_LEN);
+        if let NeighborResponse::NeighboringSwarms(page_no, total, mut page_names) = page {
+            assert_eq!(page_no as usize, i + 1);
+            assert_eq!(total as usize, count);
+            listed.append(&mut page_names);
+        } else {
+            panic!("Unexpected response {:?}", page);
+        }
+    }
+    assert_eq!(listed, names);
+}
+
+#[test]
+fn swarm_query_is_forwarded_to_given_depth() {
+    let mut sim = Simulator::with_links(3, 11, &[(0, 1), (1, 2)]);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::ListNeighboringSwarms(2));
+    let listed_by = |sim: &Simulator| -> Vec<GnomeId> {
+        sim.events(0)
+            .iter()
+            .filter_map(|(_t, event)| {
+                if let GnomeToApp::NeighboringSwarms(g_id, names) = event {
+                    assert_eq!(names, &vec![sim.swarm().name]);
+                    Some(*g_id)
+                } else {
+                    None
+                }
+            })
+            .collect()
+    };
+    let deadline = sim.now() + Duration::from_secs(10);
+    // Gnome 2 answers through gnome 1, who merges it with his own listing
+    assert!(sim.run_until(deadline, |s| !listed_by(s).is_empty()));
+    sim.run_for(Duration::from_secs(10));
+    assert_eq!(listed_by(&sim), vec![sim.id(1)]);
+}
+
+#[test]
+fn swarm_queries_share_answers_and_gather_them() {
+    let name = |i: u64| SwarmName::new(GnomeId(i), format!("/swarm/{}", i)).unwrap();
+    let (a, b, q, r) = (GnomeId(1), GnomeId(2), GnomeId(3), GnomeId(4));
+    let mut queries = SwarmQueries::new();
+    assert!(queries.start(a, vec![name(1)]));
+    assert!(!queries.start(a, vec![]));
+    assert!(queries.start(b, vec![]));
+    assert!(queries.insert(a, q, SwarmTime(10)));
+    assert!(queries.insert(a, r, SwarmTime(10)));
+    // Already asked for a, answer will be shared
+    assert!(!queries.insert(b, q, SwarmTime(12)));
+
+    assert!(queries.add_page(q, 1, 2, vec![name(3)]).is_empty());
+    let listings = queries.add_page(q, 2, 2, vec![name(4)]);
+    assert_eq!(
+        listings,
+        vec![(a, vec![name(3), name(4)]), (b, vec![name(3), name(4)])]
+    );
+    assert_eq!(
+        queries.gather(b, vec![name(3), name(4)]),
+        Some(vec![name(3), name(4)])
+    );
+    // Still waiting on r for a
+    assert_eq!(queries.gather(a, vec![name(3), name(4)]), None);
+    assert!(queries.expire(SwarmTime(10)).is_empty());
+    assert!(queries.add_page(r, 1, 2, vec![name(5)]).is_empty());
+    assert_eq!(queries.expire(SwarmTime(11)), vec![(a, r, vec![name(5)])]);
+    let gathered = queries.gather(a, vec![name(5)]).unwrap();
+    assert_eq!(gathered, vec![name(1), name(3), name(4), name(5)]);
+
+    // Merged listing is numbered anew
+    let pages = swarm_pages(gathered.clone());
+    assert_eq!(
+        pages,
+        vec![NeighborResponse::NeighboringSwarms(1, 1, gathered)]
+    );
+}
+
+fn discovery_starts(sim: &Simulator, gnome: usize) -> usize {
+    sim.manager_events(gnome)
+        .iter()
+        .filter(|(_t, event)| {
+            matches!(
+                event,
+                GnomeToManager::DiscoveryStatus(_s_id, DiscoveryStatus::Started(_count))
+            )
+        })
+        .count()
+}
 
-    thread::sleep(Duration::from_millis(100));
-    manager.turn(vec![Some(Message {
-        swarm_time: SwarmTime(16),
-        neighborhood: Neighborhood(1),
-        header: Header::Sync,
-        payload: Payload::KeepAlive,
-    })]);
-
-    manager.status();
-    println!("Trying receive another message...");
-    thread::sleep(Duration::from_millis(100));
-    manager.turn(vec![Some(Message {
-        swarm_time: SwarmTime(17),
-        neighborhood: Neighborhood(2),
-        header: Header::Sync,
-        payload: Payload::KeepAlive,
-    })]);
-    manager.finish();
-}
-
-// #[test]
-// fn exit_on_request() {
-//     let (manager, _s, _r) = TestManager::join_a_swarm("exit on request", 0);
-//     manager.status();
-//     // TODO: below is not required
-//     manager.finish();
-// }
+#[test]
+fn discovery_searches_often_with_few_neighbors() {
+    // Two neighbors is less than we would like to have
+    let mut sim = Simulator::new(3, 13);
+    sim.run_for(Duration::from_secs(1200));
+    for g in 0..3 {
+        assert!(discovery_starts(&sim, g) > 2, "Gnome {} did not search", g);
+    }
+    // With four neighbors there is no hurry
+    let mut sim = Simulator::new(5, 13);
+    sim.run_for(Duration::from_secs(1200));
+    for g in 0..5 {
+        assert_eq!(discovery_starts(&sim, g), 1);
+    }
+}
+
+fn bandwidth_reports(sim: &Simulator, gnome: usize) -> Vec<BandwidthUsage> {
+    sim.manager_events(gnome)
+        .iter()
+        .filter_map(|(_t, event)| {
+            if let GnomeToManager::BandwidthUsage(_s_id, usage) = event {
+                Some(usage.clone())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn bandwidth_usage_is_reported_and_can_be_reassigned() {
+    let mut sim = Simulator::new(3, 17);
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| !bandwidth_reports(s, 0).is_empty()));
+    let usage = bandwidth_reports(&sim, 0).remove(0);
+    assert!(usage.average > 0);
+    assert!(usage.average < usage.assigned);
+    let neighbors: Vec<GnomeId> = usage.neighbors.iter().map(|(g_id, _b)| *g_id).collect();
+    assert_eq!(neighbors, vec![sim.id(1), sim.id(2)]);
+    assert!(usage.neighbors.iter().all(|(_g_id, bytes)| *bytes > 0));
+    assert!(sim
+        .events(0)
+        .iter()
+        .any(|(_t, event)| matches!(event, GnomeToApp::BandwidthUsage(u) if *u == usage)));
+
+    sim.manager_request(0, ManagerToGnome::SetBandwidth(4096));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| bandwidth_reports(s, 0).len() > 1));
+    assert_eq!(bandwidth_reports(&sim, 0)[1].assigned, 4096);
+}
+
+fn unicast_ends(
+    sim: &Simulator,
+    gnome: usize,
+) -> (Option<&SyncSender<CastData>>, Option<&Receiver<CastData>>) {
+    let mut ends = (None, None);
+    for (_t, event) in sim.events(gnome) {
+        match event {
+            GnomeToApp::UnicastOrigin(_s_id, _c_id, sender) => ends.0 = Some(sender),
+            GnomeToApp::Unicast(_s_id, _c_id, receiver) => ends.1 = Some(receiver),
+            _ => {}
+        }
+    }
+    ends
+}
+
+#[test]
+fn unicast_carries_data_both_ways_until_ended() {
+    let mut sim = Simulator::new(2, 19);
+    sim.run_for(Duration::from_secs(5));
+    sim.request(0, ToGnome::StartUnicast(sim.id(1)));
+    let deadline = sim.now() + Duration::from_secs(10);
+    assert!(sim.run_until(deadline, |s| {
+        let (a, b) = (unicast_ends(s, 0), unicast_ends(s, 1));
+        a.0.is_some() && a.1.is_some() && b.0.is_some() && b.1.is_some()
+    }));
+    let cast_id = sim
+        .events(0)
+        .iter()
+        .find_map(|(_t, event)| {
+            if let GnomeToApp::UnicastOrigin(_s_id, c_id, _sender) = event {
+                Some(*c_id)
+            } else {
+                None
+            }
+        })
+        .unwrap();
+    assert_eq!(cast_id, CastID(0));
+
+    // App can not put more data than there is room for
+    let data = |b: u8| CastData::new(vec![b; 100]).unwrap();
+    let sender = unicast_ends(&sim, 0).0.unwrap().clone();
+    for i in 0..UNICAST_BUFFER {
+        sender.try_send(data(i as u8)).unwrap();
+    }
+    assert!(matches!(
+        sender.try_send(data(0)),
+        Err(std::sync::mpsc::TrySendError::Full(_))
+    ));
+    unicast_ends(&sim, 1).0.unwrap().send(data(200)).unwrap();
+    sim.run_for(Duration::from_secs(5));
+    let received: Vec<CastData> = unicast_ends(&sim, 1).1.unwrap().try_iter().collect();
+    let expected: Vec<CastData> = (0..UNICAST_BUFFER as u8).map(data).collect();
+    assert_eq!(received, expected);
+    let received: Vec<CastData> = unicast_ends(&sim, 0).1.unwrap().try_iter().collect();
+    assert_eq!(received, vec![data(200)]);
+
+    sim.request(0, ToGnome::EndUnicast(cast_id));
+    sim.run_for(Duration::from_secs(5));
+    assert!(sender.try_send(data(0)).is_err());
+    assert!(matches!(
+        unicast_ends(&sim, 1).1.unwrap().try_recv(),
+        Err(std::sync::mpsc::TryRecvError::Disconnected)
+    ));
+    assert!(unicast_ends(&sim, 1).0.unwrap().send(data(0)).is_err());
+    // Only the side that did not end it is told
+    let ended = |g: usize| {
+        sim.events(g)
+            .iter()
+            .filter(|(_t, event)| {
+                matches!(event, GnomeToApp::UnicastEnded(_s_id, c_id) if *c_id == cast_id)
+            })
+            .count()
+    };
+    assert_eq!(ended(0), 0);
+    assert_eq!(ended(1), 1);
+}
+
+#[test]
+fn full_unicast_receiver_pauses_sender_without_losing_data() {
+    let mut sim = Simulator::new(2, 29);
+    sim.run_for(Duration::from_secs(5));
+    sim.request(0, ToGnome::StartUnicast(sim.id(1)));
+    let deadline = sim.now() + Duration::from_secs(10);
+    assert!(sim.run_until(deadline, |s| {
+        unicast_ends(s, 0).0.is_some() && unicast_ends(s, 1).1.is_some()
+    }));
+    let data = |n: u16| CastData::new(n.to_be_bytes().to_vec()).unwrap();
+    let sender = unicast_ends(&sim, 0).0.unwrap().clone();
+
+    // Receiving app does not read, sender's app is soon stopped
+    let mut sent = 0;
+    let mut stalled = 0;
+    while stalled < 20 {
+        while sender.try_send(data(sent)).is_ok() {
+            sent += 1;
+            stalled = 0;
+        }
+        stalled += 1;
+        sim.run_for(Duration::from_millis(500));
+    }
+    assert!((sent as usize) > APP_CHANNEL_CAPACITY);
+    assert!((sent as usize) < 2 * APP_CHANNEL_CAPACITY);
+
+    // Once app reads, everything arrives in order and sender can go on
+    let mut received = vec![];
+    let deadline = sim.now() + Duration::from_secs(60);
+    while received.len() < sent as usize && sim.now() < deadline {
+        received.extend(unicast_ends(&sim, 1).1.unwrap().try_iter());
+        sim.run_for(Duration::from_millis(500));
+    }
+    let expected: Vec<CastData> = (0..sent).map(data).collect();
+    assert_eq!(received, expected);
+    assert!(sender.try_send(data(sent)).is_ok());
+}
+
+#[test]
+fn reliable_cast_delivers_in_order_and_asks_for_gaps() {
+    let data = |b: u8| CastData::new(vec![b]).unwrap();
+    let mut origin = ReliableCast::new();
+    for b in 0..3 {
+        let seq = origin.stamp();
+        origin.receive(seq, data(b));
+    }
+    assert_eq!(origin.take_in_order().len(), 3);
+    assert_eq!(origin.retransmit(&[1]), vec![(1, data(1))]);
+
+    let mut cast = ReliableCast::new();
+    cast.receive(0, data(0));
+    cast.receive(2, data(2));
+    assert_eq!(cast.take_in_order(), vec![(0, data(0))]);
+    assert_eq!(cast.nack(), Some((0, vec![1])));
+    // Not asking again right away
+    cast.receive(3, data(3));
+    assert_eq!(cast.nack(), None);
+    cast.receive(1, data(1));
+    let delivered: Vec<u32> = cast.take_in_order().iter().map(|(s, _d)| *s).collect();
+    assert_eq!(delivered, vec![1, 2, 3]);
+    assert_eq!(cast.nack(), None);
+}
+
+fn broadcast_origin(sim: &Simulator, gnome: usize) -> Option<(CastID, &SyncSender<CastData>)> {
+    sim.events(gnome).iter().find_map(|(_t, event)| {
+        if let GnomeToApp::BroadcastOrigin(_s_id, c_id, sender, _receiver) = event {
+            Some((*c_id, sender))
+        } else {
+            None
+        }
+    })
+}
+
+fn broadcast_receiver(sim: &Simulator, gnome: usize) -> Option<&Receiver<CastData>> {
+    sim.events(gnome).iter().find_map(|(_t, event)| {
+        if let GnomeToApp::Broadcast(_s_id, _c_id, receiver) = event {
+            Some(receiver)
+        } else {
+            None
+        }
+    })
+}
+
+#[test]
+fn reliable_broadcast_repairs_lost_messages() {
+    let mut sim = Simulator::new(2, 23);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::StartBroadcast(CastDescriptor::default()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| broadcast_origin(s, 0).is_some()
+        && broadcast_receiver(s, 1).is_some()));
+    let (cast_id, _sender) = broadcast_origin(&sim, 0).unwrap();
+    sim.request(0, ToGnome::ReliableBroadcast(cast_id));
+    sim.run_for(Duration::from_millis(100));
+    let lossy = Faults {
+        loss: 200,
+        ..Faults::default()
+    };
+    sim.set_faults(sim.id(0), sim.id(1), Traffic::Cast, lossy);
+    let data = |b: u8| CastData::new(vec![b; 10]).unwrap();
+    for b in 0..50 {
+        broadcast_origin(&sim, 0).unwrap().1.send(data(b)).unwrap();
+        sim.run_for(Duration::from_millis(50));
+    }
+    sim.run_for(Duration::from_secs(5));
+    assert!(sim.lost() > 0);
+    let received: Vec<CastData> = broadcast_receiver(&sim, 1).unwrap().try_iter().collect();
+    let expected: Vec<CastData> = (0..50).map(data).collect();
+    assert_eq!(received, expected);
+}
+
+#[test]
+fn cast_moves_to_new_source_before_neighbor_is_dropped() {
+    let mut sim = Simulator::with_links(4, 109, &[(0, 1), (0, 2), (1, 3), (2, 3)]);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::StartBroadcast(CastDescriptor::default()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| broadcast_origin(s, 0).is_some()
+        && broadcast_receiver(s, 1).is_some()
+        && broadcast_receiver(s, 2).is_some()
+        && broadcast_receiver(s, 3).is_some()));
+    // Gnome 3 stops hearing gnome 1's sync messages and wants to drop him
+    let deaf = Faults {
+        loss: 1000,
+        ..Faults::default()
+    };
+    sim.set_faults(sim.id(1), sim.id(3), Traffic::Sync, deaf);
+    let dropped = |s: &Simulator| {
+        s.events(3).iter().any(|(_t, event)| {
+            matches!(event, GnomeToApp::NeighborDropped(n_id, _r) if *n_id == s.id(1))
+        })
+    };
+    // Rounds advance only when there is something to agree on
+    for b in 0..60 {
+        if dropped(&sim) {
+            break;
+        }
+        sim.request(0, ToGnome::AddData(SyncData::new(vec![b, b, b]).unwrap()));
+        sim.run_for(Duration::from_secs(1));
+    }
+    assert!(dropped(&sim));
+
+    let data = CastData::new(vec![7; 10]).unwrap();
+    broadcast_origin(&sim, 0)
+        .unwrap()
+        .1
+        .send(data.clone())
+        .unwrap();
+    sim.run_for(Duration::from_secs(1));
+    // Same receiver keeps getting data, now through gnome 2
+    let received: Vec<CastData> = broadcast_receiver(&sim, 1).unwrap().try_iter().collect();
+    assert_eq!(received.last(), Some(&data));
+    let broadcasts = sim
+        .events(2)
+        .iter()
+        .filter(|(_t, event)| matches!(event, GnomeToApp::Broadcast(_s, _c, _r)));
+    assert_eq!(broadcasts.count(), 1);
+}
+
+#[test]
+fn casts_are_forwarded_by_subscribers_topics() {
+    let (source_send, source_recv) = channel();
+    let (a_send, _a_recv) = channel();
+    let (b_send, _b_recv) = channel();
+    let (app_send, app_recv) = app_channel();
+    let subscribers = HashMap::from([(GnomeId(2), a_send), (GnomeId(3), b_send)]);
+    let mut cast = Multicast::new(
+        GnomeId(0),
+        (GnomeId(0), source_recv),
+        vec![],
+        subscribers,
+        Some(app_send),
+    );
+    cast.set_subscriber_topics(GnomeId(2), TopicFilter::new(vec![b"news".to_vec()]));
+    // Subscriber 3 has not told us what it wants, so it gets everything
+    assert_eq!(cast.take_interest_update(), None);
+    cast.set_subscriber_topics(GnomeId(3), TopicFilter::new(vec![b"sport".to_vec()]));
+    cast.set_topics(TopicFilter::nothing());
+    assert_eq!(
+        cast.take_interest_update(),
+        Some((
+            GnomeId(0),
+            TopicFilter::new(vec![b"news".to_vec(), b"sport".to_vec()])
+        ))
+    );
+    assert_eq!(cast.take_interest_update(), None);
+
+    for topic in ["news/1", "sport/1", "weather/1", "news/2"] {
+        let data = CastData::new(topic.as_bytes().to_vec()).unwrap();
+        source_send
+            .send(WrappedMessage::Cast(CastMessage::new_broadcast(
+                CastID(1),
+                data,
+            )))
+            .unwrap();
+    }
+    let mut room = HashMap::from([(GnomeId(2), 8), (GnomeId(3), 8)]);
+    let mut outgoing = vec![];
+    cast.serve(u64::MAX, &mut room, &mut outgoing);
+    let sent: Vec<(GnomeId, Vec<u8>)> = outgoing
+        .into_iter()
+        .map(|(g_id, msg)| (g_id, msg.get_data().unwrap().bytes()))
+        .collect();
+    assert_eq!(
+        sent,
+        vec![
+            (GnomeId(2), b"news/1".to_vec()),
+            (GnomeId(3), b"sport/1".to_vec()),
+            (GnomeId(2), b"news/2".to_vec()),
+        ]
+    );
+    assert!(app_recv.try_recv().is_err());
+}
+
+#[test]
+fn broadcast_descriptor_is_serialized_and_listed() {
+    let descriptor = CastDescriptor::new(
+        "news",
+        "text/plain",
+        2048,
+        Requirement::Has(Capabilities::Admin),
+    );
+    let config = Configuration::StartBroadcast(GnomeId(7), CastID(3), descriptor.clone());
+    let bytes = config.bytes();
+    assert_eq!(bytes.len(), config.len());
+    assert_eq!(Configuration::from_bytes(bytes), config);
+
+    let mut sim = Simulator::new(3, 29);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::StartBroadcast(descriptor.clone()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| broadcast_origin(s, 0).is_some()
+        && broadcast_receiver(s, 1).is_some()));
+    let (cast_id, _sender) = broadcast_origin(&sim, 0).unwrap();
+    sim.request(1, ToGnome::ListCasts);
+    sim.run_for(Duration::from_secs(1));
+    let listing = sim.events(1).iter().find_map(|(_t, event)| {
+        if let GnomeToApp::Casts(_s_id, casts) = event {
+            Some(casts.clone())
+        } else {
+            None
+        }
+    });
+    assert_eq!(listing, Some(vec![(true, cast_id, sim.id(0), descriptor)]));
+}
+
+#[test]
+fn subscriber_without_required_capability_is_denied() {
+    let descriptor = CastDescriptor::new(
+        "admins",
+        "text/plain",
+        0,
+        Requirement::Has(Capabilities::Admin),
+    );
+    let mut sim = Simulator::new(2, 31);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::StartBroadcast(descriptor));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| broadcast_origin(s, 0).is_some()));
+    let (cast_id, _sender) = broadcast_origin(&sim, 0).unwrap();
+    let denied = |s: &Simulator| {
+        s.events(1)
+            .iter()
+            .any(|(_t, event)| matches!(event, GnomeToApp::SubscribeDenied(true, c_id) if *c_id == cast_id))
+    };
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, denied));
+}
+
+#[test]
+fn subscriber_is_denied_by_its_own_source() {
+    let descriptor = CastDescriptor::new(
+        "admins",
+        "text/plain",
+        0,
+        Requirement::Has(Capabilities::Admin),
+    );
+    // Gnome 2 is pushed the cast by gnome 1 only
+    let mut sim = Simulator::with_links(3, 113, &[(0, 1), (1, 2)]);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::StartBroadcast(descriptor));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| broadcast_origin(s, 0).is_some()));
+    let (cast_id, _sender) = broadcast_origin(&sim, 0).unwrap();
+    let denied = |s: &Simulator, g: usize| {
+        s.events(g)
+            .iter()
+            .any(|(_t, event)| matches!(event, GnomeToApp::SubscribeDenied(true, c_id) if *c_id == cast_id))
+    };
+    // Every gnome checks its own subscribers against the same registry,
+    // so gnome 1 denies gnome 2 even though gnome 1 is denied too
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| denied(s, 1) && denied(s, 2)));
+}
+
+#[test]
+fn cast_history_is_bounded_by_count_or_bytes() {
+    let data = |b: u8| CastData::new(vec![b; 10]).unwrap();
+    let mut cast = Multicast::new(
+        GnomeId(1),
+        (GnomeId(1), channel().1),
+        vec![],
+        HashMap::new(),
+        None,
+    );
+    assert!(cast.history().is_empty());
+    cast.keep_history(HistoryLimit::Count(3));
+    let (source_send, source_recv) = channel();
+    cast.set_source((GnomeId(1), source_recv));
+    for b in 0..5 {
+        source_send
+            .send(WrappedMessage::Cast(CastMessage::new_broadcast(
+                CastID(0),
+                data(b),
+            )))
+            .unwrap();
+    }
+    cast.serve(u64::MAX, &mut HashMap::new(), &mut vec![]);
+    assert_eq!(cast.history(), vec![data(2), data(3), data(4)]);
+    cast.keep_history(HistoryLimit::Bytes(25));
+    assert_eq!(cast.history(), vec![data(3), data(4)]);
+}
+
+#[test]
+fn late_subscriber_gets_history_before_live_data() {
+    let mut sim = Simulator::new(2, 37);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::StartBroadcast(CastDescriptor::default()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| broadcast_origin(s, 0).is_some()
+        && broadcast_receiver(s, 1).is_some()));
+    let (cast_id, _sender) = broadcast_origin(&sim, 0).unwrap();
+    sim.request(
+        0,
+        ToGnome::KeepBroadcastHistory(cast_id, HistoryLimit::Count(3)),
+    );
+    sim.run_for(Duration::from_millis(100));
+    let data = |b: u8| CastData::new(vec![b; 10]).unwrap();
+    for b in 0..5 {
+        broadcast_origin(&sim, 0).unwrap().1.send(data(b)).unwrap();
+        sim.run_for(Duration::from_millis(50));
+    }
+    sim.run_for(Duration::from_secs(1));
+    sim.request(1, ToGnome::SubscribeBroadcastWithHistory(cast_id));
+    let receivers = |s: &Simulator| {
+        s.events(1)
+            .iter()
+            .filter(|(_t, event)| matches!(event, GnomeToApp::Broadcast(..)))
+            .count()
+    };
+    let deadline = sim.now() + Duration::from_secs(10);
+    assert!(sim.run_until(deadline, |s| receivers(s) == 2));
+    sim.run_for(Duration::from_secs(1));
+    broadcast_origin(&sim, 0).unwrap().1.send(data(5)).unwrap();
+    sim.run_for(Duration::from_secs(1));
+    let receiver = sim
+        .events(1)
+        .iter()
+        .rev()
+        .find_map(|(_t, event)| {
+            if let GnomeToApp::Broadcast(_s_id, _c_id, receiver) = event {
+                Some(receiver)
+            } else {
+                None
+            }
+        })
+        .unwrap();
+    let received: Vec<CastData> = receiver.try_iter().collect();
+    assert_eq!(received, vec![data(2), data(3), data(4), data(5)]);
+}
+
+#[test]
+fn app_channel_overflows_by_policy() {
+    let data = |b: u8| CastData::new(vec![b; 10]).unwrap();
+    let fill = |policy: OverflowPolicy| {
+        let (sender, receiver) = app_channel();
+        let mut to_app = ToApp::new(sender, policy);
+        for b in 0..APP_CHANNEL_CAPACITY {
+            assert_eq!(to_app.send(data(b as u8)), Delivery::Sent);
+        }
+        (to_app, receiver)
+    };
+    let (mut to_app, receiver) = fill(OverflowPolicy::DropNewest);
+    assert_eq!(to_app.send(data(1)), Delivery::Dropped);
+    assert_eq!(receiver.try_iter().count(), APP_CHANNEL_CAPACITY);
+
+    let (mut to_app, _receiver) = fill(OverflowPolicy::Unsubscribe);
+    assert_eq!(to_app.send(data(1)), Delivery::Unsubscribe);
+
+    let (mut to_app, receiver) = fill(OverflowPolicy::Block);
+    assert_eq!(to_app.send(data(1)), Delivery::Held);
+    assert!(to_app.is_blocked());
+    receiver.recv().unwrap();
+    assert!(to_app.flush());
+    assert!(!to_app.is_blocked());
+
+    let (mut to_app, receiver) = fill(OverflowPolicy::DropOldest);
+    for b in 0..APP_CHANNEL_CAPACITY {
+        assert_eq!(to_app.send(data(b as u8)), Delivery::Held);
+    }
+    assert_eq!(to_app.send(data(7)), Delivery::Dropped);
+    assert!(!to_app.is_blocked());
+    drop(receiver);
+    assert_eq!(to_app.send(data(1)), Delivery::Gone);
+}
+
+#[test]
+fn origins_sender_is_full_when_cast_can_not_keep_up() {
+    let mut sim = Simulator::new(2, 41);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::StartBroadcast(CastDescriptor::default()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| broadcast_origin(s, 0).is_some()
+        && broadcast_receiver(s, 1).is_some()));
+    let data = CastData::new(vec![0; 1000]).unwrap();
+    let mut sent = 0;
+    while sent < 10 * APP_CHANNEL_CAPACITY {
+        match broadcast_origin(&sim, 0).unwrap().1.try_send(data.clone()) {
+            Ok(()) => sent += 1,
+            Err(TrySendError::Full(_data)) => break,
+            Err(TrySendError::Disconnected(_data)) => panic!("Origin is gone"),
+        }
+        if sent % 64 == 0 {
+            sim.run_for(Duration::from_millis(10));
+        }
+    }
+    assert!(sent > APP_CHANNEL_CAPACITY);
+    assert!(sent < 10 * APP_CHANNEL_CAPACITY);
+    // Once the cast catches up origin can send again
+    sim.run_for(Duration::from_secs(30));
+    assert!(broadcast_origin(&sim, 0).unwrap().1.try_send(data).is_ok());
+    assert!(broadcast_receiver(&sim, 1).unwrap().try_iter().count() > 0);
+}
+
+#[test]
+fn cast_drops_are_reported_to_app_and_origin() {
+    let mut sim = Simulator::new(2, 41);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::StartBroadcast(CastDescriptor::default()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| broadcast_origin(s, 0).is_some()
+        && broadcast_receiver(s, 1).is_some()));
+    // Subscriber's app does not read, DropOldest makes room
+    let data = CastData::new(vec![0; 10]).unwrap();
+    let mut sent = 0;
+    while sent < 4 * APP_CHANNEL_CAPACITY {
+        if broadcast_origin(&sim, 0)
+            .unwrap()
+            .1
+            .try_send(data.clone())
+            .is_ok()
+        {
+            sent += 1;
+        } else {
+            sim.run_for(Duration::from_millis(100));
+        }
+    }
+    sim.run_for(Duration::from_secs(30));
+    let dropped = |g: usize| -> u32 {
+        sim.events(g)
+            .iter()
+            .map(|(_t, event)| match event {
+                GnomeToApp::CastDataDropped(true, _c_id, count) if g == 1 => *count,
+                GnomeToApp::CastDownstreamFull(true, _c_id, count) if g == 0 => *count,
+                _ => 0,
+            })
+            .sum()
+    };
+    let received = broadcast_receiver(&sim, 1).unwrap().try_iter().count();
+    assert_eq!(received, APP_CHANNEL_CAPACITY);
+    assert_eq!(dropped(1) as usize, sent - 2 * APP_CHANNEL_CAPACITY);
+    assert_eq!(dropped(0), dropped(1));
+}
+
+#[test]
+fn blocks_reach_app_that_has_fallen_behind() {
+    let mut sim = Simulator::new(2, 13);
+    sim.run_for(Duration::from_secs(1));
+    sim.stall_app(1, true);
+    let seen = sim.events(1).len();
+    // Far more events than any cast channel would hold
+    let refused = 2 * APP_CHANNEL_CAPACITY;
+    for i in 0..refused {
+        let data = SyncData::new(vec![i as u8, 1, 1]).unwrap();
+        sim.request(1, ToGnome::AddData(data));
+    }
+    sim.request(0, ToGnome::AddData(SyncData::new(vec![7, 7, 7]).unwrap()));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| !blocks(s, 0).is_empty()));
+    // Gnome refuses one request per turn
+    sim.run_for(Duration::from_secs(600));
+    assert_eq!(sim.events(1).len(), seen);
+
+    sim.stall_app(1, false);
+    sim.run_for(Duration::from_secs(1));
+    let not_met = sim
+        .events(1)
+        .iter()
+        .filter(|(_t, event)| matches!(event, GnomeToApp::PolicyNotMet(_d)))
+        .count();
+    assert_eq!(not_met, refused);
+    assert_eq!(blocks(&sim, 1), blocks(&sim, 0));
+}
+
+#[test]
+fn equivocation_is_verified_with_registered_key_only() {
+    let sim = Simulator::new(2, 43);
+    let swarm = sim.swarm();
+    let round = SwarmTime(40);
+    let evidence = Equivocation {
+        offender: sim.id(1),
+        round_start: round,
+        first: (b"first".to_vec(), sim.sign(1, round, b"first")),
+        second: (b"second".to_vec(), sim.sign(1, round, b"second")),
+    };
+    assert!(evidence.verify(&swarm));
+
+    let same = Equivocation {
+        second: evidence.first.clone(),
+        ..evidence.clone()
+    };
+    assert!(!same.verify(&swarm));
+
+    // Signed by reporter, not by accused
+    let forged = Equivocation {
+        second: (b"second".to_vec(), sim.sign(0, round, b"second")),
+        ..evidence.clone()
+    };
+    assert!(!forged.verify(&swarm));
+
+    let unknown = Equivocation {
+        offender: GnomeId(99),
+        ..evidence
+    };
+    assert!(!unknown.verify(&swarm));
+}
+
+#[test]
+fn equivocation_round_trips_and_rejects_truncated_bytes() {
+    let evidence = Equivocation {
+        offender: GnomeId(5),
+        round_start: SwarmTime(7),
+        first: (vec![1, 2, 3], vec![4; 10]),
+        second: (vec![5, 6], vec![7; 10]),
+    };
+    let mut bytes = vec![];
+    evidence.append_bytes_to(&mut bytes);
+    assert_eq!(bytes.len(), evidence.len_in_bytes());
+    for cut in 0..bytes.len() {
+        assert_eq!(Equivocation::from(&mut bytes[..cut].to_vec()), None);
+    }
+    assert_eq!(
+        Equivocation::from(&mut bytes.clone()),
+        Some(evidence.clone())
+    );
+
+    let config = Configuration::ReportEquivocation(GnomeId(2), evidence.clone());
+    assert_eq!(Configuration::from_bytes(config.bytes()), config);
+    let mut short = config.bytes();
+    short.truncate(short.len() - 1);
+    assert_eq!(
+        Configuration::from_bytes(short),
+        Configuration::ReportEquivocation(GnomeId(2), Equivocation::malformed(GnomeId(2)))
+    );
+}
+
+#[test]
+fn truncated_founder_configs_are_refused() {
+    let endorsement = Endorsement {
+        owner: GnomeId(3),
+        signature: vec![9; 12],
+    };
+    let configs = [
+        Configuration::TransferFounder(GnomeId(1), GnomeId(2)),
+        Configuration::AddOwner(GnomeId(1), GnomeId(4)),
+        Configuration::FounderSuccession(GnomeId(1), vec![endorsement.clone(), endorsement]),
+    ];
+    for config in configs {
+        let bytes = config.bytes();
+        for cut in 0..bytes.len() {
+            assert_eq!(Configuration::try_from_bytes(bytes[..cut].to_vec()), None);
+        }
+        assert_eq!(Configuration::try_from_bytes(bytes), Some(config));
+    }
+}
+
+fn test_hash(bytes: &[u8]) -> u64 {
+    use std::hash::DefaultHasher;
+    use std::hash::Hash;
+    use std::hash::Hasher;
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    hasher.finish()
+}
+
+fn block_proof(sim: &Simulator, gnome: usize, round: u32, digest: u64) -> ChainProof {
+    let bytes = digest.to_be_bytes().to_vec();
+    ChainProof {
+        round_start: SwarmTime(round),
+        signer: sim.id(gnome),
+        digest,
+        is_block: true,
+        signed: (bytes.clone(), sim.sign(gnome, SwarmTime(round), &bytes)),
+    }
+}
+
+fn config_proof(sim: &Simulator, gnome: usize, round: u32, config: Configuration) -> ChainProof {
+    let bytes = config.bytes();
+    ChainProof {
+        round_start: SwarmTime(round),
+        signer: sim.id(gnome),
+        digest: test_hash(&bytes),
+        is_block: false,
+        signed: (bytes.clone(), sim.sign(gnome, SwarmTime(round), &bytes)),
+    }
+}
+
+fn chain(proofs: &[ChainProof]) -> ChainHistory {
+    let mut chain = ChainHistory::new(true);
+    for proof in proofs {
+        chain.append(proof.clone(), test_hash);
+    }
+    chain
+}
+
+#[test]
+fn chain_link_round_trips_and_rejects_truncated_bytes() {
+    let link = ChainLink {
+        round_start: SwarmTime(9),
+        hash: 123,
+        signer: GnomeId(4),
+    };
+    let mut bytes = vec![];
+    link.append_bytes_to(&mut bytes);
+    assert_eq!(bytes.len(), link.len_in_bytes());
+    for cut in 0..bytes.len() {
+        assert_eq!(ChainLink::from(&mut bytes[..cut].to_vec()), None);
+    }
+    bytes.push(1);
+    assert_eq!(ChainLink::from(&mut bytes), Some(link));
+    assert_eq!(bytes, vec![1]);
+}
+
+#[test]
+fn chain_proof_round_trips_and_rejects_truncated_bytes() {
+    let sim = Simulator::new(2, 47);
+    let proof = block_proof(&sim, 1, 12, 34);
+    let mut bytes = vec![];
+    proof.append_bytes_to(&mut bytes);
+    assert_eq!(bytes.len(), proof.len_in_bytes());
+    for cut in 0..bytes.len() {
+        assert_eq!(ChainProof::from(&mut bytes[..cut].to_vec()), None);
+    }
+    assert_eq!(ChainProof::from(&mut bytes), Some(proof));
+}
+
+#[test]
+fn chain_hash_covers_whole_history() {
+    let sim = Simulator::new(3, 53);
+    let proofs = [
+        block_proof(&sim, 0, 2, 10),
+        block_proof(&sim, 1, 4, 20),
+        block_proof(&sim, 0, 6, 30),
+    ];
+    let first = chain(&proofs);
+    let second = chain(&proofs);
+    assert_eq!(first.head(), second.head());
+    assert_eq!(first.summary(), second.summary());
+
+    // Same last block, different earlier one
+    let mut other = proofs.clone();
+    other[0] = block_proof(&sim, 0, 2, 11);
+    assert_ne!(first.head(), chain(&other).head());
+    // Same blocks, different round
+    let mut later = proofs.clone();
+    later[1] = block_proof(&sim, 1, 5, 20);
+    assert_ne!(first.head(), chain(&later).head());
+    assert_eq!(first.proofs_since(SwarmTime(4)), proofs[1..].to_vec());
+
+    let mut empty = ChainHistory::new(true);
+    assert_eq!(empty.head(), 0);
+    assert!(empty.summary().is_empty());
+    let mut shared = false;
+    for i in 0..CHAIN_INFO_INTERVAL {
+        shared = empty.append(block_proof(&sim, 0, i as u32, i), test_hash);
+    }
+    assert!(shared);
+}
+
+// We are on a branch signed by gnome 2, neighbor 3 is on a founder signed one.
+// Returns our chain, already merging with neighbor, and neighbor's chain.
+fn lost_fork(sim: &Simulator, swarm: &Swarm) -> (ChainHistory, ChainHistory) {
+    let common = [block_proof(sim, 0, 2, 10), block_proof(sim, 1, 4, 20)];
+    let mut ours = chain(&common);
+    let mut theirs = chain(&common);
+    ours.append(block_proof(sim, 2, 6, 30), test_hash);
+    ours.append(block_proof(sim, 2, 8, 40), test_hash);
+    theirs.append(block_proof(sim, 0, 6, 31), test_hash);
+    theirs.append(
+        config_proof(
+            sim,
+            0,
+            7,
+            Configuration::SetRunningPolicy(sim.id(0), Policy::Data, Requirement::None),
+        ),
+        test_hash,
+    );
+    let their_summary = theirs.summary();
+    let neighbor = sim.id(3);
+
+    let (fork_point, gnomes) = ours.compare(neighbor, &their_summary, test_hash).unwrap();
+    assert_eq!(fork_point, SwarmTime(6));
+    assert_eq!(gnomes, vec![neighbor]);
+    // Same neighbor on same fork is reported once
+    assert_eq!(ours.compare(neighbor, &their_summary, test_hash), None);
+
+    // Founder signed branch wins even though it has less signers
+    assert!(theirs.dispute(sim.id(2), fork_point, SwarmTime(10)));
+    let proofs = ours.proofs_since(fork_point);
+    let total = proofs.len() as u8;
+    for (i, proof) in proofs.into_iter().enumerate() {
+        theirs.add_proof(sim.id(2), i as u8 + 1, total, proof);
+    }
+    assert_eq!(
+        theirs.resolve(sim.id(2), swarm, test_hash, SwarmTime(10)),
+        Resolution::Kept
+    );
+    assert_eq!(theirs.summary(), their_summary);
+    assert_eq!(theirs.merging_with(), None);
+
+    assert!(ours.dispute(neighbor, fork_point, SwarmTime(10)));
+    assert!(!ours.dispute(neighbor, fork_point, SwarmTime(10)));
+    let proofs = theirs.proofs_since(fork_point);
+    assert!(!ours.add_proof(neighbor, 1, 2, proofs[0].clone()));
+    assert!(ours.add_proof(neighbor, 2, 2, proofs[1].clone()));
+    assert_eq!(
+        ours.resolve(neighbor, swarm, test_hash, SwarmTime(10)),
+        Resolution::RolledBack(vec![BlockID(30), BlockID(40)])
+    );
+    assert_eq!(ours.merging_with(), Some(neighbor));
+    (ours, theirs)
+}
+
+#[test]
+fn fork_is_resolved_with_verified_branch() {
+    let sim = Simulator::new(4, 59);
+    let swarm = sim.swarm();
+    let (ours, theirs) = lost_fork(&sim, &swarm);
+    // Winner's branch replaces ours right away
+    assert_eq!(ours.head(), theirs.head());
+    assert_eq!(ours.summary(), theirs.summary());
+    assert_eq!(
+        ours.proofs_since(SwarmTime(6)),
+        theirs.proofs_since(SwarmTime(6))
+    );
+}
+
+#[test]
+fn forged_chain_proofs_do_not_roll_back() {
+    let sim = Simulator::new(4, 61);
+    let swarm = sim.swarm();
+    let mut ours = chain(&[block_proof(&sim, 0, 2, 10), block_proof(&sim, 2, 4, 20)]);
+    let summary = ours.summary();
+    let liar = sim.id(3);
+
+    // Proofs nobody has asked for are ignored
+    assert!(!ours.add_proof(liar, 1, 1, block_proof(&sim, 0, 4, 21)));
+
+    // Claims founder has signed, but it was signed by liar
+    let mut forged = block_proof(&sim, 3, 4, 21);
+    forged.signer = sim.id(0);
+    assert!(ours.dispute(liar, SwarmTime(4), SwarmTime(5)));
+    assert!(ours.add_proof(liar, 1, 1, forged));
+    assert_eq!(
+        ours.resolve(liar, &swarm, test_hash, SwarmTime(5)),
+        Resolution::Kept
+    );
+
+    // Config bytes that do not match link's digest
+    let mut swapped = config_proof(&sim, 0, 4, Configuration::ChangeDiameter(sim.id(0), 3));
+    swapped.digest += 1;
+    assert!(ours.dispute(liar, SwarmTime(4), SwarmTime(5)));
+    assert!(ours.add_proof(liar, 1, 1, swapped));
+    assert_eq!(
+        ours.resolve(liar, &swarm, test_hash, SwarmTime(5)),
+        Resolution::Kept
+    );
+
+    // Signer not in our key registry
+    let mut unknown = block_proof(&sim, 0, 4, 21);
+    unknown.signer = GnomeId(99);
+    assert!(ours.dispute(liar, SwarmTime(4), SwarmTime(5)));
+    assert!(ours.add_proof(liar, 1, 1, unknown));
+    assert_eq!(
+        ours.resolve(liar, &swarm, test_hash, SwarmTime(5)),
+        Resolution::Kept
+    );
+
+    // Validly signed, but too short for TransferFounder
+    let bytes = vec![239, 0, 0, 0, 0, 0, 0, 0, 1];
+    let truncated = ChainProof {
+        round_start: SwarmTime(4),
+        signer: sim.id(0),
+        digest: test_hash(&bytes),
+        is_block: false,
+        signed: (bytes.clone(), sim.sign(0, SwarmTime(4), &bytes)),
+    };
+    assert!(ours.dispute(liar, SwarmTime(4), SwarmTime(5)));
+    assert!(ours.add_proof(liar, 1, 1, truncated));
+    assert_eq!(
+        ours.resolve(liar, &swarm, test_hash, SwarmTime(5)),
+        Resolution::Misbehaved
+    );
+
+    assert_eq!(ours.summary(), summary);
+    assert_eq!(ours.merging_with(), None);
+}
+
+#[test]
+fn merge_takes_only_entries_changed_since_fork() {
+    let sim = Simulator::new(4, 67);
+    let mut swarm = sim.swarm();
+    swarm.insert_capability(Capabilities::Owner, vec![sim.id(1)]);
+    swarm.insert_capability(Capabilities::Admin, vec![sim.id(1)]);
+    swarm.insert_capability(Capabilities::Moderator, vec![sim.id(1)]);
+    swarm.policy_reg.insert(
+        Policy::StartBroadcast,
+        Requirement::Has(Capabilities::Owner),
+    );
+    swarm
+        .policy_reg
+        .insert(Policy::EndBroadcast, Requirement::Has(Capabilities::Owner));
+    let ours = [
+        Configuration::SetRunningCapability(sim.id(1), Capabilities::Moderator, vec![]),
+        Configuration::SetRunningPolicy(sim.id(1), Policy::EndBroadcast, Requirement::None),
+    ];
+    let theirs = [
+        Configuration::SetRunningCapability(sim.id(0), Capabilities::Admin, vec![]),
+        Configuration::SetRunningPolicy(sim.id(0), Policy::Data, Requirement::None),
+    ];
+    let mut merge = Merge::new(sim.id(3), SwarmTime(10), &ours, &theirs);
+    merge.sync_capabilities(vec![
+        (Capabilities::Owner, vec![sim.id(3)]),
+        (Capabilities::Admin, vec![sim.id(2)]),
+    ]);
+    merge.sync_capabilities(vec![(Capabilities::Admin, vec![sim.id(3)])]);
+    merge.sync_policies(vec![
+        (Policy::StartBroadcast, Requirement::None),
+        (Policy::Data, Requirement::Has(Capabilities::Admin)),
+    ]);
+    // Nothing changes before merge is finished
+    let admins = swarm.capability_reg.get(&Capabilities::Admin).unwrap();
+    assert!(admins.contains(&sim.id(1)));
+    assert!(!admins.contains(&sim.id(2)));
+    assert_eq!(swarm.policy_reg.get(&Policy::Data), None);
+    assert_eq!(merge.finish(&mut swarm), None);
+
+    let holders = |swarm: &Swarm, cap: Capabilities| {
+        swarm
+            .capability_reg
+            .get(&cap)
+            .map(|tree| {
+                (0..4)
+                    .map(|i| sim.id(i))
+                    .filter(|id| tree.contains(id))
+                    .collect::<Vec<GnomeId>>()
+            })
+            .unwrap_or_default()
+    };
+    // Not changed since fork, winner can not touch it
+    assert_eq!(holders(&swarm, Capabilities::Owner), vec![sim.id(1)]);
+    assert_eq!(
+        swarm.policy_reg.get(&Policy::StartBroadcast),
+        Some(&Requirement::Has(Capabilities::Owner))
+    );
+    // Winner's value replaces ours
+    assert_eq!(
+        holders(&swarm, Capabilities::Admin),
+        vec![sim.id(2), sim.id(3)]
+    );
+    assert_eq!(
+        swarm.policy_reg.get(&Policy::Data),
+        Some(&Requirement::Has(Capabilities::Admin))
+    );
+    // Changed only on our rolled back branch, winner does not have them
+    assert_eq!(holders(&swarm, Capabilities::Moderator), vec![]);
+    assert_eq!(swarm.policy_reg.get(&Policy::EndBroadcast), None);
+}
+
+#[test]
+fn merge_ends_when_winner_drops_or_times_out() {
+    let sim = Simulator::new(4, 71);
+    let swarm = sim.swarm();
+    let winner = sim.id(3);
+
+    let (mut ours, _theirs) = lost_fork(&sim, &swarm);
+    ours.neighbor_dropped(sim.id(1));
+    assert_eq!(ours.merging_with(), Some(winner));
+    assert!(ours.merge_mut(sim.id(1)).is_none());
+    assert!(ours.merge_mut(winner).is_some());
+    ours.neighbor_dropped(winner);
+    assert_eq!(ours.merging_with(), None);
+
+    let (mut ours, _theirs) = lost_fork(&sim, &swarm);
+    ours.expire(SwarmTime(10) + MERGE_TIMEOUT);
+    assert_eq!(ours.merging_with(), Some(winner));
+    ours.expire(SwarmTime(11) + MERGE_TIMEOUT);
+    assert_eq!(ours.merging_with(), None);
+
+    // Neighbor that does not prove his branch is not waited for
+    assert!(ours.dispute(winner, SwarmTime(6), SwarmTime(100)));
+    ours.expire(SwarmTime(101) + MERGE_TIMEOUT);
+    assert!(!ours.add_proof(winner, 1, 1, block_proof(&sim, 0, 6, 31)));
+    assert!(ours.dispute(sim.id(1), SwarmTime(6), SwarmTime(100)));
+    ours.neighbor_dropped(sim.id(1));
+    assert!(!ours.add_proof(sim.id(1), 1, 1, block_proof(&sim, 0, 6, 31)));
+}
+
+fn genesis(sim: &Simulator, gnome: usize) -> Genesis {
+    let bytes = SwarmName {
+        founder: sim.id(gnome),
+        name: "/sim".to_string(),
+    }
+    .as_bytes();
+    Genesis {
+        founder: sim.id(gnome),
+        pub_key: sim.swarm().key_reg.get(sim.id(gnome)).unwrap(),
+        signature: sim.sign(gnome, SwarmTime(0), &bytes),
+    }
+}
+
+fn sync_response(sim: &Simulator, founder: usize) -> SwarmSyncResponse {
+    SwarmSyncResponse {
+        chill_phase: 0,
+        founder: sim.id(founder),
+        swarm_time: SwarmTime(100),
+        round_start: SwarmTime(98),
+        swarm_type: SwarmType::Catalog,
+        swarm_diameter: SwarmTime(7),
+        key_reg_size: 0,
+        capability_size: 0,
+        policy_size: 0,
+        broadcast_size: 0,
+        multicast_size: 0,
+        more_key_reg_messages: false,
+        key_reg_pairs: vec![],
+        genesis: Some(genesis(sim, founder)),
+    }
+}
+
+// Joining a swarm without knowing who founded it
+fn unknown_founder_swarm(sim: &Simulator) -> Swarm {
+    let mut swarm = sim.swarm();
+    swarm.name.founder = GnomeId::any();
+    swarm
+}
+
+#[test]
+fn presync_pins_founder_agreed_by_majority() {
+    let sim = Simulator::new(5, 73);
+    let mut swarm = unknown_founder_swarm(&sim);
+    let mut disputed = HashSet::new();
+    // First responder has signed a Genesis of his own
+    let responses = vec![
+        (sim.id(1), true, sync_response(&sim, 1)),
+        (sim.id(2), false, sync_response(&sim, 0)),
+        (sim.id(3), false, sync_response(&sim, 0)),
+    ];
+    let (source, full, response) =
+        choose_sync_response(&mut swarm, &mut disputed, responses).unwrap();
+    assert_eq!(source, sim.id(2));
+    assert!(!full);
+    assert_eq!(response.founder, sim.id(0));
+    assert_eq!(swarm.genesis(), Some(genesis(&sim, 0)));
+    assert_eq!(swarm.name.founder, sim.id(0));
+    assert_eq!(disputed, HashSet::from([sim.id(1)]));
+}
+
+#[test]
+fn presync_does_not_pin_unconfirmed_founder() {
+    let sim = Simulator::new(5, 79);
+
+    // A single neighbor can not be cross-checked
+    let mut swarm = unknown_founder_swarm(&sim);
+    let mut disputed = HashSet::new();
+    let responses = vec![(sim.id(1), true, sync_response(&sim, 1))];
+    let (source, _full, _response) =
+        choose_sync_response(&mut swarm, &mut disputed, responses).unwrap();
+    assert_eq!(source, sim.id(1));
+    assert!(!swarm.founder_verified());
+    assert!(disputed.is_empty());
+
+    // Same neighbor responding twice still has a single vote
+    let mut swarm = unknown_founder_swarm(&sim);
+    let responses = vec![
+        (sim.id(1), true, sync_response(&sim, 1)),
+        (sim.id(1), false, sync_response(&sim, 1)),
+        (sim.id(2), false, sync_response(&sim, 2)),
+    ];
+    let (source, _full, _response) =
+        choose_sync_response(&mut swarm, &mut disputed, responses).unwrap();
+    assert_eq!(source, sim.id(1));
+    assert!(!swarm.founder_verified());
+    assert_eq!(disputed, HashSet::from([sim.id(2)]));
+}
+
+#[test]
+fn presync_pins_only_genesis_of_known_founder() {
+    let sim = Simulator::new(5, 83);
+    let mut swarm = sim.swarm();
+    let mut disputed = HashSet::new();
+    let mut forged = sync_response(&sim, 0);
+    forged.genesis = Some(Genesis {
+        founder: sim.id(0),
+        ..genesis(&sim, 1)
+    });
+    let responses = vec![
+        (sim.id(1), true, sync_response(&sim, 1)),
+        (sim.id(2), false, forged),
+        (sim.id(3), false, sync_response(&sim, 0)),
+    ];
+    let (source, _full, _response) =
+        choose_sync_response(&mut swarm, &mut disputed, responses).unwrap();
+    assert_eq!(source, sim.id(2));
+    assert_eq!(swarm.genesis(), Some(genesis(&sim, 0)));
+    assert_eq!(disputed, HashSet::from([sim.id(1)]));
+
+    // Nobody agrees with founder from SwarmName
+    let mut swarm = sim.swarm();
+    let responses = vec![(sim.id(1), true, sync_response(&sim, 1))];
+    assert!(choose_sync_response(&mut swarm, &mut disputed, responses).is_none());
+    assert!(!swarm.founder_verified());
+}
+
+#[test]
+fn genesis_round_trips_and_rejects_truncated_bytes() {
+    let sim = Simulator::new(2, 89);
+    let genesis = genesis(&sim, 0);
+    let mut bytes = vec![];
+    genesis.append_bytes_to(&mut bytes);
+    assert_eq!(bytes.len(), genesis.len_in_bytes());
+    for cut in 0..bytes.len() {
+        assert_eq!(Genesis::from(&mut bytes[..cut].to_vec()), None);
+    }
+    assert_eq!(Genesis::from(&mut bytes), Some(genesis));
+}
+
+fn founder_changed(sim: &Simulator, size: usize, old: usize, new: usize) -> bool {
+    (0..size).all(|i| {
+        sim.events(i)
+            .iter()
+            .any(|(_t, event)| matches!(event, GnomeToApp::FounderChanged(o, n) if *o == sim.id(old) && *n == sim.id(new)))
+    })
+}
+
+#[test]
+fn founder_is_transferred_by_consensus() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 97);
+    sim.run_for(Duration::from_secs(1));
+    // Only founder can transfer
+    sim.request(1, ToGnome::TransferFounder(sim.id(2)));
+    sim.request(0, ToGnome::TransferFounder(sim.id(1)));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| founder_changed(s, size, 0, 1)));
+    assert!(!founder_changed(&sim, size, 1, 2));
+
+    // New founder fulfills default Data policy
+    sim.request(
+        1,
+        ToGnome::AddData(SyncData::new(vec![16, 16, 16]).unwrap()),
+    );
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| everyone_has_blocks(s, size, 1)));
+}
+
+#[test]
+fn owners_quorum_elects_new_founder() {
+    let size = 3;
+    let mut sim = Simulator::new(size, 101);
+    sim.run_for(Duration::from_secs(1));
+    sim.request(0, ToGnome::AddOwner(sim.id(1)));
+    // Gnome 1 can endorse only once he is an Owner
+    let mut endorsement = None;
+    for _i in 0..60 {
+        sim.request(1, ToGnome::EndorseSuccession(sim.id(2)));
+        sim.run_for(Duration::from_secs(1));
+        endorsement = sim.events(1).iter().find_map(|(_t, event)| match event {
+            GnomeToApp::SuccessionEndorsement(candidate, endorsement)
+                if *candidate == sim.id(2) =>
+            {
+                Some(endorsement.clone())
+            }
+            _ => None,
+        });
+        if endorsement.is_some() {
+            break;
+        }
+    }
+    let endorsement = endorsement.unwrap();
+    assert_eq!(endorsement.owner, sim.id(1));
+
+    // Owner's endorsement signed by someone else
+    let bytes = Endorsement::signed_bytes(&sim.swarm(), sim.id(2));
+    let forged = Endorsement {
+        owner: sim.id(1),
+        signature: sim.sign(2, SwarmTime(0), &bytes),
+    };
+    sim.request(2, ToGnome::ClaimFounder(vec![forged]));
+    // Not an Owner
+    let own = Endorsement {
+        owner: sim.id(2),
+        signature: sim.sign(2, SwarmTime(0), &bytes),
+    };
+    sim.request(2, ToGnome::ClaimFounder(vec![own]));
+    sim.run_for(Duration::from_secs(10));
+    assert!((0..size).all(|i| !sim
+        .events(i)
+        .iter()
+        .any(|(_t, event)| matches!(event, GnomeToApp::FounderChanged(_o, _n)))));
+
+    sim.request(2, ToGnome::ClaimFounder(vec![endorsement]));
+    let deadline = sim.now() + Duration::from_secs(60);
+    assert!(sim.run_until(deadline, |s| founder_changed(s, size, 0, 2)));
+}
+
+#[test]
+fn synced_founder_does_not_override_verified_one() {
+    let sim = Simulator::new(4, 103);
+    let mut swarm = sim.swarm();
+    assert!(swarm.set_genesis(genesis(&sim, 0)));
+    swarm.insert_capability(Capabilities::Owner, vec![sim.id(1)]);
+    swarm.insert_capability(Capabilities::Founder, vec![sim.id(3)]);
+    assert_eq!(swarm.founder(), sim.id(0));
+    swarm.clear_capabilities();
+    assert_eq!(swarm.founder(), sim.id(0));
+    assert!(!swarm.capability_reg.contains_key(&Capabilities::Owner));
+
+    // Before we have lost a fork our branch gave founder to gnome 1,
+    // winner's branch gave it to gnome 2
+    swarm.transfer_founder(sim.id(1));
+    let ours = [Configuration::TransferFounder(sim.id(0), sim.id(1))];
+    let theirs = [Configuration::TransferFounder(sim.id(0), sim.id(2))];
+    let mut merge = Merge::new(sim.id(2), SwarmTime(10), &ours, &theirs);
+    merge.sync_capabilities(vec![(Capabilities::Founder, vec![sim.id(3)])]);
+    assert_eq!(swarm.founder(), sim.id(1));
+    assert_eq!(merge.finish(&mut swarm), Some((sim.id(1), sim.id(2))));
+    assert_eq!(swarm.founder(), sim.id(2));
+}
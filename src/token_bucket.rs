@@ -0,0 +1,75 @@
+use crate::GnomeId;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::BuildHasherDefault;
+
+// Every neighbor can hold at least this many tokens,
+// so that he can send at least one message for sure
+pub const MIN_NEIGHBOR_TOKENS: u64 = 1500;
+
+// Tokens created by Gnome are split among its neighbors,
+// so that data served to one of them (responses, casts)
+// can not use up bandwith meant for the others.
+// Tokens given to buckets are taken out of Gnome's available tokens,
+// consensus messages are paid from what is left there.
+pub struct TokenBuckets {
+    buckets: HashMap<GnomeId, u64, BuildHasherDefault<DefaultHasher>>,
+}
+
+impl Default for TokenBuckets {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TokenBuckets {
+    pub fn new() -> Self {
+        TokenBuckets {
+            buckets: HashMap::default(),
+        }
+    }
+
+    // Every neighbor gets an equal share of new tokens and can store
+    // up to a second worth of his share, but no less than MIN_NEIGHBOR_TOKENS.
+    // Tokens that do not fit into a full bucket are given to those that
+    // still have room, what nobody can take is returned.
+    // Buckets of neighbors that are gone are removed, new ones start empty.
+    pub fn refill(&mut self, neighbors: &[GnomeId], mut tokens: u64, bandwith: u64) -> u64 {
+        self.buckets.retain(|id, _b| neighbors.contains(id));
+        if neighbors.is_empty() {
+            return tokens;
+        }
+        let capacity = u64::max(MIN_NEIGHBOR_TOKENS, bandwith / neighbors.len() as u64);
+        for id in neighbors {
+            self.buckets.entry(*id).or_insert(0);
+        }
+        loop {
+            let hungry: Vec<GnomeId> = neighbors
+                .iter()
+                .filter(|id| self.buckets[id] < capacity)
+                .copied()
+                .collect();
+            if hungry.is_empty() || tokens == 0 {
+                return tokens;
+            }
+            let share = u64::max(1, tokens / hungry.len() as u64);
+            for id in hungry {
+                let bucket = self.buckets.get_mut(&id).unwrap();
+                let given = share.min(capacity - *bucket).min(tokens);
+                *bucket += given;
+                tokens -= given;
+            }
+        }
+    }
+
+    pub fn available(&self, id: GnomeId) -> u64 {
+        self.buckets.get(&id).copied().unwrap_or(0)
+    }
+
+    // For data already sent, bucket can not go below zero
+    pub fn charge(&mut self, id: GnomeId, tokens: u64) {
+        if let Some(bucket) = self.buckets.get_mut(&id) {
+            *bucket = bucket.saturating_sub(tokens);
+        }
+    }
+}
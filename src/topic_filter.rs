@@ -0,0 +1,61 @@
+use crate::CastData;
+
+// Above this many prefixes a filter becomes All
+pub const MAX_TOPIC_PREFIXES: usize = 32;
+
+// Which CastData of a broadcast or multicast a subscriber wants,
+// topic is a prefix of data.
+// Filters of subscribers are merged and sent upstream,
+// so that a branch of cast tree only gets what someone down there needs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TopicFilter {
+    #[default]
+    All,
+    Prefixes(Vec<Vec<u8>>),
+}
+
+impl TopicFilter {
+    pub fn new(mut prefixes: Vec<Vec<u8>>) -> Self {
+        if prefixes.len() > MAX_TOPIC_PREFIXES || prefixes.iter().any(|p| p.is_empty()) {
+            return TopicFilter::All;
+        }
+        prefixes.sort();
+        prefixes.dedup();
+        TopicFilter::Prefixes(prefixes)
+    }
+
+    pub fn nothing() -> Self {
+        TopicFilter::Prefixes(vec![])
+    }
+
+    pub fn matches(&self, data: &CastData) -> bool {
+        match self {
+            TopicFilter::All => true,
+            TopicFilter::Prefixes(prefixes) => {
+                let bytes = data.ref_bytes();
+                prefixes.iter().any(|prefix| bytes.starts_with(prefix))
+            }
+        }
+    }
+
+    pub fn union(&self, other: &TopicFilter) -> TopicFilter {
+        match (self, other) {
+            (TopicFilter::Prefixes(ours), TopicFilter::Prefixes(theirs)) => {
+                TopicFilter::new(ours.iter().chain(theirs).cloned().collect())
+            }
+            _ => TopicFilter::All,
+        }
+    }
+
+    pub fn len_in_bytes(&self) -> usize {
+        match self {
+            TopicFilter::All => 1,
+            TopicFilter::Prefixes(prefixes) => {
+                2 + prefixes
+                    .iter()
+                    .map(|prefix| 1 + prefix.len())
+                    .sum::<usize>()
+            }
+        }
+    }
+}
@@ -0,0 +1,535 @@
+use crate::message::Header;
+use crate::message::Payload;
+use crate::multicast::CastContent;
+use crate::multicast::CastMessage;
+use crate::multicast::CastType;
+use crate::next_state::NextState;
+use crate::BlockID;
+use crate::CastID;
+use crate::Clock;
+use crate::Configuration;
+use crate::GnomeId;
+use crate::Message;
+use crate::Neighbor;
+use crate::Neighborhood;
+use crate::Signature;
+use crate::Swarm;
+use crate::SwarmTime;
+use crate::SyncData;
+use crate::WrappedMessage;
+use crate::DEFAULT_SWARM_DIAMETER;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Everything that is needed to replay what a Gnome did with
+// messages from it's neighbors.
+// Events coming from a Neighbor are recorded with that Neighbor's id,
+// TurnEnded and Accepted are recorded with Gnome's own id.
+#[derive(Clone, Debug)]
+pub enum TraceEvent {
+    Received(Message),
+    Sent(Message),
+    // Requests and Responses have no byte representation,
+    // so we only store their Debug text
+    CastReceived(CastType, CastID, Vec<u8>),
+    CastSent(CastType, CastID, Vec<u8>),
+    RoundStarted(SwarmTime),
+    // Gnome has updated it's next state with given Neighbor
+    Updated,
+    TurnEnded(bool, Header, Payload),
+    Accepted(Message),
+}
+
+impl TraceEvent {
+    pub fn from_cast(inbound: bool, message: &CastMessage) -> Self {
+        let content = match message.content {
+            CastContent::Data(ref data) => data.clone().bytes(),
+            CastContent::Sequenced(_seq, ref data) => data.clone().bytes(),
+            CastContent::History(ref data) => data.clone().bytes(),
+            CastContent::Request(ref request) => format!("{:?}", request).into_bytes(),
+            CastContent::Response(ref response) => format!("{:?}", response).into_bytes(),
+        };
+        if inbound {
+            TraceEvent::CastReceived(message.c_type, message.id, content)
+        } else {
+            TraceEvent::CastSent(message.c_type, message.id, content)
+        }
+    }
+
+    fn append_bytes_to(&self, bytes: &mut Vec<u8>) {
+        match self {
+            Self::Received(message) => {
+                bytes.push(0);
+                append_message(message, bytes);
+            }
+            Self::Sent(message) => {
+                bytes.push(1);
+                append_message(message, bytes);
+            }
+            Self::CastReceived(c_type, c_id, content) => {
+                bytes.push(2);
+                append_cast(*c_type, *c_id, content, bytes);
+            }
+            Self::CastSent(c_type, c_id, content) => {
+                bytes.push(3);
+                append_cast(*c_type, *c_id, content, bytes);
+            }
+            Self::RoundStarted(swarm_time) => {
+                bytes.push(4);
+                bytes.extend(swarm_time.0.to_be_bytes());
+            }
+            Self::Updated => bytes.push(5),
+            Self::TurnEnded(new_round, header, payload) => {
+                bytes.push(6);
+                bytes.push(*new_round as u8);
+                append_header(header, bytes);
+                append_payload(payload, bytes);
+            }
+            Self::Accepted(message) => {
+                bytes.push(7);
+                append_message(message, bytes);
+            }
+        }
+    }
+
+    fn from(reader: &mut Reader) -> Option<Self> {
+        let event = match reader.u8()? {
+            0 => Self::Received(read_message(reader)?),
+            1 => Self::Sent(read_message(reader)?),
+            2 => {
+                let (c_type, c_id, content) = read_cast(reader)?;
+                Self::CastReceived(c_type, c_id, content)
+            }
+            3 => {
+                let (c_type, c_id, content) = read_cast(reader)?;
+                Self::CastSent(c_type, c_id, content)
+            }
+            4 => Self::RoundStarted(SwarmTime(reader.u32()?)),
+            5 => Self::Updated,
+            6 => {
+                let new_round = reader.u8()? > 0;
+                let header = read_header(reader)?;
+                let payload = read_payload(reader)?;
+                Self::TurnEnded(new_round, header, payload)
+            }
+            7 => Self::Accepted(read_message(reader)?),
+            other => {
+                eprintln!("Unknown trace event: {}", other);
+                return None;
+            }
+        };
+        Some(event)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct TraceRecord {
+    pub time: Duration,
+    pub gnome_id: GnomeId,
+    pub event: TraceEvent,
+}
+
+// Writes TraceEvents to a file, every record is stamped with
+// current time of given clock.
+// Record is a u32 length followed by time in nsec, GnomeId and event.
+#[derive(Clone)]
+pub struct Tracer {
+    writer: Arc<Mutex<BufWriter<File>>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Tracer {
+    pub fn create(path: &Path, clock: Arc<dyn Clock>) -> std::io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Tracer {
+            writer: Arc::new(Mutex::new(BufWriter::new(file))),
+            clock,
+        })
+    }
+
+    pub fn record(&self, gnome_id: GnomeId, event: &TraceEvent) {
+        let mut bytes = vec![0, 0, 0, 0];
+        bytes.extend((self.clock.now().as_nanos() as u64).to_be_bytes());
+        bytes.extend(gnome_id.bytes());
+        event.append_bytes_to(&mut bytes);
+        let len = (bytes.len() - 4) as u32;
+        bytes[0..4].copy_from_slice(&len.to_be_bytes());
+        if let Err(err) = self.writer.lock().unwrap().write_all(&bytes) {
+            eprintln!("Failed to write trace: {}", err);
+        }
+    }
+
+    pub fn flush(&self) {
+        if let Err(err) = self.writer.lock().unwrap().flush() {
+            eprintln!("Failed to flush trace: {}", err);
+        }
+    }
+}
+
+impl fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Tracer")
+    }
+}
+
+// Reads all complete records from a trace file.
+// A record cut in half (e.g. gnome was killed while writing) ends reading.
+pub fn read_trace(path: &Path) -> std::io::Result<Vec<TraceRecord>> {
+    let bytes = std::fs::read(path)?;
+    let mut reader = Reader {
+        bytes: &bytes,
+        pos: 0,
+    };
+    let mut records = vec![];
+    while let Some(len) = reader.u32() {
+        let body = if let Some(body) = reader.take(len as usize) {
+            body
+        } else {
+            eprintln!("Trace ends with an incomplete record");
+            break;
+        };
+        let mut body_reader = Reader {
+            bytes: body,
+            pos: 0,
+        };
+        if let Some(record) = read_record(&mut body_reader) {
+            records.push(record);
+        } else {
+            eprintln!("Skipping malformed trace record");
+        }
+    }
+    Ok(records)
+}
+
+fn read_record(reader: &mut Reader) -> Option<TraceRecord> {
+    let time = Duration::from_nanos(reader.u64()?);
+    let gnome_id = GnomeId(reader.u64()?);
+    let event = TraceEvent::from(reader)?;
+    Some(TraceRecord {
+        time,
+        gnome_id,
+        event,
+    })
+}
+
+// Result of a single turn as seen by a replayed Gnome
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayedTurn {
+    pub time: Duration,
+    pub new_round: bool,
+    // Best header presented by our neighbors and our own
+    pub winner: Header,
+    // Neighbor that made winner replace our header,
+    // None if we kept our own
+    pub won_by: Option<GnomeId>,
+    // Header Gnome has chosen for next turn,
+    // it differs from winner when a round ends
+    // or when Gnome puts in it's own proposal
+    pub chosen: Header,
+    // Headers every updating Neighbor had during this turn
+    pub seen: Vec<(GnomeId, Header)>,
+}
+
+impl fmt::Display for ReplayedTurn {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} ", self.time)?;
+        if self.new_round {
+            write!(f, "[new round] ")?;
+        }
+        write!(f, "{} ", self.winner)?;
+        if let Some(id) = self.won_by {
+            write!(f, "from {} ", id)?;
+        } else {
+            write!(f, "kept ")?;
+        }
+        write!(f, "-> {} seen:", self.chosen)?;
+        for (id, header) in &self.seen {
+            write!(f, " {}:{}", id, header)?;
+        }
+        Ok(())
+    }
+}
+
+struct ReplayNeighbor {
+    neighbor: Neighbor,
+    inbox: Sender<Message>,
+    _cast_inbox: Sender<CastMessage>,
+    _outbox: Receiver<WrappedMessage>,
+}
+
+// Feeds recorded Messages through Neighbor::try_recv and NextState::update
+// the same way a Gnome did.
+// Given Swarm should have the same keys and policies as traced Gnome's swarm.
+// Records up to first TurnEnded only warm up the state, since we do not know
+// what our Gnome had in mind before trace started.
+pub fn replay(records: &[TraceRecord], swarm: &mut Swarm) -> Vec<ReplayedTurn> {
+    let mut neighbors: HashMap<GnomeId, ReplayNeighbor> = HashMap::new();
+    let mut next_state = NextState::new();
+    let mut turns = vec![];
+    let mut warmed_up = false;
+    let mut won_by = None;
+    let mut seen = vec![];
+    for record in records {
+        match &record.event {
+            TraceEvent::Received(message) => {
+                let replayed = replay_neighbor(&mut neighbors, record.gnome_id);
+                let _ = replayed.inbox.send(message.clone());
+            }
+            TraceEvent::Sent(message) => {
+                let replayed = replay_neighbor(&mut neighbors, record.gnome_id);
+                replayed.neighbor.send_out(message.clone());
+            }
+            TraceEvent::RoundStarted(swarm_time) => {
+                let replayed = replay_neighbor(&mut neighbors, record.gnome_id);
+                replayed.neighbor.start_new_round(*swarm_time);
+            }
+            TraceEvent::Updated => {
+                let replayed = replay_neighbor(&mut neighbors, record.gnome_id);
+                let neighbor = &mut replayed.neighbor;
+                // Gnome could take those messages in more than one go
+                while neighbor
+                    .try_recv(next_state.last_accepted_message.clone(), swarm)
+                    .0
+                {}
+                let _ = neighbor.take_verified_payloads();
+                if let Some(view) = neighbor.take_view() {
+                    let header = next_state.header;
+                    next_state.update(&view);
+                    if next_state.header != header {
+                        won_by = Some(view.id);
+                    }
+                    seen.push((view.id, view.header));
+                }
+            }
+            TraceEvent::TurnEnded(new_round, header, payload) => {
+                if warmed_up {
+                    turns.push(ReplayedTurn {
+                        time: record.time,
+                        new_round: *new_round,
+                        winner: next_state.header,
+                        won_by,
+                        chosen: *header,
+                        seen: std::mem::take(&mut seen),
+                    });
+                }
+                warmed_up = true;
+                won_by = None;
+                seen.clear();
+                next_state.reset_for_next_turn(*new_round, *header, payload.clone());
+            }
+            TraceEvent::Accepted(message) => {
+                if let Some((sign, _bytes)) = message.payload.clone().signature_and_bytes() {
+                    if let Some((g_id, pub_key)) = sign.pubkey() {
+                        swarm.key_reg.insert(g_id, pub_key);
+                    }
+                }
+                next_state.last_accepted_message = message.clone();
+            }
+            TraceEvent::CastReceived(..) | TraceEvent::CastSent(..) => {}
+        }
+    }
+    turns
+}
+
+fn replay_neighbor(
+    neighbors: &mut HashMap<GnomeId, ReplayNeighbor>,
+    id: GnomeId,
+) -> &mut ReplayNeighbor {
+    neighbors.entry(id).or_insert_with(|| {
+        let (inbox, receiver) = channel();
+        let (cast_inbox, cast_receiver) = channel();
+        let (sender, outbox) = channel();
+        // Replayed neighbors never join other swarms
+        let (shared_sender, _shared_receiver) = channel();
+        ReplayNeighbor {
+            neighbor: Neighbor::from_id_channel_time(
+                id,
+                receiver,
+                cast_receiver,
+                sender,
+                shared_sender,
+                SwarmTime(0),
+                DEFAULT_SWARM_DIAMETER,
+                vec![],
+            ),
+            inbox,
+            _cast_inbox: cast_inbox,
+            _outbox: outbox,
+        }
+    })
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.pos + len > self.bytes.len() {
+            return None;
+        }
+        let taken = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Some(taken)
+    }
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    fn vec(&mut self) -> Option<Vec<u8>> {
+        let len = self.u32()? as usize;
+        Some(self.take(len)?.to_vec())
+    }
+}
+
+fn append_vec(vec: &[u8], bytes: &mut Vec<u8>) {
+    bytes.extend((vec.len() as u32).to_be_bytes());
+    bytes.extend(vec);
+}
+
+fn append_message(message: &Message, bytes: &mut Vec<u8>) {
+    bytes.extend(message.swarm_time.0.to_be_bytes());
+    bytes.push(message.neighborhood.0);
+    append_header(&message.header, bytes);
+    append_payload(&message.payload, bytes);
+}
+
+fn read_message(reader: &mut Reader) -> Option<Message> {
+    let swarm_time = SwarmTime(reader.u32()?);
+    let neighborhood = Neighborhood(reader.u8()?);
+    let header = read_header(reader)?;
+    let payload = read_payload(reader)?;
+    Some(Message::new(swarm_time, header, payload, neighborhood))
+}
+
+fn append_header(header: &Header, bytes: &mut Vec<u8>) {
+    match *header {
+        Header::Sync => bytes.push(0),
+        Header::Reconfigure(c_type, g_id) => {
+            bytes.push(1);
+            bytes.push(c_type);
+            bytes.extend(g_id.bytes());
+        }
+        Header::Block(b_id) => {
+            bytes.push(2);
+            bytes.extend(b_id.0.to_be_bytes());
+        }
+    }
+}
+
+fn read_header(reader: &mut Reader) -> Option<Header> {
+    match reader.u8()? {
+        0 => Some(Header::Sync),
+        1 => {
+            let c_type = reader.u8()?;
+            Some(Header::Reconfigure(c_type, GnomeId(reader.u64()?)))
+        }
+        2 => Some(Header::Block(BlockID(reader.u64()?))),
+        _ => None,
+    }
+}
+
+fn append_payload(payload: &Payload, bytes: &mut Vec<u8>) {
+    match payload {
+        Payload::KeepAlive(bandwith) => {
+            bytes.push(0);
+            bytes.extend(bandwith.to_be_bytes());
+        }
+        Payload::Bye => bytes.push(1),
+        Payload::Reconfigure(signature, config) => {
+            bytes.push(2);
+            append_signature(signature, bytes);
+            append_vec(&config.bytes(), bytes);
+        }
+        Payload::Block(b_id, signature, data) => {
+            bytes.push(3);
+            bytes.extend(b_id.0.to_be_bytes());
+            append_signature(signature, bytes);
+            append_vec(data.ref_bytes(), bytes);
+        }
+    }
+}
+
+fn read_payload(reader: &mut Reader) -> Option<Payload> {
+    match reader.u8()? {
+        0 => Some(Payload::KeepAlive(reader.u64()?)),
+        1 => Some(Payload::Bye),
+        2 => {
+            let signature = read_signature(reader)?;
+            let config_bytes = reader.vec()?;
+            if config_bytes.is_empty() {
+                return None;
+            }
+            Some(Payload::Reconfigure(
+                signature,
+                Configuration::try_from_bytes(config_bytes)?,
+            ))
+        }
+        3 => {
+            let b_id = BlockID(reader.u64()?);
+            let signature = read_signature(reader)?;
+            let data = SyncData::new(reader.vec()?).ok()?;
+            Some(Payload::Block(b_id, signature, data))
+        }
+        _ => None,
+    }
+}
+
+fn append_signature(signature: &Signature, bytes: &mut Vec<u8>) {
+    bytes.push(signature.header_byte());
+    bytes.extend(signature.gnome_id().bytes());
+    match signature {
+        Signature::Regular(_gid, sign) => append_vec(sign, bytes),
+        Signature::Extended(_gid, pub_key, sign) => {
+            append_vec(pub_key, bytes);
+            append_vec(sign, bytes);
+        }
+    }
+}
+
+fn read_signature(reader: &mut Reader) -> Option<Signature> {
+    let header_byte = reader.u8()?;
+    let g_id = GnomeId(reader.u64()?);
+    if header_byte == 0 {
+        Some(Signature::Regular(g_id, reader.vec()?))
+    } else {
+        let pub_key = reader.vec()?;
+        Some(Signature::Extended(g_id, pub_key, reader.vec()?))
+    }
+}
+
+fn append_cast(c_type: CastType, c_id: CastID, content: &[u8], bytes: &mut Vec<u8>) {
+    bytes.push(match c_type {
+        CastType::Unicast => 0,
+        CastType::Multicast => 1,
+        CastType::Broadcast => 2,
+    });
+    bytes.push(c_id.0);
+    append_vec(content, bytes);
+}
+
+fn read_cast(reader: &mut Reader) -> Option<(CastType, CastID, Vec<u8>)> {
+    let c_type = match reader.u8()? {
+        0 => CastType::Unicast,
+        1 => CastType::Multicast,
+        2 => CastType::Broadcast,
+        _ => return None,
+    };
+    let c_id = CastID(reader.u8()?);
+    Some((c_type, c_id, reader.vec()?))
+}
@@ -0,0 +1,158 @@
+use crate::outbox::MAX_CAST_BACKLOG;
+use crate::CastData;
+use crate::CastID;
+use crate::CastMessage;
+use crate::GnomeId;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::BuildHasherDefault;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::SyncSender;
+use std::sync::mpsc::TryRecvError;
+
+// How many messages an app can put into a unicast before
+// its sender blocks, waiting for neighbor to take them
+pub const UNICAST_BUFFER: usize = 16;
+
+// Unicast ids 255 & 254 are reserved for NeighborRequest & NeighborResponse
+const MAX_UNICAST_ID: u8 = 253;
+
+// Set of CastIDs free for a new unicast, a bit per id,
+// so that UnicastRequest carries 32 bytes instead of 256
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UnicastIDs([u8; 32]);
+
+impl UnicastIDs {
+    pub fn new() -> Self {
+        UnicastIDs([0; 32])
+    }
+
+    pub fn from_ids(ids: impl IntoIterator<Item = CastID>) -> Self {
+        let mut set = UnicastIDs::new();
+        for id in ids {
+            set.insert(id);
+        }
+        set
+    }
+
+    pub fn insert(&mut self, id: CastID) {
+        if id.0 <= MAX_UNICAST_ID {
+            self.0[id.0 as usize / 8] |= 1 << (id.0 % 8);
+        }
+    }
+
+    pub fn contains(&self, id: CastID) -> bool {
+        self.0[id.0 as usize / 8] & (1 << (id.0 % 8)) != 0
+    }
+
+    // Lowest id present in both sets
+    pub fn first_common(&self, other: &UnicastIDs) -> Option<CastID> {
+        (0..=MAX_UNICAST_ID)
+            .map(CastID)
+            .find(|id| self.contains(*id) && other.contains(*id))
+    }
+}
+
+// Unicasts our apps send to neighbors.
+// Data is read from app only when neighbor's outbox has room for it,
+// then it waits there for neighbor's tokens like any other cast.
+// While neighbor's app is full he pauses a unicast, and our app's
+// sender fills up.
+pub struct Unicasts {
+    streams: HashMap<CastID, (GnomeId, Receiver<CastData>), BuildHasherDefault<DefaultHasher>>,
+    paused: HashSet<CastID>,
+}
+
+impl Unicasts {
+    pub fn new() -> Self {
+        Unicasts {
+            streams: HashMap::default(),
+            paused: HashSet::new(),
+        }
+    }
+
+    // Returns a sender for the app to feed the unicast with data
+    pub fn insert(&mut self, id: CastID, neighbor: GnomeId) -> SyncSender<CastData> {
+        let (sender, receiver) = sync_channel(UNICAST_BUFFER);
+        self.streams.insert(id, (neighbor, receiver));
+        sender
+    }
+
+    pub fn remove(&mut self, id: CastID) -> Option<GnomeId> {
+        self.paused.remove(&id);
+        self.streams.remove(&id).map(|(neighbor, _r)| neighbor)
+    }
+
+    // Only neighbor a unicast goes to can pause or resume it
+    pub fn set_paused(&mut self, id: CastID, neighbor: GnomeId, paused: bool) -> bool {
+        if self.streams.get(&id).map(|(n_id, _r)| *n_id) != Some(neighbor) {
+            return false;
+        }
+        if paused {
+            self.paused.insert(id)
+        } else {
+            self.paused.remove(&id)
+        }
+    }
+
+    // Remove all unicasts to given neighbor, returns their ids
+    pub fn remove_neighbor(&mut self, neighbor: GnomeId) -> Vec<CastID> {
+        let mut removed: Vec<CastID> = self
+            .streams
+            .iter()
+            .filter(|(_id, (n_id, _r))| *n_id == neighbor)
+            .map(|(id, _s)| *id)
+            .collect();
+        removed.sort();
+        for id in &removed {
+            self.paused.remove(id);
+            self.streams.remove(id);
+        }
+        removed
+    }
+
+    // Move data from apps into outgoing messages, as long as
+    // neighbors have room for them.
+    // Returns unicasts whose app has dropped its sender, these are
+    // removed only once neighbor's outbox has no casts waiting,
+    // so that all the data is sent before we end them.
+    pub fn serve(
+        &mut self,
+        room: &mut HashMap<GnomeId, usize>,
+        outgoing: &mut Vec<(GnomeId, CastMessage)>,
+    ) -> Vec<(GnomeId, CastID)> {
+        let mut ended = vec![];
+        for (id, (neighbor, receiver)) in self.streams.iter() {
+            if self.paused.contains(id) {
+                continue;
+            }
+            let n_room = if let Some(n_room) = room.get_mut(neighbor) {
+                n_room
+            } else {
+                continue;
+            };
+            while *n_room > 0 {
+                match receiver.try_recv() {
+                    Ok(data) => {
+                        outgoing.push((*neighbor, CastMessage::new_unicast(*id, data)));
+                        *n_room -= 1;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        if *n_room == MAX_CAST_BACKLOG {
+                            ended.push((*neighbor, *id));
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        ended.sort();
+        for (_neighbor, id) in &ended {
+            self.streams.remove(id);
+        }
+        ended
+    }
+}